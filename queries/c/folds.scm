@@ -0,0 +1,14 @@
+;; C folding queries for tree-sitter
+;; Each @fold node spanning more than one line folds the lines after its first
+
+[
+  (compound_statement)
+  (field_declaration_list)
+  (enumerator_list)
+  (initializer_list)
+  (parameter_list)
+  (argument_list)
+  (preproc_if)
+  (preproc_ifdef)
+  (comment)
+] @fold
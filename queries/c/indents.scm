@@ -0,0 +1,19 @@
+;; C indentation queries for tree-sitter
+;; @indent nodes indent the lines after the one they start on, @outdent takes the
+;; line it starts back out a level, and @extend keeps a blank line after the node inside it
+
+[
+  (compound_statement)
+  (field_declaration_list)
+  (enumerator_list)
+  (initializer_list)
+  (parameter_list)
+  (argument_list)
+  (case_statement)
+] @indent
+
+[
+  "}"
+  ")"
+  "]"
+] @outdent
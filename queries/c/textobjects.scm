@@ -0,0 +1,27 @@
+;; C text object queries for tree-sitter
+;; @<kind>.around is what `a<key>` selects, @<kind>.inside what `i<key>` selects
+;; Kinds: function (f), class (c), parameter (a), comment (/)
+
+; Functions
+(function_definition
+  body: (compound_statement) @function.inside) @function.around
+
+; Structs, unions and enums
+(struct_specifier
+  body: (field_declaration_list) @class.inside) @class.around
+
+(union_specifier
+  body: (field_declaration_list) @class.inside) @class.around
+
+(enum_specifier
+  body: (enumerator_list) @class.inside) @class.around
+
+; Parameters and call arguments
+(parameter_list
+  (_) @parameter.inside)
+
+(argument_list
+  (_) @parameter.inside)
+
+; Comments
+(comment) @comment.inside @comment.around
@@ -0,0 +1,17 @@
+;; Go folding queries for tree-sitter
+;; Each @fold node spanning more than one line folds the lines after its first
+
+[
+  (block)
+  (literal_value)
+  (field_declaration_list)
+  (interface_type)
+  (import_spec_list)
+  (parameter_list)
+  (argument_list)
+  (expression_switch_statement)
+  (type_switch_statement)
+  (select_statement)
+  (const_declaration)
+  (var_declaration)
+] @fold
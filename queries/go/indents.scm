@@ -0,0 +1,26 @@
+;; Go indentation queries for tree-sitter
+;; @indent nodes indent the lines after the one they start on, @outdent takes the
+;; line it starts back out a level, and @extend keeps a blank line after the node inside it
+
+[
+  (block)
+  (literal_value)
+  (field_declaration_list)
+  (interface_type)
+  (import_spec_list)
+  (parameter_list)
+  (argument_list)
+  (expression_case)
+  (type_case)
+  (default_case)
+  (communication_case)
+] @indent
+
+; Cases sit level with their switch, so only brackets that closed an indent outdent
+(block "}" @outdent)
+(literal_value "}" @outdent)
+(field_declaration_list "}" @outdent)
+(interface_type "}" @outdent)
+(import_spec_list ")" @outdent)
+(parameter_list ")" @outdent)
+(argument_list ")" @outdent)
@@ -0,0 +1,32 @@
+;; Go text object queries for tree-sitter
+;; @<kind>.around is what `a<key>` selects, @<kind>.inside what `i<key>` selects
+;; Kinds: function (f), class (c), parameter (a), comment (/)
+
+; Functions, methods and function literals
+(function_declaration
+  body: (block) @function.inside) @function.around
+
+(method_declaration
+  body: (block) @function.inside) @function.around
+
+(func_literal
+  body: (block) @function.inside) @function.around
+
+; Struct and interface types
+(type_declaration
+  (type_spec
+    type: (struct_type) @class.inside)) @class.around
+
+(type_declaration
+  (type_spec
+    type: (interface_type) @class.inside)) @class.around
+
+; Parameters and call arguments
+(parameter_list
+  (_) @parameter.inside)
+
+(argument_list
+  (_) @parameter.inside)
+
+; Comments
+(comment) @comment.inside @comment.around
@@ -0,0 +1,20 @@
+;; Python folding queries for tree-sitter
+;; Each @fold node spanning more than one line folds the lines after its first
+
+[
+  (function_definition)
+  (class_definition)
+  (if_statement)
+  (for_statement)
+  (while_statement)
+  (with_statement)
+  (try_statement)
+  (match_statement)
+  (parameters)
+  (argument_list)
+  (list)
+  (dictionary)
+  (set)
+  (tuple)
+  (string)
+] @fold
@@ -0,0 +1,44 @@
+;; Python indentation queries for tree-sitter
+;; @indent nodes indent the lines after the one they start on, @outdent takes the
+;; line it starts back out a level, and @extend keeps a blank line after the node inside it
+
+[
+  (function_definition)
+  (class_definition)
+  (if_statement)
+  (for_statement)
+  (while_statement)
+  (with_statement)
+  (try_statement)
+  (match_statement)
+  (case_clause)
+  (parameters)
+  (argument_list)
+  (list)
+  (dictionary)
+  (set)
+  (tuple)
+] @indent
+
+; Enter after the last line of a body stays in the body
+[
+  (function_definition)
+  (class_definition)
+  (if_statement)
+  (for_statement)
+  (while_statement)
+  (with_statement)
+  (try_statement)
+  (case_clause)
+] @extend
+
+; Clauses line up with the statement they continue
+[
+  "elif"
+  "else"
+  "except"
+  "finally"
+  ")"
+  "]"
+  "}"
+] @outdent
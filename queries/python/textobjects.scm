@@ -0,0 +1,27 @@
+;; Python text object queries for tree-sitter
+;; @<kind>.around is what `a<key>` selects, @<kind>.inside what `i<key>` selects
+;; Kinds: function (f), class (c), parameter (a), comment (/)
+
+; Functions and lambdas
+(function_definition
+  body: (block) @function.inside) @function.around
+
+(lambda
+  body: (_) @function.inside) @function.around
+
+; Classes
+(class_definition
+  body: (block) @class.inside) @class.around
+
+; Parameters and call arguments
+(parameters
+  (_) @parameter.inside)
+
+(lambda_parameters
+  (_) @parameter.inside)
+
+(argument_list
+  (_) @parameter.inside)
+
+; Comments
+(comment) @comment.inside @comment.around
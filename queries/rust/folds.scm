@@ -0,0 +1,17 @@
+;; Rust folding queries for tree-sitter
+;; Each @fold node spanning more than one line folds the lines after its first
+
+[
+  (block)
+  (declaration_list)
+  (field_declaration_list)
+  (enum_variant_list)
+  (field_initializer_list)
+  (match_block)
+  (use_list)
+  (parameters)
+  (arguments)
+  (array_expression)
+  (token_tree)
+  (block_comment)
+] @fold
@@ -0,0 +1,24 @@
+;; Rust indentation queries for tree-sitter
+;; @indent nodes indent the lines after the one they start on, @outdent takes the
+;; line it starts back out a level, and @extend keeps a blank line after the node inside it
+
+[
+  (block)
+  (declaration_list)
+  (field_declaration_list)
+  (enum_variant_list)
+  (field_initializer_list)
+  (match_block)
+  (use_list)
+  (parameters)
+  (arguments)
+  (array_expression)
+  (tuple_expression)
+  (token_tree)
+] @indent
+
+[
+  "}"
+  ")"
+  "]"
+] @outdent
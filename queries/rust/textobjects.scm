@@ -0,0 +1,51 @@
+;; Rust text object queries for tree-sitter
+;; @<kind>.around is what `a<key>` selects, @<kind>.inside what `i<key>` selects
+;; Kinds: function (f), class (c), parameter (a), comment (/)
+
+; Functions and closures
+(function_item
+  body: (block) @function.inside) @function.around
+
+(closure_expression
+  body: (_) @function.inside) @function.around
+
+; Types, traits, impls and modules
+(struct_item
+  body: (_) @class.inside) @class.around
+
+(enum_item
+  body: (_) @class.inside) @class.around
+
+(union_item
+  body: (_) @class.inside) @class.around
+
+(trait_item
+  body: (_) @class.inside) @class.around
+
+(impl_item
+  body: (_) @class.inside) @class.around
+
+(mod_item
+  body: (_) @class.inside) @class.around
+
+; Parameters, arguments and generics
+(parameters
+  (_) @parameter.inside)
+
+(closure_parameters
+  (_) @parameter.inside)
+
+(arguments
+  (_) @parameter.inside)
+
+(type_parameters
+  (_) @parameter.inside)
+
+(type_arguments
+  (_) @parameter.inside)
+
+; Comments
+[
+  (line_comment)
+  (block_comment)
+] @comment.inside @comment.around
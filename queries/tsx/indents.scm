@@ -0,0 +1,24 @@
+;; TSX indentation queries for tree-sitter
+;; @indent nodes indent the lines after the one they start on, @outdent takes the
+;; line it starts back out a level, and @extend keeps a blank line after the node inside it
+
+[
+  (statement_block)
+  (class_body)
+  (enum_body)
+  (object_type)
+  (object)
+  (array)
+  (switch_body)
+  (switch_case)
+  (switch_default)
+  (named_imports)
+  (formal_parameters)
+  (arguments)
+] @indent
+
+[
+  "}"
+  ")"
+  "]"
+] @outdent
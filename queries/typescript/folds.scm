@@ -0,0 +1,17 @@
+;; TypeScript folding queries for tree-sitter
+;; Each @fold node spanning more than one line folds the lines after its first
+
+[
+  (statement_block)
+  (class_body)
+  (enum_body)
+  (object_type)
+  (object)
+  (array)
+  (switch_body)
+  (named_imports)
+  (formal_parameters)
+  (arguments)
+  (template_string)
+  (comment)
+] @fold
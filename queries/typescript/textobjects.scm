@@ -0,0 +1,42 @@
+;; TypeScript text object queries for tree-sitter
+;; @<kind>.around is what `a<key>` selects, @<kind>.inside what `i<key>` selects
+;; Kinds: function (f), class (c), parameter (a), comment (/)
+
+; Functions, methods and arrow functions
+(function_declaration
+  body: (statement_block) @function.inside) @function.around
+
+(generator_function_declaration
+  body: (statement_block) @function.inside) @function.around
+
+(method_definition
+  body: (statement_block) @function.inside) @function.around
+
+(arrow_function
+  body: (_) @function.inside) @function.around
+
+; Classes, interfaces and enums
+(class_declaration
+  body: (class_body) @class.inside) @class.around
+
+(interface_declaration
+  body: (_) @class.inside) @class.around
+
+(enum_declaration
+  body: (enum_body) @class.inside) @class.around
+
+; Parameters, arguments and generics
+(formal_parameters
+  (_) @parameter.inside)
+
+(arguments
+  (_) @parameter.inside)
+
+(type_parameters
+  (_) @parameter.inside)
+
+(type_arguments
+  (_) @parameter.inside)
+
+; Comments
+(comment) @comment.inside @comment.around
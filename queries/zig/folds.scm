@@ -0,0 +1,11 @@
+;; Zig folding queries for tree-sitter
+;; Each @fold node spanning more than one line folds the lines after its first
+
+[
+  (Block)
+  (ContainerDecl)
+  (InitList)
+  (ParamDeclList)
+  (FnCallArguments)
+  (SwitchExpr)
+] @fold
@@ -0,0 +1,18 @@
+;; Zig indentation queries for tree-sitter
+;; @indent nodes indent the lines after the one they start on, @outdent takes the
+;; line it starts back out a level, and @extend keeps a blank line after the node inside it
+
+[
+  (Block)
+  (ContainerDecl)
+  (InitList)
+  (ParamDeclList)
+  (FnCallArguments)
+  (SwitchExpr)
+] @indent
+
+[
+  "}"
+  ")"
+  "]"
+] @outdent
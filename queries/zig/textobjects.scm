@@ -0,0 +1,27 @@
+;; Zig text object queries for tree-sitter
+;; @<kind>.around is what `a<key>` selects, @<kind>.inside what `i<key>` selects
+;; Kinds: function (f), class (c), parameter (a), comment (/)
+
+; Functions and tests
+(_
+  (FnProto)
+  (Block) @function.inside) @function.around
+
+(TestDecl
+  (Block) @function.inside) @function.around
+
+; Structs, enums, unions and opaques
+(ContainerDecl) @class.inside @class.around
+
+; Parameters and call arguments
+(ParamDeclList
+  (ParamDecl) @parameter.inside)
+
+(FnCallArguments
+  (_) @parameter.inside)
+
+; Comments
+[
+  (LINECOMMENT)
+  (doc_comment)
+] @comment.inside @comment.around
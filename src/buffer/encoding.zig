@@ -0,0 +1,398 @@
+//! File encodings and line endings
+//! Buffers always hold UTF-8 text with `\n` line endings. Files are decoded into
+//! that form when read and encoded back into their own format when saved, so
+//! CRLF files, byte order marks, UTF-16, and Latin-1 survive a round trip.
+
+const std = @import("std");
+
+/// Character encoding of a file on disk
+pub const Encoding = enum {
+    utf8,
+    utf16le,
+    utf16be,
+    latin1,
+
+    /// Name as shown in the statusline and accepted by `:set fileencoding`
+    pub fn name(self: Encoding) []const u8 {
+        return switch (self) {
+            .utf8 => "utf-8",
+            .utf16le => "utf-16le",
+            .utf16be => "utf-16be",
+            .latin1 => "latin1",
+        };
+    }
+
+    pub fn fromName(text: []const u8) ?Encoding {
+        const names = [_]struct { []const u8, Encoding }{
+            .{ "utf-8", .utf8 },
+            .{ "utf8", .utf8 },
+            .{ "utf-16le", .utf16le },
+            .{ "utf-16", .utf16le },
+            .{ "utf-16be", .utf16be },
+            .{ "latin1", .latin1 },
+            .{ "iso-8859-1", .latin1 },
+        };
+        for (names) |entry| {
+            if (std.ascii.eqlIgnoreCase(text, entry[0])) return entry[1];
+        }
+        return null;
+    }
+
+    fn bom(self: Encoding) []const u8 {
+        return switch (self) {
+            .utf8 => "\xEF\xBB\xBF",
+            .utf16le => "\xFF\xFE",
+            .utf16be => "\xFE\xFF",
+            .latin1 => "",
+        };
+    }
+};
+
+/// Line ending of a file on disk
+pub const LineEnding = enum {
+    lf,
+    crlf,
+    cr,
+
+    /// Vim's fileformat name, as shown in the statusline and accepted by `:set fileformat`
+    pub fn name(self: LineEnding) []const u8 {
+        return switch (self) {
+            .lf => "unix",
+            .crlf => "dos",
+            .cr => "mac",
+        };
+    }
+
+    pub fn fromName(text: []const u8) ?LineEnding {
+        if (std.mem.eql(u8, text, "unix")) return .lf;
+        if (std.mem.eql(u8, text, "dos")) return .crlf;
+        if (std.mem.eql(u8, text, "mac")) return .cr;
+        return null;
+    }
+
+    fn bytes(self: LineEnding) []const u8 {
+        return switch (self) {
+            .lf => "\n",
+            .crlf => "\r\n",
+            .cr => "\r",
+        };
+    }
+};
+
+/// How a buffer's text is stored on disk
+pub const FileFormat = struct {
+    encoding: Encoding = .utf8,
+    bom: bool = false,
+    line_ending: LineEnding = .lf,
+
+    /// Check if the file bytes are the buffer text as-is (nothing to convert)
+    pub fn isPlain(self: FileFormat) bool {
+        return self.encoding == .utf8 and !self.bom and self.line_ending == .lf;
+    }
+
+    /// Describe the format for the statusline, e.g. "utf-8-bom dos"
+    pub fn describe(self: FileFormat, buf: []u8) []const u8 {
+        return std.fmt.bufPrint(buf, "{s}{s} {s}", .{
+            self.encoding.name(),
+            if (self.bom) "-bom" else "",
+            self.line_ending.name(),
+        }) catch self.encoding.name();
+    }
+};
+
+/// Text can't be represented in the target encoding
+pub const EncodeError = error{ UnrepresentableCharacter, OutOfMemory };
+
+const REPLACEMENT: u21 = 0xFFFD;
+
+/// Bytes looked at to guess a BOM-less UTF-16 file
+const SNIFF_LEN = 4096;
+
+// === Detection ===
+
+/// Work out the encoding, BOM, and line ending of file content
+pub fn detect(bytes: []const u8) FileFormat {
+    var format = FileFormat{};
+
+    if (std.mem.startsWith(u8, bytes, Encoding.utf8.bom())) {
+        format.bom = true;
+    } else if (std.mem.startsWith(u8, bytes, Encoding.utf16le.bom())) {
+        format = .{ .encoding = .utf16le, .bom = true };
+    } else if (std.mem.startsWith(u8, bytes, Encoding.utf16be.bom())) {
+        format = .{ .encoding = .utf16be, .bom = true };
+    } else if (sniffUtf16(bytes)) |encoding| {
+        // NULs are valid UTF-8, so this has to come before validation
+        format.encoding = encoding;
+    } else if (!std.unicode.utf8ValidateSlice(bytes)) {
+        // Every byte string is valid Latin-1
+        format.encoding = .latin1;
+    }
+
+    const body = if (format.bom) bytes[format.encoding.bom().len..] else bytes;
+    format.line_ending = detectLineEnding(body, format.encoding);
+    return format;
+}
+
+/// Guess BOM-less UTF-16 from mostly-ASCII text: every other byte is NUL
+fn sniffUtf16(bytes: []const u8) ?Encoding {
+    const sample = bytes[0 .. @min(bytes.len, SNIFF_LEN) & ~@as(usize, 1)];
+    if (sample.len == 0) return null;
+
+    var even_nuls: usize = 0;
+    var odd_nuls: usize = 0;
+    for (sample, 0..) |byte, i| {
+        if (byte != 0) continue;
+        if (i % 2 == 0) even_nuls += 1 else odd_nuls += 1;
+    }
+
+    const units = sample.len / 2;
+    if (odd_nuls * 4 >= units * 3 and even_nuls == 0) return .utf16le;
+    if (even_nuls * 4 >= units * 3 and odd_nuls == 0) return .utf16be;
+    return null;
+}
+
+/// Line ending used by every line break (LF if there are none)
+/// A file that mixes them is treated as LF, which keeps each `\r` as it is and
+/// so writes the bytes back unchanged instead of converting the odd lines.
+fn detectLineEnding(bytes: []const u8, encoding: Encoding) LineEnding {
+    var lf: usize = 0;
+    var crlf: usize = 0;
+    var cr: usize = 0;
+
+    var units = UnitIterator{ .bytes = bytes, .encoding = encoding };
+    var prev: u16 = 0;
+    while (units.next()) |unit| {
+        defer prev = unit;
+        switch (unit) {
+            '\n' => if (prev == '\r') {
+                crlf += 1;
+                cr -= 1; // Counted as a lone CR a step ago
+            } else {
+                lf += 1;
+            },
+            '\r' => cr += 1,
+            else => {},
+        }
+    }
+
+    // A stray CR inside a CRLF file's lines stays put either way
+    if (crlf > 0 and lf == 0) return .crlf;
+    if (cr > 0 and lf == 0 and crlf == 0) return .cr;
+    return .lf;
+}
+
+/// Steps over code units (bytes, or 16-bit units for UTF-16)
+const UnitIterator = struct {
+    bytes: []const u8,
+    encoding: Encoding,
+    pos: usize = 0,
+
+    fn next(self: *UnitIterator) ?u16 {
+        switch (self.encoding) {
+            .utf8, .latin1 => {
+                if (self.pos >= self.bytes.len) return null;
+                defer self.pos += 1;
+                return self.bytes[self.pos];
+            },
+            .utf16le, .utf16be => {
+                if (self.pos + 2 > self.bytes.len) return null;
+                defer self.pos += 2;
+                const pair = self.bytes[self.pos..][0..2];
+                return if (self.encoding == .utf16le)
+                    std.mem.readInt(u16, pair, .little)
+                else
+                    std.mem.readInt(u16, pair, .big);
+            },
+        }
+    }
+
+    fn rest(self: *const UnitIterator) usize {
+        return self.bytes.len - self.pos;
+    }
+};
+
+// === Conversion ===
+
+/// Convert file content in `format` to buffer text (UTF-8, `\n` line endings)
+/// Invalid sequences become U+FFFD. Caller owns the result.
+pub fn decode(allocator: std.mem.Allocator, bytes: []const u8, format: FileFormat) ![]u8 {
+    const body = if (format.bom and std.mem.startsWith(u8, bytes, format.encoding.bom()))
+        bytes[format.encoding.bom().len..]
+    else
+        bytes;
+
+    var text = std.ArrayList(u8).empty;
+    errdefer text.deinit(allocator);
+
+    switch (format.encoding) {
+        .utf8 => try text.appendSlice(allocator, body),
+        .latin1 => {
+            try text.ensureTotalCapacity(allocator, body.len);
+            for (body) |byte| try appendChar(allocator, &text, byte);
+        },
+        .utf16le, .utf16be => {
+            try text.ensureTotalCapacity(allocator, body.len / 2);
+            var units = UnitIterator{ .bytes = body, .encoding = format.encoding };
+            while (units.next()) |unit| {
+                var char: u21 = unit;
+                if (std.unicode.utf16IsHighSurrogate(unit)) {
+                    const saved = units.pos;
+                    const low = units.next();
+                    if (low != null and std.unicode.utf16IsLowSurrogate(low.?)) {
+                        char = std.unicode.utf16DecodeSurrogatePair(&.{ unit, low.? }) catch REPLACEMENT;
+                    } else {
+                        units.pos = saved;
+                        char = REPLACEMENT;
+                    }
+                } else if (std.unicode.utf16IsLowSurrogate(unit)) {
+                    char = REPLACEMENT;
+                }
+                try appendChar(allocator, &text, char);
+            }
+            if (units.rest() != 0) try appendChar(allocator, &text, REPLACEMENT);
+        },
+    }
+
+    normalizeLineEndings(&text, format.line_ending);
+    return text.toOwnedSlice(allocator);
+}
+
+/// Rewrite line breaks to `\n` in place (the text only shrinks)
+fn normalizeLineEndings(text: *std.ArrayList(u8), line_ending: LineEnding) void {
+    if (line_ending == .lf) return;
+
+    const items = text.items;
+    var out: usize = 0;
+    var i: usize = 0;
+    while (i < items.len) : (i += 1) {
+        var byte = items[i];
+        if (byte == '\r') switch (line_ending) {
+            .crlf => if (i + 1 < items.len and items[i + 1] == '\n') continue,
+            .cr => byte = '\n',
+            .lf => unreachable,
+        };
+        items[out] = byte;
+        out += 1;
+    }
+    text.shrinkRetainingCapacity(out);
+}
+
+/// Convert buffer text back to file content in `format`. Caller owns the result.
+pub fn encode(allocator: std.mem.Allocator, text: []const u8, format: FileFormat) EncodeError![]u8 {
+    var bytes = std.ArrayList(u8).empty;
+    errdefer bytes.deinit(allocator);
+    try bytes.ensureTotalCapacity(allocator, text.len + text.len / 16 + 4);
+
+    if (format.bom) try bytes.appendSlice(allocator, format.encoding.bom());
+
+    var i: usize = 0;
+    while (i < text.len) {
+        // Bytes that aren't valid UTF-8 go out as U+FFFD, except in UTF-8 where they pass through
+        const len = std.unicode.utf8ByteSequenceLength(text[i]) catch 1;
+        const seq = text[i..@min(i + len, text.len)];
+        const char = std.unicode.utf8Decode(seq) catch null;
+        i += if (char != null) seq.len else 1;
+
+        if (char == '\n') {
+            for (format.line_ending.bytes()) |byte| try appendEncoded(allocator, &bytes, byte, format.encoding);
+        } else if (char) |c| {
+            if (format.encoding == .utf8) {
+                try bytes.appendSlice(allocator, seq);
+            } else {
+                try appendEncoded(allocator, &bytes, c, format.encoding);
+            }
+        } else if (format.encoding == .utf8) {
+            try bytes.append(allocator, text[i - 1]);
+        } else {
+            try appendEncoded(allocator, &bytes, REPLACEMENT, format.encoding);
+        }
+    }
+
+    return bytes.toOwnedSlice(allocator);
+}
+
+fn appendChar(allocator: std.mem.Allocator, text: *std.ArrayList(u8), char: u21) !void {
+    var buf: [4]u8 = undefined;
+    const len = std.unicode.utf8Encode(char, &buf) catch std.unicode.utf8Encode(REPLACEMENT, &buf) catch unreachable;
+    try text.appendSlice(allocator, buf[0..len]);
+}
+
+fn appendEncoded(allocator: std.mem.Allocator, bytes: *std.ArrayList(u8), char: u21, encoding: Encoding) EncodeError!void {
+    switch (encoding) {
+        .utf8 => {
+            var buf: [4]u8 = undefined;
+            const len = std.unicode.utf8Encode(char, &buf) catch return error.UnrepresentableCharacter;
+            try bytes.appendSlice(allocator, buf[0..len]);
+        },
+        .latin1 => {
+            if (char > 0xFF) return error.UnrepresentableCharacter;
+            try bytes.append(allocator, @intCast(char));
+        },
+        .utf16le, .utf16be => {
+            const endian: std.builtin.Endian = if (encoding == .utf16le) .little else .big;
+            var units: [2]u16 = undefined;
+            var count: usize = 1;
+            if (char >= 0x10000) {
+                const offset = char - 0x10000;
+                units = .{ @intCast(0xD800 + (offset >> 10)), @intCast(0xDC00 + (offset & 0x3FF)) };
+                count = 2;
+            } else {
+                units[0] = @intCast(char);
+            }
+            for (units[0..count]) |unit| {
+                var buf: [2]u8 = undefined;
+                std.mem.writeInt(u16, &buf, unit, endian);
+                try bytes.appendSlice(allocator, &buf);
+            }
+        },
+    }
+}
+
+// === Tests ===
+
+test "encoding: detect format" {
+    try std.testing.expectEqual(FileFormat{}, detect("plain\ntext\n"));
+    try std.testing.expectEqual(FileFormat{}, detect(""));
+    try std.testing.expectEqual(FileFormat{ .bom = true, .line_ending = .crlf }, detect("\xEF\xBB\xBFa\r\nb\r\n"));
+    try std.testing.expectEqual(FileFormat{ .line_ending = .cr }, detect("a\rb\r"));
+    try std.testing.expectEqual(FileFormat{}, detect("a\r\nb\nc\r\n")); // Mixed: left as it is
+    try std.testing.expectEqual(FileFormat{}, detect("a\rb\r\n"));
+    try std.testing.expectEqual(FileFormat{ .encoding = .latin1 }, detect("caf\xE9\n"));
+    try std.testing.expectEqual(FileFormat{ .encoding = .utf16le, .bom = true, .line_ending = .crlf }, detect("\xFF\xFEa\x00\r\x00\n\x00"));
+    try std.testing.expectEqual(FileFormat{ .encoding = .utf16be }, detect("\x00h\x00i\x00\n"));
+}
+
+test "encoding: round trip" {
+    const allocator = std.testing.allocator;
+    const cases = [_][]const u8{
+        "\xEF\xBB\xBFone\r\ntwo\r\n",
+        "caf\xE9\rna\xEFve\r",
+        "\xFF\xFEh\x00\xE9\x00\r\x00\n\x00=\xD8\x00\xDE", // "hé\r\n😀" in UTF-16LE
+        "\xFE\xFF\x00a\x00\n",
+    };
+
+    for (cases) |original| {
+        const format = detect(original);
+        const text = try decode(allocator, original, format);
+        defer allocator.free(text);
+        try std.testing.expect(std.mem.indexOfScalar(u8, text, '\r') == null);
+
+        const saved = try encode(allocator, text, format);
+        defer allocator.free(saved);
+        try std.testing.expectEqualSlices(u8, original, saved);
+    }
+
+    const text = try decode(allocator, "\xFF\xFEh\x00\xE9\x00\r\x00\n\x00=\xD8\x00\xDE", detect("\xFF\xFEh\x00\xE9\x00\r\x00\n\x00=\xD8\x00\xDE"));
+    defer allocator.free(text);
+    try std.testing.expectEqualStrings("hé\n😀", text);
+
+    try std.testing.expectError(error.UnrepresentableCharacter, encode(allocator, "😀", .{ .encoding = .latin1 }));
+
+    // Mixed line endings are saved exactly as they were read
+    const mixed = "one\r\ntwo\nthree\r\n";
+    const mixed_text = try decode(allocator, mixed, detect(mixed));
+    defer allocator.free(mixed_text);
+    const mixed_saved = try encode(allocator, mixed_text, detect(mixed));
+    defer allocator.free(mixed_saved);
+    try std.testing.expectEqualStrings(mixed, mixed_saved);
+}
@@ -0,0 +1,247 @@
+//! Crash-safe file writing
+//! Content goes to a temp file beside the target, is synced, then renamed over it,
+//! so an interrupted save never leaves a truncated file behind. Where the directory
+//! doesn't let us create the temp file but the file itself is writable, it's
+//! overwritten in place instead.
+
+const std = @import("std");
+const Rope = @import("rope.zig").Rope;
+
+/// Options for writing a buffer to disk
+pub const WriteOptions = struct {
+    /// Copy the previous version to `<path><backup_suffix>` before replacing it
+    backup: bool = false,
+    backup_suffix: []const u8 = "~",
+    /// Write even if the file changed on disk since it was read (checked by Buffer.save)
+    force: bool = false,
+};
+
+/// Check whether an existing file can be written by us
+pub fn isWritable(path: []const u8) bool {
+    std.posix.access(path, std.posix.W_OK) catch return false;
+    return true;
+}
+
+/// What to write into the replacement file
+const Source = union(enum) {
+    bytes: []const u8,
+    rope: *const Rope, // Streamed leaf by leaf, never copied whole
+
+    fn writeTo(self: Source, file: std.fs.File) !void {
+        switch (self) {
+            .bytes => |bytes| try file.writeAll(bytes),
+            .rope => |rope| {
+                // Leaves are small; batch them into fewer writes
+                var buf: [64 * 1024]u8 = undefined;
+                var file_writer = file.writer(&buf);
+                const writer = &file_writer.interface;
+                var chunks = rope.chunks(0, rope.len());
+                while (chunks.next()) |chunk| try writer.writeAll(chunk);
+                try writer.flush();
+            },
+        }
+    }
+};
+
+/// Atomically replace `path` with `content`
+/// Symlinks are followed so the link's target is replaced, not the link. The
+/// original file's mode is kept, and its owner/group where permitted. Fails
+/// with error.ReadOnlyFile if the target isn't writable, error.BackupFailed if the
+/// requested backup can't be written (the file is left alone), and
+/// error.PermissionDenied if its directory doesn't allow creating a new file. An
+/// existing file in such a directory is truncated and rewritten in place.
+pub fn writeFileAtomic(
+    allocator: std.mem.Allocator,
+    path: []const u8,
+    content: []const u8,
+    options: WriteOptions,
+) !void {
+    return replaceFile(allocator, path, .{ .bytes = content }, options);
+}
+
+/// Atomically replace `path` with the text of `rope`, like writeFileAtomic
+/// Memory use stays flat however large the rope is.
+pub fn writeRopeAtomic(
+    allocator: std.mem.Allocator,
+    path: []const u8,
+    rope: *const Rope,
+    options: WriteOptions,
+) !void {
+    return replaceFile(allocator, path, .{ .rope = rope }, options);
+}
+
+fn replaceFile(
+    allocator: std.mem.Allocator,
+    path: []const u8,
+    source: Source,
+    options: WriteOptions,
+) !void {
+    const target = std.fs.cwd().realpathAlloc(allocator, path) catch |err| switch (err) {
+        error.FileNotFound => try allocator.dupe(u8, path),
+        error.AccessDenied => return error.PermissionDenied,
+        else => return err,
+    };
+    defer allocator.free(target);
+
+    const existing: ?std.posix.Stat = std.posix.fstatat(std.fs.cwd().fd, target, 0) catch |err| switch (err) {
+        error.FileNotFound => null,
+        error.AccessDenied => return error.PermissionDenied,
+        else => return err,
+    };
+
+    if (existing != null) {
+        std.posix.access(target, std.posix.W_OK) catch |err| switch (err) {
+            error.PermissionDenied, error.ReadOnlyFileSystem => return error.ReadOnlyFile,
+            else => return err,
+        };
+    }
+
+    const dir_path = std.fs.path.dirname(target) orelse ".";
+    const base = std.fs.path.basename(target);
+
+    var dir = std.fs.cwd().openDir(dir_path, .{}) catch |err| switch (err) {
+        error.AccessDenied => return error.PermissionDenied,
+        else => return err,
+    };
+    defer dir.close();
+
+    // Keep the previous version around before we touch anything
+    if (options.backup and existing != null) {
+        const backup_name = try std.fmt.allocPrint(allocator, "{s}{s}", .{ base, options.backup_suffix });
+        defer allocator.free(backup_name);
+        dir.copyFile(base, dir, backup_name, .{}) catch |err| switch (err) {
+            // Not the file's fault: it may still be writable in place, just not backed up
+            error.AccessDenied => return error.BackupFailed,
+            else => return err,
+        };
+    }
+
+    var random: [8]u8 = undefined;
+    std.crypto.random.bytes(&random);
+    const tmp_name = try std.fmt.allocPrint(allocator, ".{s}.{x}.aesop-tmp", .{
+        base,
+        std.mem.readInt(u64, &random, .little),
+    });
+    defer allocator.free(tmp_name);
+
+    const mode: std.fs.File.Mode = if (existing) |st| @intCast(st.mode & 0o7777) else std.fs.File.default_mode;
+
+    const file = dir.createFile(tmp_name, .{ .exclusive = true, .mode = mode }) catch |err| switch (err) {
+        error.AccessDenied => {
+            // A writable file in a directory we can't add to (e.g. /etc/hosts via group write)
+            if (existing == null) return error.PermissionDenied;
+            return overwriteInPlace(dir, base, source);
+        },
+        else => return err,
+    };
+
+    var renamed = false;
+    defer if (!renamed) dir.deleteFile(tmp_name) catch {};
+
+    {
+        defer file.close();
+        try source.writeTo(file);
+
+        if (existing) |st| {
+            // createFile's mode is filtered through the umask; restore it exactly
+            file.chmod(mode) catch {};
+            // Only the owner (or root) can hand the file back; otherwise keep ours
+            file.chown(st.uid, st.gid) catch {};
+        }
+
+        try file.sync();
+    }
+
+    dir.rename(tmp_name, base) catch |err| switch (err) {
+        error.AccessDenied => return error.PermissionDenied,
+        else => return err,
+    };
+    renamed = true;
+
+    // Persist the rename itself; not all filesystems support this
+    std.posix.fsync(dir.fd) catch {};
+}
+
+/// Truncate and rewrite an existing file; its mode, owner and inode are untouched
+/// Not crash-safe, so only used when no temp file can be created.
+fn overwriteInPlace(dir: std.fs.Dir, base: []const u8, source: Source) !void {
+    // A rope borrowing from a mapping of this very file would read what we overwrite
+    if (source == .rope and source.rope.backing != null) return error.PermissionDenied;
+
+    const file = dir.openFile(base, .{ .mode = .write_only }) catch |err| switch (err) {
+        error.AccessDenied => return error.ReadOnlyFile,
+        else => return err,
+    };
+    defer file.close();
+
+    try file.setEndPos(0);
+    try source.writeTo(file);
+    try file.sync();
+}
+
+// === Tests ===
+
+test "file io: atomic write keeps mode and writes backup" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    try tmp.dir.writeFile(.{ .sub_path = "script.sh", .data = "old\n" });
+    const original = try tmp.dir.openFile("script.sh", .{});
+    try original.chmod(0o750);
+    original.close();
+
+    const dir_path = try tmp.dir.realpathAlloc(allocator, ".");
+    defer allocator.free(dir_path);
+    const path = try std.fs.path.join(allocator, &.{ dir_path, "script.sh" });
+    defer allocator.free(path);
+
+    try writeFileAtomic(allocator, path, "new\n", .{ .backup = true });
+
+    const written = try tmp.dir.readFileAlloc(allocator, "script.sh", 1024);
+    defer allocator.free(written);
+    try std.testing.expectEqualStrings("new\n", written);
+
+    const backup = try tmp.dir.readFileAlloc(allocator, "script.sh~", 1024);
+    defer allocator.free(backup);
+    try std.testing.expectEqualStrings("old\n", backup);
+
+    const stat = try tmp.dir.statFile("script.sh");
+    try std.testing.expectEqual(@as(std.fs.File.Mode, 0o750), stat.mode & 0o7777);
+
+    // No temp files left behind
+    var it = tmp.dir.iterate();
+    var count: usize = 0;
+    while (try it.next()) |_| count += 1;
+    try std.testing.expectEqual(@as(usize, 2), count);
+}
+
+test "file io: rewrites in place when the directory is read-only" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    try tmp.dir.makeDir("locked");
+    try tmp.dir.writeFile(.{ .sub_path = "locked/hosts", .data = "127.0.0.1 localhost\n" });
+    const dir_path = try tmp.dir.realpathAlloc(allocator, "locked");
+    defer allocator.free(dir_path);
+    const path = try std.fs.path.join(allocator, &.{ dir_path, "hosts" });
+    defer allocator.free(path);
+
+    var locked = try tmp.dir.openDir("locked", .{});
+    defer locked.close();
+    try locked.chmod(0o555);
+    defer locked.chmod(0o755) catch {};
+
+    // A backup can't go there either; that's reported before anything is written
+    try std.testing.expectError(error.BackupFailed, writeFileAtomic(allocator, path, "::1 localhost\n", .{ .backup = true }));
+    const untouched = try tmp.dir.readFileAlloc(allocator, "locked/hosts", 1024);
+    defer allocator.free(untouched);
+    try std.testing.expectEqualStrings("127.0.0.1 localhost\n", untouched);
+
+    try writeFileAtomic(allocator, path, "::1 localhost\n", .{});
+
+    const written = try tmp.dir.readFileAlloc(allocator, "locked/hosts", 1024);
+    defer allocator.free(written);
+    try std.testing.expectEqualStrings("::1 localhost\n", written);
+}
@@ -0,0 +1,197 @@
+//! File change notifications for open buffers
+//! Uses inotify on Linux. Directories are watched rather than files: saves that
+//! replace a file via rename (ours included) would silently end a file watch.
+//! Elsewhere, or if inotify is unavailable, `isActive()` is false and callers
+//! fall back to polling.
+
+const std = @import("std");
+const builtin = @import("builtin");
+
+const has_inotify = builtin.os.tag == .linux;
+
+/// Events that can mean a watched file's content changed
+const WATCH_MASK: u32 = if (has_inotify)
+    std.os.linux.IN.MODIFY | std.os.linux.IN.CLOSE_WRITE | std.os.linux.IN.MOVED_TO |
+        std.os.linux.IN.MOVED_FROM | std.os.linux.IN.CREATE | std.os.linux.IN.DELETE
+else
+    0;
+
+const WatchedDir = struct {
+    wd: i32,
+    path: []const u8, // Owned
+    refs: usize, // Open files in this directory
+};
+
+pub const FileWatcher = struct {
+    allocator: std.mem.Allocator,
+    fd: ?i32,
+    dirs: std.ArrayList(WatchedDir),
+    changed_paths: std.ArrayList([]const u8), // Paths named by the last poll's events (owned)
+    overflowed: bool, // The last poll lost events; treat every file as changed
+
+    /// Start the watcher; falls back to inactive if inotify can't be initialized
+    pub fn init(allocator: std.mem.Allocator) FileWatcher {
+        const fd: ?i32 = if (has_inotify)
+            std.posix.inotify_init1(std.os.linux.IN.NONBLOCK | std.os.linux.IN.CLOEXEC) catch null
+        else
+            null;
+
+        return .{
+            .allocator = allocator,
+            .fd = fd,
+            .dirs = .empty,
+            .changed_paths = .empty,
+            .overflowed = false,
+        };
+    }
+
+    pub fn deinit(self: *FileWatcher) void {
+        for (self.dirs.items) |dir| self.allocator.free(dir.path);
+        self.dirs.deinit(self.allocator);
+        self.clearChanged();
+        self.changed_paths.deinit(self.allocator);
+        if (self.fd) |fd| std.posix.close(fd);
+    }
+
+    /// Check if change notifications are being delivered
+    pub fn isActive(self: *const FileWatcher) bool {
+        return self.fd != null;
+    }
+
+    /// Start watching the directory holding `abs_path`
+    pub fn watch(self: *FileWatcher, abs_path: []const u8) !void {
+        const fd = self.fd orelse return;
+        const dir_path = std.fs.path.dirname(abs_path) orelse return;
+
+        for (self.dirs.items) |*dir| {
+            if (std.mem.eql(u8, dir.path, dir_path)) {
+                dir.refs += 1;
+                return;
+            }
+        }
+
+        const owned = try self.allocator.dupe(u8, dir_path);
+        errdefer self.allocator.free(owned);
+        const wd = try std.posix.inotify_add_watch(fd, dir_path, WATCH_MASK);
+        errdefer std.posix.inotify_rm_watch(fd, wd);
+        try self.dirs.append(self.allocator, .{ .wd = wd, .path = owned, .refs = 1 });
+    }
+
+    /// Stop watching for `abs_path` (the directory is dropped with its last file)
+    pub fn unwatch(self: *FileWatcher, abs_path: []const u8) void {
+        const fd = self.fd orelse return;
+        const dir_path = std.fs.path.dirname(abs_path) orelse return;
+
+        for (self.dirs.items, 0..) |*dir, i| {
+            if (!std.mem.eql(u8, dir.path, dir_path)) continue;
+            dir.refs -= 1;
+            if (dir.refs == 0) {
+                std.posix.inotify_rm_watch(fd, dir.wd);
+                self.allocator.free(dir.path);
+                _ = self.dirs.swapRemove(i);
+            }
+            return;
+        }
+    }
+
+    /// Drain pending notifications; true if anything in a watched directory changed
+    /// The paths the events name are kept until the next poll, for `changed`.
+    pub fn poll(self: *FileWatcher) bool {
+        const fd = self.fd orelse return false;
+        self.clearChanged();
+
+        const Event = std.os.linux.inotify_event;
+        var buf: [4096]u8 align(@alignOf(Event)) = undefined;
+        var any = false;
+        while (true) {
+            const n = std.posix.read(fd, &buf) catch break; // WouldBlock once drained
+            if (n == 0) break;
+            any = true;
+
+            var offset: usize = 0;
+            while (offset + @sizeOf(Event) <= n) {
+                const event: *const Event = @ptrCast(@alignCast(&buf[offset]));
+                offset += @sizeOf(Event) + event.len;
+
+                if (event.mask & std.os.linux.IN.Q_OVERFLOW != 0) {
+                    self.overflowed = true;
+                    continue;
+                }
+                const name = event.getName() orelse continue;
+                const dir = self.dirForWatch(event.wd) orelse continue;
+                self.recordChange(dir.path, name) catch {
+                    self.overflowed = true; // Can't remember which; check them all
+                };
+            }
+        }
+        return any;
+    }
+
+    /// Whether the last poll reported a change to `abs_path`
+    pub fn changed(self: *const FileWatcher, abs_path: []const u8) bool {
+        if (self.overflowed) return true;
+        for (self.changed_paths.items) |path| {
+            if (std.mem.eql(u8, path, abs_path)) return true;
+        }
+        return false;
+    }
+
+    fn dirForWatch(self: *const FileWatcher, wd: i32) ?*const WatchedDir {
+        for (self.dirs.items) |*dir| {
+            if (dir.wd == wd) return dir;
+        }
+        return null;
+    }
+
+    fn recordChange(self: *FileWatcher, dir_path: []const u8, name: []const u8) !void {
+        const path = try std.fs.path.join(self.allocator, &.{ dir_path, name });
+        if (self.changed(path)) {
+            self.allocator.free(path);
+            return;
+        }
+        errdefer self.allocator.free(path);
+        try self.changed_paths.append(self.allocator, path);
+    }
+
+    fn clearChanged(self: *FileWatcher) void {
+        for (self.changed_paths.items) |path| self.allocator.free(path);
+        self.changed_paths.clearRetainingCapacity();
+        self.overflowed = false;
+    }
+};
+
+// === Tests ===
+
+test "file watcher: reports changes in watched directories" {
+    if (!has_inotify) return error.SkipZigTest;
+    const allocator = std.testing.allocator;
+
+    var watcher = FileWatcher.init(allocator);
+    defer watcher.deinit();
+    if (!watcher.isActive()) return error.SkipZigTest;
+
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+    try tmp.dir.writeFile(.{ .sub_path = "watched.txt", .data = "one" });
+
+    const dir_path = try tmp.dir.realpathAlloc(allocator, ".");
+    defer allocator.free(dir_path);
+    const path = try std.fs.path.join(allocator, &.{ dir_path, "watched.txt" });
+    defer allocator.free(path);
+
+    try watcher.watch(path);
+    try watcher.watch(path); // Second buffer for the same directory shares the watch
+    try std.testing.expectEqual(@as(usize, 1), watcher.dirs.items.len);
+    try std.testing.expect(!watcher.poll());
+
+    try tmp.dir.writeFile(.{ .sub_path = "watched.txt", .data = "two" });
+    try std.testing.expect(watcher.poll());
+    try std.testing.expect(watcher.changed(path));
+    try std.testing.expect(!watcher.changed("/elsewhere/watched.txt"));
+    try std.testing.expect(!watcher.poll());
+    try std.testing.expect(!watcher.changed(path));
+
+    watcher.unwatch(path);
+    watcher.unwatch(path);
+    try std.testing.expectEqual(@as(usize, 0), watcher.dirs.items.len);
+}
@@ -3,10 +3,75 @@
 
 const std = @import("std");
 const Rope = @import("rope.zig").Rope;
+const MappedFile = @import("mapped_file.zig").MappedFile;
+const FileIo = @import("file_io.zig");
+const Encoding = @import("encoding.zig");
+const Undo = @import("../editor/undo.zig");
+const UndoFile = @import("../editor/undo_file.zig");
+const SwapFile = @import("../editor/swap_file.zig");
+const Cursor = @import("../editor/cursor.zig");
+
+/// Options controlling how buffers are written to disk
+pub const SaveOptions = FileIo.WriteOptions;
+
+/// Encoding and line ending of a buffer's file
+pub const FileFormat = Encoding.FileFormat;
 
 /// Buffer ID type
 pub const BufferId = u32;
 
+/// Largest file we read into memory; anything bigger opens in large-file mode
+pub const MAX_FILE_SIZE = 100 * 1024 * 1024;
+
+/// Options for opening a file into a buffer
+pub const OpenOptions = struct {
+    /// Files at least this big are memory-mapped instead of read (large-file mode)
+    large_file_threshold: u64 = 64 * 1024 * 1024,
+};
+
+/// What a buffer last read from or wrote to its file
+pub const DiskState = struct {
+    mtime: i128, // Nanoseconds
+    size: u64,
+    hash: ?u64, // Hash of the content; null for mapped files, which aren't read to open them
+
+    pub fn init(stat: std.fs.File.Stat, content: []const u8) DiskState {
+        return .{
+            .mtime = stat.mtime,
+            .size = stat.size,
+            .hash = std.hash.Wyhash.hash(0, content),
+        };
+    }
+
+    pub fn initHashed(stat: std.fs.File.Stat, hash: u64) DiskState {
+        return .{ .mtime = stat.mtime, .size = stat.size, .hash = hash };
+    }
+
+    pub fn initUnhashed(stat: std.fs.File.Stat) DiskState {
+        return .{ .mtime = stat.mtime, .size = stat.size, .hash = null };
+    }
+};
+
+/// Hash a file's content the way DiskState does, reading it in blocks
+fn hashFile(file: std.fs.File) !u64 {
+    var hasher = std.hash.Wyhash.init(0);
+    var buf: [64 * 1024]u8 = undefined;
+    while (true) {
+        const n = try file.read(&buf);
+        if (n == 0) break;
+        hasher.update(buf[0..n]);
+    }
+    return hasher.final();
+}
+
+/// How a file on disk compares with its buffer's DiskState
+pub const DiskStatus = enum {
+    unchanged,
+    changed, // Something else rewrote it
+    deleted,
+    truncated, // A mapped file shrank under unsaved edits; text past its new end is gone
+};
+
 /// Buffer metadata
 pub const BufferMetadata = struct {
     id: BufferId,
@@ -15,6 +80,11 @@ pub const BufferMetadata = struct {
     readonly: bool,
     created_at: i64,
     modified_at: i64,
+    disk: ?DiskState, // null until the buffer has been read from or written to disk
+    disk_conflict: bool, // File changed on disk while we had unsaved edits
+    disk_deleted: bool, // File was deleted on disk (reported once)
+    large_file: bool, // Memory-mapped; highlighting, LSP, swap, and persistent undo are off
+    format: Encoding.FileFormat, // Encoding and line ending to write the text back in
 
     pub fn init(id: BufferId, filepath: ?[]const u8) BufferMetadata {
         const now = std.time.milliTimestamp();
@@ -25,6 +95,11 @@ pub const BufferMetadata = struct {
             .readonly = false,
             .created_at = now,
             .modified_at = now,
+            .disk = null,
+            .disk_conflict = false,
+            .disk_deleted = false,
+            .large_file = false,
+            .format = .{},
         };
     }
 
@@ -54,40 +129,138 @@ pub const BufferMetadata = struct {
     }
 };
 
+/// Location of a byte offset in a buffer
+pub const TextPoint = struct {
+    line: usize,
+    byte_col: usize, // Column in bytes (tree-sitter points)
+    utf16_col: usize, // Column in UTF-16 code units (LSP positions)
+
+    /// Point reached after walking over `text` starting from this point
+    pub fn advance(self: TextPoint, text: []const u8) TextPoint {
+        var point = self;
+        var i: usize = 0;
+        while (i < text.len) {
+            const byte = text[i];
+            if (byte == '\n') {
+                point.line += 1;
+                point.byte_col = 0;
+                point.utf16_col = 0;
+                i += 1;
+                continue;
+            }
+
+            const seq_len = std.unicode.utf8ByteSequenceLength(byte) catch 1;
+            const step = @min(seq_len, text.len - i);
+            point.byte_col += step;
+            point.utf16_col += if (seq_len == 4) 2 else 1; // Astral plane needs a surrogate pair
+            i += step;
+        }
+        return point;
+    }
+};
+
+/// A single text change, in the shape used by tree-sitter edits and LSP incremental sync
+pub const TextChange = struct {
+    start_byte: usize,
+    old_end_byte: usize,
+    new_end_byte: usize,
+    start: TextPoint,
+    old_end: TextPoint,
+    new_end: TextPoint,
+    text: []const u8, // Inserted text (owned), empty for deletions
+
+    pub fn deinit(self: *TextChange, allocator: std.mem.Allocator) void {
+        allocator.free(self.text);
+    }
+
+    /// Lines added (positive) or removed by the change
+    pub fn lineDelta(self: TextChange) isize {
+        return @as(isize, @intCast(self.new_end.line)) - @as(isize, @intCast(self.old_end.line));
+    }
+};
+
 /// A text buffer with content and metadata
 pub const Buffer = struct {
     metadata: BufferMetadata,
     rope: Rope,
     allocator: std.mem.Allocator,
 
+    // Undo state belongs to the buffer so switching buffers can't replay edits elsewhere
+    undo_history: Undo.UndoHistory,
+    undo_group: ?Undo.OperationGroup = null, // Accumulates ops during an insert session
+    undo_holds: usize = 0, // Open holdUndoGroup calls; the group stays open while nonzero
+    persist_undo: bool = false, // Save/restore history under undo_dir
+    undo_dir: ?[]const u8 = null, // Borrowed from the manager; null is ~/.aesop/undo
+
+    // Crash recovery; the editor enables it once it has checked for an existing swap file
+    swap_path: ?[]const u8 = null, // Absolute path keying our swap file (owned), null when off
+    swap_base_hash: u64 = 0, // Hash of the on-disk content the unsaved edits start from
+    swap_written_at: i64 = 0, // When the swap file was last written (ms)
+
+    // Resolved path the editor's file watcher reports changes to (owned), null when unwatched
+    watch_path: ?[]const u8 = null,
+    mapping_cut: bool = false, // A shrunken mapping was copied out; diskStatus reports it next
+
+    // Change log read by version (LSP sync, the syntax tree, closed folds)
+    version: usize = 0, // Bumped by every change to the text
+    changes: std.ArrayList(TextChange) = .empty, // Latest changes leading up to `version`, oldest first
+    lsp_version: ?usize = null, // Version language servers last saw; null when none has the file
+
+    /// Max changes kept; consumers further behind resync the whole text
+    const MAX_TRACKED_CHANGES = 4096;
+
     /// Create empty buffer
     pub fn initEmpty(allocator: std.mem.Allocator, id: BufferId) Buffer {
         return .{
             .metadata = BufferMetadata.init(id, null),
             .rope = Rope.init(allocator),
             .allocator = allocator,
+            .undo_history = Undo.UndoHistory.init(allocator),
         };
     }
 
     /// Create buffer from file
-    pub fn initFromFile(allocator: std.mem.Allocator, id: BufferId, filepath: []const u8) !Buffer {
-        // Read file content
+    pub fn initFromFile(allocator: std.mem.Allocator, id: BufferId, filepath: []const u8, options: OpenOptions) !Buffer {
         const file = try std.fs.cwd().openFile(filepath, .{});
         defer file.close();
 
-        const content = try file.readToEndAlloc(allocator, 100 * 1024 * 1024); // 100MB max
-        defer allocator.free(content);
-
-        // Create rope from content
-        const rope = try Rope.initFromString(allocator, content);
+        const stat = try file.stat();
+        const large = stat.size > 0 and (stat.size >= options.large_file_threshold or stat.size > MAX_FILE_SIZE);
+
+        var rope: Rope = undefined;
+        var disk: DiskState = undefined;
+        var format = Encoding.FileFormat{};
+        if (large) {
+            // Mapped bytes are used as-is; converting them would mean copying the file
+            const mapped = try MappedFile.init(allocator, file, stat.size);
+            defer mapped.release(); // The rope holds its own reference
+            rope = try Rope.initFromMapping(allocator, mapped);
+            disk = DiskState.initUnhashed(stat);
+        } else {
+            const content = try file.readToEndAlloc(allocator, MAX_FILE_SIZE);
+            defer allocator.free(content);
+            format = Encoding.detect(content);
+            const text = if (format.isPlain()) content else try Encoding.decode(allocator, content, format);
+            defer if (text.ptr != content.ptr) allocator.free(text);
+            rope = try Rope.initFromString(allocator, text);
+            disk = DiskState.init(stat, content);
+        }
+        errdefer rope.deinit();
 
         // Duplicate filepath for metadata
         const owned_path = try allocator.dupe(u8, filepath);
 
+        var metadata = BufferMetadata.init(id, owned_path);
+        metadata.readonly = !FileIo.isWritable(filepath);
+        metadata.disk = disk;
+        metadata.large_file = large;
+        metadata.format = format;
+
         return .{
-            .metadata = BufferMetadata.init(id, owned_path),
+            .metadata = metadata,
             .rope = rope,
             .allocator = allocator,
+            .undo_history = Undo.UndoHistory.init(allocator),
         };
     }
 
@@ -99,27 +272,316 @@ pub const Buffer = struct {
             .metadata = BufferMetadata.init(id, null),
             .rope = rope,
             .allocator = allocator,
+            .undo_history = Undo.UndoHistory.init(allocator),
         };
     }
 
     /// Clean up buffer
     pub fn deinit(self: *Buffer) void {
+        if (self.undo_group) |*group| group.deinit(self.allocator);
+        self.undo_history.deinit();
+        if (self.swap_path) |path| self.allocator.free(path);
+        if (self.watch_path) |path| self.allocator.free(path);
+        self.dropChanges();
+        self.changes.deinit(self.allocator);
         self.rope.deinit();
         if (self.metadata.filepath) |path| {
             self.allocator.free(path);
         }
     }
 
+    /// Start an undo group for an editing session (no-op if one is open)
+    pub fn beginUndoGroup(self: *Buffer, cursor: Cursor.Position) void {
+        if (self.undo_group == null) {
+            self.undo_group = Undo.OperationGroup.init(self.allocator, cursor);
+        }
+    }
+
+    /// Open a group that every edit joins until the matching `releaseUndoGroup`
+    /// For commands that run other commands (`:normal`, a counted `.`), so the
+    /// whole thing undoes in one step. Holds nest.
+    pub fn holdUndoGroup(self: *Buffer, cursor: Cursor.Position) !void {
+        if (self.undo_holds == 0) {
+            try self.commitUndoGroup();
+            self.undo_group = Undo.OperationGroup.init(self.allocator, cursor);
+        }
+        self.undo_holds += 1;
+    }
+
+    /// End a hold; the outermost one pushes the group
+    pub fn releaseUndoGroup(self: *Buffer) !void {
+        self.undo_holds -|= 1;
+        try self.commitUndoGroup();
+    }
+
+    /// Close the open undo group, pushing it to history if it recorded anything
+    /// Does nothing while the group is held.
+    pub fn commitUndoGroup(self: *Buffer) !void {
+        if (self.undo_holds > 0) return;
+        var group = self.undo_group orelse return;
+        self.undo_group = null;
+
+        if (group.operations.items.len > 0) {
+            errdefer group.deinit(self.allocator);
+            try self.undo_history.push(group);
+        } else {
+            group.deinit(self.allocator);
+        }
+    }
+
+    /// Turn on persistent undo in `undo_dir` (null for ~/.aesop/undo) and restore
+    /// any saved history for this file. Returns true if a matching history was restored.
+    pub fn enablePersistentUndo(self: *Buffer, undo_dir: ?[]const u8) !bool {
+        self.persist_undo = true;
+        self.undo_dir = undo_dir;
+        const filepath = self.metadata.filepath orelse return false;
+
+        const abs_path = try std.fs.cwd().realpathAlloc(self.allocator, filepath);
+        defer self.allocator.free(abs_path);
+        const content = try self.getText();
+        defer self.allocator.free(content);
+
+        var dir = try UndoFile.openUndoDir(self.undo_dir);
+        defer dir.close();
+
+        const restored = try UndoFile.load(dir, self.allocator, abs_path, content) orelse return false;
+        self.undo_history.deinit();
+        self.undo_history = restored;
+        return true;
+    }
+
+    /// Write undo history to disk; only valid while the buffer matches the file
+    pub fn persistUndoHistory(self: *Buffer) !void {
+        if (!self.persist_undo) return;
+        const filepath = self.metadata.filepath orelse return;
+
+        const abs_path = try std.fs.cwd().realpathAlloc(self.allocator, filepath);
+        defer self.allocator.free(abs_path);
+        const content = try self.getText();
+        defer self.allocator.free(content);
+
+        var dir = try UndoFile.openUndoDir(self.undo_dir);
+        defer dir.close();
+
+        try UndoFile.save(dir, self.allocator, &self.undo_history, abs_path, content);
+    }
+
+    /// Read the swap file another session left for this buffer's file, if any
+    pub fn readSwap(self: *Buffer) !?SwapFile.Swap {
+        const filepath = self.metadata.filepath orelse return null;
+
+        const abs_path = try std.fs.cwd().realpathAlloc(self.allocator, filepath);
+        defer self.allocator.free(abs_path);
+
+        var dir = try SwapFile.openSwapDir();
+        defer dir.close();
+
+        return SwapFile.read(dir, self.allocator, abs_path);
+    }
+
+    /// Start keeping a swap file for this buffer, replacing any existing one
+    /// Must be called while the buffer still matches the file on disk.
+    pub fn enableSwap(self: *Buffer) !void {
+        const filepath = self.metadata.filepath orelse return;
+        const abs_path = try std.fs.cwd().realpathAlloc(self.allocator, filepath);
+        if (self.swap_path) |old| self.allocator.free(old);
+        self.swap_path = abs_path;
+
+        const content = try self.getText();
+        defer self.allocator.free(content);
+        self.swap_base_hash = SwapFile.contentHash(content);
+
+        try self.writeSwap();
+    }
+
+    /// Use the swap file another buffer of this session already keeps for the same file
+    /// Nothing is written until this buffer has edits of its own.
+    pub fn shareSwap(self: *Buffer) !void {
+        const filepath = self.metadata.filepath orelse return;
+        const abs_path = try std.fs.cwd().realpathAlloc(self.allocator, filepath);
+        if (self.swap_path) |old| self.allocator.free(old);
+        self.swap_path = abs_path;
+        self.swap_base_hash = self.contentHash();
+    }
+
+    /// Stop keeping a swap file but leave it on disk for the buffers still sharing it
+    pub fn detachSwap(self: *Buffer) void {
+        const abs_path = self.swap_path orelse return;
+        self.allocator.free(abs_path);
+        self.swap_path = null;
+    }
+
+    /// Write the swap file: the full text while modified, just an ownership marker otherwise
+    pub fn writeSwap(self: *Buffer) !void {
+        const abs_path = self.swap_path orelse return;
+
+        const text: ?[]u8 = if (self.metadata.modified) try self.getText() else null;
+        defer if (text) |t| self.allocator.free(t);
+
+        var dir = try SwapFile.openSwapDir();
+        defer dir.close();
+
+        const now = std.time.milliTimestamp();
+        try SwapFile.write(dir, self.allocator, .{
+            .pid = SwapFile.currentPid(),
+            .timestamp = now,
+            .path = abs_path,
+            .base_hash = self.swap_base_hash,
+            .text = text,
+        });
+        self.swap_written_at = now;
+    }
+
+    /// Check if there are edits the swap file doesn't have yet
+    pub fn swapOutdated(self: *const Buffer) bool {
+        return self.swap_path != null and self.metadata.modified_at >= self.swap_written_at;
+    }
+
+    /// Delete the swap file and stop keeping one (buffer closed on purpose)
+    pub fn removeSwap(self: *Buffer) void {
+        const abs_path = self.swap_path orelse return;
+        defer self.allocator.free(abs_path);
+        self.swap_path = null;
+
+        var dir = SwapFile.openSwapDir() catch return;
+        defer dir.close();
+        SwapFile.remove(dir, abs_path);
+    }
+
+    /// Replace the whole content as a single undoable change
+    pub fn replaceContent(self: *Buffer, text: []const u8) !void {
+        try self.replaceRanges(&.{.{ .start = 0, .end = self.rope.len(), .text = text }}, .{ .line = 0, .col = 0 });
+    }
+
+    /// Byte range and the text replacing it, for `replaceRanges`
+    pub const RangeEdit = struct {
+        start: usize,
+        end: usize,
+        text: []const u8,
+    };
+
+    /// Replace several byte ranges as a single undoable change
+    /// Ranges are in current offsets, sorted and non-overlapping (or it fails
+    /// with `error.OverlappingRanges` before changing anything); they're applied
+    /// back to front so the earlier offsets stay valid. A held group takes the
+    /// change instead of it becoming a step of its own.
+    pub fn replaceRanges(self: *Buffer, edits: []const RangeEdit, cursor: Cursor.Position) !void {
+        for (edits, 0..) |edit, i| {
+            if (edit.end < edit.start or edit.end > self.rope.len()) return error.InvalidRange;
+            if (i > 0 and edit.start < edits[i - 1].end) return error.OverlappingRanges;
+        }
+        try self.commitUndoGroup();
+        var own = Undo.OperationGroup.init(self.allocator, cursor);
+        errdefer own.deinit(self.allocator);
+        const group = if (self.undo_group) |*held| held else &own;
+
+        var i = edits.len;
+        while (i > 0) {
+            i -= 1;
+            const edit = edits[i];
+            const old = try self.rope.slice(self.allocator, edit.start, edit.end);
+            defer self.allocator.free(old);
+
+            const line = self.rope.byteToLine(edit.start);
+            const position = Cursor.Position{ .line = line, .col = edit.start - self.rope.lineToByte(line) };
+            try group.addOperation(self.allocator, try Undo.Operation.init(self.allocator, .replace, position, edit.text, old));
+
+            if (edit.end > edit.start) try self.delete(edit.start, edit.end);
+            try self.insert(edit.start, edit.text);
+        }
+        if (own.operations.items.len == 0) {
+            own.deinit(self.allocator);
+            return;
+        }
+        try self.undo_history.push(own);
+    }
+
     /// Insert text at byte position
     pub fn insert(self: *Buffer, pos: usize, text: []const u8) !void {
+        const start = try self.pointAt(pos);
         try self.rope.insert(pos, text);
         self.metadata.markModified();
+        self.recordChange(.{
+            .start_byte = pos,
+            .old_end_byte = pos,
+            .new_end_byte = pos + text.len,
+            .start = start,
+            .old_end = start,
+            .new_end = start.advance(text),
+            .text = text,
+        });
     }
 
     /// Delete text in byte range
     pub fn delete(self: *Buffer, start: usize, end: usize) !void {
-        try self.rope.delete(start, end);
+        const stop = @min(end, self.rope.len());
+        if (stop <= start) return;
+        const start_point = try self.pointAt(start);
+        const end_point = try self.pointAt(stop);
+        try self.rope.delete(start, stop);
         self.metadata.markModified();
+        self.recordChange(.{
+            .start_byte = start,
+            .old_end_byte = stop,
+            .new_end_byte = start,
+            .start = start_point,
+            .old_end = end_point,
+            .new_end = start_point,
+            .text = "",
+        });
+    }
+
+    /// Changes made since the buffer was at `version`, oldest first; null once they
+    /// aren't all kept (or the text was replaced wholesale), meaning start over
+    pub fn changesSince(self: *const Buffer, version: usize) ?[]const TextChange {
+        if (version > self.version) return null;
+        const behind = self.version - version;
+        const changes = self.changes.items;
+        if (behind > changes.len) return null;
+        return changes[changes.len - behind ..];
+    }
+
+    /// New version that no kept changes lead to, for text replaced without them
+    pub fn forgetChanges(self: *Buffer) void {
+        self.version += 1;
+        self.dropChanges();
+    }
+
+    fn dropChanges(self: *Buffer) void {
+        for (self.changes.items) |*change| change.deinit(self.allocator);
+        self.changes.clearRetainingCapacity();
+    }
+
+    /// Log a change (copying its text) under the next version
+    fn recordChange(self: *Buffer, change: TextChange) void {
+        self.version += 1;
+        if (self.changes.items.len >= MAX_TRACKED_CHANGES) {
+            // Keep the newer half; consumers further behind start over
+            const keep = MAX_TRACKED_CHANGES / 2;
+            const items = self.changes.items;
+            for (items[0 .. items.len - keep]) |*old| old.deinit(self.allocator);
+            std.mem.copyForwards(TextChange, items[0..keep], items[items.len - keep ..]);
+            self.changes.shrinkRetainingCapacity(keep);
+        }
+
+        var owned = change;
+        owned.text = self.allocator.dupe(u8, change.text) catch return self.dropChanges();
+        self.changes.append(self.allocator, owned) catch {
+            owned.deinit(self.allocator);
+            self.dropChanges();
+        };
+    }
+
+    /// Get line/column of a byte offset
+    pub fn pointAt(self: *const Buffer, byte_offset: usize) !TextPoint {
+        const offset = @min(byte_offset, self.rope.len());
+        const line = self.rope.byteToLine(offset);
+        const line_start = self.rope.lineToByte(line);
+        return .{
+            .line = line,
+            .byte_col = offset - line_start,
+            .utf16_col = self.rope.byteToUtf16(offset) - self.rope.byteToUtf16(line_start),
+        };
     }
 
     /// Get line count
@@ -137,25 +599,176 @@ pub const Buffer = struct {
         return self.rope.toString(self.allocator);
     }
 
-    /// Save buffer to file
-    pub fn save(self: *Buffer) !void {
+    /// Hash of the content, as DiskState and swap files record it (no copy)
+    pub fn contentHash(self: *const Buffer) u64 {
+        var hasher = std.hash.Wyhash.init(0);
+        var chunks = self.rope.chunks(0, self.rope.len());
+        while (chunks.next()) |chunk| hasher.update(chunk);
+        return hasher.final();
+    }
+
+    /// O(1) copy of the current text that later edits won't change; caller deinits it
+    pub fn snapshot(self: *const Buffer) Rope {
+        return self.rope.snapshot();
+    }
+
+    /// Copy a mapped file's text out of its mapping once the file shrank under it
+    /// Called before every frame, since reading past the new end faults.
+    pub fn guardMapping(self: *Buffer) !void {
+        const mapped = self.rope.backing orelse return;
+        const valid = mapped.validLen();
+        if (valid >= mapped.bytes.len) return;
+        try self.rope.copyBorrowed(valid);
+        self.mapping_cut = true;
+    }
+
+    /// Compare the file on disk with what this buffer last read or wrote
+    /// The file is only read when its mtime or size moved, so a bare touch is unchanged.
+    pub fn diskStatus(self: *Buffer) !DiskStatus {
+        const filepath = self.metadata.filepath orelse return .unchanged;
+        const known = self.metadata.disk orelse return .unchanged;
+
+        const file = std.fs.cwd().openFile(filepath, .{}) catch |err| switch (err) {
+            error.FileNotFound => return .deleted,
+            else => return err,
+        };
+        defer file.close();
+
+        const stat = try file.stat();
+        if (stat.mtime == known.mtime and stat.size == known.size) return .unchanged;
+
+        try self.guardMapping();
+        if (self.mapping_cut) {
+            self.mapping_cut = false;
+            if (!self.metadata.modified) return .changed; // Reloading remaps it
+            return .truncated;
+        }
+
+        // Mapped files aren't hashed, so a moved mtime or size is all there is to go on
+        const known_hash = known.hash orelse return .changed;
+        const current = DiskState.initHashed(stat, try hashFile(file));
+        if (current.hash != known_hash) return .changed;
+
+        // Same content under a new timestamp; remember it to skip the read next time
+        self.metadata.disk = current;
+        return .unchanged;
+    }
+
+    /// Replace the content with the file on disk as a single undoable change
+    /// Large files are remapped instead, which starts a fresh undo history.
+    pub fn reload(self: *Buffer) !void {
         const filepath = self.metadata.filepath orelse return error.NoFilepath;
+        if (self.metadata.large_file) return self.reloadMapped(filepath);
 
-        // Get buffer content
-        const content = try self.getText();
+        const file = try std.fs.cwd().openFile(filepath, .{});
+        defer file.close();
+        const stat = try file.stat();
+        const content = try file.readToEndAlloc(self.allocator, MAX_FILE_SIZE);
         defer self.allocator.free(content);
+        const format = Encoding.detect(content);
+        const text = if (format.isPlain()) content else try Encoding.decode(self.allocator, content, format);
+        defer if (text.ptr != content.ptr) self.allocator.free(text);
+
+        try self.replaceContent(text);
+        self.metadata.markSaved();
+        self.metadata.format = format;
+        self.metadata.disk = DiskState.init(stat, content);
+        self.metadata.disk_conflict = false;
+        self.metadata.disk_deleted = false;
+        self.metadata.readonly = !FileIo.isWritable(filepath);
+
+        if (self.swap_path != null) {
+            self.swap_base_hash = SwapFile.contentHash(text);
+            self.writeSwap() catch {};
+        }
+    }
 
-        // Write to file
-        const file = try std.fs.cwd().createFile(filepath, .{});
+    fn reloadMapped(self: *Buffer, filepath: []const u8) !void {
+        const file = try std.fs.cwd().openFile(filepath, .{});
         defer file.close();
+        const stat = try file.stat();
 
-        try file.writeAll(content);
+        var rope = Rope.init(self.allocator);
+        if (stat.size > 0) {
+            const mapped = try MappedFile.init(self.allocator, file, stat.size);
+            defer mapped.release();
+            rope = try Rope.initFromMapping(self.allocator, mapped);
+        }
+        const disk = DiskState.initUnhashed(stat);
+
+        // Keeping the old text for undo would pin the old mapping; start over instead
+        self.rope.deinit();
+        self.rope = rope;
+        self.forgetChanges();
+        if (self.undo_group) |*group| group.deinit(self.allocator);
+        self.undo_group = null;
+        self.undo_history.deinit();
+        self.undo_history = Undo.UndoHistory.init(self.allocator);
 
         self.metadata.markSaved();
+        self.metadata.disk = disk;
+        self.metadata.disk_conflict = false;
+        self.metadata.disk_deleted = false;
+        self.metadata.readonly = !FileIo.isWritable(filepath);
+    }
+
+    /// Save buffer to file
+    /// Written via temp file + rename; the file on disk is never left half-written.
+    /// Fails with error.FileChangedOnDisk if something else rewrote the file since
+    /// we read it, unless `options.force` is set.
+    pub fn save(self: *Buffer, options: SaveOptions) !void {
+        const filepath = self.metadata.filepath orelse return error.NoFilepath;
+
+        if (!options.force) {
+            const status = self.diskStatus() catch .unchanged; // Unreadable: let the write report it
+            if (status == .changed or status == .truncated) return error.FileChangedOnDisk;
+        }
+
+        const hash = self.contentHash();
+        const disk_hash = if (self.metadata.format.isPlain()) blk: {
+            // Streamed from the rope, so even huge buffers aren't copied to save them
+            try FileIo.writeRopeAtomic(self.allocator, filepath, &self.rope, options);
+            break :blk hash;
+        } else blk: {
+            const text = try self.getText();
+            defer self.allocator.free(text);
+            const bytes = try Encoding.encode(self.allocator, text, self.metadata.format);
+            defer self.allocator.free(bytes);
+            try FileIo.writeFileAtomic(self.allocator, filepath, bytes, options);
+            break :blk std.hash.Wyhash.hash(0, bytes);
+        };
+
+        self.metadata.markSaved();
+        self.metadata.readonly = false;
+        self.metadata.disk_conflict = false;
+        self.metadata.disk_deleted = false;
+        self.metadata.disk = if (std.fs.cwd().statFile(filepath)) |stat| DiskState.initHashed(stat, disk_hash) else |_| null;
+
+        // The swap file now only needs to mark the file as ours
+        if (self.swap_path != null) {
+            self.swap_base_hash = hash;
+            self.writeSwap() catch {};
+        }
+
+        // History now ends at the on-disk content; best effort
+        self.persistUndoHistory() catch {};
+    }
+
+    /// Change the encoding or line ending the buffer is saved in
+    /// The text itself is unchanged; the buffer is marked modified so the change gets written.
+    pub fn setFileFormat(self: *Buffer, format: Encoding.FileFormat) !void {
+        if (std.meta.eql(format, self.metadata.format)) return;
+        if (self.metadata.large_file) return error.LargeFile; // Saved byte-for-byte from the mapping
+        self.metadata.format = format;
+        self.metadata.markModified();
     }
 
     /// Save buffer to new file
-    pub fn saveAs(self: *Buffer, filepath: []const u8) !void {
+    pub fn saveAs(self: *Buffer, filepath: []const u8, options: SaveOptions) !void {
+        // The swap file is keyed by path; move it along with the buffer
+        const had_swap = self.swap_path != null;
+        self.removeSwap();
+
         // Free old filepath if exists
         if (self.metadata.filepath) |old_path| {
             self.allocator.free(old_path);
@@ -163,9 +776,12 @@ pub const Buffer = struct {
 
         // Duplicate new filepath
         self.metadata.filepath = try self.allocator.dupe(u8, filepath);
+        self.metadata.disk = null; // Nothing read from the new path to conflict with
+        errdefer if (had_swap) self.enableSwap() catch {};
 
         // Save to file
-        try self.save();
+        try self.save(options);
+        if (had_swap) self.enableSwap() catch {};
     }
 };
 
@@ -175,6 +791,7 @@ pub const BufferManager = struct {
     active_buffer_id: ?BufferId,
     next_id: BufferId,
     allocator: std.mem.Allocator,
+    undo_dir: ?[]const u8, // Persistent undo histories (borrowed); null is ~/.aesop/undo
 
     /// Initialize buffer manager
     pub fn init(allocator: std.mem.Allocator) BufferManager {
@@ -183,12 +800,14 @@ pub const BufferManager = struct {
             .active_buffer_id = null,
             .next_id = 1,
             .allocator = allocator,
+            .undo_dir = null,
         };
     }
 
     /// Clean up all buffers
     pub fn deinit(self: *BufferManager) void {
         for (self.buffers.items) |*buffer| {
+            if (!buffer.metadata.modified) buffer.persistUndoHistory() catch {};
             buffer.deinit();
         }
         self.buffers.deinit(self.allocator);
@@ -207,11 +826,11 @@ pub const BufferManager = struct {
     }
 
     /// Open file into new buffer
-    pub fn openFile(self: *BufferManager, filepath: []const u8) !BufferId {
+    pub fn openFile(self: *BufferManager, filepath: []const u8, options: OpenOptions) !BufferId {
         const id = self.next_id;
         self.next_id += 1;
 
-        const buffer = try Buffer.initFromFile(self.allocator, id, filepath);
+        const buffer = try Buffer.initFromFile(self.allocator, id, filepath, options);
         try self.buffers.append(self.allocator, buffer);
 
         self.active_buffer_id = id;
@@ -272,6 +891,11 @@ pub const BufferManager = struct {
         const items = self.buffers.items;
         for (items, 0..) |*buffer, i| {
             if (buffer.metadata.id == id) {
+                if (!buffer.metadata.modified) buffer.persistUndoHistory() catch {};
+                // Another buffer of the same file keeps using the swap file
+                if (buffer.swap_path) |path| {
+                    if (self.swapShared(path, id)) buffer.detachSwap() else buffer.removeSwap();
+                }
                 buffer.deinit();
 
                 // Remove from list
@@ -297,6 +921,16 @@ pub const BufferManager = struct {
         return error.BufferNotFound;
     }
 
+    /// Whether a buffer other than `except` keeps the swap file for `abs_path`
+    fn swapShared(self: *const BufferManager, abs_path: []const u8, except: BufferId) bool {
+        for (self.buffers.items) |buffer| {
+            if (buffer.metadata.id == except) continue;
+            const path = buffer.swap_path orelse continue;
+            if (std.mem.eql(u8, path, abs_path)) return true;
+        }
+        return false;
+    }
+
     /// Get buffer count
     pub fn count(self: *const BufferManager) usize {
         return self.buffers.items.len;
@@ -385,3 +1019,198 @@ test "buffer metadata: name extraction" {
     meta.filepath = null;
     try std.testing.expectEqualStrings("[No Name]", meta.getName());
 }
+
+test "buffer: change tracking" {
+    const allocator = std.testing.allocator;
+    var buffer = try Buffer.initFromString(allocator, 1, "ab\ncd");
+    defer buffer.deinit();
+
+    try buffer.insert(0, "x");
+    try buffer.insert(5, "é\n"); // "xab\ncé\nd"
+    try buffer.delete(1, 5); // "xé\nd"
+    try buffer.delete(2, 2); // Empty ranges aren't changes
+
+    try std.testing.expectEqual(@as(usize, 3), buffer.version);
+    const changes = buffer.changesSince(1).?;
+    try std.testing.expectEqual(@as(usize, 2), changes.len);
+
+    try std.testing.expectEqual(TextPoint{ .line = 1, .byte_col = 1, .utf16_col = 1 }, changes[0].start);
+    try std.testing.expectEqual(TextPoint{ .line = 2, .byte_col = 0, .utf16_col = 0 }, changes[0].new_end);
+    try std.testing.expectEqualStrings("é\n", changes[0].text);
+    try std.testing.expectEqual(@as(isize, 1), changes[0].lineDelta());
+
+    try std.testing.expectEqual(TextPoint{ .line = 0, .byte_col = 1, .utf16_col = 1 }, changes[1].start);
+    try std.testing.expectEqual(TextPoint{ .line = 1, .byte_col = 1, .utf16_col = 1 }, changes[1].old_end);
+    try std.testing.expectEqual(@as(usize, 5), changes[1].old_end_byte);
+    try std.testing.expectEqualStrings("", changes[1].text);
+    try std.testing.expectEqual(@as(isize, -1), changes[1].lineDelta());
+
+    try std.testing.expectEqual(@as(usize, 0), buffer.changesSince(3).?.len);
+    try std.testing.expect(buffer.changesSince(4) == null);
+
+    // Text replaced wholesale leaves nothing to catch up from
+    buffer.forgetChanges();
+    try std.testing.expect(buffer.changesSince(3) == null);
+    try std.testing.expectEqual(@as(usize, 0), buffer.changesSince(4).?.len);
+}
+
+test "buffer: owns undo history" {
+    const allocator = std.testing.allocator;
+    var a = try Buffer.initFromString(allocator, 1, "one");
+    defer a.deinit();
+    var b = try Buffer.initFromString(allocator, 2, "two");
+    defer b.deinit();
+
+    a.beginUndoGroup(.{ .line = 0, .col = 3 });
+    try a.insert(3, "!");
+    const op = try Undo.Operation.init(allocator, .insert, .{ .line = 0, .col = 3 }, "!", null);
+    try a.undo_group.?.addOperation(allocator, op);
+    try a.commitUndoGroup();
+
+    // Empty sessions are dropped rather than pushed
+    b.beginUndoGroup(.{ .line = 0, .col = 0 });
+    try b.commitUndoGroup();
+
+    try std.testing.expect(a.undo_history.canUndo());
+    try std.testing.expect(!b.undo_history.canUndo());
+    try std.testing.expect(b.undo_group == null);
+}
+
+test "buffer: replace content is a single undo step" {
+    const allocator = std.testing.allocator;
+    var buffer = try Buffer.initFromString(allocator, 1, "on disk\n");
+    defer buffer.deinit();
+
+    try buffer.replaceContent("recovered\ntext\n");
+    const text = try buffer.getText();
+    defer allocator.free(text);
+    try std.testing.expectEqualStrings("recovered\ntext\n", text);
+
+    const group = buffer.undo_history.getUndo().?;
+    try Undo.UndoHistory.applyUndo(group, &buffer);
+    const restored = try buffer.getText();
+    defer allocator.free(restored);
+    try std.testing.expectEqualStrings("on disk\n", restored);
+}
+
+test "buffer: external change detection" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    try tmp.dir.writeFile(.{ .sub_path = "a.txt", .data = "original\n" });
+    const dir_path = try tmp.dir.realpathAlloc(allocator, ".");
+    defer allocator.free(dir_path);
+    const path = try std.fs.path.join(allocator, &.{ dir_path, "a.txt" });
+    defer allocator.free(path);
+
+    var buffer = try Buffer.initFromFile(allocator, 1, path, .{});
+    defer buffer.deinit();
+    try std.testing.expectEqual(DiskStatus.unchanged, try buffer.diskStatus());
+
+    // Rewritten underneath us (different size, so mtime granularity doesn't matter)
+    try tmp.dir.writeFile(.{ .sub_path = "a.txt", .data = "from git checkout\n" });
+    try std.testing.expectEqual(DiskStatus.changed, try buffer.diskStatus());
+
+    try buffer.insert(0, "mine ");
+    try std.testing.expectError(error.FileChangedOnDisk, buffer.save(.{}));
+    try buffer.save(.{ .force = true });
+    try std.testing.expectEqual(DiskStatus.unchanged, try buffer.diskStatus());
+
+    try tmp.dir.writeFile(.{ .sub_path = "a.txt", .data = "regenerated\n" });
+    try buffer.reload();
+    const text = try buffer.getText();
+    defer allocator.free(text);
+    try std.testing.expectEqualStrings("regenerated\n", text);
+    try std.testing.expect(!buffer.metadata.modified);
+}
+
+test "buffer: mapped file truncated under unsaved edits" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    try tmp.dir.writeFile(.{ .sub_path = "big.log", .data = "x" ** 10000 });
+    const dir_path = try tmp.dir.realpathAlloc(allocator, ".");
+    defer allocator.free(dir_path);
+    const path = try std.fs.path.join(allocator, &.{ dir_path, "big.log" });
+    defer allocator.free(path);
+
+    var buffer = try Buffer.initFromFile(allocator, 1, path, .{ .large_file_threshold = 1 });
+    defer buffer.deinit();
+    try std.testing.expect(buffer.metadata.large_file);
+    try std.testing.expectEqual(@as(?u64, null), buffer.metadata.disk.?.hash);
+    try buffer.insert(0, "mine ");
+
+    // Shrunk in place: what's left is copied out before the next frame, the rest can't be read any more
+    try tmp.dir.writeFile(.{ .sub_path = "big.log", .data = "abc" });
+    try buffer.guardMapping();
+    try std.testing.expect(buffer.rope.backing == null);
+    try std.testing.expectEqual(DiskStatus.truncated, try buffer.diskStatus());
+    try std.testing.expectEqual(@as(usize, "mine ".len + 3), buffer.len());
+    try std.testing.expectError(error.FileChangedOnDisk, buffer.save(.{}));
+}
+
+test "buffer: persistent undo goes to the injected directory" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    try tmp.dir.writeFile(.{ .sub_path = "a.txt", .data = "one\n" });
+    const dir_path = try tmp.dir.realpathAlloc(allocator, ".");
+    defer allocator.free(dir_path);
+    const path = try std.fs.path.join(allocator, &.{ dir_path, "a.txt" });
+    defer allocator.free(path);
+    const undo_dir = try std.fs.path.join(allocator, &.{ dir_path, "undo" });
+    defer allocator.free(undo_dir);
+
+    {
+        var buffer = try Buffer.initFromFile(allocator, 1, path, .{});
+        defer buffer.deinit();
+        try std.testing.expect(!try buffer.enablePersistentUndo(undo_dir));
+
+        buffer.beginUndoGroup(.{ .line = 0, .col = 3 });
+        try buffer.insert(3, "!");
+        try buffer.undo_group.?.addOperation(allocator, try Undo.Operation.init(allocator, .insert, .{ .line = 0, .col = 3 }, "!", null));
+        try buffer.commitUndoGroup();
+        try buffer.save(.{});
+    }
+
+    var buffer = try Buffer.initFromFile(allocator, 1, path, .{});
+    defer buffer.deinit();
+    try std.testing.expect(try buffer.enablePersistentUndo(undo_dir));
+    try std.testing.expect(buffer.undo_history.canUndo());
+}
+
+test "buffer: round trips line endings and BOM" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    try tmp.dir.writeFile(.{ .sub_path = "win.txt", .data = "\xEF\xBB\xBFone\r\ntwo\r\n" });
+    const dir_path = try tmp.dir.realpathAlloc(allocator, ".");
+    defer allocator.free(dir_path);
+    const path = try std.fs.path.join(allocator, &.{ dir_path, "win.txt" });
+    defer allocator.free(path);
+
+    var buffer = try Buffer.initFromFile(allocator, 1, path, .{});
+    defer buffer.deinit();
+    try std.testing.expectEqual(Encoding.LineEnding.crlf, buffer.metadata.format.line_ending);
+    try std.testing.expect(buffer.metadata.format.bom);
+    try std.testing.expectEqual(@as(usize, 3), buffer.lineCount());
+
+    try buffer.insert(buffer.rope.len(), "three\n");
+    try buffer.save(.{});
+    try std.testing.expectEqual(DiskStatus.unchanged, try buffer.diskStatus());
+
+    const saved = try tmp.dir.readFileAlloc(allocator, "win.txt", 1024);
+    defer allocator.free(saved);
+    try std.testing.expectEqualStrings("\xEF\xBB\xBFone\r\ntwo\r\nthree\r\n", saved);
+
+    try buffer.setFileFormat(.{});
+    try std.testing.expect(buffer.metadata.modified);
+    try buffer.save(.{});
+    const unix = try tmp.dir.readFileAlloc(allocator, "win.txt", 1024);
+    defer allocator.free(unix);
+    try std.testing.expectEqualStrings("one\ntwo\nthree\n", unix);
+}
@@ -0,0 +1,87 @@
+//! Read-only memory mapping of a file, shared by the ropes that borrow from it
+//! Used for large files so opening them costs no heap memory
+
+const std = @import("std");
+
+pub const MappedFile = struct {
+    allocator: std.mem.Allocator,
+    bytes: []align(std.heap.page_size_min) const u8,
+    file: std.fs.File, // Our own handle, kept to notice truncation
+    refs: std.atomic.Value(u32),
+
+    /// Map `file` (from its start through `size` bytes)
+    pub fn init(allocator: std.mem.Allocator, file: std.fs.File, size: u64) !*MappedFile {
+        if (size == 0) return error.EmptyFile; // mmap rejects zero-length mappings
+
+        const bytes = try std.posix.mmap(
+            null,
+            @intCast(size),
+            std.posix.PROT.READ,
+            .{ .TYPE = .PRIVATE },
+            file.handle,
+            0,
+        );
+        errdefer std.posix.munmap(bytes);
+        const handle = try std.posix.dup(file.handle);
+        errdefer std.posix.close(handle);
+
+        const self = try allocator.create(MappedFile);
+        self.* = .{
+            .allocator = allocator,
+            .bytes = bytes,
+            .file = .{ .handle = handle },
+            .refs = .init(1),
+        };
+        return self;
+    }
+
+    /// How many mapped bytes are still backed by the file (all of them unless it shrank)
+    /// Reading past this faults. An unreadable stat is taken to mean nothing changed.
+    pub fn validLen(self: *const MappedFile) usize {
+        const stat = self.file.stat() catch return self.bytes.len;
+        return @intCast(@min(stat.size, self.bytes.len));
+    }
+
+    /// Whether `text` points into the mapping
+    pub fn owns(self: *const MappedFile, text: []const u8) bool {
+        const start = @intFromPtr(self.bytes.ptr);
+        const at = @intFromPtr(text.ptr);
+        return at >= start and at < start + self.bytes.len;
+    }
+
+    pub fn retain(self: *MappedFile) *MappedFile {
+        _ = self.refs.fetchAdd(1, .monotonic);
+        return self;
+    }
+
+    /// Drop a reference; the last one unmaps the file
+    pub fn release(self: *MappedFile) void {
+        if (self.refs.fetchSub(1, .acq_rel) != 1) return;
+        std.posix.munmap(self.bytes);
+        self.file.close();
+        self.allocator.destroy(self);
+    }
+};
+
+// === Tests ===
+
+test "mapped file: maps file content" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+    try tmp.dir.writeFile(.{ .sub_path = "big.log", .data = "first\nsecond\n" });
+
+    const file = try tmp.dir.openFile("big.log", .{});
+    defer file.close();
+    const mapped = try MappedFile.init(allocator, file, (try file.stat()).size);
+    defer mapped.release();
+
+    const extra = mapped.retain();
+    extra.release();
+    try std.testing.expectEqualStrings("first\nsecond\n", mapped.bytes);
+    try std.testing.expectEqual(mapped.bytes.len, mapped.validLen());
+
+    // Truncated from outside: only the part still in the file may be read
+    try tmp.dir.writeFile(.{ .sub_path = "big.log", .data = "first" });
+    try std.testing.expectEqual(@as(usize, 5), mapped.validLen());
+}
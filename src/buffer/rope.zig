@@ -4,15 +4,20 @@
 //! Features:
 //! - Balanced tree with 512-1024 byte leaves
 //! - UTF-8 aware indexing
-//! - Copy-on-write semantics for undo/redo
+//! - O(log n) byte/char/line/UTF-16 offset conversions
+//! - Zero-copy chunk, line, and cursor iteration
+//! - Persistent: nodes are immutable and reference counted, so snapshots are O(1)
 //! - Efficient insert/delete/slice operations
+//! - Leaves can borrow from a memory-mapped file (large-file mode)
 
 const std = @import("std");
+const MappedFile = @import("mapped_file.zig").MappedFile;
 
 /// Rope node - can be either a leaf or internal node
 pub const Rope = struct {
     root: ?*Node,
     allocator: std.mem.Allocator,
+    backing: ?*MappedFile = null, // Mapping that borrowed leaves point into
 
     /// Metrics tracked at each node
     pub const Metrics = struct {
@@ -20,6 +25,7 @@ pub const Rope = struct {
         chars: usize = 0, // Character count (UTF-8 codepoints)
         lines: usize = 0, // Line count
         line_breaks: usize = 0, // Number of \n characters
+        utf16: usize = 0, // UTF-16 code units (LSP positions)
 
         pub fn add(self: Metrics, other: Metrics) Metrics {
             return .{
@@ -27,32 +33,66 @@ pub const Rope = struct {
                 .chars = self.chars + other.chars,
                 .lines = self.lines + other.lines,
                 .line_breaks = self.line_breaks + other.line_breaks,
+                .utf16 = self.utf16 + other.utf16,
             };
         }
     };
 
     const Node = struct {
-        metrics: Metrics,
+        metrics: Metrics, // Only `bytes` is set until `counted` is done; use counts()
         data: union(enum) {
             leaf: Leaf,
             internal: Internal,
         },
         height: u32,
+        refs: std.atomic.Value(u32), // Ropes and parent nodes holding this node
+        counted: std.atomic.Value(Counted) = .init(.done),
+
+        /// Borrowed leaves (and nodes above them) are counted on first use, so
+        /// opening a mapped file doesn't read it
+        const Counted = enum(u8) { pending, counting, done };
 
         const Leaf = struct {
             text: []const u8,
+            owned: bool = true, // false: borrowed from the rope's backing mapping
         };
 
         const Internal = struct {
             left: *Node,
             right: *Node,
         };
+
+        /// Full metrics, counting the subtree the first time they're needed
+        /// Nodes may be shared with another thread, so only the thread that claims
+        /// the node stores the count; a racing one just uses its own.
+        fn counts(node: *Node) Metrics {
+            if (node.counted.load(.acquire) == .done) return node.metrics;
+
+            const metrics = switch (node.data) {
+                .leaf => |leaf| computeMetrics(leaf.text),
+                .internal => |internal| internal.left.counts().add(internal.right.counts()),
+            };
+            if (node.counted.cmpxchgStrong(.pending, .counting, .acquire, .monotonic) == null) {
+                // `bytes` was set at creation and may be read concurrently; leave it be
+                node.metrics.chars = metrics.chars;
+                node.metrics.lines = metrics.lines;
+                node.metrics.line_breaks = metrics.line_breaks;
+                node.metrics.utf16 = metrics.utf16;
+                node.counted.store(.done, .release);
+            }
+            return metrics;
+        }
+
+        fn isCounted(node: *Node) bool {
+            return node.counted.load(.acquire) == .done;
+        }
     };
 
     /// Configuration constants
     const MIN_LEAF = 512;
     const MAX_LEAF = 1024;
     const MAX_HEIGHT_DIFF = 2; // For balancing
+    const MAPPED_LEAF = 64 * 1024; // Borrowed leaves cost nothing to keep large
 
     /// Initialize empty rope
     pub fn init(allocator: std.mem.Allocator) Rope {
@@ -66,16 +106,64 @@ pub const Rope = struct {
     pub fn initFromString(allocator: std.mem.Allocator, text: []const u8) !Rope {
         var rope = init(allocator);
         if (text.len > 0) {
-            rope.root = try rope.createLeafNode(text);
+            rope.root = try rope.buildTree(text);
+        }
+        return rope;
+    }
+
+    /// Initialize rope over a memory-mapped file without copying it
+    pub fn initFromMapping(allocator: std.mem.Allocator, mapped: *MappedFile) !Rope {
+        var rope = init(allocator);
+        rope.backing = mapped.retain();
+        errdefer rope.deinit();
+        if (mapped.bytes.len > 0) {
+            rope.root = try rope.buildBorrowedTree(mapped.bytes);
         }
         return rope;
     }
 
-    /// Clean up rope and free memory
+    /// Release this rope's reference to its nodes
+    /// Nodes still shared with a snapshot stay alive until it is released too.
     pub fn deinit(self: *Rope) void {
         if (self.root) |root| {
-            self.freeNode(root);
+            self.release(root);
         }
+        self.root = null;
+        if (self.backing) |mapped| mapped.release();
+        self.backing = null;
+    }
+
+    /// O(1) immutable copy of the current text; caller deinits it
+    /// Later edits to either rope don't affect the other.
+    pub fn snapshot(self: *const Rope) Rope {
+        return .{
+            .root = if (self.root) |root| retain(root) else null,
+            .allocator = self.allocator,
+            .backing = if (self.backing) |mapped| mapped.retain() else null,
+        };
+    }
+
+    /// Copy borrowed text to the heap, dropping what lies past the mapping's first `valid_len` bytes
+    pub fn copyBorrowed(self: *Rope, valid_len: usize) !void {
+        const mapped = self.backing orelse return;
+        const mapping_start = @intFromPtr(mapped.bytes.ptr);
+
+        var text = std.ArrayList(u8).empty;
+        defer text.deinit(self.allocator);
+        var pieces = self.chunks(0, self.len());
+        while (pieces.next()) |chunk| {
+            if (!mapped.owns(chunk)) {
+                try text.appendSlice(self.allocator, chunk);
+                continue;
+            }
+            const offset = @intFromPtr(chunk.ptr) - mapping_start;
+            if (offset >= valid_len) continue;
+            try text.appendSlice(self.allocator, chunk[0..@min(chunk.len, valid_len - offset)]);
+        }
+
+        const copy = try initFromString(self.allocator, text.items);
+        self.deinit();
+        self.* = copy;
     }
 
     /// Get total byte count
@@ -85,12 +173,12 @@ pub const Rope = struct {
 
     /// Get character count (UTF-8 codepoints)
     pub fn charCount(self: *const Rope) usize {
-        return if (self.root) |root| root.metrics.chars else 0;
+        return if (self.root) |root| root.counts().chars else 0;
     }
 
     /// Get line count
     pub fn lineCount(self: *const Rope) usize {
-        const breaks = if (self.root) |root| root.metrics.line_breaks else 0;
+        const breaks = if (self.root) |root| root.counts().line_breaks else 0;
         return breaks + 1;
     }
 
@@ -98,7 +186,7 @@ pub const Rope = struct {
     pub fn insert(self: *Rope, pos: usize, text: []const u8) !void {
         if (text.len == 0) return;
 
-        const new_node = try self.createLeafNode(text);
+        const new_node = try self.buildTree(text);
 
         if (self.root == null) {
             self.root = new_node;
@@ -106,16 +194,18 @@ pub const Rope = struct {
         }
 
         if (pos == 0) {
-            // Insert at beginning
+            // Insert at beginning (the new node takes over our reference to the old root)
             self.root = try self.concat(new_node, self.root.?);
         } else if (pos >= self.len()) {
             // Insert at end
             self.root = try self.concat(self.root.?, new_node);
         } else {
             // Split at position and insert
-            const split_result = try self.splitAt(self.root.?, pos);
+            const old_root = self.root.?;
+            const split_result = try self.splitAt(old_root, pos);
             const left_with_insert = try self.concat(split_result.left, new_node);
             self.root = try self.concat(left_with_insert, split_result.right);
+            self.release(old_root);
         }
 
         try self.rebalance();
@@ -130,28 +220,31 @@ pub const Rope = struct {
 
         if (start == 0 and actual_end >= len_val) {
             // Delete everything
-            self.freeNode(self.root.?);
+            self.release(self.root.?);
             self.root = null;
             return;
         }
 
+        const old_root = self.root.?;
         if (start == 0) {
             // Delete from beginning
-            const split_result = try self.splitAt(self.root.?, actual_end);
-            self.freeNode(split_result.left);
+            const split_result = try self.splitAt(old_root, actual_end);
+            self.release(split_result.left);
             self.root = split_result.right;
         } else if (actual_end >= len_val) {
             // Delete to end
-            const split_result = try self.splitAt(self.root.?, start);
-            self.freeNode(split_result.right);
+            const split_result = try self.splitAt(old_root, start);
+            self.release(split_result.right);
             self.root = split_result.left;
         } else {
             // Delete middle section
-            const first_split = try self.splitAt(self.root.?, start);
+            const first_split = try self.splitAt(old_root, start);
             const second_split = try self.splitAt(first_split.right, actual_end - start);
-            self.freeNode(second_split.left);
+            self.release(first_split.right);
+            self.release(second_split.left);
             self.root = try self.concat(first_split.left, second_split.right);
         }
+        self.release(old_root);
 
         try self.rebalance();
     }
@@ -176,8 +269,423 @@ pub const Rope = struct {
         return self.slice(allocator, 0, self.len());
     }
 
+    // === Offset conversions ===
+    // Each walks a single root-to-leaf path using the per-node metrics, so the
+    // cost is O(log n) plus a scan of one leaf. Offsets past the end are clamped.
+
+    /// Byte offset where `line` (0-based) starts; the rope length past the last line
+    pub fn lineToByte(self: *const Rope, line: usize) usize {
+        if (line == 0) return 0;
+        const root = self.root orelse return 0;
+        return switch (findBreak(root, line)) {
+            .after => |offset| offset,
+            .short => root.metrics.bytes,
+        };
+    }
+
+    /// Byte offset where `line` ends, excluding its newline
+    pub fn lineEnd(self: *const Rope, line: usize) usize {
+        const root = self.root orelse return 0;
+        return switch (findBreak(root, line + 1)) {
+            .after => |offset| offset - 1,
+            .short => root.metrics.bytes,
+        };
+    }
+
+    /// Offset just past the `breaks`-th newline (1-based) under `node`, or how many it holds if fewer
+    fn findBreak(node: *Node, breaks: usize) union(enum) { after: usize, short: usize } {
+        if (node.isCounted() and node.metrics.line_breaks < breaks) return .{ .short = node.metrics.line_breaks };
+
+        switch (node.data) {
+            .internal => |internal| {
+                const passed = switch (findBreak(internal.left, breaks)) {
+                    .after => |offset| return .{ .after = offset },
+                    .short => internal.left.counts().line_breaks, // Its leaves are counted by now
+                };
+                return switch (findBreak(internal.right, breaks - passed)) {
+                    .after => |offset| .{ .after = internal.left.metrics.bytes + offset },
+                    .short => |n| .{ .short = passed + n },
+                };
+            },
+            .leaf => |leaf| {
+                const leaf_breaks = node.counts().line_breaks;
+                if (leaf_breaks < breaks) return .{ .short = leaf_breaks };
+
+                var remaining = breaks;
+                var i: usize = 0;
+                while (std.mem.indexOfScalarPos(u8, leaf.text, i, '\n')) |nl| {
+                    remaining -= 1;
+                    if (remaining == 0) return .{ .after = nl + 1 };
+                    i = nl + 1;
+                }
+                // A mapped file rewritten in place no longer matches the count
+                return .{ .short = breaks - remaining };
+            },
+        }
+    }
+
+    /// Get the text of `line` without its newline (allocates)
+    pub fn lineSlice(self: *const Rope, allocator: std.mem.Allocator, line: usize) ![]u8 {
+        return self.slice(allocator, self.lineToByte(line), self.lineEnd(line));
+    }
+
+    /// Line (0-based) containing byte offset `byte`
+    pub fn byteToLine(self: *const Rope, byte: usize) usize {
+        return self.metricsBefore(byte).line_breaks;
+    }
+
+    /// Number of characters (codepoints) before byte offset `byte`
+    pub fn byteToChar(self: *const Rope, byte: usize) usize {
+        return self.metricsBefore(byte).chars;
+    }
+
+    /// Number of UTF-16 code units before byte offset `byte`
+    /// Subtract the value at the line start to get an LSP column.
+    pub fn byteToUtf16(self: *const Rope, byte: usize) usize {
+        return self.metricsBefore(byte).utf16;
+    }
+
+    /// Byte offset of the character (codepoint) with index `char`
+    pub fn charToByte(self: *const Rope, char: usize) usize {
+        var node = self.root orelse return 0;
+        if (char >= node.counts().chars) return node.metrics.bytes;
+
+        var remaining = char;
+        var offset: usize = 0;
+        while (true) {
+            switch (node.data) {
+                .internal => |internal| {
+                    const left = internal.left.counts();
+                    if (remaining < left.chars) {
+                        node = internal.left;
+                    } else {
+                        remaining -= left.chars;
+                        offset += left.bytes;
+                        node = internal.right;
+                    }
+                },
+                .leaf => |leaf| {
+                    // Characters start at non-continuation bytes, as computeMetrics counts them
+                    for (leaf.text, 0..) |byte, i| {
+                        if ((byte & 0xC0) == 0x80) continue;
+                        if (remaining == 0) return offset + i;
+                        remaining -= 1;
+                    }
+                    return offset + leaf.text.len;
+                },
+            }
+        }
+    }
+
+    // === Iteration ===
+    // Iterators borrow leaf text directly and never allocate. They are
+    // invalidated by any edit to the rope.
+
+    /// A leaf's text and the byte offset it starts at
+    const LeafRef = struct {
+        text: []const u8,
+        start: usize,
+    };
+
+    /// Find the leaf holding byte `pos` (the last leaf when `pos` is the end)
+    fn leafAt(self: *const Rope, pos: usize) ?LeafRef {
+        var node = self.root orelse return null;
+        var remaining = @min(pos, node.metrics.bytes);
+        var start: usize = 0;
+        while (true) {
+            switch (node.data) {
+                .internal => |internal| {
+                    const left_len = internal.left.metrics.bytes;
+                    if (remaining < left_len or internal.right.metrics.bytes == 0) {
+                        node = internal.left;
+                    } else {
+                        remaining -= left_len;
+                        start += left_len;
+                        node = internal.right;
+                    }
+                },
+                .leaf => |leaf| return .{ .text = leaf.text, .start = start },
+            }
+        }
+    }
+
+    /// Leaf-backed pieces of a byte range, consumable from either end
+    pub const Chunks = struct {
+        rope: *const Rope,
+        front: usize,
+        back: usize,
+
+        /// Next piece from the front of the range
+        pub fn next(self: *Chunks) ?[]const u8 {
+            if (self.front >= self.back) return null;
+            const leaf = self.rope.leafAt(self.front).?;
+            const end = @min(leaf.start + leaf.text.len, self.back);
+            const piece = leaf.text[self.front - leaf.start .. end - leaf.start];
+            self.front = end;
+            return piece;
+        }
+
+        /// Next piece from the back of the range
+        pub fn prev(self: *Chunks) ?[]const u8 {
+            if (self.back <= self.front) return null;
+            const leaf = self.rope.leafAt(self.back - 1).?;
+            const start = @max(leaf.start, self.front);
+            const piece = leaf.text[start - leaf.start .. self.back - leaf.start];
+            self.back = start;
+            return piece;
+        }
+    };
+
+    /// Iterate the text in [start, end) as slices of the rope's leaves
+    pub fn chunks(self: *const Rope, start: usize, end: usize) Chunks {
+        const clamped_end = @min(end, self.len());
+        return .{ .rope = self, .front = @min(start, clamped_end), .back = clamped_end };
+    }
+
+    /// A line's byte range, excluding its newline
+    pub const Line = struct {
+        rope: *const Rope,
+        number: usize,
+        start: usize,
+        end: usize,
+
+        /// The line's text as leaf-backed pieces
+        pub fn chunks(self: Line) Chunks {
+            return self.rope.chunks(self.start, self.end);
+        }
+    };
+
+    /// Lines in order, starting from a given line
+    pub const Lines = struct {
+        rope: *const Rope,
+        number: usize,
+        start: usize,
+
+        pub fn next(self: *Lines) ?Line {
+            if (self.start > self.rope.len()) return null; // Past the last line
+            const line = Line{
+                .rope = self.rope,
+                .number = self.number,
+                .start = self.start,
+                .end = self.rope.lineEnd(self.number),
+            };
+            self.number += 1;
+            self.start = line.end + 1;
+            return line;
+        }
+    };
+
+    /// Iterate lines from `first_line` to the end of the rope
+    pub fn lines(self: *const Rope, first_line: usize) Lines {
+        // A line past the end starts beyond it, so there's nothing to iterate
+        const start = if (first_line == 0) 0 else if (self.root) |root| switch (findBreak(root, first_line)) {
+            .after => |offset| offset,
+            .short => root.metrics.bytes + 1,
+        } else 1;
+        return .{ .rope = self, .number = first_line, .start = start };
+    }
+
+    /// A single line's byte range
+    pub fn lineAt(self: *const Rope, number: usize) Line {
+        return .{ .rope = self, .number = number, .start = self.lineToByte(number), .end = self.lineEnd(number) };
+    }
+
+    /// Movable position in the rope, stepping by byte, character, or grapheme
+    /// Seeking is O(log n); steps within the current leaf are O(1).
+    pub const Cursor = struct {
+        rope: *const Rope,
+        pos: usize,
+        leaf: LeafRef = .{ .text = "", .start = 0 },
+
+        /// Current byte offset
+        pub fn offset(self: *const Cursor) usize {
+            return self.pos;
+        }
+
+        /// Move to byte offset `pos` (clamped to the end)
+        pub fn seek(self: *Cursor, pos: usize) void {
+            self.pos = @min(pos, self.rope.len());
+        }
+
+        /// Rest of the current leaf from the cursor (empty at the end)
+        pub fn chunk(self: *Cursor) []const u8 {
+            if (self.pos >= self.rope.len()) return "";
+            self.loadLeaf(self.pos);
+            return self.leaf.text[self.pos - self.leaf.start ..];
+        }
+
+        fn loadLeaf(self: *Cursor, pos: usize) void {
+            if (pos >= self.leaf.start and pos < self.leaf.start + self.leaf.text.len) return;
+            self.leaf = self.rope.leafAt(pos) orelse return;
+        }
+
+        fn byteAt(self: *Cursor, pos: usize) u8 {
+            self.loadLeaf(pos);
+            return self.leaf.text[pos - self.leaf.start];
+        }
+
+        pub fn peekByte(self: *Cursor) ?u8 {
+            if (self.pos >= self.rope.len()) return null;
+            return self.byteAt(self.pos);
+        }
+
+        pub fn nextByte(self: *Cursor) ?u8 {
+            const byte = self.peekByte() orelse return null;
+            self.pos += 1;
+            return byte;
+        }
+
+        pub fn prevByte(self: *Cursor) ?u8 {
+            if (self.pos == 0) return null;
+            self.pos -= 1;
+            return self.byteAt(self.pos);
+        }
+
+        /// Step over one character; invalid UTF-8 yields U+FFFD per byte
+        pub fn nextChar(self: *Cursor) ?u21 {
+            const lead = self.nextByte() orelse return null;
+            const seq_len = std.unicode.utf8ByteSequenceLength(lead) catch return std.unicode.replacement_character;
+
+            var bytes: [4]u8 = undefined;
+            bytes[0] = lead;
+            for (1..seq_len) |i| {
+                const byte = self.peekByte() orelse return std.unicode.replacement_character;
+                if ((byte & 0xC0) != 0x80) return std.unicode.replacement_character;
+                bytes[i] = byte;
+                self.pos += 1;
+            }
+            return std.unicode.utf8Decode(bytes[0..seq_len]) catch std.unicode.replacement_character;
+        }
+
+        /// Step back over one character
+        pub fn prevChar(self: *Cursor) ?u21 {
+            if (self.pos == 0) return null;
+
+            // Back up over continuation bytes to the lead byte
+            var start = self.pos - 1;
+            while (start > 0 and self.pos - start < 4 and (self.byteAt(start) & 0xC0) == 0x80) start -= 1;
+
+            self.pos = start;
+            const char = self.nextChar();
+            self.pos = start;
+            return char;
+        }
+
+        /// Step over one grapheme cluster; returns its length in bytes
+        pub fn nextGrapheme(self: *Cursor) ?usize {
+            const start = self.pos;
+            var prev = self.nextChar() orelse return null;
+            var regional_run: usize = @intFromBool(isRegionalIndicator(prev));
+
+            while (true) {
+                const before = self.pos;
+                const char = self.nextChar() orelse break;
+                if (!continuesGrapheme(prev, char, regional_run)) {
+                    self.pos = before;
+                    break;
+                }
+                regional_run = if (isRegionalIndicator(char)) regional_run + 1 else 0;
+                prev = char;
+            }
+            return self.pos - start;
+        }
+
+        /// Step back over one grapheme cluster; returns its length in bytes
+        pub fn prevGrapheme(self: *Cursor) ?usize {
+            const end = self.pos;
+            var next_char = self.prevChar() orelse return null;
+            var regional_run: usize = @intFromBool(isRegionalIndicator(next_char));
+
+            while (true) {
+                const after = self.pos;
+                const char = self.prevChar() orelse break;
+                if (!continuesGrapheme(char, next_char, regional_run)) {
+                    self.pos = after;
+                    break;
+                }
+                regional_run = if (isRegionalIndicator(char)) regional_run + 1 else 0;
+                next_char = char;
+            }
+            return end - self.pos;
+        }
+    };
+
+    /// Cursor positioned at byte offset `pos`
+    pub fn cursorAt(self: *const Rope, pos: usize) Cursor {
+        var cursor = Cursor{ .rope = self, .pos = 0 };
+        cursor.seek(pos);
+        return cursor;
+    }
+
     // === Private implementation ===
 
+    /// Metrics of the text before byte offset `pos`
+    /// An offset inside a multi-byte sequence counts that whole character.
+    fn metricsBefore(self: *const Rope, pos: usize) Metrics {
+        var node = self.root orelse return .{};
+        var remaining = @min(pos, node.metrics.bytes);
+
+        var result = Metrics{};
+        while (true) {
+            switch (node.data) {
+                .internal => |internal| {
+                    const left = internal.left.metrics.bytes;
+                    if (remaining < left) {
+                        node = internal.left;
+                    } else {
+                        result = result.add(internal.left.counts());
+                        remaining -= left;
+                        node = internal.right;
+                    }
+                },
+                .leaf => |leaf| return result.add(computeMetrics(leaf.text[0..remaining])),
+            }
+        }
+    }
+
+    /// Build a balanced tree of leaves no larger than MAX_LEAF
+    /// Leaves are split on UTF-8 boundaries so each leaf counts whole characters.
+    fn buildTree(self: *Rope, text: []const u8) !*Node {
+        if (text.len <= MAX_LEAF) return self.createLeafNode(text);
+
+        var mid = text.len / 2;
+        while (mid > 0 and (text[mid] & 0xC0) == 0x80) mid -= 1;
+        if (mid == 0) mid = text.len / 2; // Not UTF-8; any split will do
+
+        const left = try self.buildTree(text[0..mid]);
+        errdefer self.release(left);
+        const right = try self.buildTree(text[mid..]);
+        errdefer self.release(right);
+        return self.concat(left, right);
+    }
+
+    /// Like buildTree, but with leaves that borrow `text` from the backing mapping
+    fn buildBorrowedTree(self: *Rope, text: []const u8) !*Node {
+        if (text.len <= MAPPED_LEAF) return self.createBorrowedLeafNode(text);
+
+        var mid = text.len / 2;
+        while (mid > 0 and (text[mid] & 0xC0) == 0x80) mid -= 1;
+        if (mid == 0) mid = text.len / 2;
+
+        const left = try self.buildBorrowedTree(text[0..mid]);
+        errdefer self.release(left);
+        const right = try self.buildBorrowedTree(text[mid..]);
+        errdefer self.release(right);
+        return self.concat(left, right);
+    }
+
+    fn createBorrowedLeafNode(self: *Rope, text: []const u8) !*Node {
+        const node = try self.allocator.create(Node);
+        node.* = .{
+            .metrics = .{ .bytes = text.len },
+            .data = .{ .leaf = .{ .text = text, .owned = false } },
+            .height = 0,
+            .refs = .init(1),
+            .counted = .init(.pending),
+        };
+        return node;
+    }
+
     fn createLeafNode(self: *Rope, text: []const u8) !*Node {
         const owned_text = try self.allocator.dupe(u8, text);
         const metrics = computeMetrics(text);
@@ -187,29 +695,36 @@ pub const Rope = struct {
             .metrics = metrics,
             .data = .{ .leaf = .{ .text = owned_text } },
             .height = 0,
+            .refs = .init(1),
         };
         return node;
     }
 
+    /// Join two trees under a new node, which takes over the caller's references
     fn concat(self: *Rope, left: *Node, right: *Node) !*Node {
+        const counted = left.isCounted() and right.isCounted();
         const node = try self.allocator.create(Node);
         node.* = .{
-            .metrics = left.metrics.add(right.metrics),
+            .metrics = if (counted) left.metrics.add(right.metrics) else .{ .bytes = left.metrics.bytes + right.metrics.bytes },
             .data = .{ .internal = .{ .left = left, .right = right } },
             .height = @max(left.height, right.height) + 1,
+            .refs = .init(1),
+            .counted = .init(if (counted) .done else .pending),
         };
         return node;
     }
 
+    /// Split a tree at byte `pos` without modifying it
+    /// Returns new references; untouched subtrees are shared with `node`.
     fn splitAt(self: *Rope, node: *Node, pos: usize) !struct { left: *Node, right: *Node } {
         switch (node.data) {
             .leaf => |leaf| {
-                // Split leaf into two leaves
+                // Split leaf into two leaves; borrowed text stays borrowed
                 const left_text = leaf.text[0..pos];
                 const right_text = leaf.text[pos..];
-
-                const left_node = try self.createLeafNode(left_text);
-                const right_node = try self.createLeafNode(right_text);
+                const left_node = if (leaf.owned) try self.createLeafNode(left_text) else try self.createBorrowedLeafNode(left_text);
+                errdefer self.release(left_node);
+                const right_node = if (leaf.owned) try self.createLeafNode(right_text) else try self.createBorrowedLeafNode(right_text);
 
                 return .{ .left = left_node, .right = right_node };
             },
@@ -217,15 +732,18 @@ pub const Rope = struct {
             .internal => |internal| {
                 const left_len = internal.left.metrics.bytes;
 
-                if (pos <= left_len) {
+                if (pos == left_len) {
+                    // Split falls between the children; share both
+                    return .{ .left = retain(internal.left), .right = retain(internal.right) };
+                } else if (pos < left_len) {
                     // Split is in left subtree
                     const left_split = try self.splitAt(internal.left, pos);
-                    const new_right = try self.concat(left_split.right, internal.right);
+                    const new_right = try self.concat(left_split.right, retain(internal.right));
                     return .{ .left = left_split.left, .right = new_right };
                 } else {
                     // Split is in right subtree
                     const right_split = try self.splitAt(internal.right, pos - left_len);
-                    const new_left = try self.concat(internal.left, right_split.left);
+                    const new_left = try self.concat(retain(internal.left), right_split.left);
                     return .{ .left = new_left, .right = right_split.right };
                 }
             },
@@ -266,30 +784,41 @@ pub const Rope = struct {
 
     fn rebalance(self: *Rope) !void {
         if (self.root) |root| {
-            self.root = try self.rebalanceNode(root);
+            if (try self.rebalanceNode(root)) |new_root| {
+                self.root = new_root;
+                self.release(root);
+            }
         }
     }
 
     /// Rebalance a single node and its subtrees
-    fn rebalanceNode(self: *Rope, node: *Node) error{OutOfMemory}!*Node {
+    /// Returns a new reference to the rebalanced replacement, or null if `node` is
+    /// already balanced (nodes are shared, so they're replaced rather than changed).
+    fn rebalanceNode(self: *Rope, node: *Node) error{OutOfMemory}!?*Node {
         switch (node.data) {
-            .leaf => return node, // Leaves are always balanced
+            .leaf => return null, // Leaves are always balanced
             .internal => |internal| {
                 // First, recursively rebalance children
-                const left = try self.rebalanceNode(internal.left);
-                const right = try self.rebalanceNode(internal.right);
+                const new_left = try self.rebalanceNode(internal.left);
+                errdefer if (new_left) |n| self.release(n);
+                const new_right = try self.rebalanceNode(internal.right);
+                errdefer if (new_right) |n| self.release(n);
 
                 // Check balance factor
-                const left_height: i64 = @intCast(left.height);
-                const right_height: i64 = @intCast(right.height);
+                const left_height: i64 = @intCast((new_left orelse internal.left).height);
+                const right_height: i64 = @intCast((new_right orelse internal.right).height);
                 const balance = left_height - right_height;
 
-                // If balanced, recreate node with potentially rebalanced children
+                if (@abs(balance) <= MAX_HEIGHT_DIFF and new_left == null and new_right == null) {
+                    return null; // No changes needed
+                }
+
+                // From here on we build new nodes that take over these references
+                const left = new_left orelse retain(internal.left);
+                const right = new_right orelse retain(internal.right);
+
+                // If balanced, recreate node with rebalanced children
                 if (@abs(balance) <= MAX_HEIGHT_DIFF) {
-                    if (left == internal.left and right == internal.right) {
-                        return node; // No changes needed
-                    }
-                    // Children changed, create new internal node
                     return try self.concat(left, right);
                 }
 
@@ -305,7 +834,8 @@ pub const Rope = struct {
                         return try self.rotateRight(left, right);
                     } else {
                         // Left-Right case: double rotation
-                        const rotated_left = try self.rotateLeft(left_internal.left, left_internal.right);
+                        const rotated_left = try self.rotateLeft(retain(left_internal.left), retain(left_internal.right));
+                        self.release(left);
                         return try self.rotateRight(rotated_left, right);
                     }
                 } else {
@@ -319,7 +849,8 @@ pub const Rope = struct {
                         return try self.rotateLeft(left, right);
                     } else {
                         // Right-Left case: double rotation
-                        const rotated_right = try self.rotateRight(right_internal.left, right_internal.right);
+                        const rotated_right = try self.rotateRight(retain(right_internal.left), retain(right_internal.right));
+                        self.release(right);
                         return try self.rotateLeft(left, rotated_right);
                     }
                 }
@@ -328,52 +859,100 @@ pub const Rope = struct {
     }
 
     /// Rotate right: (A (B C D)) -> ((A B) C D)
+    /// Takes over the references to `left` and `right`.
     fn rotateRight(self: *Rope, left: *Node, right: *Node) !*Node {
         const left_internal = left.data.internal;
-        const new_right = try self.concat(left_internal.right, right);
-        return try self.concat(left_internal.left, new_right);
+        const new_right = try self.concat(retain(left_internal.right), right);
+        const result = try self.concat(retain(left_internal.left), new_right);
+        self.release(left);
+        return result;
     }
 
     /// Rotate left: ((A B) C D) -> (A (B C D))
+    /// Takes over the references to `left` and `right`.
     fn rotateLeft(self: *Rope, left: *Node, right: *Node) !*Node {
         const right_internal = right.data.internal;
-        const new_left = try self.concat(left, right_internal.left);
-        return try self.concat(new_left, right_internal.right);
+        const new_left = try self.concat(left, retain(right_internal.left));
+        const result = try self.concat(new_left, retain(right_internal.right));
+        self.release(right);
+        return result;
+    }
+
+    fn retain(node: *Node) *Node {
+        _ = node.refs.fetchAdd(1, .monotonic);
+        return node;
     }
 
-    fn freeNode(self: *Rope, node: *Node) void {
+    /// Drop a reference, freeing the node (and releasing its children) with the last one
+    fn release(self: *Rope, node: *Node) void {
+        if (node.refs.fetchSub(1, .acq_rel) != 1) return;
+
         switch (node.data) {
             .leaf => |leaf| {
-                self.allocator.free(leaf.text);
+                if (leaf.owned) self.allocator.free(leaf.text);
             },
             .internal => |internal| {
-                self.freeNode(internal.left);
-                self.freeNode(internal.right);
+                self.release(internal.left);
+                self.release(internal.right);
             },
         }
         self.allocator.destroy(node);
     }
 
+    /// Count metrics in one branch-free pass
+    /// A character starts at each non-continuation byte, so a codepoint split
+    /// across leaves is counted once, in the leaf holding its first byte.
     fn computeMetrics(text: []const u8) Metrics {
         var metrics = Metrics{};
         metrics.bytes = text.len;
 
-        var i: usize = 0;
-        while (i < text.len) {
-            const cp_len = std.unicode.utf8ByteSequenceLength(text[i]) catch 1;
-            metrics.chars += 1;
-
-            if (text[i] == '\n') {
-                metrics.line_breaks += 1;
-            }
-
-            i += cp_len;
+        for (text) |byte| {
+            const starts_char = @intFromBool((byte & 0xC0) != 0x80);
+            metrics.chars += starts_char;
+            metrics.utf16 += starts_char + @intFromBool(byte >= 0xF0); // Astral plane needs a surrogate pair
+            metrics.line_breaks += @intFromBool(byte == '\n');
         }
 
         return metrics;
     }
 };
 
+// === Grapheme clusters ===
+// A practical subset of UAX #29: CRLF, combining marks, variation selectors,
+// emoji modifiers, ZWJ sequences, and regional indicator pairs (flags).
+
+const ZWJ: u21 = 0x200D;
+
+/// Check whether `char` belongs to the same cluster as the preceding `prev`
+/// `regional_run` counts the regional indicators in a row ending at `prev`.
+fn continuesGrapheme(prev: u21, char: u21, regional_run: usize) bool {
+    if (prev == '\r') return char == '\n';
+    if (prev == '\n' or char == '\r' or char == '\n') return false;
+    if (isExtend(char) or char == ZWJ) return true;
+    if (prev == ZWJ and isPictographic(char)) return true;
+    return isRegionalIndicator(char) and regional_run % 2 == 1;
+}
+
+/// Marks and modifiers that attach to the preceding character
+fn isExtend(char: u21) bool {
+    return (char >= 0x0300 and char <= 0x036F) or // Combining diacritical marks
+        (char >= 0x1AB0 and char <= 0x1AFF) or
+        (char >= 0x1DC0 and char <= 0x1DFF) or
+        (char >= 0x20D0 and char <= 0x20FF) or // Combining marks for symbols
+        (char >= 0xFE00 and char <= 0xFE0F) or // Variation selectors
+        (char >= 0xFE20 and char <= 0xFE2F) or // Combining half marks
+        (char >= 0x1F3FB and char <= 0x1F3FF) or // Emoji skin tones
+        (char >= 0xE0020 and char <= 0xE007F); // Tag characters
+}
+
+fn isPictographic(char: u21) bool {
+    return (char >= 0x2600 and char <= 0x27BF) or (char >= 0x1F000 and char <= 0x1FAFF);
+}
+
+fn isRegionalIndicator(char: u21) bool {
+    return char >= 0x1F1E6 and char <= 0x1F1FF;
+}
+
 // === Tests ===
 
 test "rope: init empty" {
@@ -504,3 +1083,226 @@ test "rope: line counting" {
 
     try std.testing.expectEqual(@as(usize, 3), rope.lineCount());
 }
+
+test "rope: line and offset conversions" {
+    const allocator = std.testing.allocator;
+    var rope = try Rope.initFromString(allocator, "ab\nçd\n\n😀x");
+    defer rope.deinit();
+
+    try std.testing.expectEqual(@as(usize, 0), rope.lineToByte(0));
+    try std.testing.expectEqual(@as(usize, 3), rope.lineToByte(1));
+    try std.testing.expectEqual(@as(usize, 7), rope.lineToByte(2));
+    try std.testing.expectEqual(@as(usize, 8), rope.lineToByte(3));
+    try std.testing.expectEqual(rope.len(), rope.lineToByte(9));
+
+    try std.testing.expectEqual(@as(usize, 6), rope.lineEnd(1));
+    try std.testing.expectEqual(rope.len(), rope.lineEnd(3));
+    const line = try rope.lineSlice(allocator, 1);
+    defer allocator.free(line);
+    try std.testing.expectEqualStrings("çd", line);
+
+    try std.testing.expectEqual(@as(usize, 1), rope.byteToLine(3));
+    try std.testing.expectEqual(@as(usize, 3), rope.byteToLine(rope.len()));
+
+    // "😀" is 4 bytes, 1 char, 2 UTF-16 units
+    try std.testing.expectEqual(@as(usize, 12), rope.charToByte(8));
+    try std.testing.expectEqual(@as(usize, 8), rope.byteToChar(12));
+    try std.testing.expectEqual(@as(usize, 9), rope.byteToUtf16(12));
+    try std.testing.expectEqual(@as(usize, 2), rope.byteToUtf16(12) - rope.byteToUtf16(rope.lineToByte(3)));
+}
+
+test "rope: conversions across leaves" {
+    const allocator = std.testing.allocator;
+
+    // Enough lines to span many leaves
+    var text = std.ArrayList(u8).empty;
+    defer text.deinit(allocator);
+    for (0..500) |i| try text.writer(allocator).print("line {d} é\n", .{i});
+
+    var rope = try Rope.initFromString(allocator, text.items);
+    defer rope.deinit();
+    try std.testing.expect(rope.root.?.height > 1);
+    try std.testing.expectEqual(@as(usize, 501), rope.lineCount());
+
+    var expected_start: usize = 0;
+    var expected_chars: usize = 0;
+    for (0..500) |i| {
+        try std.testing.expectEqual(expected_start, rope.lineToByte(i));
+        try std.testing.expectEqual(i, rope.byteToLine(expected_start));
+        try std.testing.expectEqual(expected_chars, rope.byteToChar(expected_start));
+        try std.testing.expectEqual(expected_start, rope.charToByte(expected_chars));
+
+        const line = try rope.lineSlice(allocator, i);
+        defer allocator.free(line);
+        try std.testing.expect(std.mem.startsWith(u8, line, "line "));
+
+        expected_start += line.len + 1;
+        expected_chars += (std.unicode.utf8CountCodepoints(line) catch unreachable) + 1;
+    }
+}
+
+test "rope: chunk and line iteration" {
+    const allocator = std.testing.allocator;
+
+    var text = std.ArrayList(u8).empty;
+    defer text.deinit(allocator);
+    for (0..300) |i| try text.writer(allocator).print("row {d}\n", .{i});
+
+    var rope = try Rope.initFromString(allocator, text.items);
+    defer rope.deinit();
+
+    // Chunks reassemble the range from either end
+    var forward = std.ArrayList(u8).empty;
+    defer forward.deinit(allocator);
+    var it = rope.chunks(5, rope.len() - 5);
+    var pieces: usize = 0;
+    while (it.next()) |piece| : (pieces += 1) try forward.appendSlice(allocator, piece);
+    try std.testing.expect(pieces > 1);
+    try std.testing.expectEqualStrings(text.items[5 .. text.items.len - 5], forward.items);
+
+    var back_len: usize = 0;
+    var back = rope.chunks(5, rope.len() - 5);
+    while (back.prev()) |piece| {
+        back_len += piece.len;
+        try std.testing.expect(std.mem.endsWith(u8, text.items[0 .. text.items.len - 5 - back_len + piece.len], piece));
+    }
+    try std.testing.expectEqual(forward.items.len, back_len);
+
+    // Lines yield every line, including those straddling leaves
+    var lines = rope.lines(298);
+    var line_text = std.ArrayList(u8).empty;
+    defer line_text.deinit(allocator);
+    const expected = [_][]const u8{ "row 298", "row 299", "" };
+    for (expected) |want| {
+        const line = lines.next().?;
+        line_text.clearRetainingCapacity();
+        var line_chunks = line.chunks();
+        while (line_chunks.next()) |piece| try line_text.appendSlice(allocator, piece);
+        try std.testing.expectEqualStrings(want, line_text.items);
+    }
+    try std.testing.expect(lines.next() == null);
+}
+
+test "rope: cursor steps by char and grapheme" {
+    const allocator = std.testing.allocator;
+    // "e" + combining acute, a flag (two regional indicators), CRLF, then "x"
+    var rope = try Rope.initFromString(allocator, "ae\u{301}\u{1F1FA}\u{1F1F8}\r\nx");
+    defer rope.deinit();
+
+    var cursor = rope.cursorAt(1);
+    try std.testing.expectEqual(@as(u21, 'e'), cursor.nextChar().?);
+    try std.testing.expectEqual(@as(u21, 0x301), cursor.nextChar().?);
+    try std.testing.expectEqual(@as(u21, 0x301), cursor.prevChar().?);
+
+    cursor.seek(1);
+    try std.testing.expectEqual(@as(usize, 3), cursor.nextGrapheme().?); // e + U+0301
+    try std.testing.expectEqual(@as(usize, 8), cursor.nextGrapheme().?); // Flag
+    try std.testing.expectEqual(@as(usize, 2), cursor.nextGrapheme().?); // CRLF
+    try std.testing.expectEqual(@as(usize, 1), cursor.nextGrapheme().?);
+    try std.testing.expect(cursor.nextGrapheme() == null);
+
+    try std.testing.expectEqual(@as(usize, 1), cursor.prevGrapheme().?);
+    try std.testing.expectEqual(@as(usize, 2), cursor.prevGrapheme().?);
+    try std.testing.expectEqual(@as(usize, 8), cursor.prevGrapheme().?);
+    try std.testing.expectEqual(@as(usize, 3), cursor.prevGrapheme().?);
+    try std.testing.expectEqual(@as(usize, 1), cursor.offset());
+}
+
+test "rope: snapshots are unaffected by later edits" {
+    const allocator = std.testing.allocator;
+
+    var text = std.ArrayList(u8).empty;
+    defer text.deinit(allocator);
+    for (0..200) |i| try text.writer(allocator).print("line {d}\n", .{i});
+
+    var rope = try Rope.initFromString(allocator, text.items);
+    defer rope.deinit();
+
+    var snap = rope.snapshot();
+    defer snap.deinit();
+    try std.testing.expectEqual(rope.root, snap.root);
+
+    try rope.insert(100, "inserted");
+    try rope.delete(10, 20);
+    try rope.delete(0, 5);
+
+    const snap_text = try snap.toString(allocator);
+    defer allocator.free(snap_text);
+    try std.testing.expectEqualStrings(text.items, snap_text);
+
+    // The edited rope shares the untouched tail with the snapshot
+    var node = rope.root.?;
+    while (node.data == .internal) node = node.data.internal.right;
+    var snap_node = snap.root.?;
+    while (snap_node.data == .internal) snap_node = snap_node.data.internal.right;
+    try std.testing.expect(node == snap_node);
+
+    // Dropping the original leaves a snapshot of the edited text intact
+    const later = rope.snapshot();
+    rope.deinit();
+    rope = later;
+    try std.testing.expectEqual(text.items.len + "inserted".len - 15, rope.len());
+}
+
+test "rope: borrowed leaves over a mapped file" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    var text = std.ArrayList(u8).empty;
+    defer text.deinit(allocator);
+    for (0..20000) |i| try text.writer(allocator).print("entry {d}\n", .{i});
+    try tmp.dir.writeFile(.{ .sub_path = "big.log", .data = text.items });
+
+    const file = try tmp.dir.openFile("big.log", .{});
+    defer file.close();
+    const mapped = try MappedFile.init(allocator, file, text.items.len);
+    var rope = try Rope.initFromMapping(allocator, mapped);
+    defer rope.deinit();
+    mapped.release(); // The rope keeps it alive
+
+    // Finding an early line doesn't count the rest of the file
+    try std.testing.expectEqual(@as(usize, "entry 0\n".len), rope.lineToByte(1));
+    try std.testing.expect(!rope.root.?.isCounted());
+    try std.testing.expect(!rope.root.?.data.internal.right.isCounted());
+
+    try std.testing.expectEqual(@as(usize, 20001), rope.lineCount());
+    try std.testing.expect(rope.root.?.isCounted());
+    try std.testing.expectEqual(rope.lineToByte(12345), std.mem.indexOf(u8, text.items, "entry 12345\n").?);
+
+    // Edits copy only what they touch; the rest stays borrowed
+    try rope.insert(100_000, "NEW");
+    try rope.delete(10, 20);
+
+    var expected = std.ArrayList(u8).empty;
+    defer expected.deinit(allocator);
+    try expected.appendSlice(allocator, text.items[0..10]);
+    try expected.appendSlice(allocator, text.items[20..100_000]);
+    try expected.appendSlice(allocator, "NEW");
+    try expected.appendSlice(allocator, text.items[100_000..]);
+
+    const result = try rope.toString(allocator);
+    defer allocator.free(result);
+    try std.testing.expectEqualStrings(expected.items, result);
+}
+
+test "rope: mapped file rewritten in place under cached counts" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    const text = "line\n" ** 2000;
+    try tmp.dir.writeFile(.{ .sub_path = "big.log", .data = text });
+    const file = try tmp.dir.openFile("big.log", .{ .mode = .read_write });
+    defer file.close();
+    const mapped = try MappedFile.init(allocator, file, text.len);
+    var rope = try Rope.initFromMapping(allocator, mapped);
+    defer rope.deinit();
+    mapped.release();
+    try std.testing.expectEqual(@as(usize, 2001), rope.lineCount());
+
+    // The newlines the counts promise are gone; lookups stop short instead of asserting
+    try file.pwriteAll("x" ** text.len, 0);
+    try std.testing.expect(rope.lineToByte(1500) <= rope.len());
+    try std.testing.expect(rope.lineEnd(1500) <= rope.len());
+}
@@ -9,6 +9,7 @@ const Rope = @import("../buffer/rope.zig").Rope;
 /// Clipboard for yank/paste operations
 pub const Clipboard = struct {
     content: ?[]const u8 = null,
+    linewise: bool = false, // Whole lines (`yy`, `dj`): pasted on their own lines
     allocator: std.mem.Allocator,
 
     pub fn init(allocator: std.mem.Allocator) Clipboard {
@@ -29,6 +30,13 @@ pub const Clipboard = struct {
 
         // Copy new content
         self.content = try self.allocator.dupe(u8, text);
+        self.linewise = false;
+    }
+
+    /// Set content that holds whole lines
+    pub fn setLines(self: *Clipboard, text: []const u8) !void {
+        try self.setContent(text);
+        self.linewise = true;
     }
 
     pub fn getContent(self: *const Clipboard) ?[]const u8 {
@@ -46,7 +54,7 @@ pub fn insertText(
     const byte_offset = try positionToByteOffset(buffer, pos);
 
     // Insert text into rope
-    try buffer.rope.insert(byte_offset, text);
+    try buffer.insert(byte_offset, text);
 
     // Update cursor position (move to end of inserted text)
     const new_col = pos.col + text.len; // Simplified - should count characters
@@ -64,7 +72,7 @@ pub fn insertNewline(
     const byte_offset = try positionToByteOffset(buffer, pos);
 
     // Insert newline
-    try buffer.rope.insert(byte_offset, "\n");
+    try buffer.insert(byte_offset, "\n");
 
     // Move cursor to start of next line
     const new_pos = Cursor.Position{ .line = pos.line + 1, .col = 0 };
@@ -85,7 +93,7 @@ pub fn deleteChar(
     }
 
     // Delete one byte (simplified - should handle UTF-8 properly)
-    try buffer.rope.delete(byte_offset, byte_offset + 1);
+    try buffer.delete(byte_offset, byte_offset + 1);
 
     return selection;
 }
@@ -107,7 +115,7 @@ pub fn deleteCharBefore(
 
     // Delete one byte before cursor
     if (byte_offset > 0) {
-        try buffer.rope.delete(byte_offset - 1, byte_offset);
+        try buffer.delete(byte_offset - 1, byte_offset);
 
         // Move cursor back
         const new_pos = Cursor.Position{ .line = pos.line, .col = pos.col - 1 };
@@ -140,7 +148,7 @@ pub fn deleteSelection(
     }
 
     // Delete the range
-    try buffer.rope.delete(start_offset, end_offset);
+    try buffer.delete(start_offset, end_offset);
 
     // Collapse selection to start
     return selection.collapseToAnchor();
@@ -174,8 +182,29 @@ pub fn pasteAfter(
     clipboard: *const Clipboard,
 ) !Cursor.Selection {
     const content = clipboard.getContent() orelse return selection;
+    return pasteTextAfter(buffer, selection, content, clipboard.linewise);
+}
+
+/// Paste clipboard content before cursor
+pub fn pasteBefore(
+    buffer: *Buffer.Buffer,
+    selection: Cursor.Selection,
+    clipboard: *const Clipboard,
+) !Cursor.Selection {
+    const content = clipboard.getContent() orelse return selection;
+    return pasteTextBefore(buffer, selection, content, clipboard.linewise);
+}
 
+/// Paste text after the cursor, or below the cursor's line if it holds whole lines
+pub fn pasteTextAfter(
+    buffer: *Buffer.Buffer,
+    selection: Cursor.Selection,
+    content: []const u8,
+    linewise: bool,
+) !Cursor.Selection {
     const pos = selection.head;
+    if (linewise) return pasteLines(buffer, selection, content, pos.line + 1);
+
     const byte_offset = try positionToByteOffset(buffer, pos);
 
     // Insert after current position
@@ -184,7 +213,7 @@ pub fn pasteAfter(
     else
         byte_offset;
 
-    try buffer.rope.insert(insert_offset, content);
+    try buffer.insert(insert_offset, content);
 
     // Move cursor to end of pasted text
     const new_col = pos.col + 1 + content.len; // Simplified
@@ -193,18 +222,19 @@ pub fn pasteAfter(
     return selection.moveTo(new_pos);
 }
 
-/// Paste clipboard content before cursor
-pub fn pasteBefore(
+/// Paste text before the cursor, or above the cursor's line if it holds whole lines
+pub fn pasteTextBefore(
     buffer: *Buffer.Buffer,
     selection: Cursor.Selection,
-    clipboard: *const Clipboard,
+    content: []const u8,
+    linewise: bool,
 ) !Cursor.Selection {
-    const content = clipboard.getContent() orelse return selection;
-
     const pos = selection.head;
+    if (linewise) return pasteLines(buffer, selection, content, pos.line);
+
     const byte_offset = try positionToByteOffset(buffer, pos);
 
-    try buffer.rope.insert(byte_offset, content);
+    try buffer.insert(byte_offset, content);
 
     // Move cursor to end of pasted text
     const new_col = pos.col + content.len; // Simplified
@@ -213,6 +243,21 @@ pub fn pasteBefore(
     return selection.moveTo(new_pos);
 }
 
+/// Insert whole lines so they start at `line`, leaving the cursor on the first of them
+fn pasteLines(buffer: *Buffer.Buffer, selection: Cursor.Selection, content: []const u8, line: usize) !Cursor.Selection {
+    const rope = &buffer.rope;
+    if (line < rope.lineCount()) {
+        try buffer.insert(rope.lineToByte(line), content);
+    } else {
+        // Below a last line with no newline: the newline comes first instead
+        const lines = if (std.mem.endsWith(u8, content, "\n")) content[0 .. content.len - 1] else content;
+        const end = rope.len();
+        try buffer.insert(end, "\n");
+        try buffer.insert(end + 1, lines);
+    }
+    return selection.moveTo(.{ .line = line, .col = 0 });
+}
+
 /// Change (delete and enter insert mode) - helper for command layer
 pub fn changeSelection(
     buffer: *Buffer.Buffer,
@@ -227,50 +272,10 @@ pub fn changeSelection(
 
 /// Convert Position (line, col) to byte offset in rope
 pub fn positionToByteOffset(buffer: *const Buffer.Buffer, pos: Cursor.Position) !usize {
-    const allocator = std.heap.page_allocator;
-
-    var current_line: usize = 0;
-    var byte_offset: usize = 0;
-    const total_bytes = buffer.rope.len();
-
-    // Find byte offset of line start
-    while (current_line < pos.line and byte_offset < total_bytes) {
-        // Scan for newline
-        const remaining = try buffer.rope.slice(allocator, byte_offset, total_bytes);
-        defer allocator.free(remaining);
-
-        var found = false;
-        for (remaining, 0..) |ch, i| {
-            if (ch == '\n') {
-                byte_offset += i + 1;
-                current_line += 1;
-                found = true;
-                break;
-            }
-        }
-
-        if (!found) break; // Reached end without finding newline
-    }
-
-    // Now add column offset
-    if (pos.col > 0) {
-        const line_start = byte_offset;
-        const remaining = try buffer.rope.slice(allocator, line_start, total_bytes);
-        defer allocator.free(remaining);
-
-        var col: usize = 0;
-        for (remaining, 0..) |ch, i| {
-            if (ch == '\n') break;
-            if (col >= pos.col) break;
-
-            byte_offset += 1;
-            col += 1;
-
-            _ = i; // Unused but kept for clarity
-        }
-    }
-
-    return @min(byte_offset, total_bytes);
+    // Lines past the end land on the last line, like a scan that ran out of newlines
+    const line = @min(pos.line, buffer.rope.lineCount() - 1);
+    const line_start = buffer.rope.lineToByte(line);
+    return @min(line_start + pos.col, buffer.rope.lineEnd(line));
 }
 
 // === Tests ===
@@ -341,56 +346,32 @@ test "actions: clipboard yank and paste" {
     try std.testing.expectEqualStrings("hello worldhello", text);
 }
 
+test "actions: paste whole lines" {
+    const allocator = std.testing.allocator;
+    var buffer = try Buffer.Buffer.initFromString(allocator, 0, "one\ntwo");
+    defer buffer.deinit();
+
+    // Above the cursor's line, then below the last line (which has no newline)
+    const above = try pasteTextBefore(&buffer, Cursor.Selection.cursor(.{ .line = 1, .col = 2 }), "new\n", true);
+    try std.testing.expectEqual(Cursor.Position{ .line = 1, .col = 0 }, above.head);
+    const below = try pasteTextAfter(&buffer, Cursor.Selection.cursor(.{ .line = 2, .col = 0 }), "end\n", true);
+    try std.testing.expectEqual(Cursor.Position{ .line = 3, .col = 0 }, below.head);
+
+    const text = try buffer.rope.toString(allocator);
+    defer allocator.free(text);
+    try std.testing.expectEqualStrings("one\nnew\ntwo\nend", text);
+}
+
 // === Line Operations ===
 
 /// Get the start and end byte offsets of a line
 fn getLineRange(buffer: *const Buffer.Buffer, line_num: usize) !struct { start: usize, end: usize } {
-    const allocator = std.heap.page_allocator;
-    const total_bytes = buffer.rope.len();
-
-    var current_line: usize = 0;
-    var line_start: usize = 0;
-    var byte_offset: usize = 0;
-
-    // Find start of target line
-    while (current_line < line_num and byte_offset < total_bytes) {
-        const remaining = try buffer.rope.slice(allocator, byte_offset, total_bytes);
-        defer allocator.free(remaining);
-
-        if (std.mem.indexOfScalar(u8, remaining, '\n')) |newline_pos| {
-            byte_offset += newline_pos + 1;
-            current_line += 1;
-            if (current_line == line_num) {
-                line_start = byte_offset;
-            }
-        } else {
-            break;
-        }
-    }
-
-    // If we didn't reach the line, it doesn't exist
-    if (current_line < line_num) {
+    if (line_num >= buffer.rope.lineCount()) {
         return error.LineNotFound;
     }
 
-    if (current_line == line_num) {
-        line_start = byte_offset;
-    }
-
-    // Find end of line (next newline or EOF)
-    var line_end = line_start;
-    if (line_start < total_bytes) {
-        const remaining = try buffer.rope.slice(allocator, line_start, total_bytes);
-        defer allocator.free(remaining);
-
-        if (std.mem.indexOfScalar(u8, remaining, '\n')) |newline_pos| {
-            line_end = line_start + newline_pos + 1; // Include newline
-        } else {
-            line_end = total_bytes; // Last line without newline
-        }
-    }
-
-    return .{ .start = line_start, .end = line_end };
+    // End includes the newline, except on the last line
+    return .{ .start = buffer.rope.lineToByte(line_num), .end = buffer.rope.lineToByte(line_num + 1) };
 }
 
 /// Move line up (swap with previous line)
@@ -418,12 +399,12 @@ pub fn moveLineUp(
     defer allocator.free(prev_text);
 
     // Delete both lines
-    try buffer.rope.delete(prev_range.start, curr_range.end);
+    try buffer.delete(prev_range.start, curr_range.end);
 
     // Insert in swapped order
-    try buffer.rope.insert(prev_range.start, curr_text);
+    try buffer.insert(prev_range.start, curr_text);
     const curr_len = curr_text.len;
-    try buffer.rope.insert(prev_range.start + curr_len, prev_text);
+    try buffer.insert(prev_range.start + curr_len, prev_text);
 
     // Move cursor up one line
     const new_pos = Cursor.Position{ .line = line_num - 1, .col = selection.head.col };
@@ -452,12 +433,12 @@ pub fn moveLineDown(
     defer allocator.free(next_text);
 
     // Delete both lines
-    try buffer.rope.delete(curr_range.start, next_range.end);
+    try buffer.delete(curr_range.start, next_range.end);
 
     // Insert in swapped order
-    try buffer.rope.insert(curr_range.start, next_text);
+    try buffer.insert(curr_range.start, next_text);
     const next_len = next_text.len;
-    try buffer.rope.insert(curr_range.start + next_len, curr_text);
+    try buffer.insert(curr_range.start + next_len, curr_text);
 
     // Move cursor down one line
     const new_pos = Cursor.Position{ .line = line_num + 1, .col = selection.head.col };
@@ -540,6 +521,33 @@ pub fn selectWord(
     return Cursor.Selection.init(bounds.start, bounds.end);
 }
 
+/// Select the word under cursor with the whitespace after it (`aw`)
+/// Takes the whitespace before it instead when there is none after.
+pub fn selectAroundWord(
+    buffer: *const Buffer.Buffer,
+    selection: Cursor.Selection,
+    allocator: std.mem.Allocator,
+) !Cursor.Selection {
+    const bounds = try getWordBounds(buffer, selection.head);
+    if (bounds.start.eql(bounds.end)) return Cursor.Selection.init(bounds.start, bounds.end);
+
+    const line = try buffer.rope.lineSlice(allocator, bounds.start.line);
+    defer allocator.free(line);
+
+    var end_col = bounds.end.col;
+    while (end_col < line.len and (line[end_col] == ' ' or line[end_col] == '\t')) end_col += 1;
+
+    var start_col = bounds.start.col;
+    if (end_col == bounds.end.col) {
+        while (start_col > 0 and (line[start_col - 1] == ' ' or line[start_col - 1] == '\t')) start_col -= 1;
+    }
+
+    return Cursor.Selection.init(
+        .{ .line = bounds.start.line, .col = start_col },
+        .{ .line = bounds.end.line, .col = end_col },
+    );
+}
+
 /// Delete the word under cursor
 pub fn deleteWord(
     buffer: *Buffer.Buffer,
@@ -557,7 +565,7 @@ pub fn deleteWord(
     const end_offset = try positionToByteOffset(buffer, bounds.end);
 
     // Delete the word
-    try buffer.rope.delete(start_offset, end_offset);
+    try buffer.delete(start_offset, end_offset);
 
     // Return selection at word start
     return Cursor.Selection.cursor(bounds.start);
@@ -805,7 +813,7 @@ pub fn deleteInnerPair(
 
     if (start_offset >= end_offset) return selection;
 
-    try buffer.rope.delete(start_offset, end_offset);
+    try buffer.delete(start_offset, end_offset);
     return Cursor.Selection.cursor(sel.start());
 }
 
@@ -875,16 +883,16 @@ pub fn transposeChars(
     const curr_char = text[curr_offset];
 
     // Delete both characters
-    try buffer.rope.delete(prev_offset, prev_offset + 1);
-    try buffer.rope.delete(curr_offset - 1, curr_offset); // -1 because we deleted one
+    try buffer.delete(prev_offset, prev_offset + 1);
+    try buffer.delete(curr_offset - 1, curr_offset); // -1 because we deleted one
 
     // Insert them swapped
     var swap_buf: [2]u8 = undefined;
     swap_buf[0] = curr_char;
     swap_buf[1] = prev_char;
 
-    try buffer.rope.insert(prev_offset, swap_buf[0..1]);
-    try buffer.rope.insert(prev_offset + 1, swap_buf[1..2]);
+    try buffer.insert(prev_offset, swap_buf[0..1]);
+    try buffer.insert(prev_offset + 1, swap_buf[1..2]);
 
     return selection;
 }
@@ -933,11 +941,11 @@ pub fn transposeLines(
     const curr_line = text[curr_line_start..curr_line_end];
 
     // Delete both lines
-    try buffer.rope.delete(prev_line_start, curr_line_end);
+    try buffer.delete(prev_line_start, curr_line_end);
 
     // Insert swapped
-    try buffer.rope.insert(prev_line_start, curr_line);
-    try buffer.rope.insert(prev_line_start + curr_line.len, prev_line);
+    try buffer.insert(prev_line_start, curr_line);
+    try buffer.insert(prev_line_start + curr_line.len, prev_line);
 
     // Move cursor down one line
     return selection.moveTo(.{ .line = pos.line, .col = pos.col });
@@ -988,8 +996,8 @@ pub fn uppercaseSelection(
     }
 
     // Replace text
-    try buffer.rope.delete(start_offset, end_offset);
-    try buffer.rope.insert(start_offset, upper);
+    try buffer.delete(start_offset, end_offset);
+    try buffer.insert(start_offset, upper);
 
     return selection;
 }
@@ -1021,8 +1029,8 @@ pub fn lowercaseSelection(
     }
 
     // Replace text
-    try buffer.rope.delete(start_offset, end_offset);
-    try buffer.rope.insert(start_offset, lower);
+    try buffer.delete(start_offset, end_offset);
+    try buffer.insert(start_offset, lower);
 
     return selection;
 }
@@ -1061,8 +1069,8 @@ pub fn titlecaseSelection(
     }
 
     // Replace text
-    try buffer.rope.delete(start_offset, end_offset);
-    try buffer.rope.insert(start_offset, title);
+    try buffer.delete(start_offset, end_offset);
+    try buffer.insert(start_offset, title);
 
     return selection;
 }
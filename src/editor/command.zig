@@ -15,6 +15,12 @@ const Markdown = @import("../render/markdown.zig");
 /// Command context - passed to command handlers
 pub const Context = struct {
     editor: *Editor,
+    count: ?usize = null, // Count typed before the keys (the 3 in `3j`), null if none
+
+    /// Count, or 1 without one
+    pub fn getCount(self: *const Context) usize {
+        return self.count orelse 1;
+    }
 };
 
 /// Helper: Apply motion result based on current mode
@@ -58,6 +64,49 @@ pub const Command = struct {
     description: []const u8,
     handler: Handler,
     category: Category,
+    count: CountMode = .auto,
+    target: Operator.Target = .auto, // What it covers after an operator (`dw`, `ci(`)
+
+    /// How a count prefix applies to the command
+    pub const CountMode = enum {
+        auto, // Repeat motions, edits and searches; ignore it for everything else
+        repeat, // Run the handler count times
+        handler, // The handler reads ctx.count itself
+        ignore, // A count means nothing here
+    };
+
+    /// Run the handler, repeating it for a count when the command wants that
+    /// Stops at the first error, and once the command waits for a key (`3fx`):
+    /// the count is kept for when that key arrives. The repeats undo as one step.
+    pub fn run(self: Command, ctx: *Context) Result {
+        const times = if (self.countMode() == .repeat) ctx.getCount() else 1;
+
+        const held = if (times > 1) ctx.editor.activeWindowBuffer() else null;
+        if (held) |buffer| buffer.holdUndoGroup(ctx.editor.getCursorPosition()) catch return Result.err("Out of memory");
+        defer if (held) |buffer| buffer.releaseUndoGroup() catch {};
+
+        var result = Result.ok();
+        for (0..times) |_| {
+            result = self.handler(ctx);
+            if (result == .error_msg or ctx.editor.pending_command.isWaiting()) break;
+        }
+        if (ctx.editor.pending_command.isWaiting()) ctx.editor.pending_count = ctx.getCount();
+        return result;
+    }
+
+    /// How the command marks out the text for an operator typed before it
+    pub fn operatorTarget(self: Command) Operator.Target {
+        if (self.target != .auto) return self.target;
+        return if (self.category == .motion) .exclusive else .none;
+    }
+
+    fn countMode(self: Command) CountMode {
+        if (self.count != .auto) return self.count;
+        return switch (self.category) {
+            .motion, .edit, .search => .repeat,
+            else => .ignore,
+        };
+    }
 
     pub const Category = enum {
         motion, // Cursor movement
@@ -103,14 +152,14 @@ pub const Registry = struct {
     /// Execute command by name
     pub fn execute(self: *const Registry, name: []const u8, ctx: *Context) Result {
         if (self.get(name)) |cmd| {
-            const result = cmd.handler(ctx);
+            const result = cmd.run(ctx);
 
-            // Record action for repeat if it succeeded and should be recorded
+            // Record action (with its count) for repeat if it succeeded and should be recorded
             switch (result) {
                 .success => {
                     const Repeat = @import("repeat.zig");
                     if (Repeat.RepeatSystem.shouldRecord(name)) {
-                        ctx.editor.repeat_system.recordAction(name) catch {};
+                        ctx.editor.repeat_system.recordAction(name, ctx.count) catch {};
                     }
                 },
                 .error_msg => {},
@@ -164,6 +213,10 @@ const Undo = @import("undo.zig");
 const Registers = @import("registers.zig");
 const Buffer = @import("../buffer/manager.zig");
 const Rope = @import("../buffer/rope.zig").Rope;
+const Shell = @import("shell.zig");
+const Regex = @import("regex.zig");
+const Operator = @import("operator.zig");
+const TextObjects = @import("textobjects.zig");
 
 fn moveLeft(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
@@ -183,14 +236,25 @@ fn moveUp(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
     const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
     const new_sel = Motions.moveUp(primary_sel, buffer);
-    return applyMotion(ctx, new_sel);
+    return applyMotion(ctx, overClosedFolds(ctx, buffer, primary_sel, new_sel, false));
 }
 
 fn moveDown(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
     const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
     const new_sel = Motions.moveDown(primary_sel, buffer);
-    return applyMotion(ctx, new_sel);
+    return applyMotion(ctx, overClosedFolds(ctx, buffer, primary_sel, new_sel, true));
+}
+
+/// Line-by-line motion that steps over closed folds as if each were one line
+/// `to` is where the motion went without folds.
+fn overClosedFolds(ctx: *Context, buffer: *const Buffer.Buffer, from: Cursor.Selection, to: Cursor.Selection, down: bool) Cursor.Selection {
+    const fold_set = ctx.editor.activeFolds() orelse return to;
+    if (fold_set.closed.items.len == 0) return to;
+
+    const line = if (down) fold_set.nextVisible(from.head.line) else fold_set.prevVisible(from.head.line) orelse return from;
+    if (line >= buffer.lineCount()) return from; // A fold runs to the end of the file
+    return Motions.moveToLine(from, buffer, line);
 }
 
 fn moveWordForward(ctx: *Context) Result {
@@ -231,6 +295,7 @@ fn moveLineEnd(ctx: *Context) Result {
 fn moveFileStart(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
     const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
+    if (ctx.count) |line| return applyMotion(ctx, moveToLine(primary_sel, buffer, line));
     const new_sel = Motions.moveFileStart(primary_sel, buffer);
     return applyMotion(ctx, new_sel);
 }
@@ -238,10 +303,17 @@ fn moveFileStart(ctx: *Context) Result {
 fn moveFileEnd(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
     const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
+    if (ctx.count) |line| return applyMotion(ctx, moveToLine(primary_sel, buffer, line));
     const new_sel = Motions.moveFileEnd(primary_sel, buffer);
     return applyMotion(ctx, new_sel);
 }
 
+/// Start of a 1-based line, clamped to the last line (`5G`, `5gg`)
+fn moveToLine(selection: Cursor.Selection, buffer: *const Buffer.Buffer, line: usize) Cursor.Selection {
+    const last_line = buffer.lineCount() -| 1;
+    return selection.moveTo(.{ .line = @min(line -| 1, last_line), .col = 0 });
+}
+
 /// Jump to matching bracket
 fn jumpToMatchingBracket(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
@@ -503,9 +575,14 @@ fn openBelow(ctx: *Context) Result {
         const buffer = ctx.editor.buffer_manager.getBufferMut(id) orelse return Result.err("No active buffer");
         const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
 
+        // The new line's indentation, worked out before the text changes
+        const indent = ctx.editor.openedLineIndent(primary_sel.head.line, true) catch return Result.err("Failed to indent new line");
+        defer ctx.editor.allocator.free(indent);
+
         // Move to end of line, insert newline, enter insert mode
         const line_end_sel = Motions.moveLineEnd(primary_sel, buffer);
-        const new_sel = Actions.insertNewline(buffer, line_end_sel) catch return Result.err("Failed to insert newline");
+        const line_sel = Actions.insertNewline(buffer, line_end_sel) catch return Result.err("Failed to insert newline");
+        const new_sel = if (indent.len == 0) line_sel else Actions.insertText(buffer, line_sel, indent) catch return Result.err("Failed to indent new line");
 
         buffer.metadata.markModified();
         ctx.editor.selections.setSingleCursor(ctx.editor.allocator, new_sel.head) catch return Result.err("Failed to update cursor");
@@ -520,13 +597,17 @@ fn openAbove(ctx: *Context) Result {
         const buffer = ctx.editor.buffer_manager.getBufferMut(id) orelse return Result.err("No active buffer");
         const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
 
+        const indent = ctx.editor.openedLineIndent(primary_sel.head.line, false) catch return Result.err("Failed to indent new line");
+        defer ctx.editor.allocator.free(indent);
+
         // Move to start of line, insert newline before current line, enter insert mode
         const line_start_sel = Motions.moveLineStart(primary_sel, buffer);
         _ = Actions.insertText(buffer, line_start_sel, "\n") catch return Result.err("Failed to insert newline");
 
-        // Position stays at start of new line (where we just were)
+        // Position stays on the new line (where we just were), after its indentation
+        const new_sel = if (indent.len == 0) line_start_sel else Actions.insertText(buffer, line_start_sel, indent) catch return Result.err("Failed to indent new line");
         buffer.metadata.markModified();
-        ctx.editor.selections.setSingleCursor(ctx.editor.allocator, line_start_sel.head) catch return Result.err("Failed to update cursor");
+        ctx.editor.selections.setSingleCursor(ctx.editor.allocator, new_sel.head) catch return Result.err("Failed to update cursor");
         ctx.editor.enterInsertMode() catch return Result.err("Failed to enter insert mode");
         return Result.ok();
     }
@@ -547,6 +628,15 @@ fn selectMode(ctx: *Context) Result {
 
 // === Deletion commands ===
 
+/// Replace the character under the cursor (r command)
+/// Completion occurs in completeReplaceChar once the replacement is typed;
+/// a count replaces that many characters (`3rx`).
+fn replaceChar(ctx: *Context) Result {
+    ctx.editor.pending_command = .replace_char;
+    ctx.editor.prompt.show("Replace with:", .character);
+    return Result.ok();
+}
+
 fn deleteCharAtCursor(ctx: *Context) Result {
     if (ctx.editor.buffer_manager.active_buffer_id) |id| {
         const buffer = ctx.editor.buffer_manager.getBufferMut(id) orelse return Result.err("No active buffer");
@@ -578,9 +668,9 @@ fn deleteLine(ctx: *Context) Result {
         const buffer = ctx.editor.buffer_manager.getBufferMut(id) orelse return Result.err("No active buffer");
         const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
 
-        // Move to line start, select entire line (including newline), delete
+        // Move to line start, select entire line (including newline) and any more lines counted, delete
         const line_start = Motions.moveLineStart(primary_sel, buffer);
-        const line_end = Motions.moveLineEnd(line_start, buffer);
+        const line_end = Motions.moveLineEnd(lastCountedLine(ctx, line_start, buffer), buffer);
 
         // Create selection from start to end of line
         const line_selection = Cursor.Selection.init(line_start.head, line_end.head);
@@ -629,9 +719,9 @@ fn yankLine(ctx: *Context) Result {
         return Result.err("No selection");
     };
 
-    // Create selection spanning the entire line
+    // Create selection spanning the entire line (and any more lines counted)
     const line_start = Motions.moveLineStart(primary_sel, buffer);
-    const line_end = Motions.moveLineEnd(line_start, buffer);
+    const line_end = Motions.moveLineEnd(lastCountedLine(ctx, line_start, buffer), buffer);
     const line_selection = Cursor.Selection.init(line_start.head, line_end.head);
 
     // Yank to clipboard
@@ -640,11 +730,24 @@ fn yankLine(ctx: *Context) Result {
     };
 
     // Show message
-    ctx.editor.messages.add("Yanked line", .success) catch {};
+    const lines = line_end.head.line - line_start.head.line + 1;
+    if (lines == 1) {
+        ctx.editor.messages.add("Yanked line", .success) catch {};
+    } else {
+        var msg_buf: [64]u8 = undefined;
+        const msg = std.fmt.bufPrint(&msg_buf, "Yanked {d} lines", .{lines}) catch "Yanked lines";
+        ctx.editor.messages.add(msg, .success) catch {};
+    }
 
     return Result.ok();
 }
 
+/// Last line a counted line command covers (`3dd`: the current line and two more)
+fn lastCountedLine(ctx: *const Context, line_start: Cursor.Selection, buffer: *const Buffer.Buffer) Cursor.Selection {
+    const last_line = buffer.lineCount() -| 1;
+    return line_start.moveTo(.{ .line = @min(line_start.head.line + ctx.getCount() - 1, last_line), .col = 0 });
+}
+
 /// Paste clipboard content after cursor
 fn pasteAfter(ctx: *Context) Result {
     const buffer_id = ctx.editor.buffer_manager.active_buffer_id orelse {
@@ -657,15 +760,15 @@ fn pasteAfter(ctx: *Context) Result {
         return Result.err("No selection");
     };
 
-    // Check clipboard has content
-    if (ctx.editor.clipboard.getContent() == null) {
-        return Result.err("Clipboard is empty");
-    }
-
-    // Paste using Actions
-    const new_sel = Actions.pasteAfter(buffer, primary_sel, &ctx.editor.clipboard) catch {
-        return Result.err("Failed to paste");
+    // From the register picked with `"x`, otherwise the clipboard
+    const pasted = if (ctx.editor.pending_register) |register| blk: {
+        const content = ctx.editor.registers.get(register) orelse return Result.err("Register is empty");
+        break :blk Actions.pasteTextAfter(buffer, primary_sel, content.text, content.is_linewise);
+    } else blk: {
+        if (ctx.editor.clipboard.getContent() == null) return Result.err("Clipboard is empty");
+        break :blk Actions.pasteAfter(buffer, primary_sel, &ctx.editor.clipboard);
     };
+    const new_sel = pasted catch return Result.err("Failed to paste");
 
     // Update cursor
     ctx.editor.selections.setSingleCursor(ctx.editor.allocator, new_sel.head) catch {
@@ -692,15 +795,15 @@ fn pasteBefore(ctx: *Context) Result {
         return Result.err("No selection");
     };
 
-    // Check clipboard has content
-    if (ctx.editor.clipboard.getContent() == null) {
-        return Result.err("Clipboard is empty");
-    }
-
-    // Paste using Actions
-    const new_sel = Actions.pasteBefore(buffer, primary_sel, &ctx.editor.clipboard) catch {
-        return Result.err("Failed to paste");
+    // From the register picked with `"x`, otherwise the clipboard
+    const pasted = if (ctx.editor.pending_register) |register| blk: {
+        const content = ctx.editor.registers.get(register) orelse return Result.err("Register is empty");
+        break :blk Actions.pasteTextBefore(buffer, primary_sel, content.text, content.is_linewise);
+    } else blk: {
+        if (ctx.editor.clipboard.getContent() == null) return Result.err("Clipboard is empty");
+        break :blk Actions.pasteBefore(buffer, primary_sel, &ctx.editor.clipboard);
     };
+    const new_sel = pasted catch return Result.err("Failed to paste");
 
     // Update cursor
     ctx.editor.selections.setSingleCursor(ctx.editor.allocator, new_sel.head) catch {
@@ -732,8 +835,15 @@ fn deleteSelection(ctx: *Context) Result {
         return Result.err("No selection to delete");
     }
 
-    // Delete and yank to clipboard
-    const new_sel = Actions.deleteSelection(buffer, primary_sel, &ctx.editor.clipboard) catch {
+    // Yank to the register (and clipboard), then delete
+    const text = selectionText(buffer, primary_sel, ctx.editor.allocator) catch {
+        return Result.err("Failed to delete selection");
+    };
+    defer ctx.editor.allocator.free(text);
+    ctx.editor.storeText(text, false, ctx.editor.pending_register) catch {
+        return Result.err("Failed to delete selection");
+    };
+    const new_sel = Actions.deleteSelection(buffer, primary_sel, null) catch {
         return Result.err("Failed to delete selection");
     };
 
@@ -766,8 +876,12 @@ fn yankSelection(ctx: *Context) Result {
         return Result.err("No selection to yank");
     }
 
-    // Yank to clipboard
-    Actions.yankSelection(buffer, primary_sel, &ctx.editor.clipboard) catch {
+    // Yank to the register (and clipboard)
+    const text = selectionText(buffer, primary_sel, ctx.editor.allocator) catch {
+        return Result.err("Failed to yank selection");
+    };
+    defer ctx.editor.allocator.free(text);
+    ctx.editor.storeText(text, false, ctx.editor.pending_register) catch {
         return Result.err("Failed to yank selection");
     };
 
@@ -780,27 +894,32 @@ fn yankSelection(ctx: *Context) Result {
     return Result.ok();
 }
 
+/// Text a selection covers (caller frees)
+fn selectionText(buffer: *const Buffer.Buffer, selection: Cursor.Selection, allocator: std.mem.Allocator) ![]u8 {
+    const range = selection.range();
+    const start = try Actions.positionToByteOffset(buffer, range.start);
+    const end = try Actions.positionToByteOffset(buffer, range.end);
+    return buffer.rope.slice(allocator, start, end);
+}
+
 /// Undo last operation
 fn undo(ctx: *Context) Result {
-    if (!ctx.editor.undo_history.canUndo()) {
+    // History lives on the buffer shown in the active window
+    const buffer = ctx.editor.activeWindowBuffer() orelse {
+        return Result.err("No active buffer");
+    };
+
+    if (!buffer.undo_history.canUndo()) {
         return Result.err("Nothing to undo");
     }
 
     // Get undo group
-    const group = ctx.editor.undo_history.getUndo() orelse {
+    const group = buffer.undo_history.getUndo() orelse {
         return Result.err("Undo failed");
     };
 
-    // Get mutable active buffer
-    const buffer_id = ctx.editor.buffer_manager.active_buffer_id orelse {
-        return Result.err("No active buffer");
-    };
-    const buffer = ctx.editor.buffer_manager.getBufferMut(buffer_id) orelse {
-        return Result.err("No active buffer");
-    };
-
     // Apply undo operations to buffer
-    Undo.UndoHistory.applyUndo(group, &buffer.rope, ctx.editor.allocator) catch {
+    Undo.UndoHistory.applyUndo(group, buffer) catch {
         return Result.err("Failed to apply undo operations");
     };
 
@@ -824,6 +943,11 @@ fn saveBuffer(ctx: *Context) Result {
         const msg = switch (err) {
             error.NoActiveBuffer => "No active buffer to save",
             error.NoFilepath => "No file path (use save_as)",
+            error.ReadOnlyFile => "File is read-only",
+            error.FileChangedOnDisk => "File changed on disk since it was read (use :w! to overwrite)",
+            error.PermissionDenied => "Permission denied writing file",
+            error.BackupFailed => "Couldn't write the backup file, so nothing was saved (see backup_on_save)",
+            error.UnrepresentableCharacter => "Text can't be written in this file's encoding (see :set fileencoding)",
             else => "Failed to save file",
         };
         return Result.err(msg);
@@ -847,7 +971,7 @@ fn saveAllBuffers(ctx: *Context) Result {
     for (ctx.editor.buffer_manager.buffers.items) |*buffer| {
         if (buffer.metadata.modified) {
             if (buffer.metadata.filepath != null) {
-                buffer.save() catch {
+                buffer.save(ctx.editor.config.saveOptions()) catch {
                     skipped_count += 1;
                     continue;
                 };
@@ -1047,11 +1171,6 @@ fn findNext(ctx: *Context) Result {
         return Result.err("No active buffer");
     };
 
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
-    };
-    defer ctx.editor.allocator.free(text);
-
     const cursor_pos = ctx.editor.getCursorPosition();
 
     // Search from next position
@@ -1060,25 +1179,19 @@ fn findNext(ctx: *Context) Result {
         .col = cursor_pos.col + 1,
     };
 
-    if (ctx.editor.search.findNext(text, search_start)) |match| {
-        // Move cursor to match start
-        ctx.editor.selections.setSingleCursor(ctx.editor.allocator, match.start) catch {
-            return Result.err("Failed to update cursor");
-        };
-        ctx.editor.ensureCursorVisible();
-        return Result.ok();
-    } else {
-        // Wrap around to beginning
-        if (ctx.editor.search.findNext(text, .{ .line = 0, .col = 0 })) |match| {
-            ctx.editor.selections.setSingleCursor(ctx.editor.allocator, match.start) catch {
-                return Result.err("Failed to update cursor");
-            };
-            ctx.editor.ensureCursorVisible();
-            ctx.editor.messages.add("Search wrapped", .info) catch {};
-            return Result.ok();
-        }
-        return Result.err("No match found");
-    }
+    // Searched in place rather than copied out; wraps around to the beginning
+    var wrapped = false;
+    const match = ctx.editor.search.findNextInRope(&buffer.rope, search_start) orelse blk: {
+        wrapped = true;
+        break :blk ctx.editor.search.findNextInRope(&buffer.rope, .{ .line = 0, .col = 0 });
+    } orelse return Result.err("No match found");
+
+    ctx.editor.selections.setSingleCursor(ctx.editor.allocator, match.start) catch {
+        return Result.err("Failed to update cursor");
+    };
+    ctx.editor.ensureCursorVisible();
+    if (wrapped) ctx.editor.messages.add("Search wrapped", .info) catch {};
+    return Result.ok();
 }
 
 /// Find previous occurrence of search query
@@ -1090,15 +1203,9 @@ fn findPrevious(ctx: *Context) Result {
     const buffer = ctx.editor.buffer_manager.getActiveBuffer() orelse {
         return Result.err("No active buffer");
     };
-
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
-    };
-    defer ctx.editor.allocator.free(text);
-
     const cursor_pos = ctx.editor.getCursorPosition();
 
-    if (ctx.editor.search.findPrevious(text, cursor_pos)) |match| {
+    if (ctx.editor.search.findPreviousInRope(&buffer.rope, cursor_pos)) |match| {
         ctx.editor.selections.setSingleCursor(ctx.editor.allocator, match.start) catch {
             return Result.err("Failed to update cursor");
         };
@@ -1187,42 +1294,21 @@ fn startSearch(ctx: *Context) Result {
             return Result.err("No active buffer");
         };
 
-        const text = buffer.getText() catch {
-            return Result.err("Failed to get buffer text");
-        };
-        defer ctx.editor.allocator.free(text);
-
         const range = primary.range();
 
         // Extract selected text (simplified - only works for single line)
         if (range.start.line == range.end.line) {
-            // Calculate byte offset for start
-            var offset: usize = 0;
-            var line: usize = 0;
-            var col: usize = 0;
-
-            while (offset < text.len) {
-                if (line == range.start.line and col == range.start.col) {
-                    break;
-                }
-                if (text[offset] == '\n') {
-                    line += 1;
-                    col = 0;
-                } else {
-                    col += 1;
-                }
-                offset += 1;
-            }
-
-            const start_offset = offset;
-
-            // Find end offset
-            while (offset < text.len and col < range.end.col) {
-                col += 1;
-                offset += 1;
-            }
+            const start_offset = Actions.positionToByteOffset(buffer, range.start) catch {
+                return Result.err("Invalid selection");
+            };
+            const end_offset = Actions.positionToByteOffset(buffer, range.end) catch {
+                return Result.err("Invalid selection");
+            };
+            const selected_text = buffer.rope.slice(ctx.editor.allocator, start_offset, end_offset) catch {
+                return Result.err("Failed to get selected text");
+            };
+            defer ctx.editor.allocator.free(selected_text);
 
-            const selected_text = text[start_offset..offset];
             ctx.editor.search.setQuery(selected_text) catch {
                 return Result.err("Query too long");
             };
@@ -1245,34 +1331,10 @@ fn duplicateLine(ctx: *Context) Result {
     };
 
     const cursor_pos = ctx.editor.getCursorPosition();
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
+    const line_text = buffer.rope.lineSlice(ctx.editor.allocator, cursor_pos.line) catch {
+        return Result.err("Failed to get line text");
     };
-    defer ctx.editor.allocator.free(text);
-
-    // Find current line bounds
-    var line: usize = 0;
-    var line_start: usize = 0;
-    var offset: usize = 0;
-
-    while (offset < text.len) {
-        if (line == cursor_pos.line) {
-            line_start = offset;
-            break;
-        }
-        if (text[offset] == '\n') {
-            line += 1;
-        }
-        offset += 1;
-    }
-
-    // Find line end
-    var line_end = line_start;
-    while (line_end < text.len and text[line_end] != '\n') {
-        line_end += 1;
-    }
-
-    const line_text = text[line_start..line_end];
+    defer ctx.editor.allocator.free(line_text);
 
     // Build text with newline
     const dup_text = std.fmt.allocPrint(ctx.editor.allocator, "\n{s}", .{line_text}) catch {
@@ -1298,6 +1360,17 @@ fn duplicateLine(ctx: *Context) Result {
 
 /// Join current line with next line
 fn joinLines(ctx: *Context) Result {
+    // `3J` joins three lines, which takes two joins
+    const joins = @max(ctx.getCount(), 2) - 1;
+    for (0..joins) |_| {
+        const result = joinNextLine(ctx);
+        if (result == .error_msg) return result;
+    }
+    return Result.ok();
+}
+
+/// Join the line below onto the cursor line
+fn joinNextLine(ctx: *Context) Result {
     const buffer_id = ctx.editor.buffer_manager.active_buffer_id orelse {
         return Result.err("No active buffer");
     };
@@ -1312,38 +1385,13 @@ fn joinLines(ctx: *Context) Result {
         return Result.err("No next line to join");
     }
 
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
+    // Delete the newline by deleting character from start of next line
+    _ = Actions.deleteCharBefore(buffer, Cursor.Selection.cursor(.{ .line = cursor_pos.line + 1, .col = 0 })) catch {
+        return Result.err("Failed to delete newline");
     };
-    defer ctx.editor.allocator.free(text);
-
-    // Find newline at end of current line
-    var line: usize = 0;
-    var offset: usize = 0;
-
-    while (offset < text.len) {
-        if (line == cursor_pos.line) {
-            // Find the newline
-            while (offset < text.len and text[offset] != '\n') {
-                offset += 1;
-            }
-            if (offset < text.len) {
-                // Delete the newline by deleting character from start of next line
-                _ = Actions.deleteCharBefore(buffer, Cursor.Selection.cursor(.{ .line = cursor_pos.line + 1, .col = 0 })) catch {
-                    return Result.err("Failed to delete newline");
-                };
-
-                buffer.metadata.markModified();
-                return Result.ok();
-            }
-        }
-        if (text[offset] == '\n') {
-            line += 1;
-        }
-        offset += 1;
-    }
 
-    return Result.err("Failed to join lines");
+    buffer.metadata.markModified();
+    return Result.ok();
 }
 
 /// Delete to end of line
@@ -1359,37 +1407,8 @@ fn deleteToEndOfLine(ctx: *Context) Result {
         return Result.err("No selection");
     };
 
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
-    };
-    defer ctx.editor.allocator.free(text);
-
-    // Find end of current line
-    var line: usize = 0;
-    var col: usize = 0;
-    var offset: usize = 0;
-
-    while (offset < text.len) {
-        if (line == primary.head.line and col == primary.head.col) {
-            break;
-        }
-        if (text[offset] == '\n') {
-            line += 1;
-            col = 0;
-        } else {
-            col += 1;
-        }
-        offset += 1;
-    }
-
-    // Find end of line
-    var end_offset = offset;
-    while (end_offset < text.len and text[end_offset] != '\n') {
-        end_offset += 1;
-    }
-
     // Create selection from cursor to end of line
-    const end_col = col + (end_offset - offset);
+    const end_col = @max(primary.head.col, lineByteLength(buffer, primary.head.line));
     const delete_sel = Cursor.Selection{
         .anchor = primary.head,
         .head = Cursor.Position{ .line = primary.head.line, .col = end_col },
@@ -1423,6 +1442,22 @@ fn selectCurrentWord(ctx: *Context) Result {
     return Result.ok();
 }
 
+/// Select word under cursor with the whitespace after it (aw)
+fn selectAroundCurrentWord(ctx: *Context) Result {
+    const buffer = ctx.editor.buffer_manager.getActiveBuffer() orelse return Result.err("No active buffer");
+    const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
+
+    const word_sel = Actions.selectAroundWord(buffer, primary_sel, ctx.editor.allocator) catch {
+        return Result.err("Failed to select word");
+    };
+
+    ctx.editor.selections.setSingleSelection(ctx.editor.allocator, word_sel) catch {
+        return Result.err("Failed to update selection");
+    };
+
+    return Result.ok();
+}
+
 /// Delete word under cursor (text object)
 fn deleteCurrentWord(ctx: *Context) Result {
     if (ctx.editor.buffer_manager.active_buffer_id) |id| {
@@ -1692,6 +1727,200 @@ fn changeInnerBrace(ctx: *Context) Result {
     return Result.err("No active buffer");
 }
 
+/// Select inside or around a pair, for the pair text objects without a command above
+fn selectPair(ctx: *Context, pair_type: Actions.PairType, around: bool) Result {
+    const buffer = ctx.editor.buffer_manager.getActiveBuffer() orelse return Result.err("No active buffer");
+    const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
+
+    const selected = if (around)
+        Actions.selectAroundPair(buffer, primary_sel, pair_type, ctx.editor.allocator)
+    else
+        Actions.selectInnerPair(buffer, primary_sel, pair_type, ctx.editor.allocator);
+    const new_sel = selected catch return Result.err("Failed to select pair");
+
+    ctx.editor.selections.setSingleSelection(ctx.editor.allocator, new_sel) catch {
+        return Result.err("Failed to update selection");
+    };
+
+    return Result.ok();
+}
+
+fn selectAroundParen(ctx: *Context) Result {
+    return selectPair(ctx, .paren, true);
+}
+
+fn selectAroundBracket(ctx: *Context) Result {
+    return selectPair(ctx, .bracket, true);
+}
+
+fn selectAroundBrace(ctx: *Context) Result {
+    return selectPair(ctx, .brace, true);
+}
+
+fn selectAroundQuote(ctx: *Context) Result {
+    return selectPair(ctx, .double_quote, true);
+}
+
+fn selectInnerSingleQuote(ctx: *Context) Result {
+    return selectPair(ctx, .single_quote, false);
+}
+
+fn selectAroundSingleQuote(ctx: *Context) Result {
+    return selectPair(ctx, .single_quote, true);
+}
+
+fn selectInnerBacktick(ctx: *Context) Result {
+    return selectPair(ctx, .backtick, false);
+}
+
+fn selectAroundBacktick(ctx: *Context) Result {
+    return selectPair(ctx, .backtick, true);
+}
+
+fn selectInnerAngle(ctx: *Context) Result {
+    return selectPair(ctx, .angle, false);
+}
+
+fn selectAroundAngle(ctx: *Context) Result {
+    return selectPair(ctx, .angle, true);
+}
+
+/// Select a syntax text object that the language's textobjects.scm query finds
+fn selectSyntaxObject(ctx: *Context, kind: TextObjects.SyntaxKind, around: bool) Result {
+    const objects = ctx.editor.syntaxObjects(kind) catch return Result.err("No syntax tree for this file");
+    defer ctx.editor.allocator.free(objects);
+    if (objects.len == 0) return Result.err(syntaxObjectMissing(kind));
+
+    const buffer = ctx.editor.buffer_manager.getActiveBuffer() orelse return Result.err("No active buffer");
+    const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
+    const range = primary_sel.range();
+
+    const selected = TextObjects.selectSyntaxObject(
+        buffer,
+        objects,
+        kind,
+        .{ .start = range.start, .end = range.end },
+        around,
+        ctx.editor.allocator,
+    ) catch return Result.err("Failed to select text object");
+    const object = selected orelse return Result.err(syntaxObjectMissing(kind));
+
+    ctx.editor.selections.setSingleSelection(ctx.editor.allocator, Cursor.Selection.init(object.start, object.end)) catch {
+        return Result.err("Failed to update selection");
+    };
+
+    return Result.ok();
+}
+
+/// Message for a syntax text object or motion that found nothing
+fn syntaxObjectMissing(kind: TextObjects.SyntaxKind) []const u8 {
+    return switch (kind) {
+        .function => "No function here",
+        .class => "No class or type here",
+        .parameter => "No argument here",
+        .comment => "No comment here",
+    };
+}
+
+fn selectAroundFunction(ctx: *Context) Result {
+    return selectSyntaxObject(ctx, .function, true);
+}
+
+fn selectInnerFunction(ctx: *Context) Result {
+    return selectSyntaxObject(ctx, .function, false);
+}
+
+fn selectAroundClass(ctx: *Context) Result {
+    return selectSyntaxObject(ctx, .class, true);
+}
+
+fn selectInnerClass(ctx: *Context) Result {
+    return selectSyntaxObject(ctx, .class, false);
+}
+
+fn selectAroundArgument(ctx: *Context) Result {
+    return selectSyntaxObject(ctx, .parameter, true);
+}
+
+fn selectInnerArgument(ctx: *Context) Result {
+    return selectSyntaxObject(ctx, .parameter, false);
+}
+
+fn selectAroundComment(ctx: *Context) Result {
+    return selectSyntaxObject(ctx, .comment, true);
+}
+
+fn selectInnerComment(ctx: *Context) Result {
+    return selectSyntaxObject(ctx, .comment, false);
+}
+
+/// Move to the start of the next or previous function (]f, [f)
+fn moveToSyntaxObject(ctx: *Context, kind: TextObjects.SyntaxKind, forward: bool) Result {
+    const objects = ctx.editor.syntaxObjects(kind) catch return Result.err("No syntax tree for this file");
+    defer ctx.editor.allocator.free(objects);
+
+    const buffer = ctx.editor.buffer_manager.getActiveBuffer() orelse return Result.err("No active buffer");
+    const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
+
+    const found = TextObjects.syntaxObjectStart(buffer, objects, primary_sel.head, forward, ctx.editor.allocator) catch {
+        return Result.err("Failed to find text object");
+    };
+    const pos = found orelse return Result.err(syntaxObjectMissing(kind));
+
+    return applyMotion(ctx, primary_sel.moveTo(pos));
+}
+
+fn moveToNextFunction(ctx: *Context) Result {
+    return moveToSyntaxObject(ctx, .function, true);
+}
+
+fn moveToPrevFunction(ctx: *Context) Result {
+    return moveToSyntaxObject(ctx, .function, false);
+}
+
+/// Grow each selection to the syntax node around it (+)
+fn expandSelection(ctx: *Context) Result {
+    const grown = ctx.editor.expandSelection() catch |err| return syntaxSelectionFailed(err);
+    if (!grown) return Result.err("Selection already covers the file");
+    return showSyntaxSelection(ctx);
+}
+
+/// Shrink each selection back to where it was before growing, or to its first child node (-)
+fn shrinkSelection(ctx: *Context) Result {
+    const shrunk = ctx.editor.shrinkSelection() catch |err| return syntaxSelectionFailed(err);
+    if (!shrunk) return Result.err("Selection can't shrink further");
+    return showSyntaxSelection(ctx);
+}
+
+/// Select the next syntax node at the same level (]n)
+fn selectNextSibling(ctx: *Context) Result {
+    const moved = ctx.editor.selectSiblingNode(true) catch |err| return syntaxSelectionFailed(err);
+    if (!moved) return Result.err("No next node");
+    return showSyntaxSelection(ctx);
+}
+
+/// Select the previous syntax node at the same level ([n)
+fn selectPrevSibling(ctx: *Context) Result {
+    const moved = ctx.editor.selectSiblingNode(false) catch |err| return syntaxSelectionFailed(err);
+    if (!moved) return Result.err("No previous node");
+    return showSyntaxSelection(ctx);
+}
+
+/// Switch to select mode so the node just selected can be acted on (`+d`)
+fn showSyntaxSelection(ctx: *Context) Result {
+    if (ctx.editor.getMode() == .normal) {
+        ctx.editor.enterSelectMode() catch return Result.err("Failed to enter select mode");
+    }
+    return Result.ok();
+}
+
+fn syntaxSelectionFailed(err: anyerror) Result {
+    return switch (err) {
+        error.NoSyntaxTree => Result.err("No syntax tree for this file"),
+        else => Result.err("Failed to update selection"),
+    };
+}
+
 /// Set mark at cursor position (m command)
 ///
 /// Initiates mark-setting by activating the prompt system.
@@ -1806,11 +2035,6 @@ fn dedentLine(ctx: *Context) Result {
         return Result.err("No selection");
     };
 
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
-    };
-    defer ctx.editor.allocator.free(text);
-
     const range = primary.range();
 
     // Dedent all lines in selection (in reverse to maintain positions)
@@ -1818,28 +2042,18 @@ fn dedentLine(ctx: *Context) Result {
     while (current_line_signed >= @as(isize, @intCast(range.start.line))) : (current_line_signed -= 1) {
         const current_line: usize = @intCast(current_line_signed);
 
-        // Find line start in text
-        var line: usize = 0;
-        var offset: usize = 0;
-        while (offset < text.len and line < current_line) {
-            if (text[offset] == '\n') {
-                line += 1;
-            }
-            offset += 1;
-        }
-
         // Count leading spaces/tabs (up to 4 spaces or 1 tab)
         var spaces_to_remove: usize = 0;
-        var check_offset = offset;
-        while (check_offset < text.len and spaces_to_remove < 4 and text[check_offset] != '\n') {
-            if (text[check_offset] == ' ') {
+        var cursor = buffer.rope.cursorAt(buffer.rope.lineToByte(current_line));
+        while (spaces_to_remove < 4) {
+            const c = cursor.nextByte() orelse break;
+            if (c == ' ') {
                 spaces_to_remove += 1;
-                check_offset += 1;
-            } else if (text[check_offset] == '\t') {
+            } else if (c == '\t') {
                 spaces_to_remove = 1; // Remove one tab
                 break;
             } else {
-                break; // Non-whitespace, stop
+                break; // Non-whitespace or end of line, stop
             }
         }
 
@@ -1873,33 +2087,7 @@ fn selectAll(ctx: *Context) Result {
 
     const last_line = line_count - 1;
     const allocator = ctx.editor.allocator;
-
-    // Get length of last line
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
-    };
-    defer allocator.free(text);
-
-    var current_line: usize = 0;
-    var offset: usize = 0;
-    var line_start: usize = 0;
-
-    // Find the last line
-    while (offset < text.len) {
-        if (text[offset] == '\n') {
-            current_line += 1;
-            if (current_line == last_line + 1) break;
-            line_start = offset + 1;
-        }
-        offset += 1;
-    }
-
-    // Calculate last column
-    var last_col: usize = 0;
-    while (line_start < text.len and text[line_start] != '\n') {
-        last_col += 1;
-        line_start += 1;
-    }
+    const last_col = lineByteLength(buffer, last_line);
 
     // Create selection from start to end
     const selection = Cursor.Selection.init(
@@ -1922,37 +2110,7 @@ fn selectLine(ctx: *Context) Result {
 
     const cursor_pos = ctx.editor.getCursorPosition();
     const allocator = ctx.editor.allocator;
-
-    // Get line length
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
-    };
-    defer allocator.free(text);
-
-    var current_line: usize = 0;
-    var offset: usize = 0;
-    var line_start: usize = 0;
-
-    // Find the target line
-    while (offset < text.len and current_line < cursor_pos.line) {
-        if (text[offset] == '\n') {
-            current_line += 1;
-            line_start = offset + 1;
-        }
-        offset += 1;
-    }
-
-    if (current_line == cursor_pos.line) {
-        line_start = offset;
-    }
-
-    // Find line end
-    var line_end = line_start;
-    while (line_end < text.len and text[line_end] != '\n') {
-        line_end += 1;
-    }
-
-    const line_len = line_end - line_start;
+    const line_len = lineByteLength(buffer, cursor_pos.line);
 
     // Select from line start to line end
     const selection = Cursor.Selection.init(
@@ -1979,37 +2137,7 @@ fn extendToLineEnd(ctx: *Context) Result {
 
     const allocator = ctx.editor.allocator;
     const head = primary.head;
-
-    // Get line length
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
-    };
-    defer allocator.free(text);
-
-    var current_line: usize = 0;
-    var offset: usize = 0;
-    var line_start: usize = 0;
-
-    // Find the current line
-    while (offset < text.len and current_line < head.line) {
-        if (text[offset] == '\n') {
-            current_line += 1;
-            line_start = offset + 1;
-        }
-        offset += 1;
-    }
-
-    if (current_line == head.line) {
-        line_start = offset;
-    }
-
-    // Find line end
-    var line_end = line_start;
-    while (line_end < text.len and text[line_end] != '\n') {
-        line_end += 1;
-    }
-
-    const line_len = line_end - line_start;
+    const line_len = lineByteLength(buffer, head.line);
 
     // Extend selection to end of line
     const new_selection = primary.moveTo(Cursor.Position{ .line = head.line, .col = line_len });
@@ -2107,12 +2235,6 @@ fn sortLines(ctx: *Context) Result {
         const range = primary.range();
         const allocator = ctx.editor.allocator;
 
-        // Get buffer text
-        const text = buffer.getText() catch {
-            return Result.err("Failed to get buffer text");
-        };
-        defer allocator.free(text);
-
         // Extract lines in range
         var lines = std.ArrayList([]const u8).empty;
         defer {
@@ -2122,46 +2244,21 @@ fn sortLines(ctx: *Context) Result {
             lines.deinit(allocator);
         }
 
-        var current_line: usize = 0;
-        var offset: usize = 0;
-        var line_start: usize = 0;
-
-        // Find start of first line in selection
-        while (offset < text.len and current_line < range.start.line) {
-            if (text[offset] == '\n') {
-                current_line += 1;
-                line_start = offset + 1;
-            }
-            offset += 1;
-        }
-
-        line_start = offset;
-        const selection_start = line_start;
-
-        // Collect lines in range
-        while (offset < text.len and current_line <= range.end.line) {
-            if (text[offset] == '\n' or offset == text.len - 1) {
-                const line_end = if (text[offset] == '\n') offset else offset + 1;
-                const line_text = text[line_start..line_end];
-                const line_copy = allocator.dupe(u8, line_text) catch {
-                    return Result.err("Failed to copy line");
-                };
-                lines.append(allocator, line_copy) catch {
-                    return Result.err("Failed to add line");
-                };
+        const last_line = @min(range.end.line, buffer.lineCount() - 1);
+        if (range.start.line > last_line) return Result.ok();
+        const selection_start = buffer.rope.lineToByte(range.start.line);
+        const selection_end = buffer.rope.lineEnd(last_line);
 
-                if (text[offset] == '\n') {
-                    current_line += 1;
-                    line_start = offset + 1;
-                }
-            }
-            offset += 1;
+        for (range.start.line..last_line + 1) |line| {
+            const line_copy = buffer.rope.lineSlice(allocator, line) catch {
+                return Result.err("Failed to copy line");
+            };
+            lines.append(allocator, line_copy) catch {
+                allocator.free(line_copy);
+                return Result.err("Failed to add line");
+            };
         }
 
-        const selection_end = offset;
-
-        if (lines.items.len == 0) return Result.ok();
-
         // Sort lines
         std.mem.sort([]const u8, lines.items, {}, struct {
             fn lessThan(_: void, a: []const u8, b: []const u8) bool {
@@ -2185,10 +2282,10 @@ fn sortLines(ctx: *Context) Result {
         }
 
         // Replace selection with sorted text
-        buffer.rope.delete(selection_start, selection_end) catch {
+        buffer.delete(selection_start, selection_end) catch {
             return Result.err("Failed to delete selection");
         };
-        buffer.rope.insert(selection_start, sorted.items) catch {
+        buffer.insert(selection_start, sorted.items) catch {
             return Result.err("Failed to insert sorted text");
         };
 
@@ -2214,12 +2311,6 @@ fn uniqueLines(ctx: *Context) Result {
         const range = primary.range();
         const allocator = ctx.editor.allocator;
 
-        // Get buffer text
-        const text = buffer.getText() catch {
-            return Result.err("Failed to get buffer text");
-        };
-        defer allocator.free(text);
-
         // Extract and deduplicate lines
         var seen = std.StringHashMap(void).init(allocator);
         defer seen.deinit();
@@ -2232,49 +2323,29 @@ fn uniqueLines(ctx: *Context) Result {
             unique.deinit(allocator);
         }
 
-        var current_line: usize = 0;
-        var offset: usize = 0;
-        var line_start: usize = 0;
-
-        // Find start of selection
-        while (offset < text.len and current_line < range.start.line) {
-            if (text[offset] == '\n') {
-                current_line += 1;
-                line_start = offset + 1;
-            }
-            offset += 1;
-        }
-
-        line_start = offset;
-        const selection_start = line_start;
+        const last_line = @min(range.end.line, buffer.lineCount() - 1);
+        if (range.start.line > last_line) return Result.ok();
+        const selection_start = buffer.rope.lineToByte(range.start.line);
+        const selection_end = buffer.rope.lineEnd(last_line);
 
         // Collect unique lines
-        while (offset < text.len and current_line <= range.end.line) {
-            if (text[offset] == '\n' or offset == text.len - 1) {
-                const line_end = if (text[offset] == '\n') offset else offset + 1;
-                const line_text = text[line_start..line_end];
-
-                // Check if we've seen this line
-                if (seen.get(line_text) == null) {
-                    const line_copy = allocator.dupe(u8, line_text) catch {
-                        return Result.err("Failed to copy line");
-                    };
-                    unique.append(allocator, line_copy) catch {
-                        return Result.err("Failed to add line");
-                    };
-                    seen.put(line_text, {}) catch {};
-                }
+        for (range.start.line..last_line + 1) |line| {
+            const line_copy = buffer.rope.lineSlice(allocator, line) catch {
+                return Result.err("Failed to copy line");
+            };
 
-                if (text[offset] == '\n') {
-                    current_line += 1;
-                    line_start = offset + 1;
-                }
+            // Check if we've seen this line
+            if (seen.get(line_copy) != null) {
+                allocator.free(line_copy);
+                continue;
             }
-            offset += 1;
+            unique.append(allocator, line_copy) catch {
+                allocator.free(line_copy);
+                return Result.err("Failed to add line");
+            };
+            seen.put(line_copy, {}) catch {};
         }
 
-        const selection_end = offset;
-
         if (unique.items.len == 0) return Result.ok();
 
         // Build unique text
@@ -2293,10 +2364,10 @@ fn uniqueLines(ctx: *Context) Result {
         }
 
         // Replace selection
-        buffer.rope.delete(selection_start, selection_end) catch {
+        buffer.delete(selection_start, selection_end) catch {
             return Result.err("Failed to delete selection");
         };
-        buffer.rope.insert(selection_start, result_text.items) catch {
+        buffer.insert(selection_start, result_text.items) catch {
             return Result.err("Failed to insert unique text");
         };
 
@@ -2311,6 +2382,30 @@ fn uniqueLines(ctx: *Context) Result {
     return Result.err("No active buffer");
 }
 
+/// Pipe each selection through a shell command, replacing it with the output (|)
+fn shellPipe(ctx: *Context) Result {
+    return promptShell(ctx, .replace);
+}
+
+/// Insert a shell command's output before each selection (!)
+fn shellInsert(ctx: *Context) Result {
+    return promptShell(ctx, .insert);
+}
+
+/// Append a shell command's output after each selection (Alt-!)
+fn shellAppend(ctx: *Context) Result {
+    return promptShell(ctx, .append);
+}
+
+fn promptShell(ctx: *Context, mode: Shell.PipeMode) Result {
+    _ = ctx.editor.buffer_manager.getActiveBuffer() orelse return Result.err("No active buffer");
+
+    // Show prompt for the command - it runs in completeShellPipe
+    ctx.editor.pending_command = .{ .shell_pipe = mode };
+    ctx.editor.prompt.show(mode.prompt(), .text);
+    return Result.ok();
+}
+
 /// Repeat last action (like vim's dot command)
 fn repeatLastAction(ctx: *Context) Result {
     const last_action = ctx.editor.repeat_system.getLastAction();
@@ -2328,12 +2423,81 @@ fn repeatLastAction(ctx: *Context) Result {
     ctx.editor.repeat_system.startReplay();
     defer ctx.editor.repeat_system.endReplay();
 
-    // Execute the command
-    const result = command.?.handler(ctx);
+    // An operator runs its motion again from the cursor (`dw` deletes the next word)
+    if (action.operator != null) {
+        ctx.editor.replayOperator(action, ctx.count orelse action.count) catch {
+            return Result.err("Failed to repeat operator");
+        };
+        return Result.ok();
+    }
+
+    // Execute the command with its original count, unless `.` was given a new one
+    var replay = Context{ .editor = ctx.editor, .count = ctx.count orelse action.count };
+    const result = command.?.run(&replay);
 
     return result;
 }
 
+// === Operators ===
+
+fn operatorDelete(ctx: *Context) Result {
+    return beginOperator(ctx, .delete);
+}
+
+fn operatorChange(ctx: *Context) Result {
+    return beginOperator(ctx, .change);
+}
+
+fn operatorYank(ctx: *Context) Result {
+    return beginOperator(ctx, .yank);
+}
+
+fn operatorIndent(ctx: *Context) Result {
+    return beginOperator(ctx, .indent);
+}
+
+fn operatorDedent(ctx: *Context) Result {
+    return beginOperator(ctx, .dedent);
+}
+
+fn operatorLowercase(ctx: *Context) Result {
+    return beginOperator(ctx, .lowercase);
+}
+
+fn operatorUppercase(ctx: *Context) Result {
+    return beginOperator(ctx, .uppercase);
+}
+
+fn operatorReindent(ctx: *Context) Result {
+    return beginOperator(ctx, .reindent);
+}
+
+/// Wait for the operator's motion, or apply it to the selection in select mode
+fn beginOperator(ctx: *Context, operator: Operator.Operator) Result {
+    ctx.editor.beginOperator(operator, ctx.count) catch {
+        return Result.err("Failed to apply operator");
+    };
+    return Result.ok();
+}
+
+/// The current line and count - 1 more, after an operator typed twice (`dd`, `3>>`, `gUU`)
+fn operatorLines(ctx: *Context) Result {
+    const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
+    const primary_sel = ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No selection");
+    const last_line = buffer.lineCount() -| 1;
+    const line = @min(primary_sel.head.line + ctx.getCount() - 1, last_line);
+    return applyMotion(ctx, primary_sel.moveTo(.{ .line = line, .col = primary_sel.head.col }));
+}
+
+/// Pick the register for the next yank, delete or paste (`"a`)
+///
+/// Completion occurs in completeSelectRegister once the register name is typed.
+fn selectRegister(ctx: *Context) Result {
+    ctx.editor.pending_command = PendingCommand.select_register;
+    ctx.editor.prompt.show("Register:", .character);
+    return Result.ok();
+}
+
 /// Switch to next buffer
 fn nextBuffer(ctx: *Context) Result {
     const current_id = ctx.editor.buffer_manager.active_buffer_id orelse {
@@ -2540,6 +2704,41 @@ fn centerCursor(ctx: *Context) Result {
     return Result.ok();
 }
 
+/// Open the closed fold on the cursor's line, or close the innermost one around it (za)
+fn toggleFold(ctx: *Context) Result {
+    const toggled = ctx.editor.toggleFold() catch return Result.err("Failed to update folds");
+    if (!toggled) return Result.err("No fold here");
+    return Result.ok();
+}
+
+/// Close the innermost open fold around the cursor (zc)
+fn closeFold(ctx: *Context) Result {
+    const closed = ctx.editor.closeFold() catch return Result.err("Failed to update folds");
+    if (!closed) return Result.err("No open fold here");
+    return Result.ok();
+}
+
+/// Open the closed fold on the cursor's line (zo)
+fn openFold(ctx: *Context) Result {
+    const opened = ctx.editor.openFold() catch return Result.err("Failed to update folds");
+    if (!opened) return Result.err("No closed fold here");
+    return Result.ok();
+}
+
+/// Open every fold in the buffer (zR)
+fn openAllFolds(ctx: *Context) Result {
+    const opened = ctx.editor.openAllFolds() catch return Result.err("Failed to update folds");
+    if (!opened) return Result.err("No folds in this buffer");
+    return Result.ok();
+}
+
+/// Close every fold in the buffer (zM)
+fn closeAllFolds(ctx: *Context) Result {
+    const closed = ctx.editor.closeAllFolds() catch return Result.err("Failed to update folds");
+    if (!closed) return Result.err("No folds in this buffer");
+    return Result.ok();
+}
+
 /// Scroll viewport up
 fn scrollUp(ctx: *Context) Result {
     if (ctx.editor.scroll_offset > 0) {
@@ -2560,7 +2759,7 @@ fn scrollDown(ctx: *Context) Result {
     const max_scroll = if (total_lines > visible_lines) total_lines - visible_lines else 0;
 
     if (ctx.editor.scroll_offset < max_scroll) {
-        ctx.editor.scroll_offset += 1;
+        ctx.editor.scroll_offset = ctx.editor.lineAtRow(ctx.editor.scroll_offset, 1);
     }
     return Result.ok();
 }
@@ -2589,7 +2788,7 @@ fn scrollPageDown(ctx: *Context) Result {
     const visible_lines = viewport_height -| 2;
     const max_scroll = if (total_lines > visible_lines) total_lines - visible_lines else 0;
 
-    ctx.editor.scroll_offset += visible_lines;
+    ctx.editor.scroll_offset = ctx.editor.lineAtRow(ctx.editor.scroll_offset, visible_lines);
     ctx.editor.scroll_offset = @min(ctx.editor.scroll_offset, max_scroll);
 
     return Result.ok();
@@ -2621,7 +2820,7 @@ fn scrollHalfPageDown(ctx: *Context) Result {
     const max_scroll = if (total_lines > visible_lines) total_lines - visible_lines else 0;
     const half_page = visible_lines / 2;
 
-    ctx.editor.scroll_offset += half_page;
+    ctx.editor.scroll_offset = ctx.editor.lineAtRow(ctx.editor.scroll_offset, half_page);
     ctx.editor.scroll_offset = @min(ctx.editor.scroll_offset, max_scroll);
 
     return Result.ok();
@@ -2640,34 +2839,16 @@ fn toggleLineComment(ctx: *Context) Result {
         return Result.err("No selection");
     };
 
-    const text = buffer.getText() catch {
-        return Result.err("Failed to get buffer text");
-    };
-    defer ctx.editor.allocator.free(text);
-
     const range = primary.range();
+    const rope = &buffer.rope;
 
     // Check if all lines are commented
     var all_commented = true;
     var current_line = range.start.line;
     while (current_line <= range.end.line) : (current_line += 1) {
-        // Find line start in text
-        var line: usize = 0;
-        var offset: usize = 0;
-        while (offset < text.len and line < current_line) {
-            if (text[offset] == '\n') {
-                line += 1;
-            }
-            offset += 1;
-        }
-
-        // Skip leading whitespace
-        while (offset < text.len and (text[offset] == ' ' or text[offset] == '\t')) {
-            offset += 1;
-        }
-
-        // Check for //
-        if (offset + 2 > text.len or text[offset] != '/' or text[offset + 1] != '/') {
+        // Check for // after the leading whitespace
+        var cursor = rope.cursorAt(rope.lineToByte(current_line) + leadingBlanks(rope, current_line));
+        if (cursor.nextByte() != '/' or cursor.nextByte() != '/') {
             all_commented = false;
             break;
         }
@@ -2680,26 +2861,13 @@ fn toggleLineComment(ctx: *Context) Result {
         while (current_line_signed >= @as(isize, @intCast(range.start.line))) : (current_line_signed -= 1) {
             const line_num: usize = @intCast(current_line_signed);
 
-            // Find line start
-            var line: usize = 0;
-            var offset: usize = 0;
-            while (offset < text.len and line < line_num) {
-                if (text[offset] == '\n') {
-                    line += 1;
-                }
-                offset += 1;
-            }
-
             // Find comment marker position (skip whitespace)
-            var col: usize = 0;
-            while (offset < text.len and (text[offset] == ' ' or text[offset] == '\t')) {
-                offset += 1;
-                col += 1;
-            }
+            const col = leadingBlanks(rope, line_num);
 
             // Remove // and optional space
             var chars_to_remove: usize = 2; // "//"
-            if (offset + 2 < text.len and text[offset + 2] == ' ') {
+            var cursor = rope.cursorAt(rope.lineToByte(line_num) + col + 2);
+            if (cursor.peekByte() == ' ') {
                 chars_to_remove = 3; // "// "
             }
 
@@ -2713,22 +2881,8 @@ fn toggleLineComment(ctx: *Context) Result {
         // Add comments
         current_line = range.start.line;
         while (current_line <= range.end.line) : (current_line += 1) {
-            // Find line start
-            var line: usize = 0;
-            var offset: usize = 0;
-            while (offset < text.len and line < current_line) {
-                if (text[offset] == '\n') {
-                    line += 1;
-                }
-                offset += 1;
-            }
-
             // Find first non-whitespace position
-            var col: usize = 0;
-            while (offset < text.len and (text[offset] == ' ' or text[offset] == '\t')) {
-                offset += 1;
-                col += 1;
-            }
+            const col = leadingBlanks(rope, current_line);
 
             // Insert // at first non-whitespace position
             const insert_pos = Cursor.Position{ .line = current_line, .col = col };
@@ -2743,25 +2897,22 @@ fn toggleLineComment(ctx: *Context) Result {
 
 /// Redo last undone operation
 fn redo(ctx: *Context) Result {
-    if (!ctx.editor.undo_history.canRedo()) {
+    // History lives on the buffer shown in the active window
+    const buffer = ctx.editor.activeWindowBuffer() orelse {
+        return Result.err("No active buffer");
+    };
+
+    if (!buffer.undo_history.canRedo()) {
         return Result.err("Nothing to redo");
     }
 
     // Get redo group
-    const group = ctx.editor.undo_history.getRedo() orelse {
+    const group = buffer.undo_history.getRedo() orelse {
         return Result.err("Redo failed");
     };
 
-    // Get mutable active buffer
-    const buffer_id = ctx.editor.buffer_manager.active_buffer_id orelse {
-        return Result.err("No active buffer");
-    };
-    const buffer = ctx.editor.buffer_manager.getBufferMut(buffer_id) orelse {
-        return Result.err("No active buffer");
-    };
-
     // Apply redo operations to buffer
-    Undo.UndoHistory.applyRedo(group, &buffer.rope, ctx.editor.allocator) catch {
+    Undo.UndoHistory.applyRedo(group, buffer) catch {
         return Result.err("Failed to apply redo operations");
     };
 
@@ -2779,6 +2930,40 @@ fn redo(ctx: *Context) Result {
     return Result.ok();
 }
 
+/// Jump to the chronologically previous undo state (g-)
+fn undoEarlier(ctx: *Context) Result {
+    const buffer = ctx.editor.activeWindowBuffer() orelse return Result.err("No active buffer");
+    const seq = buffer.undo_history.earlierSeq() orelse return Result.err("Already at oldest change");
+
+    ctx.editor.undoJumpTo(seq) catch {
+        return Result.err("Failed to move through undo tree");
+    };
+    return Result.ok();
+}
+
+/// Jump to the chronologically next undo state (g+)
+fn undoLater(ctx: *Context) Result {
+    const buffer = ctx.editor.activeWindowBuffer() orelse return Result.err("No active buffer");
+    const seq = buffer.undo_history.laterSeq() orelse return Result.err("Already at newest change");
+
+    ctx.editor.undoJumpTo(seq) catch {
+        return Result.err("Failed to move through undo tree");
+    };
+    return Result.ok();
+}
+
+/// Toggle undo tree popup
+fn toggleUndoTree(ctx: *Context) Result {
+    if (ctx.editor.undo_tree_visible) {
+        ctx.editor.closeUndoTree(true) catch {};
+        return Result.ok();
+    }
+    ctx.editor.openUndoTree() catch {
+        return Result.err("No active buffer");
+    };
+    return Result.ok();
+}
+
 // === Visual/Display Commands ===
 
 /// Toggle syntax highlighting
@@ -2935,22 +3120,13 @@ fn replaceNext(ctx: *Context) Result {
     if (ctx.editor.buffer_manager.active_buffer_id) |id| {
         const buffer = ctx.editor.buffer_manager.getBufferMut(id) orelse return Result.err("No active buffer");
 
-        // Get buffer text
-        const text = buffer.getText() catch {
-            return Result.err("Failed to get buffer text");
-        };
-        defer ctx.editor.allocator.free(text);
-
         // Get current cursor position
         const cursor_pos = ctx.editor.getCursorPosition();
 
         // Find next match
-        const match = ctx.editor.search.findNext(text, cursor_pos);
-        if (match == null) {
+        const m = ctx.editor.search.findNextInRope(&buffer.rope, cursor_pos) orelse {
             return Result.err("No more matches");
-        }
-
-        const m = match.?;
+        };
 
         // Convert positions to byte offsets
         const start_offset = Actions.positionToByteOffset(buffer, m.start) catch {
@@ -2960,14 +3136,22 @@ fn replaceNext(ctx: *Context) Result {
             return Result.err("Invalid match position");
         };
 
+        // Expand capture references before the match text is removed (matches stay within a line)
+        const line_text = ctx.editor.search.lineText(buffer.rope.lineAt(m.start.line)) catch {
+            return Result.err("Failed to build replacement");
+        };
+        const replace_text = ctx.editor.search.expandReplacement(ctx.editor.allocator, line_text, m.start.col) catch {
+            return Result.err("Failed to build replacement");
+        };
+        defer ctx.editor.allocator.free(replace_text);
+
         // Delete the match
-        buffer.rope.delete(start_offset, end_offset) catch {
+        buffer.delete(start_offset, end_offset) catch {
             return Result.err("Failed to delete match");
         };
 
         // Insert replacement text
-        const replace_text = ctx.editor.search.getReplaceText();
-        buffer.rope.insert(start_offset, replace_text) catch {
+        buffer.insert(start_offset, replace_text) catch {
             return Result.err("Failed to insert replacement");
         };
 
@@ -2995,22 +3179,48 @@ fn replaceAll(ctx: *Context) Result {
     if (ctx.editor.buffer_manager.active_buffer_id) |id| {
         const buffer = ctx.editor.buffer_manager.getBufferMut(id) orelse return Result.err("No active buffer");
 
-        // Get buffer text
-        const text = buffer.getText() catch {
-            return Result.err("Failed to get buffer text");
-        };
-        defer ctx.editor.allocator.free(text);
-
         // Find all matches
-        const matches = ctx.editor.search.findAllMatches(text, ctx.editor.allocator) catch {
+        const allocator = ctx.editor.allocator;
+        const matches = ctx.editor.search.findAllInRope(&buffer.rope, 0, buffer.lineCount(), allocator) catch {
             return Result.err("Failed to find matches");
         };
-        defer ctx.editor.allocator.free(matches);
+        defer allocator.free(matches);
 
+        // Replacing only the matches found before giving up would be a partial edit
+        if (ctx.editor.search.takeMatchLimitHit()) {
+            return Result.err(EditorModule.search_limit_msg);
+        }
         if (matches.len == 0) {
             return Result.err("No matches found");
         }
 
+        // Compile the pattern once for every replacement
+        var re: ?Regex.Regex = if (ctx.editor.search.options.regex)
+            ctx.editor.search.compileRegex(allocator) catch return Result.err("Invalid regex")
+        else
+            null;
+        defer if (re) |*compiled| compiled.deinit();
+
+        // Expand every replacement against the unedited lines first; a regex's
+        // context (a \b after the match) may be changed by the one after it
+        const replacements = allocator.alloc([]u8, matches.len) catch {
+            return Result.err("Failed to build replacements");
+        };
+        var expanded: usize = 0;
+        defer {
+            for (replacements[0..expanded]) |replacement| allocator.free(replacement);
+            allocator.free(replacements);
+        }
+        for (matches) |m| {
+            const line_text = ctx.editor.search.lineText(buffer.rope.lineAt(m.start.line)) catch {
+                return Result.err("Failed to build replacements");
+            };
+            replacements[expanded] = ctx.editor.search.expandReplacementWith(allocator, if (re) |*compiled| compiled else null, line_text, m.start.col) catch {
+                return Result.err("Failed to build replacements");
+            };
+            expanded += 1;
+        }
+
         ctx.editor.search.replacements_made = 0;
 
         // Replace in reverse order to maintain position validity
@@ -3018,17 +3228,17 @@ fn replaceAll(ctx: *Context) Result {
         while (i > 0) {
             i -= 1;
             const m = matches[i];
+            const replace_text = replacements[i];
 
             // Convert positions to byte offsets
             const start_offset = Actions.positionToByteOffset(buffer, m.start) catch continue;
             const end_offset = Actions.positionToByteOffset(buffer, m.end) catch continue;
 
             // Delete the match
-            buffer.rope.delete(start_offset, end_offset) catch continue;
+            buffer.delete(start_offset, end_offset) catch continue;
 
             // Insert replacement text
-            const replace_text = ctx.editor.search.getReplaceText();
-            buffer.rope.insert(start_offset, replace_text) catch continue;
+            buffer.insert(start_offset, replace_text) catch continue;
 
             ctx.editor.search.replacements_made += 1;
         }
@@ -3046,6 +3256,14 @@ fn replaceAll(ctx: *Context) Result {
     return Result.err("No active buffer");
 }
 
+/// Toggle regex interpretation of the search query
+fn toggleSearchRegex(ctx: *Context) Result {
+    ctx.editor.search.toggleRegex();
+    const msg = if (ctx.editor.search.options.regex) "Regex search enabled" else "Regex search disabled";
+    ctx.editor.messages.add(msg, .info) catch {};
+    return Result.ok();
+}
+
 // === Macro Commands ===
 
 /// Start recording macro (q command)
@@ -3075,6 +3293,7 @@ fn stopMacroRecording(ctx: *Context) Result {
 
 /// Play macro from register
 fn playMacro(ctx: *Context) Result {
+    ctx.editor.macro_recorder.setPlaybackCount(ctx.getCount());
     ctx.editor.pending_command = .play_macro;
     ctx.editor.prompt.show("Play macro from register:", .character);
     return Result.ok();
@@ -3125,7 +3344,7 @@ fn lspTriggerCompletion(ctx: *Context) Result {
     ctx.editor.completion_list.show(cursor.line, cursor.col);
 
     // If LSP client is available and initialized, trigger completion request
-    if (ctx.editor.lsp_client) |*client| {
+    if (ctx.editor.activeLspClient()) |client| {
         if (client.isReady()) {
             // Get file URI
             const filepath = buffer.metadata.filepath orelse return Result.err("Buffer has no filepath");
@@ -3180,36 +3399,12 @@ fn lspCompletionNext(ctx: *Context) Result {
 
 /// Convert cursor position (line, col in characters) to byte offset
 /// Handles UTF-8 multi-byte characters correctly
-fn positionToByteOffset(rope: *const Rope, pos: Cursor.Position, allocator: std.mem.Allocator) !usize {
-    const text = try rope.toString(allocator);
-    defer allocator.free(text);
-
-    var byte_offset: usize = 0;
-    var current_line: usize = 0;
-    var current_col: usize = 0;
-
-    while (byte_offset < text.len) {
-        if (current_line == pos.line and current_col == pos.col) {
-            return byte_offset;
-        }
-
-        const byte = text[byte_offset];
-        if (byte == '\n') {
-            current_line += 1;
-            current_col = 0;
-            byte_offset += 1;
-        } else {
-            // UTF-8: skip continuation bytes (10xxxxxx)
-            // Only increment column for character start bytes
-            if ((byte & 0b11000000) != 0b10000000) {
-                current_col += 1;
-            }
-            byte_offset += 1;
-        }
-    }
+fn positionToByteOffset(rope: *const Rope, pos: Cursor.Position) usize {
+    if (pos.line >= rope.lineCount()) return rope.len();
 
-    // Position at or beyond end of file
-    return byte_offset;
+    const line_start = rope.lineToByte(pos.line);
+    const offset = rope.charToByte(rope.byteToChar(line_start) + pos.col);
+    return @min(offset, rope.lineEnd(pos.line));
 }
 
 /// Count UTF-8 characters (codepoints) in a byte slice
@@ -3237,9 +3432,7 @@ fn lspAcceptCompletion(ctx: *Context) Result {
     const cursor = (ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No cursor")).head;
 
     // Convert cursor position to byte offset (handles UTF-8 correctly)
-    const byte_offset = positionToByteOffset(&buffer.rope, cursor, ctx.editor.allocator) catch {
-        return Result.err("Failed to convert position to byte offset");
-    };
+    const byte_offset = positionToByteOffset(&buffer.rope, cursor);
 
     // Insert completion text
     buffer.insert(byte_offset, text_to_insert) catch {
@@ -3344,7 +3537,7 @@ fn lspDefinitionCallback(ctx: ?*anyopaque, result_json: []const u8) !void {
 /// Go to definition of symbol under cursor
 fn lspGotoDefinition(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
-    const client = &(ctx.editor.lsp_client orelse return Result.err("LSP not initialized"));
+    const client = ctx.editor.activeLspClient() orelse return Result.err("LSP not initialized");
 
     // Get cursor position
     const cursor = (ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No cursor")).head;
@@ -3373,7 +3566,7 @@ fn lspGotoDefinition(ctx: *Context) Result {
 /// Show hover information for symbol under cursor
 fn lspShowHover(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
-    const client = &(ctx.editor.lsp_client orelse return Result.err("LSP not initialized"));
+    const client = ctx.editor.activeLspClient() orelse return Result.err("LSP not initialized");
 
     // Get cursor position
     const cursor = (ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No cursor")).head;
@@ -3442,38 +3635,34 @@ fn lspReferencesCallback(ctx: ?*anyopaque, result_json: []const u8) !void {
     }
 }
 
+/// Spaces and tabs at the start of a line
+fn leadingBlanks(rope: *const Rope, line: usize) usize {
+    var cursor = rope.cursorAt(rope.lineToByte(line));
+    var count: usize = 0;
+    while (cursor.nextByte()) |c| : (count += 1) {
+        if (c != ' ' and c != '\t') break;
+    }
+    return count;
+}
+
+/// Length of a line in bytes (columns as positionToByteOffset counts them)
+fn lineByteLength(buffer: *const Buffer.Buffer, line: usize) usize {
+    return buffer.rope.lineEnd(line) - buffer.rope.lineToByte(line);
+}
+
 /// Convert line/character position to byte offset in buffer
 fn lineCharToByteOffset(buffer: *const Buffer.Buffer, line: u32, character: u32) !usize {
-    const offset: usize = 0;
-    var current_line: u32 = 0;
-
-    // Get buffer content
-    const content = try buffer.rope.toString(buffer.allocator);
-    defer buffer.allocator.free(content);
-
-    var i: usize = 0;
-    while (i < content.len) {
-        if (current_line == line) {
-            // We're on the target line, advance by character count
-            var char_count: u32 = 0;
-            while (i < content.len and char_count < character) {
-                const cp_len = std.unicode.utf8ByteSequenceLength(content[i]) catch 1;
-                i += cp_len;
-                char_count += 1;
-                if (i < content.len and content[i - 1] == '\n') break;
-            }
-            return i;
-        }
-
-        // Skip to next line
-        while (i < content.len and content[i] != '\n') : (i += 1) {}
-        if (i < content.len and content[i] == '\n') {
-            i += 1;
-            current_line += 1;
-        }
+    const rope = &buffer.rope;
+    if (line >= rope.lineCount()) return rope.len(); // LSP puts the end of the document past the last line
+
+    // Advance by character count, stopping at the end of the line
+    const line_end = rope.lineEnd(line);
+    var cursor = rope.cursorAt(rope.lineToByte(line));
+    var char_count: u32 = 0;
+    while (char_count < character and cursor.offset() < line_end) : (char_count += 1) {
+        _ = cursor.nextChar() orelse break;
     }
-
-    return offset;
+    return @min(cursor.offset(), line_end);
 }
 
 /// Apply text edits to buffer (used by formatting)
@@ -3517,12 +3706,12 @@ fn applyTextEdits(editor: *Editor, buffer_id: Buffer.BufferId, edits: []const @i
 
         // Delete old range (if not empty)
         if (end_offset > start_offset) {
-            try buffer.rope.delete(start_offset, end_offset);
+            try buffer.delete(start_offset, end_offset);
         }
 
         // Insert new text
         if (edit.newText.len > 0) {
-            try buffer.rope.insert(start_offset, edit.newText);
+            try buffer.insert(start_offset, edit.newText);
         }
     }
 
@@ -3580,7 +3769,7 @@ fn lspFormattingCallback(ctx: ?*anyopaque, result_json: []const u8) !void {
 /// Find all references to symbol under cursor
 fn lspFindReferences(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
-    const client = &(ctx.editor.lsp_client orelse return Result.err("LSP not initialized"));
+    const client = ctx.editor.activeLspClient() orelse return Result.err("LSP not initialized");
 
     // Get cursor position
     const cursor = (ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No cursor")).head;
@@ -3610,7 +3799,7 @@ fn lspFindReferences(ctx: *Context) Result {
 /// Format document using LSP
 fn lspFormatDocument(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
-    const client = &(ctx.editor.lsp_client orelse return Result.err("LSP not initialized"));
+    const client = ctx.editor.activeLspClient() orelse return Result.err("LSP not initialized");
 
     // Get file URI
     const filepath = buffer.metadata.filepath orelse return Result.err("Buffer has no file path");
@@ -3691,7 +3880,7 @@ fn lspCodeActionsCallback(ctx: ?*anyopaque, result_json: []const u8) !void {
 /// Request code actions at cursor position
 fn lspGetCodeActions(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
-    const client = &(ctx.editor.lsp_client orelse return Result.err("LSP not initialized"));
+    const client = ctx.editor.activeLspClient() orelse return Result.err("LSP not initialized");
 
     // Get cursor position
     const cursor = (ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No cursor")).head;
@@ -3784,7 +3973,7 @@ fn lspDocumentSymbolsCallback(ctx: ?*anyopaque, result_json: []const u8) !void {
 /// Request document symbols (outline)
 fn lspGetDocumentSymbols(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
-    const client = &(ctx.editor.lsp_client orelse return Result.err("LSP not initialized"));
+    const client = ctx.editor.activeLspClient() orelse return Result.err("LSP not initialized");
 
     // Get file URI
     const filepath = buffer.metadata.filepath orelse return Result.err("Buffer has no file path");
@@ -3861,7 +4050,7 @@ fn lspSignatureHelpCallback(ctx: ?*anyopaque, result_json: []const u8) !void {
 /// Request signature help at cursor (Stream B)
 fn lspGetSignatureHelp(ctx: *Context) Result {
     const buffer = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
-    const client = &(ctx.editor.lsp_client orelse return Result.err("LSP not initialized"));
+    const client = ctx.editor.activeLspClient() orelse return Result.err("LSP not initialized");
 
     const cursor = (ctx.editor.selections.primary(ctx.editor.allocator) orelse return Result.err("No cursor")).head;
 
@@ -3957,11 +4146,11 @@ pub fn lspRenameCallback(ctx: ?*anyopaque, result_json: []const u8) !void {
             const end_offset = lineCharToByteOffset(buffer, edit.range.end.line, edit.range.end.character) catch continue;
 
             if (end_offset > start_offset) {
-                buffer.rope.delete(start_offset, end_offset) catch continue;
+                buffer.delete(start_offset, end_offset) catch continue;
             }
 
             if (edit.newText.len > 0) {
-                buffer.rope.insert(start_offset, edit.newText) catch continue;
+                buffer.insert(start_offset, edit.newText) catch continue;
             }
 
             total_edits += 1;
@@ -3983,7 +4172,7 @@ pub fn lspRenameCallback(ctx: ?*anyopaque, result_json: []const u8) !void {
 fn lspRenameSymbol(ctx: *Context) Result {
     // Validate preconditions
     _ = ctx.editor.getActiveBuffer() orelse return Result.err("No active buffer");
-    _ = ctx.editor.lsp_client orelse return Result.err("LSP not initialized");
+    _ = ctx.editor.activeLspClient() orelse return Result.err("LSP not initialized");
 
     // Show prompt for new name - actual rename happens in completeLspRename
     ctx.editor.pending_command = .lsp_rename;
@@ -4014,6 +4203,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Move cursor up",
         .handler = moveUp,
         .category = .motion,
+        .target = .linewise,
     });
 
     try registry.register(.{
@@ -4021,6 +4211,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Move cursor down",
         .handler = moveDown,
         .category = .motion,
+        .target = .linewise,
     });
 
     // Motion commands - word
@@ -4043,6 +4234,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Move to end of word",
         .handler = moveWordEnd,
         .category = .motion,
+        .target = .inclusive,
     });
 
     // Motion commands - line
@@ -4066,6 +4258,8 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Move to start of file",
         .handler = moveFileStart,
         .category = .motion,
+        .count = .handler,
+        .target = .linewise,
     });
 
     try registry.register(.{
@@ -4073,6 +4267,8 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Move to end of file",
         .handler = moveFileEnd,
         .category = .motion,
+        .count = .handler,
+        .target = .linewise,
     });
 
     try registry.register(.{
@@ -4080,6 +4276,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Jump to matching bracket/brace/paren (%)",
         .handler = jumpToMatchingBracket,
         .category = .motion,
+        .target = .inclusive,
     });
 
     // Find/till character motions
@@ -4088,6 +4285,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Find character forward on line (f)",
         .handler = findCharForward,
         .category = .motion,
+        .target = .inclusive,
     });
 
     try registry.register(.{
@@ -4095,6 +4293,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Find character backward on line (F)",
         .handler = findCharBackward,
         .category = .motion,
+        .target = .inclusive,
     });
 
     try registry.register(.{
@@ -4102,6 +4301,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Till character forward on line (t)",
         .handler = tillCharForward,
         .category = .motion,
+        .target = .inclusive,
     });
 
     try registry.register(.{
@@ -4109,6 +4309,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Till character backward on line (T)",
         .handler = tillCharBackward,
         .category = .motion,
+        .target = .inclusive,
     });
 
     try registry.register(.{
@@ -4116,6 +4317,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Repeat last find/till (;)",
         .handler = repeatFindTill,
         .category = .motion,
+        .target = .inclusive,
     });
 
     try registry.register(.{
@@ -4123,6 +4325,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Reverse last find/till (,)",
         .handler = reverseFindTill,
         .category = .motion,
+        .target = .inclusive,
     });
 
     // Enhanced text objects
@@ -4131,6 +4334,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select paragraph (around - ap)",
         .handler = selectParagraphAround,
         .category = .selection,
+        .target = .object_lines,
     });
 
     try registry.register(.{
@@ -4138,6 +4342,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select paragraph (inside - ip)",
         .handler = selectParagraphInside,
         .category = .selection,
+        .target = .object_lines,
     });
 
     try registry.register(.{
@@ -4145,6 +4350,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select indent level (around - ai)",
         .handler = selectIndentAround,
         .category = .selection,
+        .target = .object_lines,
     });
 
     try registry.register(.{
@@ -4152,6 +4358,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select indent level (inside - ii)",
         .handler = selectIndentInside,
         .category = .selection,
+        .target = .object_lines,
     });
 
     try registry.register(.{
@@ -4159,6 +4366,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select line (around - al)",
         .handler = selectLineAround,
         .category = .selection,
+        .target = .object_lines,
     });
 
     try registry.register(.{
@@ -4166,6 +4374,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select line (inside - il)",
         .handler = selectLineInside,
         .category = .selection,
+        .target = .object,
     });
 
     try registry.register(.{
@@ -4173,6 +4382,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select entire buffer (around - ab)",
         .handler = selectBufferAround,
         .category = .selection,
+        .target = .object_lines,
     });
 
     try registry.register(.{
@@ -4180,6 +4390,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select entire buffer (inside - ib)",
         .handler = selectBufferInside,
         .category = .selection,
+        .target = .object_lines,
     });
 
     // Mode commands
@@ -4254,11 +4465,20 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .category = .edit,
     });
 
+    try registry.register(.{
+        .name = "replace_char",
+        .description = "Replace character at cursor (r)",
+        .handler = replaceChar,
+        .category = .edit,
+        .count = .handler,
+    });
+
     try registry.register(.{
         .name = "delete_line",
         .description = "Delete current line (dd)",
         .handler = deleteLine,
         .category = .edit,
+        .count = .handler,
     });
 
     try registry.register(.{
@@ -4274,6 +4494,15 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select word under cursor (iw)",
         .handler = selectCurrentWord,
         .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_around_word",
+        .description = "Select word and the whitespace after it (aw)",
+        .handler = selectAroundCurrentWord,
+        .category = .selection,
+        .target = .object,
     });
 
     try registry.register(.{
@@ -4338,6 +4567,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select inside parentheses (vi()",
         .handler = selectInnerParen,
         .category = .selection,
+        .target = .object,
     });
 
     try registry.register(.{
@@ -4359,6 +4589,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select inside double quotes (vi\")",
         .handler = selectInnerQuote,
         .category = .selection,
+        .target = .object,
     });
 
     try registry.register(.{
@@ -4366,6 +4597,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select inside brackets (vi[)",
         .handler = selectInnerBracket,
         .category = .selection,
+        .target = .object,
     });
 
     try registry.register(.{
@@ -4380,6 +4612,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Select inside braces (vi{)",
         .handler = selectInnerBrace,
         .category = .selection,
+        .target = .object,
     });
 
     try registry.register(.{
@@ -4396,12 +4629,164 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .category = .edit,
     });
 
+    try registry.register(.{
+        .name = "select_around_paren",
+        .description = "Select around parentheses (va()",
+        .handler = selectAroundParen,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_around_bracket",
+        .description = "Select around brackets (va[)",
+        .handler = selectAroundBracket,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_around_brace",
+        .description = "Select around braces (va{)",
+        .handler = selectAroundBrace,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_around_quote",
+        .description = "Select around double quotes (va\")",
+        .handler = selectAroundQuote,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_inner_single_quote",
+        .description = "Select inside single quotes (vi')",
+        .handler = selectInnerSingleQuote,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_around_single_quote",
+        .description = "Select around single quotes (va')",
+        .handler = selectAroundSingleQuote,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_inner_backtick",
+        .description = "Select inside backticks (vi`)",
+        .handler = selectInnerBacktick,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_around_backtick",
+        .description = "Select around backticks (va`)",
+        .handler = selectAroundBacktick,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_inner_angle",
+        .description = "Select inside angle brackets (vi<)",
+        .handler = selectInnerAngle,
+        .category = .selection,
+        .target = .object,
+    });
+
+    try registry.register(.{
+        .name = "select_around_angle",
+        .description = "Select around angle brackets (va<)",
+        .handler = selectAroundAngle,
+        .category = .selection,
+        .target = .object,
+    });
+
+    // Syntax text objects, from the language's textobjects.scm query
+    // A count reaches an enclosing one (`d2af` deletes the function around this one)
+    const syntax_objects = [_]struct { []const u8, []const u8, Handler }{
+        .{ "select_around_function", "Select function (af)", selectAroundFunction },
+        .{ "select_inner_function", "Select function body (if)", selectInnerFunction },
+        .{ "select_around_class", "Select class or type (ac)", selectAroundClass },
+        .{ "select_inner_class", "Select class or type body (ic)", selectInnerClass },
+        .{ "select_around_argument", "Select argument and its comma (aa)", selectAroundArgument },
+        .{ "select_inner_argument", "Select argument (ia)", selectInnerArgument },
+        .{ "select_around_comment", "Select comment (a/)", selectAroundComment },
+        .{ "select_inner_comment", "Select comment text (i/)", selectInnerComment },
+    };
+    for (syntax_objects) |entry| {
+        try registry.register(.{
+            .name = entry[0],
+            .description = entry[1],
+            .handler = entry[2],
+            .category = .selection,
+            .count = .repeat,
+            .target = .object,
+        });
+    }
+
+    try registry.register(.{
+        .name = "move_next_function",
+        .description = "Move to next function (]f)",
+        .handler = moveToNextFunction,
+        .category = .motion,
+    });
+
+    try registry.register(.{
+        .name = "move_prev_function",
+        .description = "Move to previous function ([f)",
+        .handler = moveToPrevFunction,
+        .category = .motion,
+    });
+
+    // Structural selection over the syntax tree
+    try registry.register(.{
+        .name = "expand_selection",
+        .description = "Expand selection to the enclosing syntax node (+)",
+        .handler = expandSelection,
+        .category = .selection,
+        .count = .repeat,
+    });
+
+    try registry.register(.{
+        .name = "shrink_selection",
+        .description = "Shrink selection to the previous or first inner node (-)",
+        .handler = shrinkSelection,
+        .category = .selection,
+        .count = .repeat,
+    });
+
+    try registry.register(.{
+        .name = "select_next_sibling",
+        .description = "Select the next syntax node (]n)",
+        .handler = selectNextSibling,
+        .category = .selection,
+        .count = .repeat,
+    });
+
+    try registry.register(.{
+        .name = "select_prev_sibling",
+        .description = "Select the previous syntax node ([n)",
+        .handler = selectPrevSibling,
+        .category = .selection,
+        .count = .repeat,
+    });
+
     // Mark commands
     try registry.register(.{
         .name = "set_mark",
         .description = "Set mark at cursor position (m)",
         .handler = setMark,
         .category = .motion,
+        .count = .ignore,
+        .target = .none,
     });
 
     try registry.register(.{
@@ -4409,6 +4794,8 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Jump to mark (')",
         .handler = jumpToMark,
         .category = .motion,
+        .count = .ignore,
+        .target = .none,
     });
 
     try registry.register(.{
@@ -4416,6 +4803,8 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "List all marks (:marks)",
         .handler = listMarks,
         .category = .motion,
+        .count = .ignore,
+        .target = .none,
     });
 
     // Paragraph navigation
@@ -4439,6 +4828,89 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Repeat last action (dot command: .)",
         .handler = repeatLastAction,
         .category = .edit,
+        .count = .handler,
+    });
+
+    // Operators, which wait for a motion or text object
+    try registry.register(.{
+        .name = "operator_delete",
+        .description = "Delete over a motion or text object (d)",
+        .handler = operatorDelete,
+        .category = .edit,
+        .count = .handler,
+    });
+
+    try registry.register(.{
+        .name = "operator_change",
+        .description = "Change over a motion or text object (c)",
+        .handler = operatorChange,
+        .category = .edit,
+        .count = .handler,
+    });
+
+    try registry.register(.{
+        .name = "operator_yank",
+        .description = "Yank over a motion or text object (y)",
+        .handler = operatorYank,
+        .category = .edit,
+        .count = .handler,
+    });
+
+    try registry.register(.{
+        .name = "operator_indent",
+        .description = "Indent lines over a motion or text object (>)",
+        .handler = operatorIndent,
+        .category = .edit,
+        .count = .handler,
+    });
+
+    try registry.register(.{
+        .name = "operator_dedent",
+        .description = "Dedent lines over a motion or text object (<)",
+        .handler = operatorDedent,
+        .category = .edit,
+        .count = .handler,
+    });
+
+    try registry.register(.{
+        .name = "operator_lowercase",
+        .description = "Lowercase over a motion or text object (gu)",
+        .handler = operatorLowercase,
+        .category = .edit,
+        .count = .handler,
+    });
+
+    try registry.register(.{
+        .name = "operator_uppercase",
+        .description = "Uppercase over a motion or text object (gU)",
+        .handler = operatorUppercase,
+        .category = .edit,
+        .count = .handler,
+    });
+
+    try registry.register(.{
+        .name = "operator_reindent",
+        .description = "Reindent lines over a motion or text object (=)",
+        .handler = operatorReindent,
+        .category = .edit,
+        .count = .handler,
+    });
+
+    try registry.register(.{
+        .name = "operator_lines",
+        .description = "Current line and count - 1 more, for an operator (dd, yy, >>)",
+        .handler = operatorLines,
+        .category = .motion,
+        .count = .handler,
+        .target = .linewise,
+    });
+
+    try registry.register(.{
+        .name = "select_register",
+        .description = "Use a register for the next yank, delete or paste (\")",
+        .handler = selectRegister,
+        .category = .edit,
+        .count = .handler,
     });
 
     // Transpose commands
@@ -4462,6 +4934,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Sort selected lines alphabetically",
         .handler = sortLines,
         .category = .edit,
+        .count = .ignore,
     });
 
     try registry.register(.{
@@ -4469,6 +4942,29 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Remove duplicate lines from selection",
         .handler = uniqueLines,
         .category = .edit,
+        .count = .ignore,
+    });
+
+    // Shell filters
+    try registry.register(.{
+        .name = "shell_pipe",
+        .description = "Pipe selections through a shell command (|)",
+        .handler = shellPipe,
+        .category = .edit,
+    });
+
+    try registry.register(.{
+        .name = "shell_insert",
+        .description = "Insert shell command output before selections (!)",
+        .handler = shellInsert,
+        .category = .edit,
+    });
+
+    try registry.register(.{
+        .name = "shell_append",
+        .description = "Append shell command output after selections (Alt-!)",
+        .handler = shellAppend,
+        .category = .edit,
     });
 
     // Line manipulation commands
@@ -4484,6 +4980,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Join current line with next (J)",
         .handler = joinLines,
         .category = .edit,
+        .count = .handler,
     });
 
     try registry.register(.{
@@ -4512,6 +5009,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Toggle line comments (gcc or Ctrl+/)",
         .handler = toggleLineComment,
         .category = .edit,
+        .count = .ignore,
     });
 
     try registry.register(.{
@@ -4543,12 +5041,35 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .category = .edit,
     });
 
+    try registry.register(.{
+        .name = "undo_earlier",
+        .description = "Go to older undo state (g-)",
+        .handler = undoEarlier,
+        .category = .edit,
+    });
+
+    try registry.register(.{
+        .name = "undo_later",
+        .description = "Go to newer undo state (g+)",
+        .handler = undoLater,
+        .category = .edit,
+    });
+
+    try registry.register(.{
+        .name = "toggle_undo_tree",
+        .description = "Show undo tree (Space u)",
+        .handler = toggleUndoTree,
+        .category = .edit,
+        .count = .ignore,
+    });
+
     // Clipboard commands
     try registry.register(.{
         .name = "yank_line",
         .description = "Yank (copy) current line (yy)",
         .handler = yankLine,
         .category = .edit,
+        .count = .handler,
     });
 
     try registry.register(.{
@@ -4683,6 +5204,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Find next occurrence (n)",
         .handler = findNext,
         .category = .search,
+        .target = .exclusive,
     });
 
     try registry.register(.{
@@ -4690,6 +5212,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Find previous occurrence (N)",
         .handler = findPrevious,
         .category = .search,
+        .target = .exclusive,
     });
 
     try registry.register(.{
@@ -4704,6 +5227,15 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Replace all occurrences",
         .handler = replaceAll,
         .category = .search,
+        .count = .ignore,
+    });
+
+    try registry.register(.{
+        .name = "toggle_search_regex",
+        .description = "Toggle regex search",
+        .handler = toggleSearchRegex,
+        .category = .search,
+        .count = .ignore,
     });
 
     // Multi-cursor operations
@@ -4734,6 +5266,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Switch to next buffer (]b)",
         .handler = nextBuffer,
         .category = .buffer,
+        .count = .repeat,
     });
 
     try registry.register(.{
@@ -4741,6 +5274,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Switch to previous buffer ([b)",
         .handler = previousBuffer,
         .category = .buffer,
+        .count = .repeat,
     });
 
     try registry.register(.{
@@ -4756,6 +5290,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Jump to start of buffer (gg)",
         .handler = gotoStart,
         .category = .motion,
+        .target = .linewise,
     });
 
     try registry.register(.{
@@ -4763,6 +5298,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Jump to end of buffer (G)",
         .handler = gotoEnd,
         .category = .motion,
+        .target = .linewise,
     });
 
     try registry.register(.{
@@ -4770,6 +5306,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Jump to specific line number (:goto or Ctrl+G)",
         .handler = gotoLine,
         .category = .motion,
+        .target = .none,
     });
 
     try registry.register(.{
@@ -4779,11 +5316,50 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .category = .view,
     });
 
+    // Folding
+    try registry.register(.{
+        .name = "toggle_fold",
+        .description = "Open or close the fold at the cursor (za)",
+        .handler = toggleFold,
+        .category = .view,
+    });
+
+    try registry.register(.{
+        .name = "close_fold",
+        .description = "Close the fold around the cursor (zc)",
+        .handler = closeFold,
+        .category = .view,
+        .count = .repeat,
+    });
+
+    try registry.register(.{
+        .name = "open_fold",
+        .description = "Open the fold at the cursor (zo)",
+        .handler = openFold,
+        .category = .view,
+        .count = .repeat,
+    });
+
+    try registry.register(.{
+        .name = "open_all_folds",
+        .description = "Open all folds (zR)",
+        .handler = openAllFolds,
+        .category = .view,
+    });
+
+    try registry.register(.{
+        .name = "close_all_folds",
+        .description = "Close all folds (zM)",
+        .handler = closeAllFolds,
+        .category = .view,
+    });
+
     try registry.register(.{
         .name = "scroll_up",
         .description = "Scroll viewport up (Ctrl+Y)",
         .handler = scrollUp,
         .category = .view,
+        .count = .repeat,
     });
 
     try registry.register(.{
@@ -4791,6 +5367,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Scroll viewport down (Ctrl+E)",
         .handler = scrollDown,
         .category = .view,
+        .count = .repeat,
     });
 
     try registry.register(.{
@@ -4798,6 +5375,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Scroll viewport up by one page (Page Up)",
         .handler = scrollPageUp,
         .category = .view,
+        .count = .repeat,
     });
 
     try registry.register(.{
@@ -4805,6 +5383,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Scroll viewport down by one page (Page Down)",
         .handler = scrollPageDown,
         .category = .view,
+        .count = .repeat,
     });
 
     try registry.register(.{
@@ -4812,6 +5391,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Scroll viewport up by half page (Ctrl+U)",
         .handler = scrollHalfPageUp,
         .category = .view,
+        .count = .repeat,
     });
 
     try registry.register(.{
@@ -4819,6 +5399,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Scroll viewport down by half page (Ctrl+D)",
         .handler = scrollHalfPageDown,
         .category = .view,
+        .count = .repeat,
     });
 
     // Visual/display commands
@@ -4856,6 +5437,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Navigate to next window (Space w)",
         .handler = nextWindow,
         .category = .view,
+        .count = .repeat,
     });
 
     try registry.register(.{
@@ -4863,6 +5445,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Navigate to previous window (Space W)",
         .handler = previousWindow,
         .category = .view,
+        .count = .repeat,
     });
 
     // Search commands
@@ -4871,6 +5454,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Start incremental search (/)",
         .handler = startIncrementalSearch,
         .category = .search,
+        .count = .ignore,
     });
 
     try registry.register(.{
@@ -4878,6 +5462,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Cancel search (Escape)",
         .handler = cancelSearch,
         .category = .search,
+        .count = .ignore,
     });
 
     // Macro commands
@@ -4900,6 +5485,7 @@ pub fn registerBuiltins(registry: *Registry) !void {
         .description = "Play macro from register (@)",
         .handler = playMacro,
         .category = .system,
+        .count = .handler,
     });
 
     try registry.register(.{
@@ -5066,3 +5652,37 @@ test "registry: list commands" {
 
     try std.testing.expect(names.len > 0);
 }
+
+test "registry: counts repeat motions, span line commands, and replay with ." {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+    _ = try editor.buffer_manager.createFromString("abcdefg\nb\nc\nd\n");
+
+    // 3j
+    var down = Context{ .editor = &editor, .count = 3 };
+    try std.testing.expect(editor.command_registry.execute("move_down", &down) == .success);
+    try std.testing.expectEqual(@as(usize, 3), editor.getCursorPosition().line);
+
+    // 2G goes to line 2
+    var goto = Context{ .editor = &editor, .count = 2 };
+    try std.testing.expect(editor.command_registry.execute("move_file_end", &goto) == .success);
+    try std.testing.expectEqual(@as(usize, 1), editor.getCursorPosition().line);
+
+    // 2yy yanks both lines
+    var yank = Context{ .editor = &editor, .count = 2 };
+    try std.testing.expect(editor.command_registry.execute("yank_line", &yank) == .success);
+    try std.testing.expectEqualStrings("b\nc", editor.clipboard.getContent().?);
+
+    // 3x, then . deletes three more
+    var start = Context{ .editor = &editor };
+    try std.testing.expect(editor.command_registry.execute("move_file_start", &start) == .success);
+    var delete = Context{ .editor = &editor, .count = 3 };
+    try std.testing.expect(editor.command_registry.execute("delete_char", &delete) == .success);
+    var repeat = Context{ .editor = &editor };
+    try std.testing.expect(editor.command_registry.execute("repeat_last_action", &repeat) == .success);
+
+    const text = try editor.getActiveBuffer().?.getText();
+    defer allocator.free(text);
+    try std.testing.expectEqualStrings("g\nb\nc\nd\n", text);
+}
@@ -1,14 +1,18 @@
 //! Command line parser for ex-style commands
-//! Parses commands like :q, :w, :wq, :e, etc.
+//! Parses commands like :q, :w, :wq, :e, and line-range commands such as
+//! :%s/pat/rep/g, :g/pat/d, :'a,'bm$ (executed by ex.zig).
 
 const std = @import("std");
 
 /// Parsed command
 pub const Command = union(enum) {
     quit: struct { force: bool = false },
-    write: struct { path: ?[]const u8 = null },
+    write: struct { force: bool = false, path: ?[]const u8 = null },
     write_quit: struct { force: bool = false, path: ?[]const u8 = null },
     edit: struct { path: []const u8 },
+    set: struct { option: []const u8, value: ?[]const u8 = null }, // `:set opt=value`, or `:set opt` to show it
+    ex: Ex,
+    invalid: []const u8, // Static message for a malformed ex command
     unknown: []const u8,
 
     /// Check if command should quit editor
@@ -26,6 +30,80 @@ pub const Command = union(enum) {
             else => false,
         };
     }
+
+    /// Check if the command was given with `!`
+    pub fn isForced(self: Command) bool {
+        return switch (self) {
+            .quit => |q| q.force,
+            .write => |w| w.force,
+            .write_quit => |wq| wq.force,
+            else => false,
+        };
+    }
+};
+
+/// Line address: a base (`.`, `$`, `12`, `'a`, `/pat/`, `?pat?`) followed by
+/// any number of `+n`/`-n` offsets
+pub const Address = struct {
+    base: Base = .current,
+    offset: i64 = 0,
+
+    pub const Base = union(enum) {
+        current,
+        last,
+        line: usize, // 1-based, as typed; 0 addresses "before the first line"
+        mark: u8, // a-z, A-Z, or `<`/`>` for the last selection
+        search_forward: []const u8, // Regex, still escaped; empty repeats the last search
+        search_backward: []const u8,
+    };
+};
+
+/// Line range of an ex command: `%`, `5`, `.,$`, `'a,'b`, `/pat/;+2`
+pub const Range = struct {
+    start: Address,
+    end: Address, // Same as `start` when only one address was given
+    chained: bool = false, // `;` resolves `end` relative to `start` instead of the cursor
+};
+
+/// Ex command with an optional line range
+/// Slices in `range` and `action` point into `text`, which the command owns.
+pub const Ex = struct {
+    text: []const u8,
+    range: ?Range,
+    action: Action,
+
+    pub const Action = union(enum) {
+        goto, // A bare range (`:42`) moves the cursor
+        substitute: Substitute,
+        global: Global,
+        delete,
+        move: Address,
+        copy: Address,
+        normal: []const u8, // Keys to run on each line
+        sort: Sort,
+        read: []const u8, // File path, or `!cmd` to read a command's output
+        shell: []const u8, // `:!cmd` shows the output; with a range, the lines are filtered through it
+    };
+
+    pub const Substitute = struct {
+        pattern: []const u8, // Still escaped; empty reuses the last search
+        replacement: []const u8, // Vim syntax (`&`, `\1`), translated by ex.zig
+        global: bool = false, // `g`: every match in the line, not just the first
+        ignore_case: bool = false, // `i`
+    };
+
+    pub const Global = struct {
+        pattern: []const u8,
+        command: []const u8, // Ex command run on each matching line
+        invert: bool = false, // `:g!` / `:v` run on lines that don't match
+    };
+
+    pub const Sort = struct {
+        reverse: bool = false, // `:sort!`
+        ignore_case: bool = false, // `i`
+        numeric: bool = false, // `n`: by the first number in the line
+        unique: bool = false, // `u`: drop repeated lines
+    };
 };
 
 /// Parse a command line string (without the leading ':')
@@ -34,30 +112,40 @@ pub fn parse(allocator: std.mem.Allocator, input: []const u8) !Command {
     const trimmed = std.mem.trim(u8, input, &std.ascii.whitespace);
     if (trimmed.len == 0) return Command{ .unknown = "" };
 
-    // Check for force modifier (!)
-    const has_force = std.mem.endsWith(u8, trimmed, "!");
-    const cmd_text = if (has_force)
-        trimmed[0 .. trimmed.len - 1]
-    else
-        trimmed;
+    if (try parseEx(allocator, trimmed)) |cmd| return cmd;
 
     // Split command and arguments
-    var parts = std.mem.splitScalar(u8, cmd_text, ' ');
-    const cmd = parts.first();
+    var parts = std.mem.splitScalar(u8, trimmed, ' ');
+    const cmd_word = parts.first();
     const args = parts.rest();
 
+    // Check for force modifier (!), as in `:q!` or `:w! path`
+    const has_force = std.mem.endsWith(u8, cmd_word, "!");
+    const cmd = if (has_force) cmd_word[0 .. cmd_word.len - 1] else cmd_word;
+    const cmd_text = if (has_force and args.len == 0) cmd else trimmed;
+
     // Parse commands
     if (std.mem.eql(u8, cmd, "q") or std.mem.eql(u8, cmd, "quit")) {
         return Command{ .quit = .{ .force = has_force } };
     } else if (std.mem.eql(u8, cmd, "w") or std.mem.eql(u8, cmd, "write")) {
         const path = if (args.len > 0) try allocator.dupe(u8, std.mem.trim(u8, args, &std.ascii.whitespace)) else null;
-        return Command{ .write = .{ .path = path } };
+        return Command{ .write = .{ .force = has_force, .path = path } };
     } else if (std.mem.eql(u8, cmd, "wq") or std.mem.eql(u8, cmd, "x")) {
         const path = if (args.len > 0) try allocator.dupe(u8, std.mem.trim(u8, args, &std.ascii.whitespace)) else null;
         return Command{ .write_quit = .{ .force = has_force, .path = path } };
     } else if (std.mem.eql(u8, cmd, "e") or std.mem.eql(u8, cmd, "edit")) {
-        if (args.len == 0) return Command{ .unknown = cmd_text };
+        if (args.len == 0) return Command{ .unknown = try allocator.dupe(u8, cmd_text) };
         return Command{ .edit = .{ .path = try allocator.dupe(u8, std.mem.trim(u8, args, &std.ascii.whitespace)) } };
+    } else if (std.mem.eql(u8, cmd, "set") or std.mem.eql(u8, cmd, "se")) {
+        const arg = std.mem.trim(u8, args, &std.ascii.whitespace);
+        if (arg.len == 0) return Command{ .unknown = try allocator.dupe(u8, cmd_text) };
+
+        const eq = std.mem.indexOfScalar(u8, arg, '=');
+        const option = std.mem.trimRight(u8, if (eq) |i| arg[0..i] else arg, "?");
+        const owned_option = try allocator.dupe(u8, option);
+        errdefer allocator.free(owned_option);
+        const value = if (eq) |i| try allocator.dupe(u8, arg[i + 1 ..]) else null;
+        return Command{ .set = .{ .option = owned_option, .value = value } };
     }
 
     // Unknown command
@@ -70,11 +158,272 @@ pub fn deinit(cmd: Command, allocator: std.mem.Allocator) void {
         .write => |w| if (w.path) |p| allocator.free(p),
         .write_quit => |wq| if (wq.path) |p| allocator.free(p),
         .edit => |e| allocator.free(e.path),
+        .set => |s| {
+            allocator.free(s.option);
+            if (s.value) |v| allocator.free(v);
+        },
+        .ex => |e| allocator.free(e.text),
         .unknown => |u| if (u.len > 0) allocator.free(u),
         else => {},
     }
 }
 
+/// Parse an ex command with a range or a line-editing command name
+/// Returns null for anything else (`:w`, `:set`, ...), which `parse` handles.
+fn parseEx(allocator: std.mem.Allocator, trimmed: []const u8) !?Command {
+    const text = try allocator.dupe(u8, trimmed);
+    var keep = false;
+    defer if (!keep) allocator.free(text);
+
+    var scanner = Scanner{ .text = text };
+    const range = scanner.parseRange() catch return Command{ .invalid = "Invalid range" };
+    scanner.skipSpaces();
+
+    const name_start = scanner.pos;
+    while (scanner.peek()) |c| {
+        if (!std.ascii.isAlphabetic(c)) break;
+        scanner.pos += 1;
+    }
+    const name = text[name_start..scanner.pos];
+    const bang = scanner.eat('!');
+
+    const action: Ex.Action = if (name.len == 0 and !bang) blk: {
+        if (range == null) return null;
+        if (!scanner.atEnd()) return Command{ .invalid = "Trailing characters" };
+        break :blk .goto;
+    } else scanner.parseAction(name, bang) catch |err| return Command{ .invalid = switch (err) {
+        error.InvalidRange => "Invalid range",
+        error.MissingPattern => "Missing pattern delimiter",
+        error.MissingCommand => "Missing command",
+        error.MissingFileName => "No file name",
+        error.UnsupportedFlag => "Unsupported flag",
+        error.TrailingCharacters => "Trailing characters",
+    } } orelse {
+        if (range != null) return Command{ .invalid = "Command doesn't take a range" };
+        return null;
+    };
+
+    keep = true;
+    return Command{ .ex = .{ .text = text, .range = range, .action = action } };
+}
+
+const ParseError = error{
+    InvalidRange,
+    MissingPattern,
+    MissingCommand,
+    MissingFileName,
+    UnsupportedFlag,
+    TrailingCharacters,
+};
+
+/// Cursor over an ex command line
+const Scanner = struct {
+    text: []const u8,
+    pos: usize = 0,
+
+    fn peek(self: *const Scanner) ?u8 {
+        return if (self.pos < self.text.len) self.text[self.pos] else null;
+    }
+
+    fn eat(self: *Scanner, c: u8) bool {
+        if (self.peek() != c) return false;
+        self.pos += 1;
+        return true;
+    }
+
+    fn atEnd(self: *Scanner) bool {
+        self.skipSpaces();
+        return self.pos == self.text.len;
+    }
+
+    fn skipSpaces(self: *Scanner) void {
+        while (self.peek()) |c| {
+            if (c != ' ' and c != '\t') break;
+            self.pos += 1;
+        }
+    }
+
+    fn rest(self: *Scanner) []const u8 {
+        const text = std.mem.trim(u8, self.text[self.pos..], &std.ascii.whitespace);
+        self.pos = self.text.len;
+        return text;
+    }
+
+    fn number(self: *Scanner) ?usize {
+        const start = self.pos;
+        while (self.peek()) |c| {
+            if (!std.ascii.isDigit(c)) break;
+            self.pos += 1;
+        }
+        if (self.pos == start) return null;
+        return std.fmt.parseInt(usize, self.text[start..self.pos], 10) catch std.math.maxInt(usize);
+    }
+
+    /// Text up to the next unescaped `delim`, which is consumed if present
+    /// Escapes are kept: `\/` stays in the pattern for the caller to unescape.
+    fn delimited(self: *Scanner, delim: u8) []const u8 {
+        const start = self.pos;
+        while (self.peek()) |c| {
+            if (c == delim) {
+                self.pos += 1;
+                return self.text[start .. self.pos - 1];
+            }
+            self.pos += if (c == '\\' and self.pos + 1 < self.text.len) 2 else 1;
+        }
+        return self.text[start..];
+    }
+
+    fn parseAddress(self: *Scanner) ParseError!?Address {
+        var address = Address{};
+        var found = true;
+        switch (self.peek() orelse 0) {
+            '.' => self.pos += 1,
+            '$' => {
+                self.pos += 1;
+                address.base = .last;
+            },
+            '0'...'9' => address.base = .{ .line = self.number().? },
+            '\'' => {
+                self.pos += 1;
+                const mark = self.peek() orelse return error.InvalidRange;
+                if (!std.ascii.isAlphabetic(mark) and mark != '<' and mark != '>') return error.InvalidRange;
+                self.pos += 1;
+                address.base = .{ .mark = mark };
+            },
+            '/' => {
+                self.pos += 1;
+                address.base = .{ .search_forward = self.delimited('/') };
+            },
+            '?' => {
+                self.pos += 1;
+                address.base = .{ .search_backward = self.delimited('?') };
+            },
+            else => found = false,
+        }
+
+        // `+`/`-` with no base are relative to the current line
+        while (self.peek()) |c| {
+            if (c != '+' and c != '-') break;
+            self.pos += 1;
+            const n: i64 = @intCast(@min(self.number() orelse 1, std.math.maxInt(i32)));
+            address.offset += if (c == '+') n else -n;
+            found = true;
+        }
+        return if (found) address else null;
+    }
+
+    fn parseRange(self: *Scanner) ParseError!?Range {
+        self.skipSpaces();
+        if (self.eat('%')) return Range{ .start = .{ .base = .{ .line = 1 } }, .end = .{ .base = .last } };
+
+        const start = try self.parseAddress();
+        const sep = self.peek();
+        if (sep != ',' and sep != ';') {
+            const address = start orelse return null;
+            return Range{ .start = address, .end = address };
+        }
+        self.pos += 1;
+
+        // A missing address on either side of the separator is the current line
+        const first = start orelse Address{};
+        const last = try self.parseAddress() orelse Address{};
+        return Range{ .start = first, .end = last, .chained = sep == ';' };
+    }
+
+    /// Parse the part after the command name; null if `name` isn't an ex command
+    fn parseAction(self: *Scanner, name: []const u8, bang: bool) ParseError!?Ex.Action {
+        if (name.len == 0 and bang) {
+            const command = self.rest();
+            if (command.len == 0) return error.MissingCommand;
+            return .{ .shell = command };
+        }
+
+        if (isName(name, &.{ "s", "su", "substitute" }) and !bang) {
+            const delim = self.peek() orelse return error.MissingPattern;
+            if (std.ascii.isAlphanumeric(delim) or delim == ' ' or delim == '\\' or delim == '"') return error.MissingPattern;
+            self.pos += 1;
+
+            var sub = Ex.Substitute{ .pattern = self.delimited(delim), .replacement = self.delimited(delim) };
+            while (self.peek()) |c| : (self.pos += 1) {
+                switch (c) {
+                    'g' => sub.global = true,
+                    'i' => sub.ignore_case = true,
+                    'I' => sub.ignore_case = false,
+                    ' ', '\t' => {},
+                    else => return error.UnsupportedFlag,
+                }
+            }
+            return .{ .substitute = sub };
+        }
+
+        if (isName(name, &.{ "g", "global", "v", "vglobal" })) {
+            const delim = self.peek() orelse return error.MissingPattern;
+            if (std.ascii.isAlphanumeric(delim) or delim == ' ' or delim == '\\' or delim == '"') return error.MissingPattern;
+            self.pos += 1;
+
+            const pattern = self.delimited(delim);
+            const command = self.rest();
+            if (command.len == 0) return error.MissingCommand;
+            return .{ .global = .{ .pattern = pattern, .command = command, .invert = bang or name[0] == 'v' } };
+        }
+
+        if (isName(name, &.{ "d", "de", "del", "delete" }) and !bang) {
+            if (!self.atEnd()) return error.TrailingCharacters;
+            return .delete;
+        }
+
+        if (isName(name, &.{ "m", "mo", "move" }) and !bang) {
+            return .{ .move = try self.destination() };
+        }
+
+        if (isName(name, &.{ "t", "co", "copy" }) and !bang) {
+            return .{ .copy = try self.destination() };
+        }
+
+        if (isName(name, &.{ "norm", "normal" })) {
+            if (self.peek() != ' ') return error.MissingCommand;
+            return .{ .normal = self.text[self.pos + 1 ..] };
+        }
+
+        if (isName(name, &.{ "sor", "sort" })) {
+            var sort = Ex.Sort{ .reverse = bang };
+            while (self.peek()) |c| : (self.pos += 1) {
+                switch (c) {
+                    'i' => sort.ignore_case = true,
+                    'n' => sort.numeric = true,
+                    'u' => sort.unique = true,
+                    ' ', '\t' => {},
+                    else => return error.UnsupportedFlag,
+                }
+            }
+            return .{ .sort = sort };
+        }
+
+        if (isName(name, &.{ "r", "read" }) and !bang) {
+            const path = self.rest();
+            if (path.len == 0) return error.MissingFileName;
+            return .{ .read = path };
+        }
+
+        return null;
+    }
+
+    /// Target address of `:m`/`:t`, which is required
+    fn destination(self: *Scanner) ParseError!Address {
+        self.skipSpaces();
+        const address = try self.parseAddress() orelse return error.InvalidRange;
+        if (!self.atEnd()) return error.TrailingCharacters;
+        return address;
+    }
+};
+
+fn isName(name: []const u8, names: []const []const u8) bool {
+    for (names) |candidate| {
+        if (std.mem.eql(u8, name, candidate)) return true;
+    }
+    return false;
+}
+
 // === Tests ===
 
 test "parse: quit" {
@@ -107,6 +456,13 @@ test "parse: write" {
     defer deinit(cmd2, allocator);
     try std.testing.expect(cmd2 == .write);
     try std.testing.expectEqualStrings("test.txt", cmd2.write.path.?);
+    try std.testing.expect(!cmd2.isForced());
+
+    const cmd3 = try parse(allocator, "w! test.txt");
+    defer deinit(cmd3, allocator);
+    try std.testing.expect(cmd3 == .write);
+    try std.testing.expect(cmd3.isForced());
+    try std.testing.expectEqualStrings("test.txt", cmd3.write.path.?);
 }
 
 test "parse: write-quit" {
@@ -136,6 +492,87 @@ test "parse: edit" {
     try std.testing.expectEqualStrings("newfile.txt", cmd.edit.path);
 }
 
+test "parse: set" {
+    const allocator = std.testing.allocator;
+
+    const cmd1 = try parse(allocator, "set fileformat=dos");
+    defer deinit(cmd1, allocator);
+    try std.testing.expect(cmd1 == .set);
+    try std.testing.expectEqualStrings("fileformat", cmd1.set.option);
+    try std.testing.expectEqualStrings("dos", cmd1.set.value.?);
+
+    const cmd2 = try parse(allocator, "se fenc?");
+    defer deinit(cmd2, allocator);
+    try std.testing.expectEqualStrings("fenc", cmd2.set.option);
+    try std.testing.expect(cmd2.set.value == null);
+}
+
+test "parse: ex ranges" {
+    const allocator = std.testing.allocator;
+
+    const cmd1 = try parse(allocator, "%s/a\\/b/[&]/gi");
+    defer deinit(cmd1, allocator);
+    try std.testing.expect(cmd1 == .ex);
+    try std.testing.expect(cmd1.ex.range.?.start.base.line == 1);
+    try std.testing.expect(cmd1.ex.range.?.end.base == .last);
+    const sub = cmd1.ex.action.substitute;
+    try std.testing.expectEqualStrings("a\\/b", sub.pattern);
+    try std.testing.expectEqualStrings("[&]", sub.replacement);
+    try std.testing.expect(sub.global and sub.ignore_case);
+
+    const cmd2 = try parse(allocator, "'a,/end/-1m$");
+    defer deinit(cmd2, allocator);
+    const range = cmd2.ex.range.?;
+    try std.testing.expectEqual(@as(u8, 'a'), range.start.base.mark);
+    try std.testing.expectEqualStrings("end", range.end.base.search_forward);
+    try std.testing.expectEqual(@as(i64, -1), range.end.offset);
+    try std.testing.expect(cmd2.ex.action.move.base == .last);
+
+    const cmd3 = try parse(allocator, "42");
+    defer deinit(cmd3, allocator);
+    try std.testing.expect(cmd3.ex.action == .goto);
+    try std.testing.expectEqual(@as(usize, 42), cmd3.ex.range.?.start.base.line);
+
+    const cmd4 = try parse(allocator, "1,5w");
+    defer deinit(cmd4, allocator);
+    try std.testing.expect(cmd4 == .invalid);
+}
+
+test "parse: ex commands" {
+    const allocator = std.testing.allocator;
+
+    const cmd1 = try parse(allocator, "g!/^#/normal A;");
+    defer deinit(cmd1, allocator);
+    try std.testing.expect(cmd1.ex.range == null);
+    try std.testing.expectEqualStrings("^#", cmd1.ex.action.global.pattern);
+    try std.testing.expectEqualStrings("normal A;", cmd1.ex.action.global.command);
+    try std.testing.expect(cmd1.ex.action.global.invert);
+
+    const cmd2 = try parse(allocator, "sort! n u");
+    defer deinit(cmd2, allocator);
+    const sort = cmd2.ex.action.sort;
+    try std.testing.expect(sort.reverse and sort.numeric and sort.unique and !sort.ignore_case);
+
+    const cmd3 = try parse(allocator, ".,+2t0");
+    defer deinit(cmd3, allocator);
+    try std.testing.expectEqual(@as(i64, 2), cmd3.ex.range.?.end.offset);
+    try std.testing.expectEqual(@as(usize, 0), cmd3.ex.action.copy.base.line);
+
+    const cmd4 = try parse(allocator, "r notes.txt");
+    defer deinit(cmd4, allocator);
+    try std.testing.expectEqualStrings("notes.txt", cmd4.ex.action.read);
+
+    const cmd6 = try parse(allocator, ".,$!sort -u");
+    defer deinit(cmd6, allocator);
+    try std.testing.expect(cmd6.ex.range.?.end.base == .last);
+    try std.testing.expectEqualStrings("sort -u", cmd6.ex.action.shell);
+
+    // Not ex commands: left to the regular parser
+    const cmd5 = try parse(allocator, "set ff=unix");
+    defer deinit(cmd5, allocator);
+    try std.testing.expect(cmd5 == .set);
+}
+
 test "parse: unknown" {
     const allocator = std.testing.allocator;
 
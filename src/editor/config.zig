@@ -2,6 +2,19 @@
 //! Provides typed access to user preferences with sensible defaults
 
 const std = @import("std");
+const LspServers = @import("../lsp/servers.zig");
+const FileIo = @import("../buffer/file_io.zig");
+const Keymap = @import("keymap.zig");
+
+/// A `keys = command` line from a `[keys.<mode>]` section
+/// Kept as written: the editor checks the mode, keys and command when it applies them.
+pub const KeyBinding = struct {
+    mode: []const u8, // Section suffix: "normal", "insert" or "select"
+    keys: []const u8, // Key notation, e.g. "<space>f f"
+    command: []const u8, // Command name; empty unbinds the keys
+    line: usize, // Line in the config file, for error messages
+    malformed: bool = false, // Line had no '='; `keys` holds all of it, for the editor to report
+};
 
 /// Editor configuration
 pub const Config = struct {
@@ -18,11 +31,13 @@ pub const Config = struct {
     highlight_current_line: bool = true,
     show_indent_guides: bool = false,
     syntax_highlighting: bool = true,
+    fold_markers: bool = true, // Show where folds start in the gutter
     theme_name: []const u8 = "yonce", // Theme name (default: "yonce")
 
     // Search settings
     search_case_sensitive: bool = false,
     search_wrap_around: bool = true,
+    search_regex: bool = false,
 
     // Multi-cursor settings
     multi_cursor_enabled: bool = true,
@@ -34,12 +49,26 @@ pub const Config = struct {
     // Performance settings
     scroll_offset: usize = 3, // Lines to keep above/below cursor
     max_undo_history: usize = 1000,
+    persistent_undo: bool = true, // Keep undo history in ~/.aesop/undo across sessions
 
     // File handling
     auto_save: bool = false,
     auto_save_delay_ms: u64 = 1000,
     trim_trailing_whitespace: bool = false,
     ensure_newline_at_eof: bool = true,
+    backup_on_save: bool = false, // Keep the previous version as `<file>~` when saving
+    swap_files: bool = true, // Write unsaved changes to ~/.aesop/swap for crash recovery
+    swap_interval_ms: u64 = 2000, // Minimum time between swap file writes per buffer
+    large_file_threshold_mb: u64 = 64, // Files this big are memory-mapped, without highlighting or LSP
+
+    // Language servers
+    lsp_enabled: bool = true,
+    lsp_servers: std.StringHashMapUnmanaged([]const u8) = .empty, // languageId -> command line (owned, overrides defaults)
+
+    // Key bindings
+    leader: Keymap.Key = .{ .char = ' ' }, // Key that `<leader>` and the default leader chords use
+    which_key_delay_ms: u64 = 500, // Pause after a partial key sequence before listing what can follow
+    key_bindings: std.ArrayList(KeyBinding) = .empty, // From [keys.<mode>] sections, in file order (owned)
 
     allocator: std.mem.Allocator,
 
@@ -52,12 +81,25 @@ pub const Config = struct {
 
     /// Clean up configuration
     pub fn deinit(self: *Config) void {
-        _ = self;
+        var iter = self.lsp_servers.iterator();
+        while (iter.next()) |entry| {
+            self.allocator.free(entry.key_ptr.*);
+            self.allocator.free(entry.value_ptr.*);
+        }
+        self.lsp_servers.deinit(self.allocator);
+
+        for (self.key_bindings.items) |binding| {
+            self.allocator.free(binding.mode);
+            self.allocator.free(binding.keys);
+            self.allocator.free(binding.command);
+        }
+        self.key_bindings.deinit(self.allocator);
     }
 
     /// Load configuration from file (simple key=value format)
     pub fn loadFromFile(allocator: std.mem.Allocator, path: []const u8) !Config {
         var config = Config.init(allocator);
+        errdefer config.deinit();
 
         // Try to open file, if it doesn't exist, return defaults
         const file = std.fs.cwd().openFile(path, .{}) catch |err| {
@@ -73,29 +115,60 @@ pub const Config = struct {
         const contents = try file.readToEndAlloc(allocator, max_size);
         defer allocator.free(contents);
 
+        try config.parseContents(contents);
+
+        // Validate after loading
+        try config.validate();
+
+        return config;
+    }
+
+    /// Parse config file contents
+    /// Settings come first; a `[keys.<mode>]` header starts a section of key
+    /// bindings, and any other `[...]` header goes back to settings.
+    fn parseContents(self: *Config, contents: []const u8) !void {
+        var section: ?[]const u8 = null; // Mode name inside a [keys.<mode>] section
+        var line_number: usize = 0;
+
         // Parse line by line
         var lines = std.mem.splitSequence(u8, contents, "\n");
         while (lines.next()) |line| {
+            line_number += 1;
+
             // Skip empty lines and comments
             const trimmed = std.mem.trim(u8, line, " \t\r");
             if (trimmed.len == 0 or trimmed[0] == '#') continue;
 
-            // Parse key=value
-            var parts = std.mem.splitSequence(u8, trimmed, "=");
-            const key = parts.next() orelse continue;
-            const value = parts.next() orelse continue;
+            if (trimmed[0] == '[' and trimmed[trimmed.len - 1] == ']') {
+                const name = std.mem.trim(u8, trimmed[1 .. trimmed.len - 1], " \t");
+                section = if (std.mem.startsWith(u8, name, "keys.")) name["keys.".len..] else null;
+                continue;
+            }
+
+            if (section) |mode| {
+                // Command names never contain '=', so the last one ends the keys (which may be `=`)
+                const eq = std.mem.lastIndexOfScalar(u8, trimmed, '=') orelse {
+                    try self.addKeyBinding(mode, trimmed, "", line_number);
+                    self.key_bindings.items[self.key_bindings.items.len - 1].malformed = true;
+                    continue;
+                };
+                const keys = std.mem.trim(u8, trimmed[0..eq], " \t");
+                const command = std.mem.trim(u8, trimmed[eq + 1 ..], " \t");
+                try self.addKeyBinding(mode, keys, command, line_number);
+                continue;
+            }
+
+            // Parse key=value (split at the first '=' so values such as server arguments may contain one)
+            const eq = std.mem.indexOfScalar(u8, trimmed, '=') orelse continue;
+            const key = trimmed[0..eq];
+            const value = trimmed[eq + 1 ..];
 
             const key_trimmed = std.mem.trim(u8, key, " \t");
             const value_trimmed = std.mem.trim(u8, value, " \t");
 
             // Set config values based on key
-            try config.parseKeyValue(key_trimmed, value_trimmed);
+            try self.parseKeyValue(key_trimmed, value_trimmed);
         }
-
-        // Validate after loading
-        try config.validate();
-
-        return config;
     }
 
     /// Parse a single key=value pair
@@ -120,12 +193,16 @@ pub const Config = struct {
             self.show_indent_guides = try parseBool(value);
         } else if (std.mem.eql(u8, key, "syntax_highlighting")) {
             self.syntax_highlighting = try parseBool(value);
+        } else if (std.mem.eql(u8, key, "fold_markers")) {
+            self.fold_markers = try parseBool(value);
         } else if (std.mem.eql(u8, key, "theme_name")) {
             self.theme_name = value; // Note: value lifetime tied to config file contents
         } else if (std.mem.eql(u8, key, "search_case_sensitive")) {
             self.search_case_sensitive = try parseBool(value);
         } else if (std.mem.eql(u8, key, "search_wrap_around")) {
             self.search_wrap_around = try parseBool(value);
+        } else if (std.mem.eql(u8, key, "search_regex")) {
+            self.search_regex = try parseBool(value);
         } else if (std.mem.eql(u8, key, "multi_cursor_enabled")) {
             self.multi_cursor_enabled = try parseBool(value);
         } else if (std.mem.eql(u8, key, "max_cursors")) {
@@ -136,6 +213,8 @@ pub const Config = struct {
             self.scroll_offset = try std.fmt.parseInt(usize, value, 10);
         } else if (std.mem.eql(u8, key, "max_undo_history")) {
             self.max_undo_history = try std.fmt.parseInt(usize, value, 10);
+        } else if (std.mem.eql(u8, key, "persistent_undo")) {
+            self.persistent_undo = try parseBool(value);
         } else if (std.mem.eql(u8, key, "auto_save")) {
             self.auto_save = try parseBool(value);
         } else if (std.mem.eql(u8, key, "auto_save_delay_ms")) {
@@ -144,16 +223,84 @@ pub const Config = struct {
             self.trim_trailing_whitespace = try parseBool(value);
         } else if (std.mem.eql(u8, key, "ensure_newline_at_eof")) {
             self.ensure_newline_at_eof = try parseBool(value);
+        } else if (std.mem.eql(u8, key, "backup_on_save")) {
+            self.backup_on_save = try parseBool(value);
+        } else if (std.mem.eql(u8, key, "swap_files")) {
+            self.swap_files = try parseBool(value);
+        } else if (std.mem.eql(u8, key, "swap_interval_ms")) {
+            self.swap_interval_ms = try std.fmt.parseInt(u64, value, 10);
+        } else if (std.mem.eql(u8, key, "large_file_threshold_mb")) {
+            self.large_file_threshold_mb = try std.fmt.parseInt(u64, value, 10);
+        } else if (std.mem.eql(u8, key, "leader")) {
+            const keys = Keymap.parseKeys(value, self.leader) catch return error.InvalidLeader;
+            if (keys.len != 1) return error.InvalidLeader;
+            self.leader = keys.keys[0];
+        } else if (std.mem.eql(u8, key, "which_key_delay_ms")) {
+            self.which_key_delay_ms = try std.fmt.parseInt(u64, value, 10);
+        } else if (std.mem.eql(u8, key, "lsp_enabled")) {
+            self.lsp_enabled = try parseBool(value);
+        } else if (std.mem.startsWith(u8, key, "lsp.")) {
+            try self.setLspServer(key["lsp.".len..], value);
         }
         // Unknown keys are silently ignored
     }
 
+    /// Override the server command line for a language (empty disables the server)
+    pub fn setLspServer(self: *Config, language_id: []const u8, command_line: []const u8) !void {
+        if (language_id.len == 0) return error.InvalidLspLanguage;
+
+        const value = try self.allocator.dupe(u8, command_line);
+        errdefer self.allocator.free(value);
+
+        const gop = try self.lsp_servers.getOrPut(self.allocator, language_id);
+        if (gop.found_existing) {
+            self.allocator.free(gop.value_ptr.*);
+        } else {
+            gop.key_ptr.* = self.allocator.dupe(u8, language_id) catch |err| {
+                self.lsp_servers.removeByPtr(gop.key_ptr);
+                return err;
+            };
+        }
+        gop.value_ptr.* = value;
+    }
+
+    /// Record a key binding for a mode (empty command unbinds the keys)
+    pub fn addKeyBinding(self: *Config, mode: []const u8, keys: []const u8, command: []const u8, line: usize) !void {
+        const owned_mode = try self.allocator.dupe(u8, mode);
+        errdefer self.allocator.free(owned_mode);
+        const owned_keys = try self.allocator.dupe(u8, keys);
+        errdefer self.allocator.free(owned_keys);
+        const owned_command = try self.allocator.dupe(u8, command);
+        errdefer self.allocator.free(owned_command);
+
+        try self.key_bindings.append(self.allocator, .{
+            .mode = owned_mode,
+            .keys = owned_keys,
+            .command = owned_command,
+            .line = line,
+        });
+    }
+
+    /// Options for writing buffers to disk
+    pub fn saveOptions(self: *const Config) FileIo.WriteOptions {
+        return .{ .backup = self.backup_on_save };
+    }
+
+    /// Get server command line for a language (null if none configured or disabled)
+    pub fn lspServerCommand(self: *const Config, language_id: []const u8) ?[]const u8 {
+        if (!self.lsp_enabled) return null;
+        if (self.lsp_servers.get(language_id)) |command_line| {
+            return if (command_line.len == 0) null else command_line;
+        }
+        return LspServers.defaultCommand(language_id);
+    }
+
     /// Save configuration to file (simple key=value format)
     pub fn saveToFile(self: *const Config, path: []const u8) !void {
         const file = try std.fs.cwd().createFile(path, .{});
         defer file.close();
 
-        var buf: [8192]u8 = undefined;
+        var buf: [16384]u8 = undefined;
         var fbs = std.io.fixedBufferStream(&buf);
         const writer = fbs.writer();
 
@@ -173,11 +320,13 @@ pub const Config = struct {
         try writer.print("highlight_current_line={s}\n", .{if (self.highlight_current_line) "true" else "false"});
         try writer.print("show_indent_guides={s}\n", .{if (self.show_indent_guides) "true" else "false"});
         try writer.print("syntax_highlighting={s}\n", .{if (self.syntax_highlighting) "true" else "false"});
+        try writer.print("fold_markers={s}\n", .{if (self.fold_markers) "true" else "false"});
         try writer.print("theme_name={s}\n\n", .{self.theme_name});
 
         try writer.writeAll("# Search settings\n");
         try writer.print("search_case_sensitive={s}\n", .{if (self.search_case_sensitive) "true" else "false"});
-        try writer.print("search_wrap_around={s}\n\n", .{if (self.search_wrap_around) "true" else "false"});
+        try writer.print("search_wrap_around={s}\n", .{if (self.search_wrap_around) "true" else "false"});
+        try writer.print("search_regex={s}\n\n", .{if (self.search_regex) "true" else "false"});
 
         try writer.writeAll("# Multi-cursor settings\n");
         try writer.print("multi_cursor_enabled={s}\n", .{if (self.multi_cursor_enabled) "true" else "false"});
@@ -188,13 +337,44 @@ pub const Config = struct {
 
         try writer.writeAll("# Performance settings\n");
         try writer.print("scroll_offset={d}\n", .{self.scroll_offset});
-        try writer.print("max_undo_history={d}\n\n", .{self.max_undo_history});
+        try writer.print("max_undo_history={d}\n", .{self.max_undo_history});
+        try writer.print("persistent_undo={s}\n\n", .{if (self.persistent_undo) "true" else "false"});
 
         try writer.writeAll("# File handling\n");
         try writer.print("auto_save={s}\n", .{if (self.auto_save) "true" else "false"});
         try writer.print("auto_save_delay_ms={d}\n", .{self.auto_save_delay_ms});
         try writer.print("trim_trailing_whitespace={s}\n", .{if (self.trim_trailing_whitespace) "true" else "false"});
         try writer.print("ensure_newline_at_eof={s}\n", .{if (self.ensure_newline_at_eof) "true" else "false"});
+        try writer.print("backup_on_save={s}\n", .{if (self.backup_on_save) "true" else "false"});
+        try writer.print("swap_files={s}\n", .{if (self.swap_files) "true" else "false"});
+        try writer.print("swap_interval_ms={d}\n", .{self.swap_interval_ms});
+        try writer.print("large_file_threshold_mb={d}\n\n", .{self.large_file_threshold_mb});
+
+        try writer.writeAll("# Keys\n");
+        try writer.writeAll("leader=");
+        try self.leader.writeNotation(writer);
+        try writer.print("\nwhich_key_delay_ms={d}\n\n", .{self.which_key_delay_ms});
+
+        try writer.writeAll("# Language servers\n");
+        try writer.print("lsp_enabled={s}\n", .{if (self.lsp_enabled) "true" else "false"});
+        var servers = self.lsp_servers.iterator();
+        while (servers.next()) |entry| {
+            try writer.print("lsp.{s}={s}\n", .{ entry.key_ptr.*, entry.value_ptr.* });
+        }
+
+        // Key bindings last, since a section runs to the next header
+        var section: ?[]const u8 = null;
+        for (self.key_bindings.items) |binding| {
+            if (section == null or !std.mem.eql(u8, section.?, binding.mode)) {
+                try writer.print("\n[keys.{s}]\n", .{binding.mode});
+                section = binding.mode;
+            }
+            if (binding.malformed) {
+                try writer.print("{s}\n", .{binding.keys});
+            } else {
+                try writer.print("{s} = {s}\n", .{ binding.keys, binding.command });
+            }
+        }
 
         try file.writeAll(fbs.getWritten());
     }
@@ -344,6 +524,7 @@ test "config: builder" {
 
     try std.testing.expectEqual(@as(u8, 2), config.tab_width);
     try std.testing.expectEqual(false, config.expand_tabs);
+    try std.testing.expect(config.leader.eql(.{ .char = ',' }));
     try std.testing.expectEqual(@as(usize, 50), config.max_cursors);
 }
 
@@ -385,3 +566,64 @@ test "config: load from missing file" {
     try std.testing.expectEqual(@as(u8, 4), config.tab_width);
     try std.testing.expectEqual(true, config.expand_tabs);
 }
+
+test "config: lsp server overrides" {
+    const allocator = std.testing.allocator;
+    var config = Config.init(allocator);
+    defer config.deinit();
+
+    // Built-in defaults
+    try std.testing.expectEqualStrings("zls", config.lspServerCommand("zig").?);
+    try std.testing.expect(config.lspServerCommand("markdown") == null);
+
+    // Overrides keep arguments, including '='
+    try config.parseKeyValue("lsp.zig", "/opt/zls/zls --config-path=/tmp/zls.json");
+    try std.testing.expectEqualStrings("/opt/zls/zls --config-path=/tmp/zls.json", config.lspServerCommand("zig").?);
+
+    // Empty value disables the server, lsp_enabled disables all of them
+    try config.parseKeyValue("lsp.rust", "");
+    try std.testing.expect(config.lspServerCommand("rust") == null);
+    try config.parseKeyValue("lsp_enabled", "false");
+    try std.testing.expect(config.lspServerCommand("zig") == null);
+}
+
+test "config: key binding sections" {
+    const allocator = std.testing.allocator;
+    var config = Config.init(allocator);
+    defer config.deinit();
+
+    try config.parseContents(
+        \\tab_width=2
+        \\leader=,
+        \\
+        \\[keys.normal]
+        \\# Leader bindings
+        \\<space>f f = toggle_file_finder
+        \\= = indent_line
+        \\g d =
+        \\<C-x> save
+        \\
+        \\[editor]
+        \\expand_tabs=false
+    );
+
+    try std.testing.expectEqual(@as(u8, 2), config.tab_width);
+    try std.testing.expectEqual(false, config.expand_tabs);
+
+    const bindings = config.key_bindings.items;
+    try std.testing.expectEqual(@as(usize, 4), bindings.len);
+    try std.testing.expectEqualStrings("normal", bindings[0].mode);
+    try std.testing.expectEqualStrings("<space>f f", bindings[0].keys);
+    try std.testing.expectEqualStrings("toggle_file_finder", bindings[0].command);
+    try std.testing.expectEqual(@as(usize, 6), bindings[0].line);
+    try std.testing.expectEqualStrings("=", bindings[1].keys);
+    try std.testing.expectEqualStrings("indent_line", bindings[1].command);
+    try std.testing.expectEqualStrings("g d", bindings[2].keys);
+    try std.testing.expectEqualStrings("", bindings[2].command);
+    try std.testing.expect(!bindings[2].malformed);
+
+    // A line without '=' is kept whole, with its line number, to be reported
+    try std.testing.expect(bindings[3].malformed);
+    try std.testing.expectEqualStrings("<C-x> save", bindings[3].keys);
+    try std.testing.expectEqual(@as(usize, 9), bindings[3].line);
+}
@@ -0,0 +1,168 @@
+//! Line-based text diff
+//! Used to preview how two versions of a file differ (e.g. swap file recovery)
+
+const std = @import("std");
+
+pub const LineKind = enum {
+    context, // Present in both versions
+    removed, // Only in the old version
+    added, // Only in the new version
+    separator, // Unchanged lines omitted between hunks
+};
+
+/// One line of diff output; text borrows from the compared inputs
+pub const DiffLine = struct {
+    kind: LineKind,
+    text: []const u8,
+};
+
+/// Largest LCS table we build; bigger changes are shown as a plain replace
+const MAX_TABLE_CELLS = 1024 * 1024;
+
+/// Diff `old` against `new`, keeping `context` unchanged lines around each change
+/// Returns an empty slice when the texts are identical. Caller frees the slice.
+pub fn diffLines(allocator: std.mem.Allocator, old: []const u8, new: []const u8, context: usize) ![]DiffLine {
+    const a = try splitLines(allocator, old);
+    defer allocator.free(a);
+    const b = try splitLines(allocator, new);
+    defer allocator.free(b);
+
+    var all = std.ArrayList(DiffLine).empty;
+    defer all.deinit(allocator);
+
+    // Trim the common head and tail so the LCS only covers the changed middle
+    var prefix: usize = 0;
+    while (prefix < a.len and prefix < b.len and std.mem.eql(u8, a[prefix], b[prefix])) prefix += 1;
+    var suffix: usize = 0;
+    while (suffix < a.len - prefix and suffix < b.len - prefix and
+        std.mem.eql(u8, a[a.len - 1 - suffix], b[b.len - 1 - suffix])) suffix += 1;
+
+    for (a[0..prefix]) |line| try all.append(allocator, .{ .kind = .context, .text = line });
+    try diffMiddle(allocator, &all, a[prefix .. a.len - suffix], b[prefix .. b.len - suffix]);
+    for (a[a.len - suffix ..]) |line| try all.append(allocator, .{ .kind = .context, .text = line });
+
+    return collapseContext(allocator, all.items, context);
+}
+
+fn splitLines(allocator: std.mem.Allocator, text: []const u8) ![][]const u8 {
+    var lines = std.ArrayList([]const u8).empty;
+    errdefer lines.deinit(allocator);
+
+    var it = std.mem.splitScalar(u8, text, '\n');
+    while (it.next()) |line| try lines.append(allocator, line);
+
+    // A trailing newline ends the last line rather than starting an empty one
+    if (lines.items.len > 1 and lines.items[lines.items.len - 1].len == 0) _ = lines.pop();
+    return lines.toOwnedSlice(allocator);
+}
+
+fn diffMiddle(allocator: std.mem.Allocator, out: *std.ArrayList(DiffLine), a: []const []const u8, b: []const []const u8) !void {
+    const width = b.len + 1;
+    if (a.len == 0 or b.len == 0 or (a.len + 1) * width > MAX_TABLE_CELLS) {
+        for (a) |line| try out.append(allocator, .{ .kind = .removed, .text = line });
+        for (b) |line| try out.append(allocator, .{ .kind = .added, .text = line });
+        return;
+    }
+
+    // table[i][j] = LCS length of a[i..] and b[j..]
+    const table = try allocator.alloc(u32, (a.len + 1) * width);
+    defer allocator.free(table);
+    @memset(table, 0);
+
+    var i = a.len;
+    while (i > 0) {
+        i -= 1;
+        var j = b.len;
+        while (j > 0) {
+            j -= 1;
+            table[i * width + j] = if (std.mem.eql(u8, a[i], b[j]))
+                table[(i + 1) * width + j + 1] + 1
+            else
+                @max(table[(i + 1) * width + j], table[i * width + j + 1]);
+        }
+    }
+
+    i = 0;
+    var j: usize = 0;
+    while (i < a.len and j < b.len) {
+        if (std.mem.eql(u8, a[i], b[j])) {
+            try out.append(allocator, .{ .kind = .context, .text = a[i] });
+            i += 1;
+            j += 1;
+        } else if (table[(i + 1) * width + j] >= table[i * width + j + 1]) {
+            try out.append(allocator, .{ .kind = .removed, .text = a[i] });
+            i += 1;
+        } else {
+            try out.append(allocator, .{ .kind = .added, .text = b[j] });
+            j += 1;
+        }
+    }
+    for (a[i..]) |line| try out.append(allocator, .{ .kind = .removed, .text = line });
+    for (b[j..]) |line| try out.append(allocator, .{ .kind = .added, .text = line });
+}
+
+/// Drop unchanged lines further than `context` from any change
+fn collapseContext(allocator: std.mem.Allocator, lines: []const DiffLine, context: usize) ![]DiffLine {
+    var out = std.ArrayList(DiffLine).empty;
+    errdefer out.deinit(allocator);
+
+    // Distance to the nearest change before each line, then check the one after
+    var last_change: ?usize = null;
+    var next_change = std.ArrayList(?usize).empty;
+    defer next_change.deinit(allocator);
+    try next_change.resize(allocator, lines.len);
+    var upcoming: ?usize = null;
+    var k = lines.len;
+    while (k > 0) {
+        k -= 1;
+        if (lines[k].kind != .context) upcoming = k;
+        next_change.items[k] = upcoming;
+    }
+
+    var skipped = false;
+    for (lines, 0..) |line, idx| {
+        if (line.kind != .context) last_change = idx;
+
+        const near_prev = if (last_change) |c| idx - c <= context else false;
+        const near_next = if (next_change.items[idx]) |c| c - idx <= context else false;
+        if (near_prev or near_next) {
+            if (skipped and out.items.len > 0) try out.append(allocator, .{ .kind = .separator, .text = "" });
+            skipped = false;
+            try out.append(allocator, line);
+        } else {
+            skipped = true;
+        }
+    }
+
+    return out.toOwnedSlice(allocator);
+}
+
+// === Tests ===
+
+test "diff: changed lines with context" {
+    const allocator = std.testing.allocator;
+
+    const old = "a\nb\nc\nd\ne\nf\ng\n";
+    const new = "a\nb\nC\nd\ne\nf\ng\nh\n";
+    const lines = try diffLines(allocator, old, new, 1);
+    defer allocator.free(lines);
+
+    const expected = [_]DiffLine{
+        .{ .kind = .context, .text = "b" },
+        .{ .kind = .removed, .text = "c" },
+        .{ .kind = .added, .text = "C" },
+        .{ .kind = .context, .text = "d" },
+        .{ .kind = .separator, .text = "" },
+        .{ .kind = .context, .text = "g" },
+        .{ .kind = .added, .text = "h" },
+    };
+    try std.testing.expectEqual(expected.len, lines.len);
+    for (expected, lines) |want, got| {
+        try std.testing.expectEqual(want.kind, got.kind);
+        try std.testing.expectEqualStrings(want.text, got.text);
+    }
+
+    const same = try diffLines(allocator, old, old, 3);
+    defer allocator.free(same);
+    try std.testing.expectEqual(@as(usize, 0), same.len);
+}
@@ -2,21 +2,27 @@
 //! Ties together buffers, selections, modes, commands, and rendering
 
 const std = @import("std");
+const build_options = @import("build_options");
 const Mode = @import("mode.zig");
 const Cursor = @import("cursor.zig");
 const Buffer = @import("../buffer/manager.zig");
+const FileWatcher = @import("../buffer/file_watcher.zig").FileWatcher;
+const Encoding = @import("../buffer/encoding.zig");
 const Command = @import("command.zig");
 const Keymap = @import("keymap.zig");
 const Motions = @import("motions.zig");
 const Actions = @import("actions.zig");
 const Message = @import("message.zig");
 const Undo = @import("undo.zig");
+const SwapFile = @import("swap_file.zig");
+const Diff = @import("diff.zig");
 const Palette = @import("palette.zig");
 const Search = @import("search.zig");
 const Marks = @import("marks.zig");
 const Repeat = @import("repeat.zig");
 const Prompt = @import("prompt.zig");
 const Registers = @import("registers.zig");
+const Operator = @import("operator.zig");
 const Macros = @import("macros.zig");
 const Config = @import("config.zig");
 const Window = @import("window.zig");
@@ -29,8 +35,19 @@ const PluginSystem = @import("../plugin/system.zig");
 const Renderer = @import("../render/renderer.zig").Renderer;
 const LspClient = @import("../lsp/client.zig").Client;
 const LspHandlers = @import("../lsp/handlers.zig");
+const LspServers = @import("../lsp/servers.zig");
+const LspSync = @import("../lsp/sync.zig");
 const LspDiagnostics = @import("../lsp/diagnostics.zig");
 const CompletionList = @import("completion.zig").CompletionList;
+const Shell = @import("shell.zig");
+const TextObjects = @import("textobjects.zig");
+const Indent = @import("indent.zig");
+const Fold = @import("fold.zig");
+const Language = @import("language.zig").Language;
+const TreeSitter = if (build_options.enable_treesitter)
+    @import("treesitter.zig")
+else
+    @import("treesitter_stub.zig");
 
 /// Pending command awaiting user input
 ///
@@ -60,6 +77,10 @@ pub const PendingCommand = union(enum) {
     lsp_rename,
     /// Jump to line number - awaiting line number input
     goto_line,
+    /// Shell command for the selections (|, !, Alt-!) - awaiting command input
+    shell_pipe: Shell.PipeMode,
+    /// Register for the next yank, delete or paste (" command)
+    select_register,
 
     /// Check if a command is awaiting input
     pub fn isWaiting(self: PendingCommand) bool {
@@ -67,6 +88,92 @@ pub const PendingCommand = union(enum) {
     }
 };
 
+/// Unsaved edits from a crashed session, found when their file was opened
+pub const SwapRecovery = struct {
+    buffer_id: Buffer.BufferId,
+    swap: SwapFile.Swap,
+    disk_text: []u8, // File content as opened
+    diff: []Diff.DiffLine, // Disk -> swap; borrows from disk_text and swap.text
+    show_diff: bool = false,
+    scroll: usize = 0, // First diff line shown
+
+    /// Lines of unchanged context kept around each change in the diff view
+    const DIFF_CONTEXT = 2;
+
+    pub fn deinit(self: *SwapRecovery, allocator: std.mem.Allocator) void {
+        allocator.free(self.diff);
+        allocator.free(self.disk_text);
+        self.swap.deinit(allocator);
+    }
+
+    /// Check if the file was changed by something else after the edits began
+    pub fn diskChanged(self: *const SwapRecovery) bool {
+        return SwapFile.contentHash(self.disk_text) != self.swap.base_hash;
+    }
+};
+
+/// A modified buffer whose file was rewritten on disk, awaiting the user's decision
+pub const DiskConflict = struct {
+    buffer_id: Buffer.BufferId,
+    buffer_text: []u8,
+    disk_text: []u8,
+    diff: []Diff.DiffLine, // Buffer -> disk; borrows from the texts above
+    show_diff: bool = false,
+    scroll: usize = 0, // First diff line shown
+
+    pub fn deinit(self: *DiskConflict, allocator: std.mem.Allocator) void {
+        allocator.free(self.diff);
+        allocator.free(self.disk_text);
+        allocator.free(self.buffer_text);
+    }
+};
+
+/// How to resolve a pending disk conflict
+pub const ConflictAction = enum {
+    reload, // Take the version on disk (undo brings our edits back)
+    overwrite, // Write our version over the one on disk
+    keep, // Keep editing; saving still needs :w!
+};
+
+/// How often files are checked for outside changes when inotify isn't available
+const DISK_POLL_INTERVAL_MS = 1000;
+
+/// How to resolve a pending swap recovery
+pub const RecoveryAction = enum {
+    recover, // Load the swap file's text into the buffer
+    discard, // Delete the swap file and keep the file as on disk
+    defer_decision, // Leave the swap file for later; the buffer gets no swap file
+};
+
+/// Lines a selection covered (0-based, inclusive)
+pub const LineSpan = struct {
+    first: usize,
+    last: usize,
+};
+
+/// Shown when a regex search runs out of steps instead of reporting no match
+pub const search_limit_msg = "Regex search gave up: the pattern backtracks too much";
+
+/// Selections before and after one syntax expansion, so shrinking can go back
+const SyntaxExpansion = struct {
+    buffer_id: Buffer.BufferId,
+    version: usize, // Version of the buffer when it was made
+    before: []Cursor.Selection,
+    after: []Cursor.Selection,
+
+    fn deinit(self: SyntaxExpansion, allocator: std.mem.Allocator) void {
+        allocator.free(self.before);
+        allocator.free(self.after);
+    }
+};
+
+/// Where the insert session after a change began; what it typed is what grew there
+const ChangeInsert = struct {
+    buffer_id: Buffer.BufferId,
+    start: usize, // Byte offset typing began at
+    len: usize, // Buffer length then
+};
+
 /// Editor state - the main coordinator
 pub const Editor = struct {
     allocator: std.mem.Allocator,
@@ -79,8 +186,6 @@ pub const Editor = struct {
     keymap_manager: Keymap.KeymapManager,
     clipboard: Actions.Clipboard,
     messages: Message.MessageQueue,
-    undo_history: Undo.UndoHistory,
-    current_undo_group: ?Undo.OperationGroup, // Accumulates ops during insert session
     palette: Palette.Palette,
     search: Search.Search,
     marks: Marks.MarkRegistry,
@@ -90,6 +195,10 @@ pub const Editor = struct {
     find_till_state: Motions.FindTillState,
     macro_recorder: Macros.MacroRecorder,
     pending_command: PendingCommand,
+    pending_count: usize, // Count typed before a command that waits for a key (`3fx`, `10@q`)
+    pending_operator: ?Operator.Pending, // Operator waiting for its motion or text object (`d` of `dw`)
+    pending_register: ?Registers.RegisterId, // Register picked with `"x` for the next command
+    change_insert: ?ChangeInsert, // Insert session a change (`cw`) started, whose text `.` types again
     config: Config.Config,
     theme: *const Theme, // Current editor theme
     window_manager: Window.WindowManager,
@@ -98,10 +207,23 @@ pub const Editor = struct {
     file_tree: FileTree.FileTree,
     buffer_switcher_visible: bool,
     buffer_switcher_selected: usize,
-    lsp_client: ?LspClient, // Optional LSP client (null if not initialized)
+    undo_tree_visible: bool,
+    undo_tree_selected: usize, // Row in the undo tree popup
+    undo_tree_origin: usize, // Undo state to return to if the popup is cancelled
+    swap_recovery: ?SwapRecovery, // Crashed session's edits awaiting recover/diff/discard
+    disk_conflict: ?DiskConflict, // File changed on disk under unsaved edits, awaiting a decision
+    visual_lines: ?LineSpan, // Last selection `:` was entered from, for the '< and '> addresses
+    file_watcher: FileWatcher, // Notifies us when open files change on disk
+    last_disk_check: i64, // When files were last polled (ms), if the watcher is inactive
+    lsp_servers: LspServers.ServerRegistry, // Language server sessions per (language, workspace root)
     completion_list: CompletionList, // Code completion popup
     diagnostic_manager: LspDiagnostics.DiagnosticManager, // LSP diagnostics storage
     hover_content: ?[]const u8, // Current hover text (allocated, must free)
+    syntax_parser: ?TreeSitter.Parser, // Parser for the active buffer's language
+    syntax_buffer: ?Buffer.BufferId, // Buffer the parser's tree was built from
+    syntax_version: usize, // That buffer's version when it was parsed
+    syntax_history: std.ArrayList(SyntaxExpansion), // Expansions shrinking can undo, innermost first
+    folds: std.AutoHashMap(Buffer.BufferId, Fold.FoldSet), // Fold ranges and closed folds per buffer
 
     // Viewport (legacy - will be replaced by window_manager)
     scroll_offset: usize, // Line offset for scrolling
@@ -123,6 +245,7 @@ pub const Editor = struct {
             .case_sensitive = config.search_case_sensitive,
             .whole_word = false,
             .wrap_around = config.search_wrap_around,
+            .regex = config.search_regex,
         };
 
         var editor = Editor{
@@ -134,8 +257,6 @@ pub const Editor = struct {
             .keymap_manager = Keymap.KeymapManager.init(allocator),
             .clipboard = Actions.Clipboard.init(allocator),
             .messages = Message.MessageQueue.init(allocator),
-            .undo_history = Undo.UndoHistory.init(allocator),
-            .current_undo_group = null,
             .palette = Palette.Palette.init(allocator),
             .search = Search.Search.initWithOptions(allocator, search_options),
             .marks = Marks.MarkRegistry.init(allocator),
@@ -145,6 +266,10 @@ pub const Editor = struct {
             .find_till_state = Motions.FindTillState{},
             .macro_recorder = Macros.MacroRecorder.init(allocator),
             .pending_command = .none,
+            .pending_count = 1,
+            .pending_operator = null,
+            .pending_register = null,
+            .change_insert = null,
             .config = config,
             .theme = &yonce_theme, // Default theme
             .window_manager = try Window.WindowManager.init(allocator, initial_dims),
@@ -153,10 +278,23 @@ pub const Editor = struct {
             .file_tree = FileTree.FileTree.init(allocator),
             .buffer_switcher_visible = false,
             .buffer_switcher_selected = 0,
-            .lsp_client = null, // LSP client initialized on-demand
+            .undo_tree_visible = false,
+            .undo_tree_selected = 0,
+            .undo_tree_origin = 0,
+            .swap_recovery = null,
+            .disk_conflict = null,
+            .visual_lines = null,
+            .file_watcher = FileWatcher.init(allocator),
+            .last_disk_check = 0,
+            .lsp_servers = LspServers.ServerRegistry.init(allocator),
             .completion_list = CompletionList.init(allocator),
             .diagnostic_manager = LspDiagnostics.DiagnosticManager.init(allocator),
             .hover_content = null,
+            .syntax_parser = null,
+            .syntax_buffer = null,
+            .syntax_version = 0,
+            .syntax_history = .empty,
+            .folds = std.AutoHashMap(Buffer.BufferId, Fold.FoldSet).init(allocator),
             .scroll_offset = 0,
             .col_offset = 0,
         };
@@ -175,11 +313,18 @@ pub const Editor = struct {
         if (self.hover_content) |content| {
             self.allocator.free(content);
         }
+        if (self.swap_recovery) |*recovery| recovery.deinit(self.allocator);
+        if (self.disk_conflict) |*conflict| conflict.deinit(self.allocator);
+        if (self.syntax_parser) |*parser| parser.deinit();
+        self.clearSyntaxHistory();
+        self.syntax_history.deinit(self.allocator);
+        var folds = self.folds.valueIterator();
+        while (folds.next()) |fold_set| fold_set.deinit(self.allocator);
+        self.folds.deinit();
+        self.file_watcher.deinit();
         self.diagnostic_manager.deinit();
         self.completion_list.deinit();
-        if (self.lsp_client) |*client| {
-            client.deinit();
-        }
+        self.lsp_servers.deinit();
         self.file_finder.deinit();
         self.file_tree.deinit();
         self.plugin_manager.deinit();
@@ -192,7 +337,6 @@ pub const Editor = struct {
         self.marks.deinit();
         self.search.deinit();
         self.palette.deinit();
-        self.undo_history.deinit();
         self.messages.deinit();
         self.clipboard.deinit();
         self.selections.deinit(self.allocator);
@@ -216,6 +360,417 @@ pub const Editor = struct {
         return self.buffer_manager.getActiveBuffer();
     }
 
+    /// Parser holding a syntax tree of the active buffer's current text
+    /// Null for large files and text that doesn't parse. After edits the tree
+    /// is moved past them and parsed again incrementally; it's only rebuilt from
+    /// scratch for another buffer or when the buffer no longer has the edits.
+    pub fn syntaxParser(self: *Editor) ?*TreeSitter.Parser {
+        const buffer_id = self.buffer_manager.active_buffer_id orelse return null;
+        const buffer = self.buffer_manager.getBuffer(buffer_id) orelse return null;
+        if (buffer.metadata.large_file) return null;
+
+        const language = TreeSitter.Language.fromFilename(buffer.metadata.getName());
+        if (self.syntax_parser) |*parser| {
+            if (parser.language != language) {
+                parser.deinit();
+                self.syntax_parser = null;
+            }
+        }
+        if (self.syntax_parser == null) {
+            self.syntax_parser = TreeSitter.Parser.init(self.allocator, language) catch return null;
+            self.syntax_buffer = null;
+        }
+        const parser = &self.syntax_parser.?;
+
+        const same_buffer = if (self.syntax_buffer) |id| id == buffer_id else false;
+        if (same_buffer and self.syntax_version == buffer.version) return parser;
+
+        const changes = if (same_buffer) buffer.changesSince(self.syntax_version) else null;
+        if (changes) |list| {
+            for (list) |change| parser.applyBufferEdit(change);
+        } else {
+            parser.clearTree();
+        }
+        self.syntax_buffer = null;
+        parser.parseRope(&buffer.rope) catch return null;
+        self.syntax_buffer = buffer_id;
+        self.syntax_version = buffer.version;
+        return parser;
+    }
+
+    /// Syntax text objects of one kind in the active buffer (`af`, `]f`); caller frees
+    pub fn syntaxObjects(self: *Editor, kind: TextObjects.SyntaxKind) ![]TextObjects.SyntaxObject {
+        const parser = self.syntaxParser() orelse return error.NoSyntaxTree;
+        return parser.textObjects(self.allocator, kind);
+    }
+
+    /// Leading whitespace for a line of the active buffer (see Indent.Line); caller frees
+    /// `content` is its text from the first non-blank character. It comes from the
+    /// language's indents.scm where the code around the line parses, and from the
+    /// nearest non-blank line above otherwise.
+    pub fn indentFor(self: *Editor, line: Indent.Line, content: []const u8) ![]u8 {
+        const unit = try self.config.getTabString(self.allocator);
+        defer self.allocator.free(unit);
+        const buffer = self.getActiveBuffer() orelse return self.allocator.dupe(u8, "");
+        const rope = &buffer.rope;
+
+        if (self.syntaxParser()) |parser| {
+            const at = line.first orelse line.start;
+            if (!parser.inErrorNode(at)) {
+                if (try parser.indentCaptures(self.allocator, rope.lineToByte(line.above), at + 1)) |captures| {
+                    defer self.allocator.free(captures);
+                    return Indent.whitespace(self.allocator, unit, Indent.indentLevel(captures, line));
+                }
+            }
+        }
+
+        var above_line = line.above;
+        while (true) : (above_line -= 1) {
+            const above = try rope.slice(self.allocator, rope.lineToByte(above_line), @min(rope.lineEnd(above_line), line.start));
+            defer self.allocator.free(above);
+            if (std.mem.trim(u8, above, " \t\r").len > 0 or above_line == 0) {
+                return Indent.fallback(self.allocator, above, content, unit);
+            }
+        }
+    }
+
+    /// Indentation for a blank line opened below or above `line` (`o`, `O`); caller frees
+    /// Empty when auto_indent is off.
+    pub fn openedLineIndent(self: *Editor, line: usize, below: bool) ![]u8 {
+        if (!self.config.auto_indent) return self.allocator.dupe(u8, "");
+        const buffer = self.getActiveBuffer() orelse return self.allocator.dupe(u8, "");
+        const rope = &buffer.rope;
+        const at = @min(line, rope.lineCount() - 1);
+        if (below) return self.indentFor(.{ .start = rope.lineEnd(at), .first = null, .above = at }, "");
+        return self.indentFor(.{ .start = rope.lineToByte(at), .first = null, .above = at -| 1 }, "");
+    }
+
+    /// Text Enter inserts at `pos`, and the column the cursor lands on in the new line
+    /// Between brackets (`{|}`) the closing one moves down a further line, leaving
+    /// the cursor on an indented line of its own.
+    fn newlineText(self: *Editor, pos: Cursor.Position) !struct { text: []u8, col: usize } {
+        const buffer = self.getActiveBuffer() orelse return error.NoActiveBuffer;
+        const rope = &buffer.rope;
+        const offset = try Actions.positionToByteOffset(buffer, pos);
+        const rest = try rope.slice(self.allocator, offset, rope.lineEnd(pos.line));
+        defer self.allocator.free(rest);
+        const content = std.mem.trimLeft(u8, rest, " \t");
+        const first: ?usize = if (std.mem.trimRight(u8, content, "\r").len == 0) null else offset + rest.len - content.len;
+
+        const indent = try self.indentFor(.{ .start = offset, .first = first, .above = pos.line }, content);
+        defer self.allocator.free(indent);
+
+        var cursor = rope.cursorAt(offset);
+        const before = cursor.prevByte() orelse 0;
+        if (first == null or !Indent.isOpener(before) or !Indent.isCloser(content[0])) {
+            return .{ .text = try std.mem.concat(self.allocator, u8, &.{ "\n", indent }), .col = indent.len };
+        }
+
+        const inner = try self.indentFor(.{ .start = offset, .first = null, .above = pos.line }, "");
+        defer self.allocator.free(inner);
+        return .{ .text = try std.mem.concat(self.allocator, u8, &.{ "\n", inner, "\n", indent }), .col = inner.len };
+    }
+
+    /// Reindent the line of a closing bracket just typed as its first character
+    /// Recorded in the open undo group, so it undoes along with the typing.
+    fn reindentClosingLine(self: *Editor, buffer: *Buffer.Buffer, sel: Cursor.Selection) !Cursor.Selection {
+        const rope = &buffer.rope;
+        const line = sel.head.line;
+        const line_start = rope.lineToByte(line);
+        const text = try rope.lineSlice(self.allocator, line);
+        defer self.allocator.free(text);
+        const old = Indent.leading(text);
+        if (sel.head.col != old.len + 1) return sel; // Something precedes the bracket
+
+        const indent = try self.indentFor(.{ .start = line_start, .first = line_start + old.len, .above = line -| 1 }, text[old.len..]);
+        defer self.allocator.free(indent);
+        if (std.mem.eql(u8, indent, old)) return sel;
+
+        if (buffer.undo_group) |*group| {
+            try group.addOperation(self.allocator, try Undo.Operation.init(self.allocator, .replace, .{ .line = line, .col = 0 }, indent, old));
+        }
+        if (old.len > 0) try buffer.delete(line_start, line_start + old.len);
+        try buffer.insert(line_start, indent);
+        return Cursor.Selection.cursor(.{ .line = line, .col = indent.len + 1 });
+    }
+
+    /// Lines `first`..`last` reindented from the syntax tree, as `=` rewrites them; caller frees
+    /// Null when the language has no indents.scm. Lines in code that doesn't parse keep
+    /// their indentation, and blank lines lose theirs.
+    fn syntaxReindent(self: *Editor, first: usize, last: usize) !?[]u8 {
+        if (first > last) return null;
+        const parser = self.syntaxParser() orelse return null;
+        const buffer = self.getActiveBuffer() orelse return null;
+        const rope = &buffer.rope;
+        const span = Operator.lineSpan(buffer, first, last);
+        const captures = try parser.indentCaptures(self.allocator, rope.lineToByte(first -| 1), span.end) orelse return null;
+        defer self.allocator.free(captures);
+
+        const unit = try self.config.getTabString(self.allocator);
+        defer self.allocator.free(unit);
+
+        var out = std.ArrayList(u8).empty;
+        errdefer out.deinit(self.allocator);
+        const last_line = @min(last, rope.lineCount() - 1);
+        for (first..last_line + 1) |line| {
+            if (line > first) try out.append(self.allocator, '\n');
+            const text = try rope.lineSlice(self.allocator, line);
+            defer self.allocator.free(text);
+            const old = Indent.leading(text);
+            const content = text[old.len..];
+            if (std.mem.trimRight(u8, content, "\r").len == 0) {
+                try out.appendSlice(self.allocator, content);
+                continue;
+            }
+
+            const line_start = rope.lineToByte(line);
+            if (parser.inErrorNode(line_start + old.len)) {
+                try out.appendSlice(self.allocator, text);
+                continue;
+            }
+            const level = Indent.indentLevel(captures, .{ .start = line_start, .first = line_start + old.len, .above = line -| 1 });
+            for (0..level) |_| try out.appendSlice(self.allocator, unit);
+            try out.appendSlice(self.allocator, content);
+        }
+        if (last_line + 1 < rope.lineCount()) try out.append(self.allocator, '\n');
+        return try out.toOwnedSlice(self.allocator);
+    }
+
+    /// Folds of the active buffer, found again if it changed since they last were
+    /// Ranges come from the language's folds.scm, or from indentation without one.
+    /// Null for large files, which are too big to scan on every edit.
+    pub fn refreshFolds(self: *Editor) !?*Fold.FoldSet {
+        const id = self.buffer_manager.active_buffer_id orelse return null;
+        const buffer = self.buffer_manager.getBuffer(id) orelse return null;
+        if (buffer.metadata.large_file) return null;
+
+        if (self.folds.getPtr(id)) |fold_set| {
+            if (fold_set.version == buffer.version) return fold_set;
+        }
+
+        const from_tree = if (self.syntaxParser()) |parser| try parser.foldRanges(self.allocator) else null;
+        const ranges = from_tree orelse try Fold.indentRanges(self.allocator, &buffer.rope);
+        errdefer self.allocator.free(ranges);
+
+        const entry = try self.folds.getOrPut(id);
+        if (!entry.found_existing) entry.value_ptr.* = .{};
+        const changes = if (entry.value_ptr.version) |version| buffer.changesSince(version) else null;
+        entry.value_ptr.update(self.allocator, ranges, buffer.version, changes);
+        return entry.value_ptr;
+    }
+
+    /// Folds of the active buffer brought up to date, if folds were used in it yet
+    /// Buffers nobody folded aren't scanned after every edit.
+    pub fn usedFolds(self: *Editor) !?*Fold.FoldSet {
+        const id = self.buffer_manager.active_buffer_id orelse return null;
+        if (!self.folds.contains(id)) return null;
+        return self.refreshFolds();
+    }
+
+    /// Folds of the active buffer as last found, for drawing; null before they first are
+    pub fn activeFolds(self: *const Editor) ?*const Fold.FoldSet {
+        const id = self.buffer_manager.active_buffer_id orelse return null;
+        return self.folds.getPtr(id);
+    }
+
+    /// Close the innermost open fold around the cursor (`zc`)
+    /// The cursor moves to the fold's first line. Returns false if there was none.
+    pub fn closeFold(self: *Editor) !bool {
+        const fold_set = try self.refreshFolds() orelse return false;
+        const range = fold_set.openAround(self.getCursorPosition().line) orelse return false;
+        try fold_set.close(self.allocator, range);
+        try self.leaveClosedFolds(fold_set);
+        return true;
+    }
+
+    /// Open the closed fold on the cursor's line (`zo`); false if there was none
+    pub fn openFold(self: *Editor) !bool {
+        const fold_set = try self.refreshFolds() orelse return false;
+        return fold_set.open(self.getCursorPosition().line);
+    }
+
+    /// Open the closed fold on the cursor's line, or close the one around it (`za`)
+    pub fn toggleFold(self: *Editor) !bool {
+        if (try self.openFold()) return true;
+        return self.closeFold();
+    }
+
+    /// Close every fold (`zM`); false if the buffer has none
+    pub fn closeAllFolds(self: *Editor) !bool {
+        const fold_set = try self.refreshFolds() orelse return false;
+        if (fold_set.ranges.len == 0) return false;
+        try fold_set.closeAll(self.allocator);
+        try self.leaveClosedFolds(fold_set);
+        return true;
+    }
+
+    /// Open every fold (`zR`); false if the buffer has none
+    pub fn openAllFolds(self: *Editor) !bool {
+        const fold_set = try self.refreshFolds() orelse return false;
+        if (fold_set.ranges.len == 0) return false;
+        fold_set.openAll();
+        return true;
+    }
+
+    /// Move the cursor onto the line a fold that just closed over it shows
+    fn leaveClosedFolds(self: *Editor, fold_set: *const Fold.FoldSet) !void {
+        const buffer = self.getActiveBuffer() orelse return;
+        const primary = self.selections.primary(self.allocator) orelse return;
+        const line = fold_set.visibleLine(primary.head.line);
+        if (line == primary.head.line) return;
+        try self.selections.setSingleCursor(self.allocator, Motions.moveToLine(primary, buffer, line).head);
+    }
+
+    /// Grow every selection to the syntax node around it
+    /// Remembers the selections it replaced, so `shrinkSelection` can restore them.
+    /// Returns false if no selection could grow.
+    pub fn expandSelection(self: *Editor) !bool {
+        const before = try self.allocator.dupe(Cursor.Selection, self.selections.all(self.allocator));
+        errdefer self.allocator.free(before);
+
+        if (!try self.stepSelections(.parent)) {
+            self.allocator.free(before);
+            return false;
+        }
+        // History from other text or other selections no longer leads here
+        if (!self.syntaxHistoryCurrent(before)) self.clearSyntaxHistory();
+
+        const after = try self.allocator.dupe(Cursor.Selection, self.selections.all(self.allocator));
+        errdefer self.allocator.free(after);
+        try self.syntax_history.append(self.allocator, .{
+            .buffer_id = self.syntax_buffer.?,
+            .version = self.syntax_version,
+            .before = before,
+            .after = after,
+        });
+        return true;
+    }
+
+    /// Undo the last expansion, or shrink every selection to its first child node
+    /// Returns false if no selection could shrink.
+    pub fn shrinkSelection(self: *Editor) !bool {
+        _ = self.syntaxParser() orelse return error.NoSyntaxTree;
+        if (self.syntaxHistoryCurrent(self.selections.all(self.allocator))) {
+            const last = self.syntax_history.pop().?;
+            defer last.deinit(self.allocator);
+            try self.selections.setSelections(self.allocator, last.before);
+            return true;
+        }
+        self.clearSyntaxHistory();
+        return self.stepSelections(.first_child);
+    }
+
+    /// Move every selection to the next or previous sibling node
+    /// Returns false if no selection had one.
+    pub fn selectSiblingNode(self: *Editor, forward: bool) !bool {
+        return self.stepSelections(if (forward) .next_sibling else .prev_sibling);
+    }
+
+    /// Whether the last expansion ended at `selections`, in the text just parsed
+    fn syntaxHistoryCurrent(self: *const Editor, selections: []const Cursor.Selection) bool {
+        const last = self.syntax_history.getLastOrNull() orelse return false;
+        const buffer_id = self.syntax_buffer orelse return false;
+        if (buffer_id != last.buffer_id or self.syntax_version != last.version) return false;
+        if (last.after.len != selections.len) return false;
+        for (last.after, selections) |a, b| {
+            if (!a.anchor.eql(b.anchor) or !a.head.eql(b.head)) return false;
+        }
+        return true;
+    }
+
+    fn clearSyntaxHistory(self: *Editor) void {
+        for (self.syntax_history.items) |expansion| expansion.deinit(self.allocator);
+        self.syntax_history.clearRetainingCapacity();
+    }
+
+    /// Replace each selection with the node one `step` away in the syntax tree
+    /// Selections with nowhere to go stay put; ones that land on the same node merge.
+    fn stepSelections(self: *Editor, step: TreeSitter.NodeStep) !bool {
+        const parser = self.syntaxParser() orelse return error.NoSyntaxTree;
+        const buffer = self.buffer_manager.getActiveBuffer() orelse return error.NoSyntaxTree;
+
+        var next = std.ArrayList(Cursor.Selection).empty;
+        defer next.deinit(self.allocator);
+        var moved = false;
+        for (self.selections.all(self.allocator)) |sel| {
+            const range = sel.range();
+            const start = try Actions.positionToByteOffset(buffer, range.start);
+            var end = try Actions.positionToByteOffset(buffer, range.end);
+            // A cursor stands for the character under it
+            if (end == start) end = @min(start + 1, buffer.rope.len());
+
+            var new_sel = sel;
+            if (parser.syntaxNode(start, end, step)) |node| {
+                new_sel = Cursor.Selection.init(positionAt(buffer, node.start_byte), positionAt(buffer, node.end_byte));
+                moved = true;
+            }
+            for (next.items) |other| {
+                if (other.anchor.eql(new_sel.anchor) and other.head.eql(new_sel.head)) break;
+            } else try next.append(self.allocator, new_sel);
+        }
+        if (!moved) return false;
+
+        try self.selections.setSelections(self.allocator, next.items);
+        self.ensureCursorVisible();
+        return true;
+    }
+
+    /// Get buffer shown in the active window (falls back to the active buffer)
+    pub fn activeWindowBuffer(self: *Editor) ?*Buffer.Buffer {
+        if (self.window_manager.getActiveWindow()) |window| {
+            if (window.buffer_id) |id| {
+                if (self.buffer_manager.getBufferMut(id)) |buffer| return buffer;
+            }
+        }
+        const id = self.buffer_manager.active_buffer_id orelse return null;
+        return self.buffer_manager.getBufferMut(id);
+    }
+
+    // === Undo Tree ===
+
+    /// Move the active window's buffer to an undo state and restore its cursor
+    pub fn undoJumpTo(self: *Editor, seq: usize) !void {
+        const buffer = self.activeWindowBuffer() orelse return error.NoActiveBuffer;
+        if (try buffer.undo_history.jumpTo(seq, buffer, self.allocator)) |cursor| {
+            try self.selections.setSingleCursor(self.allocator, cursor);
+            self.ensureCursorVisible();
+        }
+    }
+
+    /// Open the undo tree popup with the current state selected
+    pub fn openUndoTree(self: *Editor) !void {
+        const buffer = self.activeWindowBuffer() orelse return error.NoActiveBuffer;
+        const entries = try buffer.undo_history.listTree(self.allocator);
+        defer self.allocator.free(entries);
+
+        self.undo_tree_origin = buffer.undo_history.currentSeq();
+        self.undo_tree_selected = 0;
+        for (entries, 0..) |entry, i| {
+            if (entry.is_current) self.undo_tree_selected = i;
+        }
+        self.undo_tree_visible = true;
+    }
+
+    /// Move the popup selection and preview that state in the buffer
+    pub fn moveUndoTreeSelection(self: *Editor, delta: isize) !void {
+        const buffer = self.activeWindowBuffer() orelse return error.NoActiveBuffer;
+        const entries = try buffer.undo_history.listTree(self.allocator);
+        defer self.allocator.free(entries);
+
+        const target = @as(isize, @intCast(self.undo_tree_selected)) + delta;
+        if (target < 0 or target >= @as(isize, @intCast(entries.len))) return;
+
+        self.undo_tree_selected = @intCast(target);
+        try self.undoJumpTo(entries[self.undo_tree_selected].seq);
+    }
+
+    /// Close the undo tree popup; cancelling returns to the state it was opened at
+    pub fn closeUndoTree(self: *Editor, accept: bool) !void {
+        self.undo_tree_visible = false;
+        self.undo_tree_selected = 0;
+        if (!accept) try self.undoJumpTo(self.undo_tree_origin);
+    }
+
     /// Create new empty buffer
     pub fn newBuffer(self: *Editor) !void {
         _ = try self.buffer_manager.createEmpty();
@@ -227,50 +782,543 @@ pub const Editor = struct {
 
     /// Open file
     pub fn openFile(self: *Editor, filepath: []const u8) !void {
-        const buffer_id = try self.buffer_manager.openFile(filepath);
+        const buffer_id = try self.buffer_manager.openFile(filepath, .{
+            .large_file_threshold = self.config.large_file_threshold_mb * 1024 * 1024,
+        });
         // Reset selections for new buffer
         try self.selections.setSingleCursor(self.allocator, .{ .line = 0, .col = 0 });
         self.scroll_offset = 0;
         self.col_offset = 0;
 
+        // Watching still matters for large files; everything that copies the text doesn't
+        const large = if (self.buffer_manager.getBuffer(buffer_id)) |buffer| buffer.metadata.large_file else false;
+        if (large) {
+            self.messages.add("Large file: highlighting, LSP, swap file, and persistent undo are off", .info) catch {};
+            if (self.buffer_manager.getBufferMut(buffer_id)) |buffer| self.watchFile(buffer, true);
+            self.plugin_manager.dispatchBufferOpen(buffer_id) catch {};
+            return;
+        }
+
+        // Pick up undo history from a previous session
+        if (self.config.persistent_undo) {
+            if (self.buffer_manager.getBufferMut(buffer_id)) |buffer| {
+                const restored = buffer.enablePersistentUndo(self.buffer_manager.undo_dir) catch false;
+                if (restored) self.messages.add("Restored undo history", .info) catch {};
+            }
+        }
+
+        // Claim the file for crash recovery, unless a crash or another instance got there first
+        if (self.config.swap_files) {
+            if (self.buffer_manager.getBufferMut(buffer_id)) |buffer| {
+                self.attachSwap(buffer) catch {
+                    self.messages.add("Could not create swap file", .warning) catch {};
+                };
+            }
+        }
+
+        // Notice when git, generators, or other editors rewrite the file
+        if (self.buffer_manager.getBufferMut(buffer_id)) |buffer| self.watchFile(buffer, true);
+
         // Dispatch buffer open event to plugins
         self.plugin_manager.dispatchBufferOpen(buffer_id) catch {};
 
-        // Notify LSP if available
-        if (self.lsp_client) |*client| {
-            // Set notification handler if not already set
-            if (client.notification_handler == null) {
-                client.setNotificationHandler(handleLspNotification, self);
+        // Hand the document to the language server for its language
+        self.lspOpenDocument(filepath) catch |err| {
+            self.reportLsp("[LSP] Failed to open document {s}: {s}", .{ filepath, @errorName(err) });
+        };
+    }
+
+    /// Apply `:set option=value` to the active buffer, or show the option without a value
+    /// Supports fileformat (ff), fileencoding (fenc), and bomb/nobomb.
+    pub fn setOption(self: *Editor, option: []const u8, value: ?[]const u8) void {
+        const buffer_id = self.buffer_manager.active_buffer_id orelse return;
+        const buffer = self.buffer_manager.getBufferMut(buffer_id) orelse return;
+        var format = buffer.metadata.format;
+        var msg_buf: [128]u8 = undefined;
+
+        if (std.mem.eql(u8, option, "fileformat") or std.mem.eql(u8, option, "ff")) {
+            const name = value orelse {
+                const msg = std.fmt.bufPrint(&msg_buf, "fileformat={s}", .{format.line_ending.name()}) catch return;
+                self.messages.add(msg, .info) catch {};
+                return;
+            };
+            format.line_ending = Encoding.LineEnding.fromName(name) orelse {
+                self.messages.add("Unknown fileformat (use unix, dos, or mac)", .error_msg) catch {};
+                return;
+            };
+        } else if (std.mem.eql(u8, option, "fileencoding") or std.mem.eql(u8, option, "fenc")) {
+            const name = value orelse {
+                const msg = std.fmt.bufPrint(&msg_buf, "fileencoding={s}", .{format.encoding.name()}) catch return;
+                self.messages.add(msg, .info) catch {};
+                return;
+            };
+            format.encoding = Encoding.Encoding.fromName(name) orelse {
+                self.messages.add("Unknown fileencoding (use utf-8, utf-16le, utf-16be, or latin1)", .error_msg) catch {};
+                return;
+            };
+            // Latin-1 has no byte order mark
+            if (format.encoding == .latin1) format.bom = false;
+        } else if (std.mem.eql(u8, option, "bomb") or std.mem.eql(u8, option, "nobomb")) {
+            format.bom = option[0] == 'b' and format.encoding != .latin1;
+        } else {
+            const msg = std.fmt.bufPrint(&msg_buf, "Unknown option: {s}", .{option}) catch "Unknown option";
+            self.messages.add(msg, .error_msg) catch {};
+            return;
+        }
+
+        buffer.setFileFormat(format) catch {
+            self.messages.add("Large files are saved byte-for-byte; their format can't be changed", .error_msg) catch {};
+        };
+    }
+
+    // === Swap Files ===
+
+    /// Check for an existing swap file before starting one for a newly opened buffer
+    fn attachSwap(self: *Editor, buffer: *Buffer.Buffer) !void {
+        var swap = (try buffer.readSwap()) orelse return buffer.enableSwap();
+        var keep_swap = false;
+        defer if (!keep_swap) swap.deinit(self.allocator);
+
+        var msg_buf: [256]u8 = undefined;
+        const name = buffer.metadata.getName();
+
+        // Already open in another buffer of this session; both write to it, and
+        // closing one leaves it for the other
+        if (swap.pid == SwapFile.currentPid()) return buffer.shareSwap();
+
+        if (swap.ownerAlive()) {
+            // Leave their swap file alone; ours would overwrite it
+            const msg = std.fmt.bufPrint(&msg_buf, "{s} is already being edited by another aesop (pid {d})", .{
+                name,
+                swap.pid,
+            }) catch "File is already being edited by another aesop";
+            self.messages.add(msg, .warning) catch {};
+            return;
+        }
+
+        const unsaved = swap.text orelse return buffer.enableSwap();
+        if (self.swap_recovery != null) {
+            // One decision at a time; this one waits until the file is reopened
+            const msg = std.fmt.bufPrint(&msg_buf, "{s} has unsaved changes from a crash; reopen it to recover", .{name}) catch
+                "File has unsaved changes from a crash";
+            self.messages.add(msg, .warning) catch {};
+            return;
+        }
+
+        const disk_text = try buffer.getText();
+        if (std.mem.eql(u8, disk_text, unsaved)) {
+            // The edits made it to disk after all
+            self.allocator.free(disk_text);
+            return buffer.enableSwap();
+        }
+        errdefer self.allocator.free(disk_text);
+
+        const diff = try Diff.diffLines(self.allocator, disk_text, unsaved, SwapRecovery.DIFF_CONTEXT);
+        self.swap_recovery = .{
+            .buffer_id = buffer.metadata.id,
+            .swap = swap,
+            .disk_text = disk_text,
+            .diff = diff,
+        };
+        keep_swap = true;
+    }
+
+    /// Act on the swap recovery prompt
+    pub fn resolveSwapRecovery(self: *Editor, action: RecoveryAction) !void {
+        var recovery = self.swap_recovery orelse return;
+        self.swap_recovery = null;
+        defer recovery.deinit(self.allocator);
+
+        const buffer = self.buffer_manager.getBufferMut(recovery.buffer_id) orelse return;
+        var msg_buf: [256]u8 = undefined;
+        const name = buffer.metadata.getName();
+
+        switch (action) {
+            .recover => {
+                try buffer.enableSwap();
+                try buffer.replaceContent(recovery.swap.text.?);
+                try buffer.writeSwap();
+                try self.selections.setSingleCursor(self.allocator, .{ .line = 0, .col = 0 });
+
+                const msg = std.fmt.bufPrint(&msg_buf, "Recovered unsaved changes to {s}", .{name}) catch "Recovered unsaved changes";
+                self.messages.add(msg, .success) catch {};
+            },
+            .discard => {
+                try buffer.enableSwap();
+                self.messages.add("Discarded unsaved changes from swap file", .info) catch {};
+            },
+            .defer_decision => {
+                const msg = std.fmt.bufPrint(&msg_buf, "Swap file kept; {s} has no crash protection until reopened", .{name}) catch
+                    "Swap file kept";
+                self.messages.add(msg, .warning) catch {};
+            },
+        }
+    }
+
+    /// Scroll the diff view of the open recovery or conflict prompt
+    pub fn scrollPromptDiff(self: *Editor, delta: isize) void {
+        if (self.swap_recovery) |*recovery| {
+            recovery.scroll = scrollClamped(recovery.scroll, delta, recovery.diff.len);
+        } else if (self.disk_conflict) |*conflict| {
+            conflict.scroll = scrollClamped(conflict.scroll, delta, conflict.diff.len);
+        }
+    }
+
+    fn scrollClamped(scroll: usize, delta: isize, line_count: usize) usize {
+        const target = @as(isize, @intCast(scroll)) + delta;
+        return @min(@as(usize, @intCast(@max(target, 0))), line_count -| 1);
+    }
+
+    /// Write swap files for buffers edited since their last write, at most once per interval
+    pub fn updateSwapFiles(self: *Editor) void {
+        const now = std.time.milliTimestamp();
+        for (self.buffer_manager.buffers.items) |*buffer| {
+            if (!buffer.swapOutdated()) continue;
+            if (now - buffer.swap_written_at < @as(i64, @intCast(self.config.swap_interval_ms))) continue;
+
+            buffer.writeSwap() catch {
+                // Don't retry until the next interval
+                buffer.swap_written_at = now;
+                self.messages.add("Failed to write swap file", .warning) catch {};
+            };
+        }
+    }
+
+    /// Write every outdated swap file now (the editor is about to be killed)
+    pub fn flushSwapFiles(self: *Editor) void {
+        for (self.buffer_manager.buffers.items) |*buffer| {
+            if (buffer.swapOutdated()) buffer.writeSwap() catch {};
+        }
+    }
+
+    /// Delete all swap files on a clean exit; unsaved changes were abandoned on purpose
+    pub fn releaseSwapFiles(self: *Editor) void {
+        for (self.buffer_manager.buffers.items) |*buffer| {
+            buffer.removeSwap();
+        }
+    }
+
+    /// Tell the user about crashed sessions whose files aren't open
+    pub fn reportOrphanedSwaps(self: *Editor) void {
+        if (!self.config.swap_files) return;
+
+        var dir = SwapFile.openSwapDir() catch return;
+        defer dir.close();
+        const orphans = SwapFile.listOrphans(dir, self.allocator) catch return;
+        defer {
+            for (orphans) |path| self.allocator.free(path);
+            self.allocator.free(orphans);
+        }
+
+        var count: usize = 0;
+        var first: []const u8 = "";
+        for (orphans) |path| {
+            if (self.isSwapPathOpen(path)) continue;
+            if (count == 0) first = path;
+            count += 1;
+        }
+        if (count == 0) return;
+
+        var msg_buf: [512]u8 = undefined;
+        const msg = if (count == 1)
+            std.fmt.bufPrint(&msg_buf, "{s} has unsaved changes from a crash; open it to recover", .{first}) catch
+                "A file has unsaved changes from a crash"
+        else
+            std.fmt.bufPrint(&msg_buf, "{d} files have unsaved changes from a crash; open them to recover", .{count}) catch
+                "Some files have unsaved changes from a crash";
+        self.messages.add(msg, .warning) catch {};
+    }
+
+    fn isSwapPathOpen(self: *const Editor, abs_path: []const u8) bool {
+        if (self.swap_recovery) |recovery| {
+            if (std.mem.eql(u8, recovery.swap.path, abs_path)) return true;
+        }
+        for (self.buffer_manager.buffers.items) |buffer| {
+            if (buffer.swap_path) |path| {
+                if (std.mem.eql(u8, path, abs_path)) return true;
             }
+        }
+        return false;
+    }
 
-            if (client.isReady()) {
-                const buffer = self.buffer_manager.getActiveBuffer() orelse return;
-                const uri = try self.makeFileUri(filepath);
-                defer self.allocator.free(uri);
-                const text = try buffer.getText();
-                defer self.allocator.free(text);
-                LspHandlers.didOpen(client, uri, "zig", 0, text) catch {};
+    // === External Changes ===
+
+    /// Start or stop change notifications for a buffer's file
+    /// The resolved path is kept on the buffer to match events against, and to
+    /// unwatch with after the file is gone.
+    fn watchFile(self: *Editor, buffer: *Buffer.Buffer, watch: bool) void {
+        if (buffer.watch_path) |old| {
+            self.file_watcher.unwatch(old);
+            buffer.allocator.free(old);
+            buffer.watch_path = null;
+        }
+        if (!watch) return;
+
+        const filepath = buffer.metadata.filepath orelse return;
+        const abs_path = std.fs.cwd().realpathAlloc(buffer.allocator, filepath) catch return;
+        self.file_watcher.watch(abs_path) catch {
+            buffer.allocator.free(abs_path);
+            return;
+        };
+        buffer.watch_path = abs_path;
+    }
+
+    /// Reload or flag buffers whose files changed on disk (called from the event loop)
+    /// Unmodified buffers are reloaded; modified ones open the conflict prompt.
+    pub fn checkExternalChanges(self: *Editor) void {
+        const now = std.time.milliTimestamp();
+        if (!self.file_watcher.poll()) {
+            // Nothing reported; without a watcher, look anyway every so often
+            if (self.file_watcher.isActive() or now - self.last_disk_check < DISK_POLL_INTERVAL_MS) return;
+        }
+        self.last_disk_check = now;
+
+        var msg_buf: [256]u8 = undefined;
+        for (self.buffer_manager.buffers.items) |*buffer| {
+            // With notifications, only files they named need a stat
+            if (self.file_watcher.isActive()) {
+                const path = buffer.watch_path orelse continue;
+                if (!self.file_watcher.changed(path)) continue;
+            }
+            const status = buffer.diskStatus() catch continue;
+            const name = buffer.metadata.getName();
+
+            switch (status) {
+                .unchanged => buffer.metadata.disk_deleted = false,
+                .deleted => {
+                    if (buffer.metadata.disk_deleted) continue;
+                    buffer.metadata.disk_deleted = true;
+                    const msg = std.fmt.bufPrint(&msg_buf, "{s} was deleted on disk", .{name}) catch "File was deleted on disk";
+                    self.messages.add(msg, .warning) catch {};
+                },
+                .changed => {
+                    if (!buffer.metadata.modified) {
+                        buffer.reload() catch continue;
+                        if (self.buffer_manager.active_buffer_id == buffer.metadata.id) self.clampCursorToBuffer(buffer);
+                        const msg = std.fmt.bufPrint(&msg_buf, "Reloaded {s} (changed on disk)", .{name}) catch "Reloaded file";
+                        self.messages.add(msg, .info) catch {};
+                    } else if (buffer.metadata.large_file) {
+                        // Too big to diff; just warn (saving needs :w! from here on)
+                        if (buffer.metadata.disk_conflict) continue;
+                        buffer.metadata.disk_conflict = true;
+                        const msg = std.fmt.bufPrint(&msg_buf, "{s} changed on disk; :w! overwrites it, reopening discards your changes", .{name}) catch "File changed on disk";
+                        self.messages.add(msg, .warning) catch {};
+                    } else if (!buffer.metadata.disk_conflict and self.disk_conflict == null) {
+                        self.openDiskConflict(buffer) catch continue;
+                    }
+                },
+                .truncated => {
+                    // The buffer now holds a copy, so saving it needs :w! like any conflict
+                    buffer.metadata.disk_conflict = true;
+                    if (self.buffer_manager.active_buffer_id == buffer.metadata.id) self.clampCursorToBuffer(buffer);
+                    const msg = std.fmt.bufPrint(&msg_buf, "{s} was truncated on disk; text past its new end is gone from the buffer", .{name}) catch "File was truncated on disk";
+                    self.messages.add(msg, .warning) catch {};
+                },
             }
         }
     }
 
+    /// Copy mapped files that shrank out of their mappings (see Buffer.guardMapping)
+    /// The next checkExternalChanges reports them.
+    pub fn guardMappedFiles(self: *Editor) void {
+        for (self.buffer_manager.buffers.items) |*buffer| {
+            buffer.guardMapping() catch {};
+            if (buffer.mapping_cut and self.buffer_manager.active_buffer_id == buffer.metadata.id) self.clampCursorToBuffer(buffer);
+        }
+    }
+
+    /// Keep the cursor inside a buffer whose content was replaced
+    fn clampCursorToBuffer(self: *Editor, buffer: *const Buffer.Buffer) void {
+        const cursor = self.getCursorPosition();
+        const last_line = buffer.lineCount() -| 1;
+        if (cursor.line > last_line) {
+            self.selections.setSingleCursor(self.allocator, .{ .line = last_line, .col = 0 }) catch {};
+        }
+        self.ensureCursorVisible();
+    }
+
+    fn openDiskConflict(self: *Editor, buffer: *Buffer.Buffer) !void {
+        const filepath = buffer.metadata.filepath orelse return;
+        const disk_bytes = try std.fs.cwd().readFileAlloc(self.allocator, filepath, Buffer.MAX_FILE_SIZE);
+        defer self.allocator.free(disk_bytes);
+        // Compare like with like: the buffer holds decoded text
+        const disk_text = try Encoding.decode(self.allocator, disk_bytes, Encoding.detect(disk_bytes));
+        errdefer self.allocator.free(disk_text);
+        const buffer_text = try buffer.getText();
+        errdefer self.allocator.free(buffer_text);
+        const diff = try Diff.diffLines(self.allocator, buffer_text, disk_text, SwapRecovery.DIFF_CONTEXT);
+
+        buffer.metadata.disk_conflict = true;
+        self.disk_conflict = .{
+            .buffer_id = buffer.metadata.id,
+            .buffer_text = buffer_text,
+            .disk_text = disk_text,
+            .diff = diff,
+        };
+    }
+
+    /// Act on the disk conflict prompt
+    pub fn resolveDiskConflict(self: *Editor, action: ConflictAction) !void {
+        var conflict = self.disk_conflict orelse return;
+        self.disk_conflict = null;
+        defer conflict.deinit(self.allocator);
+
+        const buffer = self.buffer_manager.getBufferMut(conflict.buffer_id) orelse return;
+        var msg_buf: [256]u8 = undefined;
+        const name = buffer.metadata.getName();
+
+        switch (action) {
+            .reload => {
+                try buffer.reload();
+                if (self.buffer_manager.active_buffer_id == buffer.metadata.id) self.clampCursorToBuffer(buffer);
+                const msg = std.fmt.bufPrint(&msg_buf, "Reloaded {s}; undo to get your changes back", .{name}) catch "Reloaded file";
+                self.messages.add(msg, .info) catch {};
+            },
+            .overwrite => {
+                var options = self.config.saveOptions();
+                options.force = true;
+                try buffer.save(options);
+                const msg = std.fmt.bufPrint(&msg_buf, "Overwrote {s} with your version", .{name}) catch "File saved";
+                self.messages.add(msg, .success) catch {};
+            },
+            .keep => {
+                const msg = std.fmt.bufPrint(&msg_buf, "Keeping your changes to {s}; :w! overwrites the file on disk", .{name}) catch
+                    "Keeping your changes";
+                self.messages.add(msg, .warning) catch {};
+            },
+        }
+    }
+
+    /// Send didOpen for a file, starting the server for its language and workspace if needed
+    fn lspOpenDocument(self: *Editor, filepath: []const u8) !void {
+        const language_id = Language.fromFilename(filepath).lspId() orelse return;
+        const command_line = self.config.lspServerCommand(language_id) orelse return;
+
+        // Route server notifications (diagnostics etc.) and errors back to this editor
+        if (self.lsp_servers.notification_handler == null) {
+            self.lsp_servers.setNotificationHandler(handleLspNotification, self);
+            self.lsp_servers.setLogHandler(handleLspLog, self);
+        }
+
+        const abs_path = try std.fs.cwd().realpathAlloc(self.allocator, filepath);
+        defer self.allocator.free(abs_path);
+        const root_path = try LspServers.findWorkspaceRoot(self.allocator, language_id, abs_path);
+        defer self.allocator.free(root_path);
+        const root_uri = try self.makeFileUri(root_path);
+        defer self.allocator.free(root_uri);
+
+        const session = self.lsp_servers.ensure(language_id, root_uri, command_line) catch |err| {
+            // Only report the first failure; the registry remembers it
+            if (err != error.ServerUnavailable) {
+                var msg_buf: [256]u8 = undefined;
+                const msg = std.fmt.bufPrint(&msg_buf, "Language server for {s} unavailable: {s}", .{
+                    language_id,
+                    @errorName(err),
+                }) catch "Language server unavailable";
+                self.messages.add(msg, .warning) catch {};
+            }
+            return;
+        };
+
+        const buffer_id = self.buffer_manager.active_buffer_id orelse return;
+        const buffer = self.buffer_manager.getBufferMut(buffer_id) orelse return;
+        const uri = try self.makeFileUri(abs_path);
+        defer self.allocator.free(uri);
+        const text = try buffer.getText();
+        defer self.allocator.free(text);
+        try self.lsp_servers.openDocument(session, uri, text);
+
+        // Changes after this version go out as didChange
+        buffer.lsp_version = buffer.version;
+    }
+
+    /// Send didChange for a buffer's edits to the session that owns it
+    fn syncLspDocument(self: *Editor, buffer: *Buffer.Buffer) void {
+        const since = buffer.lsp_version orelse return;
+        if (since == buffer.version) return;
+        buffer.lsp_version = buffer.version;
+
+        const filepath = buffer.metadata.filepath orelse return;
+        const uri = self.makeFileUri(filepath) catch return;
+        defer self.allocator.free(uri);
+        const session = self.lsp_servers.sessionForUri(uri) orelse return;
+
+        self.sendLspChanges(session, uri, buffer, since) catch |err| {
+            self.reportLsp("[LSP] Failed to sync {s}: {s}", .{ uri, @errorName(err) });
+        };
+    }
+
+    /// Send a buffer's changes since version `since` the way the session takes them
+    fn sendLspChanges(self: *Editor, session: *LspServers.Session, uri: []const u8, buffer: *const Buffer.Buffer, since: usize) !void {
+        switch (session.syncNeed(uri)) {
+            .nothing => return,
+            .changes => if (buffer.changesSince(since)) |changes| {
+                const events = try self.allocator.alloc(LspSync.TextDocumentContentChangeEvent, changes.len);
+                defer self.allocator.free(events);
+                for (changes, events) |change, *event| event.* = .{
+                    .range = .{
+                        .start = .{ .line = @intCast(change.start.line), .character = @intCast(change.start.utf16_col) },
+                        .end = .{ .line = @intCast(change.old_end.line), .character = @intCast(change.old_end.utf16_col) },
+                    },
+                    .text = change.text,
+                };
+                return session.syncChanges(uri, events);
+            },
+            .text => {},
+        }
+
+        // Full sync, or the changes are no longer all kept
+        const text = try buffer.getText();
+        defer self.allocator.free(text);
+        try session.syncText(uri, text);
+    }
+
+    /// Get ready LSP client of the session that owns a file (null if none or still starting)
+    pub fn lspClientForPath(self: *Editor, filepath: []const u8) ?*LspClient {
+        const uri = self.makeFileUri(filepath) catch return null;
+        defer self.allocator.free(uri);
+        return self.lsp_servers.clientForUri(uri);
+    }
+
+    /// Get ready LSP client serving the active buffer
+    /// Flushes pending edits first so requests see the current text.
+    pub fn activeLspClient(self: *Editor) ?*LspClient {
+        const buffer_id = self.buffer_manager.active_buffer_id orelse return null;
+        const buffer = self.buffer_manager.getBufferMut(buffer_id) orelse return null;
+        const filepath = buffer.metadata.filepath orelse return null;
+        self.syncLspDocument(buffer);
+        return self.lspClientForPath(filepath);
+    }
+
+    /// Sync edits to language servers, process their output, and drop sessions whose server exited
+    pub fn pollLsp(self: *Editor) void {
+        for (self.buffer_manager.buffers.items) |*buffer| {
+            self.syncLspDocument(buffer);
+        }
+
+        self.lsp_servers.pollAll();
+
+        while (self.lsp_servers.takeExited()) |session_id| {
+            self.diagnostic_manager.clearSession(session_id);
+            self.messages.add("Language server exited", .warning) catch {};
+        }
+    }
+
     /// Save active buffer
     pub fn save(self: *Editor) !void {
         if (self.buffer_manager.active_buffer_id) |id| {
             const buffer = self.buffer_manager.getBufferMut(id) orelse return error.NoActiveBuffer;
             const filepath = buffer.metadata.filepath orelse return error.NoFilepath;
-            try buffer.save();
+            try buffer.save(self.config.saveOptions());
 
             // Dispatch buffer save event to plugins
             self.plugin_manager.dispatchBufferSave(id) catch {};
 
             // Notify LSP if available
-            if (self.lsp_client) |*client| {
-                if (client.isReady()) {
-                    const uri = try self.makeFileUri(filepath);
-                    defer self.allocator.free(uri);
-                    LspHandlers.didSave(client, uri, null) catch {};
-                }
+            if (self.lspClientForPath(filepath)) |client| {
+                const uri = try self.makeFileUri(filepath);
+                defer self.allocator.free(uri);
+                LspHandlers.didSave(client, uri, null) catch {};
             }
         } else {
             return error.NoActiveBuffer;
@@ -281,7 +1329,9 @@ pub const Editor = struct {
     pub fn saveAs(self: *Editor, filepath: []const u8) !void {
         if (self.buffer_manager.active_buffer_id) |id| {
             const buffer = self.buffer_manager.getBufferMut(id) orelse return error.NoActiveBuffer;
-            try buffer.saveAs(filepath);
+            self.watchFile(buffer, false);
+            defer self.watchFile(buffer, true);
+            try buffer.saveAs(filepath, self.config.saveOptions());
         } else {
             return error.NoActiveBuffer;
         }
@@ -290,23 +1340,26 @@ pub const Editor = struct {
     /// Close active buffer
     pub fn closeBuffer(self: *Editor) !void {
         if (self.buffer_manager.active_buffer_id) |id| {
-            const buffer = self.buffer_manager.getBuffer(id) orelse return error.NoActiveBuffer;
+            const buffer = self.buffer_manager.getBufferMut(id) orelse return error.NoActiveBuffer;
             const filepath = buffer.metadata.filepath;
 
+            self.watchFile(buffer, false);
+
             // Notify LSP before closing
             if (filepath) |path| {
-                if (self.lsp_client) |*client| {
-                    if (client.isReady()) {
-                        const uri = try self.makeFileUri(path);
-                        defer self.allocator.free(uri);
-                        LspHandlers.didClose(client, uri) catch {};
-                    }
-                }
+                const uri = try self.makeFileUri(path);
+                defer self.allocator.free(uri);
+                self.lsp_servers.closeDocument(uri);
             }
 
             // Dispatch close event to plugins
             self.plugin_manager.dispatchBufferClose(id) catch {};
 
+            if (self.folds.fetchRemove(id)) |entry| {
+                var fold_set = entry.value;
+                fold_set.deinit(self.allocator);
+            }
+
             // Close buffer in manager
             try self.buffer_manager.closeBuffer(id);
         } else {
@@ -315,7 +1368,7 @@ pub const Editor = struct {
     }
 
     /// Handle LSP notifications from server
-    pub fn handleLspNotification(ctx: ?*anyopaque, method: []const u8, params_json: []const u8) !void {
+    pub fn handleLspNotification(ctx: ?*anyopaque, session: *LspServers.Session, method: []const u8, params_json: []const u8) !void {
         const self: *Editor = @ptrCast(@alignCast(ctx orelse return));
         const ResponseParser = @import("../lsp/response_parser.zig");
 
@@ -326,13 +1379,14 @@ pub const Editor = struct {
                 self.allocator,
                 params_json,
             ) catch |err| {
-                std.debug.print("[LSP] Failed to parse diagnostics: {}\n", .{err});
+                self.reportLsp("[LSP] Failed to parse diagnostics: {s}", .{@errorName(err)});
                 return;
             };
 
-            // Update diagnostic manager
-            self.diagnostic_manager.update(result.uri, result.diagnostics) catch |err| {
-                std.debug.print("[LSP] Failed to update diagnostics: {}\n", .{err});
+            // Update diagnostic manager, tagged with the publishing session
+            // (takes ownership of result.uri, which may be freed if the key already exists)
+            self.diagnostic_manager.updateForSession(session.id, result.uri, result.diagnostics) catch |err| {
+                self.reportLsp("[LSP] Failed to update diagnostics: {s}", .{@errorName(err)});
                 // Clean up on error
                 self.allocator.free(result.uri);
                 for (result.diagnostics) |*diag| {
@@ -341,15 +1395,21 @@ pub const Editor = struct {
                 self.allocator.free(result.diagnostics);
                 return;
             };
-
-            std.debug.print("[LSP] Updated diagnostics for {s}: {} items\n", .{
-                result.uri,
-                result.diagnostics.len,
-            });
-        } else {
-            // Unknown notification, ignore
-            std.debug.print("[LSP] Ignoring notification: {s}\n", .{method});
         }
+        // Other notifications are ignored
+    }
+
+    /// Show a language server error in the message line (the TUI owns the terminal)
+    fn reportLsp(self: *Editor, comptime fmt: []const u8, args: anytype) void {
+        var msg_buf: [256]u8 = undefined;
+        const msg = std.fmt.bufPrint(&msg_buf, fmt, args) catch "[LSP] Language server error";
+        self.messages.add(msg, .warning) catch {};
+    }
+
+    /// Errors reported by server sessions
+    fn handleLspLog(ctx: ?*anyopaque, message: []const u8) void {
+        const self: *Editor = @ptrCast(@alignCast(ctx orelse return));
+        self.messages.add(message, .warning) catch {};
     }
 
     /// Helper to check if character needs percent-encoding in URI (RFC 3986)
@@ -369,7 +1429,7 @@ pub const Editor = struct {
             try self.allocator.dupe(u8, filepath)
         else
             try std.fs.cwd().realpathAlloc(self.allocator, filepath);
-        defer if (!std.fs.path.isAbsolute(filepath)) self.allocator.free(abs_path);
+        defer self.allocator.free(abs_path);
 
         // Count characters that need encoding to calculate buffer size
         var encoded_len: usize = 0;
@@ -403,6 +1463,8 @@ pub const Editor = struct {
 
     /// Process key input
     pub fn processKey(self: *Editor, key: Keymap.Key) !void {
+        defer self.reportSearchLimit();
+
         // Dismiss hover popup on any key press (except the hover trigger itself)
         if (self.hover_content != null) {
             // Check if this is NOT the hover trigger key (K in normal mode)
@@ -434,8 +1496,15 @@ pub const Editor = struct {
 
         // Priority 3: Try to match key to command
         if (try self.keymap_manager.processKey(mode, key)) |command_name| {
-            // Execute command with editor context
-            var ctx = Command.Context{ .editor = self };
+            // The motion or text object an operator waits for
+            if (mode == .operator_pending) {
+                try self.runOperatorMotion(command_name, self.keymap_manager.takeCount());
+                self.ensureCursorVisible();
+                return;
+            }
+
+            // Execute command with editor context and any count typed before it
+            var ctx = Command.Context{ .editor = self, .count = self.keymap_manager.takeCount() };
             const result = self.command_registry.execute(command_name, &ctx);
 
             switch (result) {
@@ -446,10 +1515,16 @@ pub const Editor = struct {
                 },
             }
 
+            // A register picked with `"x` is for this command only
+            if (!self.pending_command.isWaiting()) self.pending_register = null;
+
             // Auto-scroll viewport to follow cursor
             self.ensureCursorVisible();
-        } else if (mode.acceptsTextInput()) {
-            // Handle text input in insert/command mode
+        } else if (mode == .operator_pending and !self.keymap_manager.hasPending()) {
+            // Anything but a motion or text object cancels the operator
+            try self.cancelOperator(null);
+        } else if (mode.acceptsTextInput() and !(key == .char and key.char < 0x20)) {
+            // Handle text input in insert/command mode (unbound Ctrl keys aren't text)
             try self.handleTextInput(key);
 
             // Auto-scroll viewport to follow cursor
@@ -457,7 +1532,73 @@ pub const Editor = struct {
         }
     }
 
+    /// Apply the config's leader and `[keys.<mode>]` bindings on top of the defaults
+    /// Bad lines are reported as `<source>:<line>: <problem>` and skipped;
+    /// returns how many there were.
+    pub fn applyKeyBindings(self: *Editor, source: []const u8) !usize {
+        // The default leader chords are bound with the leader key itself
+        if (!self.config.leader.eql(self.keymap_manager.leader)) {
+            self.keymap_manager.leader = self.config.leader;
+            self.keymap_manager.reset();
+            try Keymap.setupDefaults(&self.keymap_manager);
+        }
+
+        var failed: usize = 0;
+        for (self.config.key_bindings.items) |binding| {
+            if (!try self.applyKeyBinding(source, binding)) failed += 1;
+        }
+        return failed;
+    }
+
+    /// Bind or unbind one config line, returns false if it was reported as an error
+    fn applyKeyBinding(self: *Editor, source: []const u8, binding: Config.KeyBinding) !bool {
+        if (binding.malformed) {
+            try self.reportConfigError(source, binding.line, "Expected '<keys> = <command>', got '{s}'", .{binding.keys});
+            return false;
+        }
+        const mode = Keymap.modeFromName(binding.mode) orelse {
+            try self.reportConfigError(source, binding.line, "Unknown mode '{s}' (use normal, insert, select or operator_pending)", .{binding.mode});
+            return false;
+        };
+        const keys = Keymap.parseKeys(binding.keys, self.keymap_manager.leader) catch |err| {
+            try self.reportConfigError(source, binding.line, "{s}: {s}", .{ Keymap.notationErrorMessage(err), binding.keys });
+            return false;
+        };
+
+        if (binding.command.len == 0) {
+            if (!self.keymap_manager.unbind(mode, keys.constSlice())) {
+                try self.reportConfigError(source, binding.line, "Nothing bound to {s} in {s} mode", .{ binding.keys, binding.mode });
+                return false;
+            }
+            return true;
+        }
+
+        // Bind the registry's name, which outlives the config
+        const command = self.command_registry.get(binding.command) orelse {
+            try self.reportConfigError(source, binding.line, "Unknown command '{s}'", .{binding.command});
+            return false;
+        };
+        _ = self.keymap_manager.unbind(mode, keys.constSlice());
+        try self.keymap_manager.bind(mode, .{ .keys = keys, .command = command.name });
+        return true;
+    }
+
+    fn reportConfigError(self: *Editor, source: []const u8, line: usize, comptime fmt: []const u8, args: anytype) !void {
+        const msg = try std.fmt.allocPrint(self.allocator, "{s}:{d}: " ++ fmt, .{ source, line } ++ args);
+        defer self.allocator.free(msg);
+        try self.messages.add(msg, .error_msg);
+    }
+
     /// Handle input during incremental search
+    /// Report a regex search that gave up, once while it keeps happening (rendering searches every frame)
+    pub fn reportSearchLimit(self: *Editor) void {
+        if (!self.search.takeMatchLimitHit()) return;
+        if (self.messages.current()) |msg| {
+            if (std.mem.eql(u8, msg.content, search_limit_msg)) return;
+        }
+        self.messages.add(search_limit_msg, .error_msg) catch {};
+    }
+
     fn handleSearchInput(self: *Editor, key: Keymap.Key) !void {
         switch (key) {
             .char => |c| {
@@ -474,11 +1615,9 @@ pub const Editor = struct {
                 // Find first match and jump to it
                 if (self.buffer_manager.active_buffer_id) |id| {
                     const buffer = self.buffer_manager.getBuffer(id) orelse return;
-                    const text = try buffer.getText();
-                    defer self.allocator.free(text);
-
                     const cursor_pos = self.getCursorPosition();
-                    if (self.search.findNext(text, cursor_pos)) |match| {
+
+                    if (self.search.findNextInRope(&buffer.rope, cursor_pos)) |match| {
                         try self.selections.setSingleCursor(self.allocator, match.start);
                         self.ensureCursorVisible();
                     }
@@ -524,13 +1663,15 @@ pub const Editor = struct {
         // Handle escape - cancel pending command
         if (key == .special and key.special == .escape) {
             self.pending_command = .none;
+            self.pending_count = 1;
             self.prompt.hide();
             self.messages.clear();
+            if (self.pending_operator != null) try self.cancelOperator(null);
             return;
         }
 
-        // Handle text prompts (LSP rename) - accumulate input until Enter
-        if (self.pending_command == .lsp_rename) {
+        // Handle text prompts (LSP rename, shell command) - accumulate input until Enter
+        if (self.pending_command == .lsp_rename or self.pending_command == .shell_pipe) {
             switch (key) {
                 .char => |c| {
                     const char_byte = @as(u8, @intCast(c));
@@ -539,10 +1680,14 @@ pub const Editor = struct {
                 .special => |special_key| {
                     switch (special_key) {
                         .enter => {
-                            const new_name = self.prompt.getInput();
+                            const input = self.prompt.getInput();
+                            const pending = self.pending_command;
                             self.pending_command = .none;
                             self.prompt.hide();
-                            try self.completeLspRename(new_name);
+                            switch (pending) {
+                                .shell_pipe => |mode| try self.completeShellPipe(mode, input),
+                                else => try self.completeLspRename(input),
+                            }
                         },
                         .backspace => self.prompt.backspace(),
                         .delete => self.prompt.deleteChar(),
@@ -591,6 +1736,7 @@ pub const Editor = struct {
         };
 
         // Dispatch to appropriate completion handler based on pending command type
+        defer self.pending_count = 1;
         switch (self.pending_command) {
             .none => unreachable, // Should not be called if no pending command
 
@@ -630,11 +1776,22 @@ pub const Editor = struct {
                 try self.completeReplaceChar(char_byte);
             },
 
+            .select_register => {
+                self.pending_command = .none;
+                self.prompt.hide();
+                try self.completeSelectRegister(char_byte);
+            },
+
             .lsp_rename => unreachable, // Handled above before character extraction
 
             .goto_line => unreachable, // Handled above before character extraction
+
+            .shell_pipe => unreachable, // Handled above before character extraction
         }
 
+        // `dfx`: the operator acts once its motion has its character
+        if (self.pending_operator != null) try self.finishOperator();
+
         self.ensureCursorVisible();
     }
 
@@ -648,6 +1805,24 @@ pub const Editor = struct {
         const viewport_height: usize = 40;
         const visible_lines = viewport_height -| 2; // Reserve for status
 
+        // Only closed folds change what's on screen
+        const folded = if (self.usedFolds() catch null) |fold_set| (if (fold_set.closed.items.len > 0) fold_set else null) else null;
+        if (folded) |fold_set| {
+            // Jumps and searches that land in a closed fold open it
+            _ = fold_set.reveal(cursor_pos.line);
+
+            // A closed fold takes one row, so distances count the lines shown
+            self.scroll_offset = fold_set.visibleLine(@min(self.scroll_offset, total_lines -| 1));
+            if (cursor_pos.line < self.scroll_offset) {
+                self.scroll_offset = cursor_pos.line;
+            } else if (fold_set.rowsBetween(self.scroll_offset, cursor_pos.line) >= visible_lines) {
+                self.scroll_offset = fold_set.retreat(cursor_pos.line, visible_lines - 1);
+            }
+            const last_line = fold_set.visibleLine(total_lines -| 1);
+            self.scroll_offset = @min(self.scroll_offset, fold_set.retreat(last_line, visible_lines - 1));
+            return;
+        }
+
         // Calculate desired viewport bounds
         const viewport_end = self.scroll_offset + visible_lines;
 
@@ -675,12 +1850,9 @@ pub const Editor = struct {
             // Get all selections
             const all_selections = self.selections.all(self.allocator);
 
-            // Get or create undo group for this editing session
-            // If we're in insert mode but don't have a group yet, create one
-            if (self.current_undo_group == null) {
-                const cursor_before = if (all_selections.len > 0) all_selections[0].head else Cursor.Position{ .line = 0, .col = 0 };
-                self.current_undo_group = Undo.OperationGroup.init(self.allocator, cursor_before);
-            }
+            // Get or create the buffer's undo group for this editing session
+            const cursor_before = if (all_selections.len > 0) all_selections[0].head else Cursor.Position{ .line = 0, .col = 0 };
+            buffer.beginUndoGroup(cursor_before);
 
             // Check for auto-pairing
             const autopair_config = AutoPair.AutoPairConfig{ .enabled = self.config.auto_pair_brackets };
@@ -727,9 +1899,17 @@ pub const Editor = struct {
                 i -= 1;
                 const sel = all_selections[i];
 
+                // Enter carries the new line's indentation
+                const newline = if (key == .special and key.special == .enter and self.config.auto_indent)
+                    try self.newlineText(sel.head)
+                else
+                    null;
+                defer if (newline) |line| self.allocator.free(line.text);
+                const inserted: ?[]const u8 = if (newline) |line| line.text else text_to_insert;
+
                 // Record operation for undo (add to current editing session's group)
-                if (self.current_undo_group) |*group| {
-                    if (text_to_insert) |txt| {
+                if (buffer.undo_group) |*group| {
+                    if (inserted) |txt| {
                         const op = try Undo.Operation.init(
                             self.allocator,
                             .insert,
@@ -752,7 +1932,7 @@ pub const Editor = struct {
                 }
 
                 var new_sel = blk: {
-                    if (text_to_insert) |txt| {
+                    if (inserted) |txt| {
                         break :blk try Actions.insertText(buffer, sel, txt);
                     } else if (key == .special) {
                         // Handle backspace specially
@@ -772,6 +1952,16 @@ pub const Editor = struct {
                     }
                 }
 
+                // A line break moves the cursor down to the new line
+                if (key == .special and key.special == .enter) {
+                    new_sel = Cursor.Selection.cursor(.{ .line = sel.head.line + 1, .col = if (newline) |line| line.col else 0 });
+                }
+
+                // A closing bracket typed first on its line goes back out to its block's level
+                if (key == .char and key.char < 0x80 and Indent.isCloser(@intCast(key.char)) and self.config.auto_indent) {
+                    new_sel = try self.reindentClosingLine(buffer, new_sel);
+                }
+
                 // Prepend to maintain order
                 try new_selections.insert(self.allocator, 0, new_sel);
             }
@@ -784,7 +1974,7 @@ pub const Editor = struct {
             self.selections.primary_index = if (new_selections.items.len > 0) new_selections.items.len - 1 else 0;
 
             // Update cursor_after in current undo group
-            if (self.current_undo_group) |*group| {
+            if (buffer.undo_group) |*group| {
                 if (new_selections.items.len > 0) {
                     group.cursor_after = new_selections.items[0].head;
                 }
@@ -799,26 +1989,36 @@ pub const Editor = struct {
     pub fn enterNormalMode(self: *Editor) !void {
         const old_mode = self.mode_manager.getMode();
 
-        // If exiting insert mode, push the accumulated undo group
-        if (old_mode == .insert and self.current_undo_group != null) {
-            var group = self.current_undo_group.?;
-            self.current_undo_group = null;
-
-            // Only push if it has operations
-            if (group.operations.items.len > 0) {
-                try self.undo_history.push(group);
-            } else {
-                group.deinit(self.allocator);
+        // If exiting insert mode, push the accumulated undo groups
+        // (every buffer, in case the session switched buffers midway)
+        if (old_mode == .insert) {
+            if (self.change_insert) |change| self.recordChangeText(change) catch {};
+            self.change_insert = null;
+            for (self.buffer_manager.buffers.items) |*buffer| {
+                try buffer.commitUndoGroup();
             }
         }
 
         try self.mode_manager.enterNormal();
         self.keymap_manager.clearPending();
+        self.pending_operator = null;
 
         // Dispatch mode change to plugins
         self.plugin_manager.dispatchModeChange(@intFromEnum(old_mode), @intFromEnum(Mode.Mode.normal)) catch {};
     }
 
+    /// Keep what was typed after a change on the repeat action, for `.` to type again
+    /// Text typed anywhere but where the change left the cursor isn't kept.
+    fn recordChangeText(self: *Editor, change: ChangeInsert) !void {
+        const buffer = self.buffer_manager.getBuffer(change.buffer_id) orelse return;
+        const rope = &buffer.rope;
+        const len = rope.len();
+        const grown = if (len > change.len) len - change.len else 0;
+        const text = try rope.slice(self.allocator, change.start, @min(change.start + grown, len));
+        defer self.allocator.free(text);
+        try self.repeat_system.recordInserted(text);
+    }
+
     pub fn enterInsertMode(self: *Editor) !void {
         const old_mode = self.mode_manager.getMode();
         try self.mode_manager.enterInsert();
@@ -826,7 +2026,9 @@ pub const Editor = struct {
 
         // Start a new undo group for this editing session
         const cursor_pos = if (self.selections.primary(self.allocator)) |sel| sel.head else Cursor.Position{ .line = 0, .col = 0 };
-        self.current_undo_group = Undo.OperationGroup.init(self.allocator, cursor_pos);
+        if (self.buffer_manager.active_buffer_id) |id| {
+            if (self.buffer_manager.getBufferMut(id)) |buffer| buffer.beginUndoGroup(cursor_pos);
+        }
 
         // Dispatch mode change to plugins
         self.plugin_manager.dispatchModeChange(@intFromEnum(old_mode), @intFromEnum(Mode.Mode.insert)) catch {};
@@ -841,6 +2043,17 @@ pub const Editor = struct {
         self.plugin_manager.dispatchModeChange(@intFromEnum(old_mode), @intFromEnum(Mode.Mode.select)) catch {};
     }
 
+    /// Wait in operator-pending mode for the motion or text object of `pending`
+    fn enterOperatorPendingMode(self: *Editor, pending: Operator.Pending) !void {
+        const old_mode = self.mode_manager.getMode();
+        try self.mode_manager.enterOperatorPending();
+        self.keymap_manager.clearPending();
+        self.pending_operator = pending;
+
+        // Dispatch mode change to plugins
+        self.plugin_manager.dispatchModeChange(@intFromEnum(old_mode), @intFromEnum(Mode.Mode.operator_pending)) catch {};
+    }
+
     pub fn enterCommandMode(self: *Editor) !void {
         const old_mode = self.mode_manager.getMode();
         try self.mode_manager.enterCommand();
@@ -859,7 +2072,11 @@ pub const Editor = struct {
         const total_lines = if (buffer) |b| b.lineCount() else 0;
 
         const visible_lines = screen_height -| 2; // Reserve space for status line
-        const end_line = @min(self.scroll_offset + visible_lines, total_lines);
+        // Lines in closed folds take no rows, so more of the buffer fits
+        const end_line = if (self.activeFolds()) |fold_set|
+            @min(fold_set.nextVisible(fold_set.advance(self.scroll_offset, visible_lines -| 1, total_lines)), total_lines)
+        else
+            @min(self.scroll_offset + visible_lines, total_lines);
 
         return .{
             .start_line = self.scroll_offset,
@@ -867,6 +2084,21 @@ pub const Editor = struct {
         };
     }
 
+    /// Buffer line drawn `row` rows below `start_line`, past closed folds; may be past the end
+    pub fn lineAtRow(self: *const Editor, start_line: usize, row: usize) usize {
+        const fold_set = self.activeFolds() orelse return start_line + row;
+        var line = start_line;
+        for (0..row) |_| line = fold_set.nextVisible(line);
+        return line;
+    }
+
+    /// Rows `line` is drawn below `start_line`; null if a closed fold hides it
+    pub fn rowOfLine(self: *const Editor, start_line: usize, line: usize) ?usize {
+        const fold_set = self.activeFolds() orelse return line -| start_line;
+        if (fold_set.isHidden(line)) return null;
+        return fold_set.rowsBetween(start_line, line);
+    }
+
     /// Scroll viewport vertically
     pub fn scroll(self: *Editor, delta: isize) void {
         if (delta < 0) {
@@ -928,13 +2160,15 @@ pub const Editor = struct {
             .file_path = if (buffer) |b| b.metadata.filepath else null,
             .modified = if (buffer) |b| b.metadata.modified else false,
             .readonly = if (buffer) |b| b.metadata.readonly else false,
+            .large_file = if (buffer) |b| b.metadata.large_file else false,
+            .file_format = if (buffer) |b| b.metadata.format else .{},
             .line = current_line, // 1-indexed for display
             .col = cursor_pos.col + 1,
             .total_lines = total_lines,
             .percent = percent,
             .selection_count = self.selections.count(self.allocator),
-            .can_undo = self.undo_history.canUndo(),
-            .can_redo = self.undo_history.canRedo(),
+            .can_undo = if (buffer) |b| b.undo_history.canUndo() else false,
+            .can_redo = if (buffer) |b| b.undo_history.canRedo() else false,
         };
     }
 
@@ -953,23 +2187,34 @@ pub const Editor = struct {
         const buffer = self.getActiveBuffer() orelse return error.NoActiveBuffer;
         const primary_sel = self.selections.primary(self.allocator) orelse return error.NoSelection;
 
+        // A count (`3fx`) first finds the earlier matches; the last find or till goes from there
+        var from = primary_sel;
+        for (1..self.pending_count) |_| {
+            const next = if (forward) Motions.findCharForward(from, buffer, ch) else Motions.findCharBackward(from, buffer, ch);
+            if (next.head.eql(from.head)) {
+                self.messages.add("Character not found", .error_msg) catch {};
+                return;
+            }
+            from = next;
+        }
+
         // Execute the appropriate motion
         const new_sel = if (forward) blk: {
             if (till) {
-                break :blk Motions.tillCharForward(primary_sel, buffer, ch);
+                break :blk Motions.tillCharForward(from, buffer, ch);
             } else {
-                break :blk Motions.findCharForward(primary_sel, buffer, ch);
+                break :blk Motions.findCharForward(from, buffer, ch);
             }
         } else blk: {
             if (till) {
-                break :blk Motions.tillCharBackward(primary_sel, buffer, ch);
+                break :blk Motions.tillCharBackward(from, buffer, ch);
             } else {
-                break :blk Motions.findCharBackward(primary_sel, buffer, ch);
+                break :blk Motions.findCharBackward(from, buffer, ch);
             }
         };
 
         // Check if motion succeeded
-        if (new_sel.head.eql(primary_sel.head)) {
+        if (new_sel.head.eql(from.head)) {
             self.messages.add("Character not found", .error_msg) catch {};
             return;
         }
@@ -1071,6 +2316,9 @@ pub const Editor = struct {
     /// show error messages. Command failures are reported but don't halt execution
     /// of remaining commands (vim-like graceful degradation).
     fn completePlayMacro(self: *Editor, register: u8) !void {
+        // Taken first so a failed playback doesn't leave it for the next one (`10@q`)
+        const times = self.macro_recorder.consumePlaybackCount();
+
         const reg_id = Registers.RegisterId{ .named = register };
         const content = self.registers.get(reg_id) orelse {
             const msg = try std.fmt.allocPrint(self.allocator, "Register '{c}' is empty", .{register});
@@ -1091,21 +2339,26 @@ pub const Editor = struct {
             return;
         }
 
-        // Execute each command in the macro
-        for (commands) |cmd| {
-            var ctx = Command.Context{ .editor = self };
-            const result = self.command_registry.execute(cmd.name, &ctx);
-
-            switch (result) {
-                .success => {},
-                .error_msg => |msg| {
-                    // Show error but continue execution
-                    self.messages.add(msg, .error_msg) catch {};
-                },
+        // Execute each command in the macro, as many times as the count asked
+        for (0..times) |_| {
+            for (commands) |cmd| {
+                var ctx = Command.Context{ .editor = self };
+                const result = self.command_registry.execute(cmd.name, &ctx);
+
+                switch (result) {
+                    .success => {},
+                    .error_msg => |msg| {
+                        // Show error but continue execution
+                        self.messages.add(msg, .error_msg) catch {};
+                    },
+                }
             }
         }
 
-        const msg = try std.fmt.allocPrint(self.allocator, "Played macro from '{c}'", .{register});
+        const msg = if (times == 1)
+            try std.fmt.allocPrint(self.allocator, "Played macro from '{c}'", .{register})
+        else
+            try std.fmt.allocPrint(self.allocator, "Played macro from '{c}' {d} times", .{ register, times });
         defer self.allocator.free(msg);
         self.messages.add(msg, .info) catch {};
     }
@@ -1118,26 +2371,340 @@ pub const Editor = struct {
     /// existing, well-tested primitives.
     ///
     /// Cursor position is preserved (vim 'r' command doesn't move cursor).
+    /// Replace the character at the cursor, and for a count the ones after it (`3rx`)
+    /// As in vim, a line with fewer characters left than the count is left alone,
+    /// and the cursor ends on the last character replaced.
     fn completeReplaceChar(self: *Editor, ch: u8) !void {
         const buffer_id = self.buffer_manager.active_buffer_id orelse return error.NoActiveBuffer;
         const buffer = self.buffer_manager.getBufferMut(buffer_id) orelse return error.NoActiveBuffer;
         const primary_sel = self.selections.primary(self.allocator) orelse return error.NoSelection;
+        const count = self.pending_count;
+
+        const start = try Actions.positionToByteOffset(buffer, primary_sel.head);
+        var cursor = buffer.rope.cursorAt(start);
+        for (0..count) |_| {
+            const byte = cursor.peekByte() orelse return;
+            if (byte == '\n') return;
+            _ = cursor.nextChar();
+        }
+
+        const text = try self.allocator.alloc(u8, count);
+        defer self.allocator.free(text);
+        @memset(text, ch);
+        try buffer.replaceRanges(&.{.{ .start = start, .end = cursor.offset(), .text = text }}, primary_sel.head);
+        buffer.metadata.markModified();
+        try self.selections.setSingleCursor(self.allocator, .{ .line = primary_sel.head.line, .col = primary_sel.head.col + count - 1 });
+    }
 
-        // Delete character at cursor
-        _ = Actions.deleteChar(buffer, primary_sel) catch {
-            self.messages.add("Failed to delete character", .error_msg) catch {};
+    /// Use the register named by `ch` for the next yank, delete or paste
+    fn completeSelectRegister(self: *Editor, ch: u8) !void {
+        const register = Registers.RegisterId.fromChar(ch) orelse {
+            self.messages.add("Invalid register (use a-z, 0-9, \", +, * or _)", .error_msg) catch {};
             return;
         };
+        self.pending_register = register;
 
-        // Insert new character at same position
-        const char_str = &[_]u8{ch};
-        _ = Actions.insertText(buffer, primary_sel, char_str) catch {
-            self.messages.add("Failed to insert character", .error_msg) catch {};
-            return;
+        // `3"ap` pastes three times: the count carries over to the command after the register
+        if (self.pending_count > 1) self.keymap_manager.count = self.pending_count;
+    }
+
+    // === Operators ===
+
+    /// Start an operator (`d`, `c`, `y`...), with the count typed before it
+    /// In select mode it acts on the selection at once; otherwise it waits in
+    /// operator-pending mode for a motion or text object.
+    pub fn beginOperator(self: *Editor, operator: Operator.Operator, count: ?usize) !void {
+        const register = self.pending_register;
+        self.pending_register = null;
+        const primary_sel = self.selections.primary(self.allocator) orelse return error.NoSelection;
+
+        if (self.getMode() == .select) {
+            const buffer = self.getActiveBuffer() orelse return error.NoActiveBuffer;
+            const span = try Operator.objectSpan(buffer, primary_sel, false) orelse return self.enterNormalMode();
+            return self.applyOperator(operator, register, span);
+        }
+
+        try self.enterOperatorPendingMode(.{
+            .operator = operator,
+            .count = count,
+            .register = register,
+            .origin = primary_sel.head,
+        });
+    }
+
+    /// Run the motion or text object for the pending operator, then apply the operator
+    /// A motion that waits for a character (`dfx`) finishes in handlePendingCommandInput.
+    fn runOperatorMotion(self: *Editor, command_name: []const u8, count: ?usize) !void {
+        const pending = if (self.pending_operator) |*operator| operator else return;
+
+        // `cw` on a word changes to its end, like `ce`
+        const name = if (pending.operator == .change and std.mem.eql(u8, command_name, "move_word_forward") and !self.cursorOnWhitespace())
+            "move_word_end"
+        else
+            command_name;
+
+        const command = self.command_registry.get(name) orelse return self.cancelOperator("Command not found");
+        if (command.operatorTarget() == .none) return self.cancelOperator("Not a motion or text object");
+
+        pending.motion = command.name;
+        pending.motion_count = count;
+        var ctx = Command.Context{ .editor = self, .count = Operator.combineCounts(pending.count, count) };
+        switch (command.run(&ctx)) {
+            .success => {},
+            .error_msg => |msg| return self.cancelOperator(msg),
+        }
+
+        if (self.pending_command.isWaiting()) return;
+        try self.finishOperator();
+    }
+
+    /// Apply the pending operator to what its motion or text object covered
+    fn finishOperator(self: *Editor) !void {
+        const pending = self.pending_operator orelse return;
+        const motion = pending.motion orelse return self.cancelOperator(null);
+        const command = self.command_registry.get(motion) orelse return self.cancelOperator(null);
+        const buffer = self.getActiveBuffer() orelse return self.cancelOperator(null);
+        const primary_sel = self.selections.primary(self.allocator) orelse return self.cancelOperator(null);
+
+        const covered = switch (command.operatorTarget()) {
+            .object => try Operator.objectSpan(buffer, primary_sel, false),
+            .object_lines => try Operator.objectSpan(buffer, primary_sel, true),
+            else => |target| try Operator.motionSpan(buffer, pending.origin, primary_sel.head, target),
+        };
+        const span = covered orelse return self.cancelOperator(null);
+
+        // The cursor goes back to where the operator was typed; applyOperator moves it on
+        try self.selections.setSingleCursor(self.allocator, pending.origin);
+        try self.applyOperator(pending.operator, pending.register, span);
+
+        // For `.`, a find (`dfx`) becomes `;` with the character it found
+        if (pending.operator == .yank) return;
+        const finds = std.mem.startsWith(u8, motion, "find_char_") or std.mem.startsWith(u8, motion, "till_char_");
+        self.repeat_system.recordOperator(
+            pending.operator,
+            if (finds) "repeat_find_till" else motion,
+            Operator.combineCounts(pending.count, pending.motion_count),
+            if (finds) self.find_till_state else null,
+        ) catch {};
+    }
+
+    /// Drop the pending operator, putting the cursor back where it was typed
+    fn cancelOperator(self: *Editor, msg: ?[]const u8) !void {
+        if (self.pending_operator) |pending| try self.selections.setSingleCursor(self.allocator, pending.origin);
+        if (msg) |text| self.messages.add(text, .error_msg) catch {};
+        try self.enterNormalMode();
+    }
+
+    /// Run a recorded operator again from the cursor (`.` after `dw`)
+    pub fn replayOperator(self: *Editor, action: Repeat.Action, count: ?usize) !void {
+        const operator = action.operator orelse return;
+        const primary_sel = self.selections.primary(self.allocator) orelse return error.NoSelection;
+        if (action.find) |find| self.find_till_state = find;
+
+        self.pending_operator = .{
+            .operator = operator,
+            .count = null,
+            .register = null,
+            .origin = primary_sel.head,
         };
+        try self.runOperatorMotion(action.command_name, count);
+
+        // A change types its text again, in the same undo step as the delete
+        if (operator != .change or self.getMode() != .insert) return;
+        if (action.inserted) |text| {
+            const buffer = self.activeWindowBuffer() orelse return error.NoActiveBuffer;
+            const cursor = self.getCursorPosition();
+            const offset = try Actions.positionToByteOffset(buffer, cursor);
+            if (buffer.undo_group) |*group| {
+                try group.addOperation(self.allocator, try Undo.Operation.init(self.allocator, .insert, cursor, text, null));
+            }
+            try buffer.insert(offset, text);
+            buffer.metadata.markModified();
+            try self.selections.setSingleCursor(self.allocator, positionAt(buffer, offset + text.len));
+        }
+        try self.enterNormalMode();
+    }
+
+    /// Apply an operator to a span of the active buffer, as one undo step
+    fn applyOperator(self: *Editor, operator: Operator.Operator, register: ?Registers.RegisterId, target: Operator.Span) !void {
+        const buffer_id = self.buffer_manager.active_buffer_id orelse return error.NoActiveBuffer;
+        const buffer = self.buffer_manager.getBufferMut(buffer_id) orelse return error.NoActiveBuffer;
+        const rope = &buffer.rope;
+        const cursor = self.getCursorPosition();
+
+        // `>w` indents the whole line
+        var span = target;
+        if (operator.isLinewise() and !span.linewise) {
+            span = Operator.lineSpan(buffer, rope.byteToLine(span.start), rope.byteToLine(span.end - 1));
+        }
+        const first_line = rope.byteToLine(span.start);
+
+        const text = try rope.slice(self.allocator, span.start, span.end);
+        defer self.allocator.free(text);
+
+        switch (operator) {
+            .yank => {
+                try self.storeText(text, span.linewise, register);
+                try self.selections.setSingleCursor(self.allocator, if (span.linewise) cursor else positionAt(buffer, span.start));
+            },
+            .delete, .change => {
+                try self.storeText(text, span.linewise, register);
+
+                var start = span.start;
+                var end = span.end;
+                if (span.linewise and operator == .change) {
+                    // `cc` empties the lines but keeps one to type on, indented as before
+                    if (std.mem.endsWith(u8, text, "\n")) end -= 1;
+                    if (self.config.auto_indent) start += text.len - std.mem.trimLeft(u8, text, " \t").len;
+                } else if (span.linewise and end == rope.len() and !std.mem.endsWith(u8, text, "\n") and start > 0) {
+                    // The last line has no newline of its own, so it takes the one before it
+                    start -= 1;
+                }
+
+                if (operator == .change) {
+                    // The delete opens the insert session's undo group, so the change undoes in one step
+                    try buffer.commitUndoGroup();
+                    buffer.beginUndoGroup(cursor);
+                    if (buffer.undo_group) |*group| {
+                        const old = try rope.slice(self.allocator, start, end);
+                        defer self.allocator.free(old);
+                        try group.addOperation(self.allocator, try Undo.Operation.init(self.allocator, .replace, positionAt(buffer, start), "", old));
+                    }
+                    if (end > start) try buffer.delete(start, end);
+                    buffer.metadata.markModified();
+                    self.change_insert = .{ .buffer_id = buffer_id, .start = start, .len = rope.len() };
+                } else {
+                    try buffer.replaceRanges(&[_]Buffer.Buffer.RangeEdit{.{ .start = start, .end = end, .text = "" }}, cursor);
+                }
+                const new_cursor = if (span.linewise and operator == .delete)
+                    Operator.firstNonBlank(buffer, first_line)
+                else
+                    positionAt(buffer, start);
+                try self.selections.setSingleCursor(self.allocator, new_cursor);
+            },
+            .indent, .dedent, .lowercase, .uppercase, .reindent => {
+                const unit = try self.config.getTabString(self.allocator);
+                defer self.allocator.free(unit);
+                const above = if (first_line > 0) try rope.lineSlice(self.allocator, first_line - 1) else try self.allocator.dupe(u8, "");
+                defer self.allocator.free(above);
+
+                // `=` follows the syntax tree where the language has an indents.scm
+                const last_line = if (span.end > span.start) rope.byteToLine(span.end - 1) else first_line;
+                const from_tree = if (operator == .reindent) try self.syntaxReindent(first_line, last_line) else null;
+                const rewritten = from_tree orelse try Operator.rewrite(self.allocator, operator, text, unit, above);
+                defer self.allocator.free(rewritten);
+                if (!std.mem.eql(u8, rewritten, text)) {
+                    try buffer.replaceRanges(&[_]Buffer.Buffer.RangeEdit{.{ .start = span.start, .end = span.end, .text = rewritten }}, cursor);
+                }
+
+                const new_cursor = if (span.linewise) Operator.firstNonBlank(buffer, first_line) else positionAt(buffer, span.start);
+                try self.selections.setSingleCursor(self.allocator, new_cursor);
+            },
+        }
 
-        // In vim, 'r' command doesn't move the cursor
-        // No cursor movement needed
+        self.pending_operator = null;
+        if (operator == .change) try self.enterInsertMode() else try self.enterNormalMode();
+    }
+
+    /// Keep yanked or deleted text in a register (unnamed unless one was picked) and the clipboard
+    /// Whole lines always end in a newline, so they paste as lines.
+    pub fn storeText(self: *Editor, text: []const u8, linewise: bool, register: ?Registers.RegisterId) !void {
+        if (register) |id| if (id == .black_hole) return;
+
+        const stored = if (linewise and !std.mem.endsWith(u8, text, "\n"))
+            try std.mem.concat(self.allocator, u8, &.{ text, "\n" })
+        else
+            try self.allocator.dupe(u8, text);
+        defer self.allocator.free(stored);
+
+        try self.registers.yank(stored, linewise, register);
+        if (linewise) try self.clipboard.setLines(stored) else try self.clipboard.setContent(stored);
+    }
+
+    /// Whether the cursor is on a space, tab or line end
+    fn cursorOnWhitespace(self: *const Editor) bool {
+        const buffer = self.getActiveBuffer() orelse return true;
+        const offset = Actions.positionToByteOffset(buffer, self.getCursorPosition()) catch return true;
+        var cursor = buffer.rope.cursorAt(offset);
+        const c = cursor.peekByte() orelse return true;
+        return c == ' ' or c == '\t' or c == '\n';
+    }
+
+    /// Run a shell command for each selection (|, !, Alt-!) as one undo step
+    /// Every command runs before the buffer is touched, so a failure changes nothing.
+    fn completeShellPipe(self: *Editor, mode: Shell.PipeMode, command: []const u8) !void {
+        if (std.mem.trim(u8, command, &std.ascii.whitespace).len == 0) return;
+        const buffer = self.activeWindowBuffer() orelse return;
+        const selections = self.selections.all(self.allocator);
+
+        const edits = try self.allocator.alloc(Buffer.Buffer.RangeEdit, selections.len);
+        defer self.allocator.free(edits);
+        for (selections, edits) |sel, *edit| {
+            const range = sel.range();
+            const start = try Actions.positionToByteOffset(buffer, range.start);
+            const end = try Actions.positionToByteOffset(buffer, range.end);
+            edit.* = switch (mode) {
+                .replace => .{ .start = start, .end = end, .text = "" },
+                .insert => .{ .start = start, .end = start, .text = "" },
+                .append => .{ .start = end, .end = end, .text = "" },
+            };
+        }
+        std.mem.sort(Buffer.Buffer.RangeEdit, edits, {}, struct {
+            fn lessThan(_: void, a: Buffer.Buffer.RangeEdit, b: Buffer.Buffer.RangeEdit) bool {
+                return a.start < b.start or (a.start == b.start and a.end < b.end);
+            }
+        }.lessThan);
+        const merged = mergeRanges(edits);
+
+        var outputs = std.ArrayList([]u8).empty;
+        defer {
+            for (outputs.items) |output| self.allocator.free(output);
+            outputs.deinit(self.allocator);
+        }
+        for (merged) |*edit| {
+            const input = if (mode == .replace) try buffer.rope.slice(self.allocator, edit.start, edit.end) else null;
+            defer if (input) |bytes| self.allocator.free(bytes);
+
+            var output = Shell.run(self.allocator, command, input) catch {
+                try self.messages.add("Failed to run shell command", .error_msg);
+                return;
+            };
+            if (!output.succeeded()) {
+                defer output.deinit(self.allocator);
+                const msg = try output.failureMessage(self.allocator, command);
+                defer self.allocator.free(msg);
+                try self.messages.add(msg, .error_msg);
+                return;
+            }
+            self.allocator.free(output.stderr);
+            outputs.append(self.allocator, output.stdout) catch |err| {
+                self.allocator.free(output.stdout);
+                return err;
+            };
+            edit.text = output.stdout;
+        }
+
+        // Where each output lands once the earlier ones have shifted it
+        const new_selections = try self.allocator.alloc(Cursor.Selection, merged.len);
+        defer self.allocator.free(new_selections);
+        var shift: usize = 0;
+        var removed: usize = 0;
+        const spans = try self.allocator.alloc([2]usize, merged.len);
+        defer self.allocator.free(spans);
+        for (merged, 0..) |edit, i| {
+            const start = edit.start + shift - removed;
+            spans[i] = .{ start, start + edit.text.len };
+            shift += edit.text.len;
+            removed += edit.end - edit.start;
+        }
+
+        try buffer.replaceRanges(merged, self.getCursorPosition());
+
+        for (spans, new_selections) |span, *sel| {
+            sel.* = Cursor.Selection.init(positionAt(buffer, span[0]), positionAt(buffer, span[1]));
+        }
+        try self.selections.setSelections(self.allocator, new_selections);
+        self.ensureCursorVisible();
     }
 
     /// Complete LSP rename operation with user-provided new name
@@ -1152,10 +2719,10 @@ pub const Editor = struct {
             return;
         };
 
-        const client = &(self.lsp_client orelse {
+        const client = self.activeLspClient() orelse {
             self.messages.add("LSP not initialized", .error_msg) catch {};
             return;
-        });
+        };
 
         const cursor = (self.selections.primary(self.allocator) orelse {
             self.messages.add("No cursor", .error_msg) catch {};
@@ -1239,6 +2806,8 @@ pub const Editor = struct {
         file_path: ?[]const u8,
         modified: bool,
         readonly: bool,
+        large_file: bool,
+        file_format: Buffer.FileFormat,
         line: usize,
         col: usize,
         total_lines: usize,
@@ -1249,6 +2818,30 @@ pub const Editor = struct {
     };
 };
 
+/// Line and byte column of a byte offset
+fn positionAt(buffer: *const Buffer.Buffer, offset: usize) Cursor.Position {
+    const line = buffer.rope.byteToLine(offset);
+    return .{ .line = line, .col = offset - buffer.rope.lineToByte(line) };
+}
+
+/// Join sorted ranges that overlap or insert at the same spot, so each
+/// byte is filtered once; shrinks `edits` in place and returns the kept part
+fn mergeRanges(edits: []Buffer.Buffer.RangeEdit) []Buffer.Buffer.RangeEdit {
+    var kept: usize = 0;
+    for (edits) |edit| {
+        if (kept > 0) {
+            const prev = &edits[kept - 1];
+            if (edit.start < prev.end or edit.start == prev.start) {
+                prev.end = @max(prev.end, edit.end);
+                continue;
+            }
+        }
+        edits[kept] = edit;
+        kept += 1;
+    }
+    return edits[0..kept];
+}
+
 test "editor: init and deinit" {
     const allocator = std.testing.allocator;
     var editor = try Editor.init(allocator);
@@ -1532,6 +3125,32 @@ test "completeReplaceChar: replaces character at cursor" {
     try std.testing.expect(std.mem.startsWith(u8, text, "Xbcdef"));
 }
 
+test "completeReplaceChar: a count replaces that many characters in one undo step" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+
+    try editor.newBuffer();
+    const buffer = editor.activeWindowBuffer().?;
+    try buffer.rope.setText("abcdef");
+    try editor.selections.setSingleCursor(allocator, .{ .line = 0, .col = 1 });
+
+    for ("3rx") |c| try editor.processKey(.{ .char = c });
+    const text = try buffer.rope.toString(allocator);
+    defer allocator.free(text);
+    try std.testing.expectEqualStrings("axxxef", text);
+    try std.testing.expectEqual(@as(usize, 3), editor.getCursorPosition().col);
+
+    // Past the end of the line nothing changes
+    for ("9ry") |c| try editor.processKey(.{ .char = c });
+    try std.testing.expectEqual(@as(usize, 1), buffer.undo_history.stateCount() - 1);
+
+    try editor.processKey(.{ .char = 'u' });
+    const restored = try buffer.rope.toString(allocator);
+    defer allocator.free(restored);
+    try std.testing.expectEqualStrings("abcdef", restored);
+}
+
 test "handlePendingCommandInput: escape cancels pending command" {
     const allocator = std.testing.allocator;
     var editor = try Editor.init(allocator);
@@ -1589,3 +3208,288 @@ test "handlePendingCommandInput: character dispatches to handler" {
     const pos = editor.getCursorPosition();
     try std.testing.expectEqual(@as(usize, 3), pos.col);
 }
+
+test "applyKeyBindings: binds, unbinds and reports bad lines" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+
+    try editor.config.addKeyBinding("normal", "<C-s>", "save", 3);
+    try editor.config.addKeyBinding("normal", "g d", "", 4);
+    try editor.config.addKeyBinding("normal", "<space>x", "no_such_command", 5);
+    try editor.config.addKeyBinding("visual", "x", "save", 6);
+    try editor.config.addKeyBinding("normal", "<leader>x", "save", 7);
+    try editor.config.addKeyBinding("normal", "<C-x> save", "", 8);
+    editor.config.key_bindings.items[5].malformed = true;
+    editor.config.leader = .{ .char = ',' };
+    try std.testing.expectEqual(@as(usize, 3), try editor.applyKeyBindings("config.conf"));
+
+    const normal = editor.keymap_manager.getKeymap(.normal);
+    try std.testing.expectEqualStrings("save", normal.lookup(&[_]Keymap.Key{.{ .char = 0x13 }}).?);
+    try std.testing.expectEqualStrings("save", normal.lookup(&[_]Keymap.Key{ .{ .char = ',' }, .{ .char = 'x' } }).?);
+
+    // Default leader chords move to the new leader
+    try std.testing.expectEqualStrings("toggle_file_finder", normal.lookup(&[_]Keymap.Key{ .{ .char = ',' }, .{ .char = 'f' } }).?);
+    try std.testing.expect(normal.lookup(&[_]Keymap.Key{ .{ .char = ' ' }, .{ .char = 'f' } }) == null);
+    try std.testing.expect(normal.lookup(&[_]Keymap.Key{ .{ .char = 'g' }, .{ .char = 'd' } }) == null);
+
+    const messages = editor.messages.all();
+    try std.testing.expectEqual(@as(usize, 3), messages.len);
+    try std.testing.expectEqualStrings("config.conf:5: Unknown command 'no_such_command'", messages[0].content);
+    try std.testing.expectEqualStrings("config.conf:6: Unknown mode 'visual' (use normal, insert, select or operator_pending)", messages[1].content);
+    try std.testing.expectEqualStrings("config.conf:8: Expected '<keys> = <command>', got '<C-x> save'", messages[2].content);
+}
+
+test "editor: operators with motions, text objects, registers and repeat" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+
+    try editor.newBuffer();
+    const buffer_id = editor.buffer_manager.active_buffer_id.?;
+    const buffer = editor.buffer_manager.getBufferMut(buffer_id).?;
+    try buffer.rope.setText("one two three four five\nsix\n");
+    try editor.selections.setSingleCursor(allocator, .{ .line = 0, .col = 0 });
+
+    // `d2w` deletes two words, `.` deletes two more
+    for ("d2w") |c| try editor.processKey(.{ .char = c });
+    try std.testing.expectEqual(Mode.Mode.normal, editor.getMode());
+    try editor.processKey(.{ .char = '.' });
+    const deleted = try buffer.rope.toString(allocator);
+    defer allocator.free(deleted);
+    try std.testing.expectEqualStrings("five\nsix\n", deleted);
+
+    // `ciw` empties the word and switches to insert mode
+    for ("ciw") |c| try editor.processKey(.{ .char = c });
+    try std.testing.expectEqual(Mode.Mode.insert, editor.getMode());
+    try std.testing.expect(editor.pending_operator == null);
+    try editor.processKey(.{ .special = .escape });
+
+    // `"ayy` yanks the line into register a, `"ap` puts it below
+    try editor.selections.setSingleCursor(allocator, .{ .line = 1, .col = 0 });
+    for ("\"ayy\"ap>>") |c| try editor.processKey(.{ .char = c });
+    const text = try buffer.rope.toString(allocator);
+    defer allocator.free(text);
+    const unit = try editor.config.getTabString(allocator);
+    defer allocator.free(unit);
+    const expected = try std.fmt.allocPrint(allocator, "\nsix\n{s}six\n", .{unit});
+    defer allocator.free(expected);
+    try std.testing.expectEqualStrings(expected, text);
+    try std.testing.expectEqualStrings("six\n", editor.registers.get(.{ .named = 'a' }).?.text);
+
+    // `==` in an empty buffer has no lines to reindent
+    try buffer.rope.setText("");
+    try editor.selections.setSingleCursor(allocator, .{ .line = 0, .col = 0 });
+    for ("==") |c| try editor.processKey(.{ .char = c });
+    try std.testing.expectEqual(@as(usize, 0), buffer.rope.len());
+}
+
+test "editor: repeating a change types its text again, undone in one step" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+
+    try editor.newBuffer();
+    const buffer = editor.activeWindowBuffer().?;
+    try buffer.rope.setText("one two three\n");
+    try editor.selections.setSingleCursor(allocator, .{ .line = 0, .col = 0 });
+
+    for ("cwONE") |c| try editor.processKey(.{ .char = c });
+    try editor.processKey(.{ .special = .escape });
+    try editor.selections.setSingleCursor(allocator, .{ .line = 0, .col = 4 });
+    try editor.processKey(.{ .char = '.' });
+    try std.testing.expectEqual(Mode.Mode.normal, editor.getMode());
+
+    const expected = [_][]const u8{ "ONE ONE three\n", "ONE two three\n", "one two three\n" };
+    for (expected, 0..) |want, i| {
+        if (i > 0) try editor.processKey(.{ .char = 'u' });
+        const text = try buffer.rope.toString(allocator);
+        defer allocator.free(text);
+        try std.testing.expectEqualStrings(want, text);
+    }
+}
+
+test "editor: shell commands filter, insert and append at every selection" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+
+    try editor.newBuffer();
+    const buffer = editor.activeWindowBuffer().?;
+    try buffer.rope.setText("ab cd\n");
+
+    // The repeated selection is filtered once, not spliced in twice
+    try editor.selections.setSelections(allocator, &.{
+        Cursor.Selection.init(.{ .line = 0, .col = 0 }, .{ .line = 0, .col = 2 }),
+        Cursor.Selection.init(.{ .line = 0, .col = 3 }, .{ .line = 0, .col = 5 }),
+        Cursor.Selection.init(.{ .line = 0, .col = 3 }, .{ .line = 0, .col = 5 }),
+    });
+    const keys = [_]struct { key: ?u21, command: []const u8, expected: []const u8 }{
+        .{ .key = '|', .command = "tr a-z A-Z", .expected = "AB CD\n" },
+        .{ .key = '!', .command = "printf x", .expected = "xAB xCD\n" },
+        .{ .key = null, .command = "printf y", .expected = "xyAB xyCD\n" }, // Alt-!, bound by the app
+    };
+    for (keys) |step| {
+        if (step.key) |key| {
+            try editor.processKey(.{ .char = key });
+        } else {
+            var ctx = Command.Context{ .editor = &editor };
+            try std.testing.expect(editor.command_registry.execute("shell_append", &ctx) == .success);
+        }
+        for (step.command) |c| try editor.processKey(.{ .char = c });
+        try editor.processKey(.{ .special = .enter });
+
+        const text = try buffer.rope.toString(allocator);
+        defer allocator.free(text);
+        try std.testing.expectEqualStrings(step.expected, text);
+        try std.testing.expectEqual(@as(usize, 2), editor.selections.count(allocator));
+    }
+
+    // Each command is one undo step
+    try std.testing.expectEqual(@as(usize, 3), buffer.undo_history.stateCount() - 1);
+    const overlapping = [_]Buffer.Buffer.RangeEdit{ .{ .start = 0, .end = 3, .text = "" }, .{ .start = 2, .end = 4, .text = "" } };
+    try std.testing.expectError(error.OverlappingRanges, buffer.replaceRanges(&overlapping, .{ .line = 0, .col = 0 }));
+}
+
+test "editor: enter between braces opens an indented line" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+    editor.config.auto_indent = true;
+
+    try editor.newBuffer();
+    const buffer = editor.buffer_manager.getBufferMut(editor.buffer_manager.active_buffer_id.?).?;
+    try buffer.rope.setText("fn f() {}\n");
+    try editor.selections.setSingleCursor(allocator, .{ .line = 0, .col = 8 });
+
+    try editor.processKey(.{ .char = 'i' });
+    try editor.processKey(.{ .special = .enter });
+
+    const unit = try editor.config.getTabString(allocator);
+    defer allocator.free(unit);
+    const expected = try std.fmt.allocPrint(allocator, "fn f() {{\n{s}\n}}\n", .{unit});
+    defer allocator.free(expected);
+    const text = try buffer.rope.toString(allocator);
+    defer allocator.free(text);
+    try std.testing.expectEqualStrings(expected, text);
+    try std.testing.expectEqual(Cursor.Position{ .line = 1, .col = unit.len }, editor.selections.primary(allocator).?.head);
+}
+
+test "editor: enter, a typed closing brace and = indent from indents.scm" {
+    if (!build_options.enable_treesitter) return error.SkipZigTest;
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+    editor.config.auto_indent = true;
+    const unit = try editor.config.getTabString(allocator);
+    defer allocator.free(unit);
+
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+    try tmp.dir.writeFile(.{ .sub_path = "lib.rs", .data = "fn f() {\ng();\n}\n" });
+    const path = try tmp.dir.realpathAlloc(allocator, "lib.rs");
+    defer allocator.free(path);
+    _ = try editor.buffer_manager.openFile(path, .{});
+    const buffer = editor.activeWindowBuffer().?;
+
+    // Enter inside the block indents by the tree, not by the unindented line above
+    try editor.selections.setSingleCursor(allocator, .{ .line = 1, .col = 0 });
+    try editor.processKey(.{ .char = 'A' });
+    try editor.processKey(.{ .special = .enter });
+    try std.testing.expectEqual(Cursor.Position{ .line = 2, .col = unit.len }, editor.getCursorPosition());
+    try editor.processKey(.{ .special = .escape });
+
+    // A `}` typed to close the block goes back out to the `fn` line
+    try editor.selections.setSingleCursor(allocator, .{ .line = 3, .col = 0 });
+    for ("ddkA}") |c| try editor.processKey(.{ .char = c });
+    try editor.processKey(.{ .special = .escape });
+    const typed = try buffer.rope.toString(allocator);
+    defer allocator.free(typed);
+    try std.testing.expectEqualStrings("fn f() {\ng();\n}\n", typed);
+
+    // `=` over the selection fixes the line Enter came from
+    try editor.selections.setSingleSelection(allocator, Cursor.Selection.init(.{ .line = 0, .col = 0 }, .{ .line = 2, .col = 0 }));
+    try editor.enterSelectMode();
+    try editor.processKey(.{ .char = '=' });
+    const reindented = try buffer.rope.toString(allocator);
+    defer allocator.free(reindented);
+    const expected = try std.fmt.allocPrint(allocator, "fn f() {{\n{s}g();\n}}\n", .{unit});
+    defer allocator.free(expected);
+    try std.testing.expectEqualStrings(expected, reindented);
+
+    // `==` on the empty line after the last newline leaves the text alone
+    try editor.selections.setSingleCursor(allocator, .{ .line = 3, .col = 0 });
+    for ("==") |c| try editor.processKey(.{ .char = c });
+    const unchanged = try buffer.rope.toString(allocator);
+    defer allocator.free(unchanged);
+    try std.testing.expectEqualStrings(expected, unchanged);
+}
+
+test "editor: folds close, are stepped over, and open where the cursor lands" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+
+    try editor.newBuffer();
+    const buffer = editor.buffer_manager.getBufferMut(editor.buffer_manager.active_buffer_id.?).?;
+    try buffer.rope.setText("a\n    b\n    c\nd\n");
+    try editor.selections.setSingleCursor(allocator, .{ .line = 1, .col = 2 });
+
+    // `zc` folds the indented lines under `a` and moves the cursor onto it
+    for ("zc") |c| try editor.processKey(.{ .char = c });
+    try std.testing.expectEqual(@as(usize, 0), editor.getCursorPosition().line);
+    try std.testing.expect(editor.activeFolds().?.isHidden(2));
+
+    // `j` and `k` treat the closed fold as one line
+    try editor.processKey(.{ .char = 'j' });
+    try std.testing.expectEqual(@as(usize, 3), editor.getCursorPosition().line);
+    try std.testing.expectEqual(@as(?usize, 1), editor.rowOfLine(0, 3));
+    try editor.processKey(.{ .char = 'k' });
+    try std.testing.expectEqual(@as(usize, 0), editor.getCursorPosition().line);
+
+    // Landing inside it (a search match, a jump) opens it
+    try editor.selections.setSingleCursor(allocator, .{ .line = 2, .col = 0 });
+    editor.ensureCursorVisible();
+    try std.testing.expect(!editor.activeFolds().?.isHidden(2));
+
+    for ("zMzR") |c| try editor.processKey(.{ .char = c });
+    try std.testing.expectEqual(@as(usize, 0), editor.activeFolds().?.closed.items.len);
+}
+
+test "editor: expand, shrink and sibling selection over the syntax tree" {
+    if (!build_options.enable_treesitter) return error.SkipZigTest;
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+    try tmp.dir.writeFile(.{ .sub_path = "main.zig", .data = "fn main() void {\n    run(1, 2);\n}\n" });
+    const path = try tmp.dir.realpathAlloc(allocator, "main.zig");
+    defer allocator.free(path);
+    _ = try editor.buffer_manager.openFile(path, .{});
+
+    // Cursors on both arguments grow into the one argument list
+    const one = Cursor.Position{ .line = 1, .col = 8 };
+    const two = Cursor.Position{ .line = 1, .col = 11 };
+    try editor.selections.setSingleCursor(allocator, one);
+    try editor.selections.addCursor(allocator, two);
+    try editor.processKey(.{ .char = '+' });
+    try std.testing.expectEqual(Mode.Mode.select, editor.getMode());
+    try std.testing.expectEqual(@as(usize, 1), editor.selections.count(allocator));
+    const grown = editor.selections.primary(allocator).?.range();
+    try std.testing.expect(!grown.start.greaterThan(one) and two.lessThan(grown.end));
+
+    // Shrinking goes back to both cursors
+    try editor.processKey(.{ .char = '-' });
+    const restored = editor.selections.all(allocator);
+    try std.testing.expectEqual(@as(usize, 2), restored.len);
+    try std.testing.expect(restored[0].head.eql(one) and restored[1].head.eql(two));
+
+    // The node after the first argument is the second
+    try editor.selections.setSingleSelection(allocator, Cursor.Selection.init(one, .{ .line = 1, .col = 9 }));
+    try std.testing.expect(try editor.selectSiblingNode(true));
+    const sibling = editor.selections.primary(allocator).?;
+    try std.testing.expect(sibling.anchor.eql(two));
+    try std.testing.expect(sibling.head.eql(.{ .line = 1, .col = 12 }));
+}
@@ -0,0 +1,767 @@
+//! Ex command execution: line ranges and the line-editing commands
+//! Runs what commandline.zig parses (:s, :g, :d, :m, :t, :normal, :sort, :r, :!)
+
+const std = @import("std");
+const Editor = @import("editor.zig").Editor;
+const Cursor = @import("cursor.zig");
+const Buffer = @import("../buffer/manager.zig");
+const Encoding = @import("../buffer/encoding.zig");
+const Undo = @import("undo.zig");
+const Regex = @import("regex.zig");
+const Keymap = @import("keymap.zig");
+const Commandline = @import("commandline.zig");
+const Shell = @import("shell.zig");
+const Command = @import("command.zig");
+
+const Ex = Commandline.Ex;
+const Address = Commandline.Address;
+const Range = Commandline.Range;
+
+/// Marked line that an edit removed
+const DELETED = std.math.maxInt(usize);
+
+/// Run an ex command on the active window's buffer
+pub fn execute(editor: *Editor, ex: Ex) !void {
+    const buffer = editor.activeWindowBuffer() orelse return error.NoActiveBuffer;
+    const cursor = editor.getCursorPosition();
+    try buffer.holdUndoGroup(cursor);
+
+    var session = Session{
+        .editor = editor,
+        .buffer = buffer,
+        .cursor_line = cursor.line,
+    };
+
+    // Edits made before a failure stay, and stay undoable
+    const result = session.run(ex);
+    try session.finish();
+    try result;
+
+    if (session.substituted_lines > 1) {
+        const msg = try std.fmt.allocPrint(editor.allocator, "{d} substitutions on {d} lines", .{ session.substitutions, session.substituted_lines });
+        defer editor.allocator.free(msg);
+        try editor.messages.add(msg, .info);
+    }
+}
+
+/// User-facing message for an error from `execute`
+pub fn errorMessage(err: anyerror) []const u8 {
+    return switch (err) {
+        error.NoActiveBuffer => "No active buffer",
+        error.InvalidRange => "Invalid range",
+        error.MarkNotSet => "Mark not set",
+        error.PatternNotFound => "Pattern not found",
+        error.NoPreviousPattern => "No previous search pattern",
+        error.InvalidPattern => "Invalid pattern",
+        error.MatchLimitExceeded => "Pattern is too slow to match",
+        error.NestedGlobal => "Cannot nest :g",
+        error.InvalidCommand => "Not a line command",
+        error.MoveIntoItself => "Cannot move a range of lines into itself",
+        error.FileNotFound => "Can't open file",
+        error.ShellFailed => "Failed to run shell command",
+        error.OutOfMemory => "Out of memory",
+        else => "Command failed",
+    };
+}
+
+/// State for one command line: where the cursor ends up and the lines :g has left to visit
+const Session = struct {
+    editor: *Editor,
+    buffer: *Buffer.Buffer, // Its undo group is held until `finish`
+    cursor_line: usize,
+    marked: ?*std.ArrayList(usize) = null, // :g's matching lines, kept current as lines move
+    last_pattern: ?[]const u8 = null, // Reused by an empty pattern, as in :g/pat/s//x/
+    substitutions: usize = 0,
+    substituted_lines: usize = 0,
+
+    /// Push the recorded edits as one undo step and place the cursor
+    fn finish(self: *Session) !void {
+        const line = @min(self.cursor_line, self.lastLine());
+        if (self.buffer.undo_group) |*group| group.cursor_after = .{ .line = line, .col = 0 };
+        try self.buffer.releaseUndoGroup();
+
+        try self.editor.selections.setSingleCursor(self.editor.allocator, .{ .line = line, .col = 0 });
+        self.editor.ensureCursorVisible();
+    }
+
+    fn run(self: *Session, ex: Ex) anyerror!void {
+        switch (ex.action) {
+            .goto => {
+                const lines = try self.lineRange(ex.range, .current, true);
+                self.cursor_line = lines.last -| 1;
+            },
+            .substitute => |sub| try self.substitute(sub, try self.lineRange(ex.range, .current, false)),
+            .global => |g| try self.global(g, try self.lineRange(ex.range, .whole, false)),
+            .delete => {
+                const lines = try self.lineRange(ex.range, .current, false);
+                try self.deleteLines(lines.first - 1, lines.last - 1, true);
+                self.cursor_line = lines.first - 1;
+            },
+            .move => |dest| try self.move(try self.lineRange(ex.range, .current, false), try self.resolve(dest, self.cursor_line)),
+            .copy => |dest| {
+                const lines = try self.lineRange(ex.range, .current, false);
+                const after = try self.resolve(dest, self.cursor_line);
+                const text = try self.linesText(lines.first - 1, lines.last - 1);
+                defer self.editor.allocator.free(text);
+                try self.insertLines(after, text);
+                self.cursor_line = after + (lines.last - lines.first);
+            },
+            .normal => |keys| try self.normal(keys, try self.lineRange(ex.range, .current, false)),
+            .sort => |sort| try self.sortLines(sort, try self.lineRange(ex.range, .whole, false)),
+            .read => |path| {
+                const lines = try self.lineRange(ex.range, .current, true);
+                try self.readFile(path, lines.last);
+            },
+            .shell => |command| {
+                if (ex.range == null) return self.showShellOutput(command);
+                try self.filterLines(command, try self.lineRange(ex.range, .current, false));
+            },
+        }
+    }
+
+    // === Addresses ===
+
+    const LineRange = struct { first: usize, last: usize }; // 1-based, inclusive
+
+    /// Resolve a range (or the command's default) to 1-based line numbers
+    /// Line 0 is only kept when `allow_zero` (":0r", ":0" goto); otherwise it means line 1.
+    fn lineRange(self: *Session, range: ?Range, default: enum { current, whole }, allow_zero: bool) !LineRange {
+        const r = range orelse return switch (default) {
+            .current => .{ .first = self.cursor_line + 1, .last = self.cursor_line + 1 },
+            .whole => .{ .first = 1, .last = self.lastLine() + 1 },
+        };
+
+        const first = try self.resolve(r.start, self.cursor_line);
+        const last = try self.resolve(r.end, if (r.chained) first -| 1 else self.cursor_line);
+        const lo = @min(first, last);
+        const hi = @max(first, last);
+        if (allow_zero) return .{ .first = lo, .last = hi };
+        return .{ .first = @max(lo, 1), .last = @max(hi, 1) };
+    }
+
+    /// Resolve an address to a 1-based line number (0 is "before the first line")
+    /// `from` is the 0-based line that relative addresses and searches start at.
+    fn resolve(self: *Session, address: Address, from: usize) !usize {
+        const base: usize = switch (address.base) {
+            .current => from + 1,
+            .last => self.lastLine() + 1,
+            .line => |n| n,
+            .mark => |name| (try self.markLine(name)) + 1,
+            .search_forward => |pattern| (try self.searchLine(pattern, from, true)) + 1,
+            .search_backward => |pattern| (try self.searchLine(pattern, from, false)) + 1,
+        };
+
+        const line = @as(i64, @intCast(@min(base, std.math.maxInt(i32)))) + address.offset;
+        if (line < 0 or line > self.lastLine() + 1) return error.InvalidRange;
+        return @intCast(line);
+    }
+
+    fn markLine(self: *Session, name: u8) !usize {
+        if (name == '<' or name == '>') {
+            const lines = self.editor.visual_lines orelse return error.MarkNotSet;
+            return if (name == '<') lines.first else lines.last;
+        }
+        const mark = self.editor.marks.getMark(name) orelse return error.MarkNotSet;
+        if (mark.buffer_id != self.buffer.metadata.id) return error.MarkNotSet;
+        return mark.position.line;
+    }
+
+    /// Next (or previous) line matching `pattern` after `from`, wrapping around
+    fn searchLine(self: *Session, pattern: []const u8, from: usize, forward: bool) !usize {
+        var re = try self.compilePattern(pattern, false);
+        defer re.deinit();
+
+        const total = self.lastLine() + 1;
+        var step: usize = 1;
+        while (step <= total) : (step += 1) {
+            const line = if (forward) (from + step) % total else (from + total - step % total) % total;
+            if (try self.lineMatches(&re, line)) return line;
+        }
+        return error.PatternNotFound;
+    }
+
+    /// Compile a pattern; an empty one reuses the last pattern of this command or the last search
+    fn compilePattern(self: *Session, pattern: []const u8, ignore_case: bool) !Regex.Regex {
+        const allocator = self.editor.allocator;
+        const search = &self.editor.search;
+        const options = Regex.Options{ .case_insensitive = ignore_case or !search.options.case_sensitive };
+
+        if (pattern.len > 0) {
+            self.last_pattern = pattern;
+            return compileRegex(allocator, pattern, options);
+        }
+        if (self.last_pattern) |last| return compileRegex(allocator, last, options);
+
+        const query = search.getQuery();
+        if (query.len == 0) return error.NoPreviousPattern;
+        if (search.options.regex) return search.compileRegex(allocator) catch |err| switch (err) {
+            error.OutOfMemory => return err,
+            else => return error.InvalidPattern,
+        };
+
+        const escaped = try escapeLiteral(allocator, query);
+        defer allocator.free(escaped);
+        return compileRegex(allocator, escaped, options);
+    }
+
+    fn lineMatches(self: *Session, re: *const Regex.Regex, line: usize) !bool {
+        const text = try self.buffer.rope.lineSlice(self.editor.allocator, line);
+        defer self.editor.allocator.free(text);
+        var caps = Regex.Captures{};
+        return try re.find(text, 0, &caps) != null;
+    }
+
+    // === Line Edits ===
+
+    /// Last line holding text; a final newline ends the last line rather than starting one
+    fn lastLine(self: *const Session) usize {
+        const count = self.buffer.rope.lineCount();
+        return if (self.endsWithNewline()) count - 2 else count - 1;
+    }
+
+    fn endsWithNewline(self: *const Session) bool {
+        const rope = &self.buffer.rope;
+        const count = rope.lineCount();
+        return count > 1 and rope.lineToByte(count - 1) == rope.len();
+    }
+
+    /// Text of 0-based lines `first..last`, always newline-terminated (caller owns)
+    fn linesText(self: *Session, first: usize, last: usize) ![]u8 {
+        const rope = &self.buffer.rope;
+        const start = rope.lineToByte(first);
+        const end = rope.lineToByte(last + 1);
+        const text = try rope.slice(self.editor.allocator, start, end);
+        if (std.mem.endsWith(u8, text, "\n")) return text;
+
+        defer self.editor.allocator.free(text);
+        return std.mem.concat(self.editor.allocator, u8, &.{ text, "\n" });
+    }
+
+    /// Replace bytes `start..end`, recording the change in this command's undo step
+    fn replace(self: *Session, start: usize, end: usize, text: []const u8) !void {
+        const allocator = self.editor.allocator;
+        const rope = &self.buffer.rope;
+        const old = try rope.slice(allocator, start, end);
+        defer allocator.free(old);
+
+        const line = rope.byteToLine(start);
+        const position = Cursor.Position{ .line = line, .col = start - rope.lineToByte(line) };
+        try self.record(position, text, old);
+
+        if (end > start) try self.buffer.delete(start, end);
+        try self.buffer.insert(start, text);
+    }
+
+    fn record(self: *Session, position: Cursor.Position, text: []const u8, old: []const u8) !void {
+        const allocator = self.editor.allocator;
+        const group = if (self.buffer.undo_group) |*group| group else return;
+        try group.addOperation(allocator, try Undo.Operation.init(allocator, .replace, position, text, old));
+    }
+
+    /// Run a builtin from command.zig on 0-based lines `first..last`, recording what it changed
+    fn builtin(self: *Session, name: []const u8, first: usize, last: usize) !void {
+        const allocator = self.editor.allocator;
+        const rope = &self.buffer.rope;
+        const start = rope.lineToByte(first);
+        const old = try rope.slice(allocator, start, rope.lineEnd(last));
+        defer allocator.free(old);
+        const before = rope.len();
+
+        const start_pos = Cursor.Position{ .line = first, .col = 0 };
+        try self.editor.selections.setSingleSelection(allocator, Cursor.Selection.init(start_pos, .{ .line = last, .col = 0 }));
+        var ctx = Command.Context{ .editor = self.editor };
+        if (self.editor.command_registry.execute(name, &ctx) != .success) return error.CommandFailed;
+
+        const end = start + old.len + rope.len() - before;
+        const text = try rope.slice(allocator, start, end);
+        defer allocator.free(text);
+        if (!std.mem.eql(u8, text, old)) try self.record(start_pos, text, old);
+    }
+
+    /// Keep :g's marked lines pointing at the same text after `removed` lines at
+    /// `first` were replaced by `inserted` lines
+    fn shiftMarks(self: *Session, first: usize, removed: usize, inserted: usize) void {
+        const marked = self.marked orelse return;
+        for (marked.items) |*line| {
+            if (line.* == DELETED or line.* < first) continue;
+            if (line.* < first + removed) {
+                line.* = DELETED;
+            } else {
+                line.* = line.* - removed + inserted;
+            }
+        }
+    }
+
+    /// Delete 0-based lines `first..last`, optionally yanking them
+    fn deleteLines(self: *Session, first: usize, last: usize, yank: bool) !void {
+        const rope = &self.buffer.rope;
+        var start = rope.lineToByte(first);
+        const end = rope.lineToByte(last + 1);
+
+        if (yank) {
+            const text = try self.linesText(first, last);
+            defer self.editor.allocator.free(text);
+            try self.editor.storeText(text, true, null);
+        }
+
+        // Without a final newline, the last line takes the newline before it along
+        if (end == rope.len() and !self.endsWithNewline() and first > 0) start -= 1;
+        try self.replace(start, end, "");
+        self.shiftMarks(first, last - first + 1, 0);
+    }
+
+    /// Insert newline-terminated `text` after 1-based line `after` (0 inserts at the top)
+    fn insertLines(self: *Session, after: usize, text: []const u8) !void {
+        if (text.len == 0) return;
+        const rope = &self.buffer.rope;
+        const count = std.mem.count(u8, text, "\n");
+
+        if (after > self.lastLine() and !self.endsWithNewline()) {
+            // Appending past a last line with no newline: end that line first
+            const joined = try std.mem.concat(self.editor.allocator, u8, &.{ "\n", text[0 .. text.len - 1] });
+            defer self.editor.allocator.free(joined);
+            try self.replace(rope.len(), rope.len(), joined);
+        } else {
+            const pos = rope.lineToByte(after);
+            try self.replace(pos, pos, text);
+        }
+        self.shiftMarks(after, 0, count);
+    }
+
+    // === Commands ===
+
+    fn substitute(self: *Session, sub: Ex.Substitute, lines: LineRange) !void {
+        const allocator = self.editor.allocator;
+        var re = try self.compilePattern(sub.pattern, sub.ignore_case);
+        defer re.deinit();
+
+        const template = try translateReplacement(allocator, sub.replacement);
+        defer allocator.free(template);
+
+        // Lines the replacement splits push the rest of the range down
+        var line = lines.first - 1;
+        var last = lines.last - 1;
+        var changed = false;
+        while (line <= last) : (line += 1) {
+            const added = try self.substituteLine(&re, template, sub.global, line) orelse continue;
+            changed = true;
+            line += added;
+            last += added;
+        }
+
+        // Inside :g, lines without a match are expected
+        if (!changed and self.marked == null) return error.PatternNotFound;
+    }
+
+    /// Substitute in one 0-based line; returns the number of lines the replacement added, or null if nothing matched
+    fn substituteLine(self: *Session, re: *const Regex.Regex, template: []const u8, global_flag: bool, line: usize) !?usize {
+        const allocator = self.editor.allocator;
+        const text = try self.buffer.rope.lineSlice(allocator, line);
+        defer allocator.free(text);
+
+        var result = std.ArrayList(u8).empty;
+        defer result.deinit(allocator);
+
+        var count: usize = 0;
+        var copied: usize = 0; // End of the text already copied into `result`
+        var pos: usize = 0;
+        while (pos <= text.len) {
+            var caps = Regex.Captures{};
+            const span = try re.find(text, pos, &caps) orelse break;
+
+            const replacement = try Regex.expandReplacement(allocator, template, text, &caps);
+            defer allocator.free(replacement);
+            try result.appendSlice(allocator, text[copied..span.start]);
+            try result.appendSlice(allocator, replacement);
+            copied = span.end;
+            count += 1;
+
+            if (!global_flag) break;
+            pos = if (span.end == span.start) span.end + 1 else span.end; // Step past empty matches
+        }
+        if (count == 0) return null;
+        try result.appendSlice(allocator, text[copied..]);
+
+        const start = self.buffer.rope.lineToByte(line);
+        try self.replace(start, start + text.len, result.items);
+
+        const added = std.mem.count(u8, result.items, "\n");
+        self.shiftMarks(line + 1, 0, added);
+        self.cursor_line = line + added;
+        self.substitutions += count;
+        self.substituted_lines += 1;
+        return added;
+    }
+
+    fn global(self: *Session, g: Ex.Global, lines: LineRange) !void {
+        if (self.marked != null) return error.NestedGlobal;
+        const allocator = self.editor.allocator;
+
+        const cmd = try Commandline.parse(allocator, g.command);
+        defer Commandline.deinit(cmd, allocator);
+        const sub = switch (cmd) {
+            .ex => |ex| ex,
+            else => return error.InvalidCommand,
+        };
+        if (sub.action == .global) return error.NestedGlobal;
+
+        // Mark every line first, so edits made by the command don't change what it visits
+        var marked = std.ArrayList(usize).empty;
+        defer marked.deinit(allocator);
+        {
+            var re = try self.compilePattern(g.pattern, false);
+            defer re.deinit();
+            var line = lines.first - 1;
+            while (line < lines.last) : (line += 1) {
+                if (try self.lineMatches(&re, line) != g.invert) try marked.append(allocator, line);
+            }
+        }
+        if (marked.items.len == 0) return error.PatternNotFound;
+
+        self.marked = &marked;
+        defer self.marked = null;
+        for (marked.items) |line| {
+            if (line == DELETED) continue;
+            self.cursor_line = line;
+            try self.run(sub);
+        }
+    }
+
+    fn move(self: *Session, lines: LineRange, after: usize) !void {
+        if (after >= lines.first and after < lines.last) return error.MoveIntoItself;
+        const count = lines.last - lines.first + 1;
+        if (after == lines.last or after + 1 == lines.first) {
+            self.cursor_line = if (after == lines.last) lines.last - 1 else after + count - 1;
+            return;
+        }
+
+        const text = try self.linesText(lines.first - 1, lines.last - 1);
+        defer self.editor.allocator.free(text);
+
+        // Edit the later spot first so the earlier line numbers stay valid
+        if (after > lines.last) {
+            try self.insertLines(after, text);
+            try self.deleteLines(lines.first - 1, lines.last - 1, false);
+            self.cursor_line = after - 1;
+        } else {
+            try self.deleteLines(lines.first - 1, lines.last - 1, false);
+            try self.insertLines(after, text);
+            self.cursor_line = after + count - 1;
+        }
+    }
+
+    fn normal(self: *Session, keys: []const u8, lines: LineRange) !void {
+        const editor = self.editor;
+        var line = lines.first - 1;
+        var last = lines.last - 1;
+        while (line <= last) : (line += 1) {
+            const before = self.buffer.rope.lineCount();
+            try editor.selections.setSingleCursor(editor.allocator, .{ .line = line, .col = 0 });
+            for (keys) |c| try editor.processKey(Keymap.Key{ .char = c });
+            if (editor.getMode() != .normal) try editor.enterNormalMode();
+
+            // Keys that add or remove lines move the rest of the range with them
+            const after = self.buffer.rope.lineCount();
+            if (after > before) {
+                self.shiftMarks(line + 1, 0, after - before);
+                line += after - before;
+                last += after - before;
+            } else if (before > after) {
+                self.shiftMarks(line + 1, before - after, 0);
+                last -|= before - after;
+            }
+            self.cursor_line = editor.getCursorPosition().line;
+        }
+    }
+
+    fn sortLines(self: *Session, sort: Ex.Sort, lines: LineRange) !void {
+        const allocator = self.editor.allocator;
+        const rope = &self.buffer.rope;
+        const first = lines.first - 1;
+        const last = lines.last - 1;
+        self.cursor_line = first;
+        if (std.meta.eql(sort, Ex.Sort{})) {
+            return self.builtin("sort_lines", first, last);
+        }
+        const start = rope.lineToByte(first);
+        const end = rope.lineEnd(last);
+
+        const text = try rope.slice(allocator, start, end);
+        defer allocator.free(text);
+
+        var items = std.ArrayList([]const u8).empty;
+        defer items.deinit(allocator);
+        var it = std.mem.splitScalar(u8, text, '\n');
+        while (it.next()) |line| try items.append(allocator, line);
+
+        std.mem.sort([]const u8, items.items, sort, sortLessThan);
+        if (sort.reverse) std.mem.reverse([]const u8, items.items);
+
+        var kept: usize = items.items.len;
+        if (sort.unique and kept > 0) {
+            kept = 1;
+            for (items.items[1..]) |line| {
+                const prev = items.items[kept - 1];
+                if (!sortLessThan(sort, prev, line) and !sortLessThan(sort, line, prev)) continue;
+                items.items[kept] = line;
+                kept += 1;
+            }
+        }
+
+        const sorted = try std.mem.join(allocator, "\n", items.items[0..kept]);
+        defer allocator.free(sorted);
+        if (std.mem.eql(u8, sorted, text)) return;
+
+        try self.replace(start, end, sorted);
+        self.shiftMarks(first + kept, items.items.len - kept, 0);
+    }
+
+    /// Insert a file, or a command's output for `:r !cmd`, after 1-based line `after`
+    fn readFile(self: *Session, path: []const u8, after: usize) !void {
+        const allocator = self.editor.allocator;
+        const decoded = if (std.mem.startsWith(u8, path, "!")) blk: {
+            const output = try self.runShell(std.mem.trimLeft(u8, path[1..], " "), null) orelse return;
+            allocator.free(output.stderr);
+            break :blk output.stdout;
+        } else blk: {
+            const raw = std.fs.cwd().readFileAlloc(allocator, path, Buffer.MAX_FILE_SIZE) catch |err| switch (err) {
+                error.OutOfMemory => return err,
+                else => return error.FileNotFound,
+            };
+
+            // Same conversion as opening the file: UTF-8 text with `\n` line endings
+            const format = Encoding.detect(raw);
+            if (format.isPlain()) break :blk raw;
+            defer allocator.free(raw);
+            break :blk try Encoding.decode(allocator, raw, format);
+        };
+        defer allocator.free(decoded);
+        if (decoded.len == 0) return;
+
+        const text = if (std.mem.endsWith(u8, decoded, "\n"))
+            try allocator.dupe(u8, decoded)
+        else
+            try std.mem.concat(allocator, u8, &.{ decoded, "\n" });
+        defer allocator.free(text);
+
+        try self.insertLines(after, text);
+        self.cursor_line = after;
+    }
+
+    /// Replace lines with what they print when piped through `command` (`:{range}!cmd`)
+    fn filterLines(self: *Session, command: []const u8, lines: LineRange) !void {
+        const allocator = self.editor.allocator;
+        const first = lines.first - 1;
+        const last = lines.last - 1;
+
+        const input = try self.linesText(first, last);
+        defer allocator.free(input);
+        var output = try self.runShell(command, input) orelse return;
+        defer output.deinit(allocator);
+        self.cursor_line = first;
+
+        if (output.stdout.len == 0) return self.deleteLines(first, last, false);
+
+        // The output takes the range's place; a last line without a newline keeps lacking one
+        const rope = &self.buffer.rope;
+        const start = rope.lineToByte(first);
+        const end = rope.lineToByte(last + 1);
+        const unterminated = end == rope.len() and !self.endsWithNewline();
+        const body = if (std.mem.endsWith(u8, output.stdout, "\n")) output.stdout[0 .. output.stdout.len - 1] else output.stdout;
+        const text = try std.mem.concat(allocator, u8, &.{ body, if (unterminated) "" else "\n" });
+        defer allocator.free(text);
+
+        try self.replace(start, end, text);
+        self.shiftMarks(first, last - first + 1, std.mem.count(u8, body, "\n") + 1);
+    }
+
+    /// Run `:!cmd` and show what it printed
+    fn showShellOutput(self: *Session, command: []const u8) !void {
+        const allocator = self.editor.allocator;
+        var output = try self.runShell(command, null) orelse return;
+        defer output.deinit(allocator);
+
+        const text = std.mem.trimRight(u8, output.stdout, &std.ascii.whitespace);
+        if (text.len == 0) return;
+        const more = std.mem.count(u8, text, "\n");
+        const msg = if (more == 0)
+            try allocator.dupe(u8, text)
+        else
+            try std.fmt.allocPrint(allocator, "{s} (+{d} more lines)", .{ Shell.firstLine(text), more });
+        defer allocator.free(msg);
+        try self.editor.messages.add(msg, .info);
+    }
+
+    /// Run a shell command; a non-zero exit is reported and gives null
+    fn runShell(self: *Session, command: []const u8, input: ?[]const u8) !?Shell.Output {
+        const allocator = self.editor.allocator;
+        var output = Shell.run(allocator, command, input) catch |err| switch (err) {
+            error.OutOfMemory => return err,
+            else => return error.ShellFailed,
+        };
+        if (output.succeeded()) return output;
+
+        defer output.deinit(allocator);
+        const msg = try output.failureMessage(allocator, command);
+        defer allocator.free(msg);
+        try self.editor.messages.add(msg, .error_msg);
+        return null;
+    }
+};
+
+fn compileRegex(allocator: std.mem.Allocator, pattern: []const u8, options: Regex.Options) !Regex.Regex {
+    return Regex.Regex.compile(allocator, pattern, options) catch |err| switch (err) {
+        error.OutOfMemory => return err,
+        else => return error.InvalidPattern,
+    };
+}
+
+/// Escape regex metacharacters so a plain search query matches literally
+fn escapeLiteral(allocator: std.mem.Allocator, text: []const u8) ![]u8 {
+    var result = std.ArrayList(u8).empty;
+    errdefer result.deinit(allocator);
+    for (text) |c| {
+        if (std.mem.indexOfScalar(u8, "\\^$.|?*+()[]{}", c) != null) try result.append(allocator, '\\');
+        try result.append(allocator, c);
+    }
+    return result.toOwnedSlice(allocator);
+}
+
+/// Translate a vim replacement (`&`, `\1`, `\n`) to a `Regex.expandReplacement` template
+fn translateReplacement(allocator: std.mem.Allocator, vim: []const u8) ![]u8 {
+    var result = std.ArrayList(u8).empty;
+    errdefer result.deinit(allocator);
+
+    var i: usize = 0;
+    while (i < vim.len) : (i += 1) {
+        const c = vim[i];
+        switch (c) {
+            '&' => try result.appendSlice(allocator, "$0"),
+            '$' => try result.appendSlice(allocator, "$$"),
+            '\\' => {
+                if (i + 1 == vim.len) {
+                    try result.append(allocator, '\\');
+                    continue;
+                }
+                i += 1;
+                const e = vim[i];
+                switch (e) {
+                    '0'...'9' => try result.appendSlice(allocator, &.{ '$', '{', e, '}' }),
+                    'n', 'r' => try result.append(allocator, '\n'),
+                    't' => try result.append(allocator, '\t'),
+                    '$' => try result.appendSlice(allocator, "$$"),
+                    else => try result.append(allocator, e), // `\&`, `\\`, `\/`
+                }
+            },
+            else => try result.append(allocator, c),
+        }
+    }
+    return result.toOwnedSlice(allocator);
+}
+
+fn sortLessThan(sort: Ex.Sort, a: []const u8, b: []const u8) bool {
+    if (sort.numeric) {
+        // Lines without a number sort first, in their original order
+        const x = leadingNumber(a) orelse return leadingNumber(b) != null;
+        const y = leadingNumber(b) orelse return false;
+        return x < y;
+    }
+    if (sort.ignore_case) return std.ascii.lessThanIgnoreCase(a, b);
+    return std.mem.lessThan(u8, a, b);
+}
+
+/// First decimal number in a line (with its minus sign), for `:sort n`
+fn leadingNumber(line: []const u8) ?i64 {
+    const digit = std.mem.indexOfAny(u8, line, "0123456789") orelse return null;
+    var end = digit;
+    while (end < line.len and std.ascii.isDigit(line[end])) end += 1;
+    const start = if (digit > 0 and line[digit - 1] == '-') digit - 1 else digit;
+    return std.fmt.parseInt(i64, line[start..end], 10) catch null;
+}
+
+// === Tests ===
+
+fn expectBuffer(editor: *Editor, expected: []const u8) !void {
+    const text = try editor.getActiveBuffer().?.getText();
+    defer std.testing.allocator.free(text);
+    try std.testing.expectEqualStrings(expected, text);
+}
+
+fn runLine(editor: *Editor, line: []const u8) !void {
+    const cmd = try Commandline.parse(std.testing.allocator, line);
+    defer Commandline.deinit(cmd, std.testing.allocator);
+    try execute(editor, cmd.ex);
+}
+
+test "ex: substitute, delete, move, copy, sort" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+    _ = try editor.buffer_manager.createFromString("b 10\na 2\nc 1\n");
+
+    try runLine(&editor, "%s/\\d+/<&>/");
+    try expectBuffer(&editor, "b <10>\na <2>\nc <1>\n");
+
+    try runLine(&editor, "%s/<(\\d+)>/\\1/g");
+    try runLine(&editor, "sort n");
+    try expectBuffer(&editor, "c 1\na 2\nb 10\n");
+
+    try runLine(&editor, "1m$");
+    try expectBuffer(&editor, "a 2\nb 10\nc 1\n");
+
+    try runLine(&editor, "/^b/t0");
+    try expectBuffer(&editor, "b 10\na 2\nb 10\nc 1\n");
+
+    try runLine(&editor, "2,$-1d");
+    try expectBuffer(&editor, "b 10\nc 1\n");
+    try std.testing.expectEqualStrings("a 2\nb 10\n", editor.clipboard.getContent().?);
+
+    // The whole command line is one undo step
+    const buffer = editor.activeWindowBuffer().?;
+    try std.testing.expectEqual(@as(usize, 6), buffer.undo_history.stateCount() - 1);
+    try std.testing.expectError(error.PatternNotFound, runLine(&editor, "s/zzz/y/"));
+}
+
+test "ex: global runs a command on each matching line" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+    _ = try editor.buffer_manager.createFromString("keep 1\ndrop\nkeep 2\ndrop\nlast");
+
+    try runLine(&editor, "g/drop/d");
+    try expectBuffer(&editor, "keep 1\nkeep 2\nlast");
+
+    try runLine(&editor, "v/keep/s/$/!/");
+    try expectBuffer(&editor, "keep 1\nkeep 2\nlast!");
+
+    try runLine(&editor, "g/keep/m0");
+    try expectBuffer(&editor, "keep 2\nkeep 1\nlast!");
+
+    try std.testing.expectError(error.NestedGlobal, runLine(&editor, "g/keep/g/1/d"));
+
+    // Filtered lines keep the missing final newline
+    try runLine(&editor, "%!sort");
+    try expectBuffer(&editor, "keep 1\nkeep 2\nlast!");
+}
+
+test "ex: normal runs keys on each line as one undo step" {
+    const allocator = std.testing.allocator;
+    var editor = try Editor.init(allocator);
+    defer editor.deinit();
+    _ = try editor.buffer_manager.createFromString("a\nb\nc\n");
+
+    try runLine(&editor, "%normal A;");
+    try expectBuffer(&editor, "a;\nb;\nc;\n");
+
+    var ctx = Command.Context{ .editor = &editor };
+    try std.testing.expect(editor.command_registry.execute("undo", &ctx) == .success);
+    try expectBuffer(&editor, "a\nb\nc\n");
+
+    // Plain :sort is the sort_lines builtin, undone the same way
+    try runLine(&editor, "%normal A;");
+    try runLine(&editor, "sort!");
+    try runLine(&editor, "sort");
+    try expectBuffer(&editor, "a;\nb;\nc;\n");
+    try std.testing.expect(editor.command_registry.execute("undo", &ctx) == .success);
+    try expectBuffer(&editor, "c;\nb;\na;\n");
+}
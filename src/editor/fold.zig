@@ -0,0 +1,346 @@
+//! Code folding
+//! Fold ranges come from the language's folds.scm query, or from indentation without one
+
+const std = @import("std");
+const Rope = @import("../buffer/rope.zig").Rope;
+const TextChange = @import("../buffer/manager.zig").TextChange;
+
+/// Lines a fold covers; `start` stays visible when it's closed, `start + 1..end` hide
+pub const Range = struct {
+    start: usize,
+    end: usize, // Inclusive
+
+    pub fn contains(self: Range, line: usize) bool {
+        return line >= self.start and line <= self.end;
+    }
+
+    /// Whether closing it hides `line`
+    pub fn hides(self: Range, line: usize) bool {
+        return line > self.start and line <= self.end;
+    }
+
+    /// Where the lines are after `change`; null if it reached across the fold's first or last line
+    /// Lines added or removed before the fold move it, and inside it move its end.
+    pub fn afterEdit(self: Range, change: TextChange) ?Range {
+        const delta = change.lineDelta();
+        if (change.start.line > self.end) return self;
+        if (change.old_end.line < self.start or (change.old_end.line == self.start and change.old_end.byte_col == 0)) {
+            return .{ .start = shift(self.start, delta), .end = shift(self.end, delta) };
+        }
+        if (change.start.line >= self.start and change.old_end.line <= self.end) {
+            return .{ .start = self.start, .end = shift(self.end, delta) };
+        }
+        return null;
+    }
+
+    fn shift(line: usize, delta: isize) usize {
+        return @intCast(@max(0, @as(isize, @intCast(line)) + delta));
+    }
+};
+
+/// Ranges sorted by start line, keeping the largest of those starting on the same line
+/// so each line starts at most one fold. Shrinks `ranges` in place; returns the kept part.
+pub fn normalize(ranges: []Range) []Range {
+    std.mem.sort(Range, ranges, {}, struct {
+        fn lessThan(_: void, a: Range, b: Range) bool {
+            return a.start < b.start or (a.start == b.start and a.end > b.end);
+        }
+    }.lessThan);
+
+    var kept: usize = 0;
+    for (ranges) |range| {
+        if (range.end <= range.start) continue;
+        if (kept > 0 and ranges[kept - 1].start == range.start) continue;
+        ranges[kept] = range;
+        kept += 1;
+    }
+    return ranges[0..kept];
+}
+
+/// Fold ranges from indentation (a line folds the more-indented ones after it); caller frees
+pub fn indentRanges(allocator: std.mem.Allocator, rope: *const Rope) ![]Range {
+    const line_count = rope.lineCount();
+
+    // Indent width of every line, counting a tab as four; null for blank ones
+    const widths = try allocator.alloc(?usize, line_count);
+    defer allocator.free(widths);
+    var lines = rope.lines(0);
+    while (lines.next()) |line| {
+        var cursor = rope.cursorAt(line.start);
+        var width: usize = 0;
+        var blank = true;
+        while (cursor.offset() < line.end) {
+            const c = cursor.nextByte() orelse break;
+            if (c == ' ') {
+                width += 1;
+            } else if (c == '\t') {
+                width += 4;
+            } else {
+                blank = c == '\r';
+                break;
+            }
+        }
+        widths[line.number] = if (blank) null else width;
+    }
+
+    var ranges = std.ArrayList(Range).empty;
+    errdefer ranges.deinit(allocator);
+    for (widths, 0..) |maybe_width, line| {
+        const width = maybe_width orelse continue;
+        var end = line;
+        var next = line + 1;
+        while (next < line_count) : (next += 1) {
+            if (widths[next]) |deeper| {
+                if (deeper <= width) break;
+                end = next;
+            }
+        }
+        if (end > line) try ranges.append(allocator, .{ .start = line, .end = end });
+    }
+    return ranges.toOwnedSlice(allocator);
+}
+
+/// Fold ranges of one buffer and which of them are closed
+pub const FoldSet = struct {
+    ranges: []Range = &.{}, // Every fold, sorted by start line
+    closed: std.ArrayList(Range) = .empty, // Closed ones, sorted by start line
+    version: ?usize = null, // Version of the buffer the ranges were found in
+
+    pub fn deinit(self: *FoldSet, allocator: std.mem.Allocator) void {
+        allocator.free(self.ranges);
+        self.closed.deinit(allocator);
+    }
+
+    /// Replace the ranges with ones found after `changes` (taking ownership of them)
+    /// Closed folds move with the changed lines; with no record of the changes (null) they open.
+    pub fn update(self: *FoldSet, allocator: std.mem.Allocator, ranges: []Range, version: usize, changes: ?[]const TextChange) void {
+        allocator.free(self.ranges);
+        self.ranges = ranges;
+        self.version = version;
+
+        const known = changes orelse return self.closed.clearRetainingCapacity();
+        var kept: usize = 0;
+        next: for (self.closed.items) |closed| {
+            var moved = closed;
+            for (known) |change| moved = moved.afterEdit(change) orelse continue :next;
+            const range = self.startingAt(moved.start) orelse continue;
+            if (kept > 0 and self.closed.items[kept - 1].start == range.start) continue;
+            self.closed.items[kept] = range;
+            kept += 1;
+        }
+        self.closed.shrinkRetainingCapacity(kept);
+    }
+
+    /// Fold starting on `line`
+    pub fn startingAt(self: *const FoldSet, line: usize) ?Range {
+        for (self.ranges) |range| {
+            if (range.start == line) return range;
+            if (range.start > line) break;
+        }
+        return null;
+    }
+
+    /// Innermost fold containing `line` that isn't closed
+    pub fn openAround(self: *const FoldSet, line: usize) ?Range {
+        var innermost: ?Range = null;
+        for (self.ranges) |range| {
+            if (range.start > line) break;
+            if (!range.contains(line) or self.isClosed(range.start)) continue;
+            innermost = range; // Later starts nest inside earlier ones
+        }
+        return innermost;
+    }
+
+    /// Whether the fold starting on `line` is closed
+    pub fn isClosed(self: *const FoldSet, line: usize) bool {
+        return self.closedIndex(line) != null;
+    }
+
+    fn closedIndex(self: *const FoldSet, line: usize) ?usize {
+        for (self.closed.items, 0..) |range, i| {
+            if (range.start == line) return i;
+        }
+        return null;
+    }
+
+    pub fn close(self: *FoldSet, allocator: std.mem.Allocator, range: Range) !void {
+        if (self.isClosed(range.start)) return;
+        var at: usize = 0;
+        while (at < self.closed.items.len and self.closed.items[at].start < range.start) at += 1;
+        try self.closed.insert(allocator, at, range);
+    }
+
+    /// Open the fold starting on `line`; false if it wasn't closed
+    pub fn open(self: *FoldSet, line: usize) bool {
+        const i = self.closedIndex(line) orelse return false;
+        _ = self.closed.orderedRemove(i);
+        return true;
+    }
+
+    pub fn closeAll(self: *FoldSet, allocator: std.mem.Allocator) !void {
+        self.closed.clearRetainingCapacity();
+        try self.closed.appendSlice(allocator, self.ranges);
+    }
+
+    pub fn openAll(self: *FoldSet) void {
+        self.closed.clearRetainingCapacity();
+    }
+
+    /// Open every closed fold hiding `line`; false if none did
+    pub fn reveal(self: *FoldSet, line: usize) bool {
+        var kept: usize = 0;
+        for (self.closed.items) |range| {
+            if (range.hides(line)) continue;
+            self.closed.items[kept] = range;
+            kept += 1;
+        }
+        const revealed = kept < self.closed.items.len;
+        self.closed.shrinkRetainingCapacity(kept);
+        return revealed;
+    }
+
+    /// Last line hidden along with `line` by the closed folds over it; null if it's visible
+    fn hiddenUntil(self: *const FoldSet, line: usize) ?usize {
+        var until: ?usize = null;
+        for (self.closed.items) |range| {
+            if (range.start >= line) break;
+            if (range.hides(line)) until = @max(until orelse 0, range.end);
+        }
+        return until;
+    }
+
+    pub fn isHidden(self: *const FoldSet, line: usize) bool {
+        return self.hiddenUntil(line) != null;
+    }
+
+    /// Line shown in place of `line`: itself, or the first line of the closed fold hiding it
+    pub fn visibleLine(self: *const FoldSet, line: usize) usize {
+        var visible = line;
+        outer: while (true) {
+            for (self.closed.items) |range| {
+                if (range.hides(visible)) {
+                    visible = range.start;
+                    continue :outer;
+                }
+            }
+            return visible;
+        }
+    }
+
+    /// First line shown after `line`, skipping what closed folds hide; may be past the end
+    pub fn nextVisible(self: *const FoldSet, line: usize) usize {
+        var next = line + 1;
+        while (self.hiddenUntil(next)) |end| next = end + 1;
+        return next;
+    }
+
+    /// Last line shown before `line`; null at the top
+    pub fn prevVisible(self: *const FoldSet, line: usize) ?usize {
+        if (line == 0) return null;
+        return self.visibleLine(line - 1);
+    }
+
+    /// Line `rows` screen rows below `line`, stopping at `line_count - 1`
+    pub fn advance(self: *const FoldSet, line: usize, rows: usize, line_count: usize) usize {
+        var at = line;
+        for (0..rows) |_| {
+            const next = self.nextVisible(at);
+            if (next >= line_count) break;
+            at = next;
+        }
+        return at;
+    }
+
+    /// Line `rows` screen rows above `line`, stopping at the top
+    pub fn retreat(self: *const FoldSet, line: usize, rows: usize) usize {
+        var at = line;
+        for (0..rows) |_| at = self.prevVisible(at) orelse break;
+        return at;
+    }
+
+    /// Screen rows between `from` and `to` (the number of visible lines in `from..to`)
+    pub fn rowsBetween(self: *const FoldSet, from: usize, to: usize) usize {
+        var rows: usize = 0;
+        var at = from;
+        while (at < to) : (at = self.nextVisible(at)) rows += 1;
+        return rows;
+    }
+};
+
+// === Tests ===
+
+test "fold: ranges from indentation" {
+    const allocator = std.testing.allocator;
+    var rope = try Rope.initFromString(allocator, "fn a() {\n    if (x) {\n        y();\n\n    }\n}\nz\n");
+    defer rope.deinit();
+
+    const ranges = try indentRanges(allocator, &rope);
+    defer allocator.free(ranges);
+    try std.testing.expectEqualSlices(Range, &.{
+        .{ .start = 0, .end = 4 },
+        .{ .start = 1, .end = 2 },
+    }, ranges);
+
+    var overlapping = [_]Range{ .{ .start = 3, .end = 4 }, .{ .start = 0, .end = 5 }, .{ .start = 0, .end = 2 }, .{ .start = 6, .end = 6 } };
+    try std.testing.expectEqualSlices(Range, &.{
+        .{ .start = 0, .end = 5 },
+        .{ .start = 3, .end = 4 },
+    }, normalize(&overlapping));
+}
+
+test "fold: closed folds hide lines and are skipped over" {
+    const allocator = std.testing.allocator;
+    var folds = FoldSet{};
+    defer folds.deinit(allocator);
+    const ranges = try allocator.dupe(Range, &.{ .{ .start = 1, .end = 6 }, .{ .start = 2, .end = 4 } });
+    folds.update(allocator, ranges, 0, null);
+
+    try folds.close(allocator, folds.openAround(3).?);
+    try std.testing.expectEqual(Range{ .start = 2, .end = 4 }, folds.closed.items[0]);
+    try std.testing.expect(folds.isHidden(3));
+    try std.testing.expectEqual(@as(usize, 5), folds.nextVisible(2));
+    try std.testing.expectEqual(@as(?usize, 2), folds.prevVisible(5));
+
+    // Closing again reaches the enclosing fold
+    try folds.close(allocator, folds.openAround(2).?);
+    try std.testing.expectEqual(@as(usize, 7), folds.nextVisible(1));
+    try std.testing.expectEqual(@as(usize, 1), folds.visibleLine(3));
+    try std.testing.expectEqual(@as(usize, 2), folds.rowsBetween(0, 7));
+    try std.testing.expectEqual(@as(usize, 7), folds.advance(0, 2, 10));
+
+    // Jumping into a fold opens every fold over the line
+    try std.testing.expect(folds.reveal(3));
+    try std.testing.expect(!folds.isHidden(3));
+    try std.testing.expectEqual(@as(usize, 0), folds.closed.items.len);
+
+    // Closed folds move with lines added above them and stay closed
+    try folds.closeAll(allocator);
+    const added = TextChange{
+        .start_byte = 0,
+        .old_end_byte = 0,
+        .new_end_byte = 2,
+        .start = .{ .line = 0, .byte_col = 0, .utf16_col = 0 },
+        .old_end = .{ .line = 0, .byte_col = 0, .utf16_col = 0 },
+        .new_end = .{ .line = 2, .byte_col = 0, .utf16_col = 0 },
+        .text = "\n\n",
+    };
+    folds.update(allocator, try allocator.dupe(Range, &.{ .{ .start = 3, .end = 8 }, .{ .start = 4, .end = 6 } }), 1, &.{added});
+    try std.testing.expectEqualSlices(Range, &.{ .{ .start = 3, .end = 8 }, .{ .start = 4, .end = 6 } }, folds.closed.items);
+
+    // Lines removed across the inner fold's last line open it; the outer one shrinks
+    const removed = TextChange{
+        .start_byte = 10,
+        .old_end_byte = 14,
+        .new_end_byte = 10,
+        .start = .{ .line = 5, .byte_col = 0, .utf16_col = 0 },
+        .old_end = .{ .line = 7, .byte_col = 0, .utf16_col = 0 },
+        .new_end = .{ .line = 5, .byte_col = 0, .utf16_col = 0 },
+        .text = "",
+    };
+    folds.update(allocator, try allocator.dupe(Range, &.{.{ .start = 3, .end = 6 }}), 2, &.{removed});
+    try std.testing.expectEqualSlices(Range, &.{.{ .start = 3, .end = 6 }}, folds.closed.items);
+
+    // Without the edits there's no telling where they went
+    folds.update(allocator, try allocator.dupe(Range, &.{.{ .start = 3, .end = 5 }}), 5, null);
+    try std.testing.expectEqual(@as(usize, 0), folds.closed.items.len);
+}
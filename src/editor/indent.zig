@@ -0,0 +1,160 @@
+//! Smart indentation for new lines, closing brackets and `=`
+//! Levels come from the language's `indents.scm` query on the syntax tree:
+//! an `@indent` node indents the lines after the one it starts on, an
+//! `@outdent` node (a closing bracket) takes its line back out a level, and an
+//! `@extend` node keeps indenting a blank line typed right after it (the body
+//! of a Python function). Without a query the line above is followed instead.
+
+const std = @import("std");
+
+/// What an indents.scm capture does
+pub const CaptureKind = enum {
+    indent,
+    outdent,
+    extend,
+
+    /// Kind for a capture name (`@indent`), or null for names we don't use
+    pub fn fromName(name: []const u8) ?CaptureKind {
+        return std.meta.stringToEnum(CaptureKind, name);
+    }
+};
+
+/// Node captured by indents.scm
+pub const Capture = struct {
+    kind: CaptureKind,
+    start: usize, // Byte range of the node
+    end: usize,
+    start_line: usize, // Lines it starts and ends on
+    end_line: usize,
+};
+
+/// Line whose indentation is wanted
+pub const Line = struct {
+    start: usize, // Byte it begins at; for a line Enter is about to open, the cursor
+    first: ?usize, // Byte of its first non-blank character; null if blank
+    above: usize, // Number of the line before it
+};
+
+/// Indent level of `line`, from captures sorted by start byte
+/// Nodes starting on the same line indent once between them, so `foo({` is one level.
+pub fn indentLevel(captures: []const Capture, line: Line) usize {
+    const at = line.first orelse line.start;
+    var level: usize = 0;
+    var counted_line: ?usize = null;
+
+    for (captures) |capture| {
+        if (capture.start >= line.start) continue; // Opens on this line or later
+        const counts = switch (capture.kind) {
+            .indent => capture.end > at,
+            .extend => line.first == null and capture.end <= at and capture.end_line >= line.above,
+            .outdent => false,
+        };
+        if (!counts) continue;
+        if (counted_line) |counted| if (counted == capture.start_line) continue;
+        counted_line = capture.start_line;
+        level += 1;
+    }
+
+    // A closing bracket at the start of the line goes back to where its block opened
+    if (line.first) |first| {
+        for (captures) |capture| {
+            if (capture.kind == .outdent and capture.start == first) {
+                level -|= 1;
+                break;
+            }
+        }
+    }
+    return level;
+}
+
+/// `level` repetitions of `unit`; caller frees
+pub fn whitespace(allocator: std.mem.Allocator, unit: []const u8, level: usize) ![]u8 {
+    const out = try allocator.alloc(u8, unit.len * level);
+    for (0..level) |i| @memcpy(out[i * unit.len ..][0..unit.len], unit);
+    return out;
+}
+
+/// Indentation without a syntax tree; caller frees
+/// The line above's, a level deeper when it ends in an opening bracket or a colon,
+/// and a level shallower when the line (`content`) starts with a closing bracket.
+pub fn fallback(allocator: std.mem.Allocator, above: []const u8, content: []const u8, unit: []const u8) ![]u8 {
+    const base = leading(above);
+    const above_content = std.mem.trimRight(u8, above, " \t\r");
+    const opens = above_content.len > 0 and (isOpener(above_content[above_content.len - 1]) or above_content[above_content.len - 1] == ':');
+    const closes = content.len > 0 and isCloser(content[0]);
+
+    if (opens and !closes) return std.mem.concat(allocator, u8, &.{ base, unit });
+    if (closes and !opens) return allocator.dupe(u8, dedented(base, unit.len));
+    return allocator.dupe(u8, base);
+}
+
+/// Leading spaces and tabs of a line
+pub fn leading(line: []const u8) []const u8 {
+    return line[0 .. line.len - std.mem.trimLeft(u8, line, " \t").len];
+}
+
+/// Line without one level of indentation: up to `width` spaces, or a tab
+pub fn dedented(line: []const u8, width: usize) []const u8 {
+    if (line.len > 0 and line[0] == '\t') return line[1..];
+    var i: usize = 0;
+    while (i < line.len and i < width and line[i] == ' ') i += 1;
+    return line[i..];
+}
+
+pub fn isOpener(c: u8) bool {
+    return c == '{' or c == '(' or c == '[';
+}
+
+pub fn isCloser(c: u8) bool {
+    return c == '}' or c == ')' or c == ']';
+}
+
+// === Tests ===
+
+test "indent: levels from captures" {
+    // fn f() {
+    //     g(a,
+    //       b);
+    // }
+    const captures = [_]Capture{
+        .{ .kind = .indent, .start = 7, .end = 29, .start_line = 0, .end_line = 3 }, // Block
+        .{ .kind = .indent, .start = 14, .end = 26, .start_line = 1, .end_line = 2 }, // Arguments
+        .{ .kind = .outdent, .start = 28, .end = 29, .start_line = 3, .end_line = 3 }, // }
+    };
+
+    try std.testing.expectEqual(@as(usize, 1), indentLevel(&captures, .{ .start = 9, .first = 13, .above = 0 }));
+    try std.testing.expectEqual(@as(usize, 2), indentLevel(&captures, .{ .start = 18, .first = 24, .above = 1 }));
+    try std.testing.expectEqual(@as(usize, 0), indentLevel(&captures, .{ .start = 28, .first = 28, .above = 2 }));
+    // A blank line opened before the closing bracket stays inside the block
+    try std.testing.expectEqual(@as(usize, 1), indentLevel(&captures, .{ .start = 28, .first = null, .above = 2 }));
+
+    // def f():
+    //     x = 1
+    // An extend node keeps indenting the blank line Enter opens after it
+    const python = [_]Capture{
+        .{ .kind = .indent, .start = 0, .end = 18, .start_line = 0, .end_line = 1 },
+        .{ .kind = .extend, .start = 0, .end = 18, .start_line = 0, .end_line = 1 },
+    };
+    try std.testing.expectEqual(@as(usize, 1), indentLevel(&python, .{ .start = 18, .first = null, .above = 1 }));
+    try std.testing.expectEqual(@as(usize, 0), indentLevel(&python, .{ .start = 19, .first = 19, .above = 1 }));
+}
+
+test "indent: fallback follows the line above" {
+    const allocator = std.testing.allocator;
+
+    const deeper = try fallback(allocator, "  if (x) {", "", "    ");
+    defer allocator.free(deeper);
+    try std.testing.expectEqualStrings("      ", deeper);
+
+    const colon = try fallback(allocator, "def f():", "", "    ");
+    defer allocator.free(colon);
+    try std.testing.expectEqualStrings("    ", colon);
+
+    const closing = try fallback(allocator, "        x();", "}", "    ");
+    defer allocator.free(closing);
+    try std.testing.expectEqualStrings("    ", closing);
+
+    const same = try fallback(allocator, "\tx();", "y();", "\t");
+    defer allocator.free(same);
+    try std.testing.expectEqualStrings("\t", same);
+}
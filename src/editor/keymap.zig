@@ -17,6 +17,40 @@ pub const Key = union(enum) {
         };
     }
 
+    /// Write the key in config notation (`g`, `<space>`, `<C-s>`, `<esc>`)
+    pub fn writeNotation(self: Key, writer: anytype) !void {
+        switch (self) {
+            .char => |c| switch (c) {
+                ' ' => try writer.writeAll("<space>"),
+                '<' => try writer.writeAll("<lt>"),
+                '#' => try writer.writeAll("<hash>"),
+                0x01...0x1a => try writer.print("<C-{c}>", .{@as(u8, @intCast(c)) + 0x60}),
+                else => {
+                    if (c < 0x20 or c == 0x7f) {
+                        try writer.print("U+{X}", .{c});
+                    } else {
+                        var buf: [4]u8 = undefined;
+                        const len = std.unicode.utf8Encode(c, &buf) catch return writer.print("U+{X}", .{c});
+                        try writer.writeAll(buf[0..len]);
+                    }
+                },
+            },
+            .special => |s| {
+                const name = switch (s) {
+                    .escape => "esc",
+                    .enter => "cr",
+                    .backspace => "bs",
+                    .delete => "del",
+                    .insert => "ins",
+                    .page_up => "pageup",
+                    .page_down => "pagedown",
+                    else => @tagName(s),
+                };
+                try writer.print("<{s}>", .{name});
+            },
+        }
+    }
+
     pub fn format(
         self: Key,
         comptime fmt: []const u8,
@@ -39,9 +73,15 @@ pub const Key = union(enum) {
     }
 };
 
-/// Key sequence (for chords like "g g")
+/// Longest key sequence a binding can use
+pub const max_sequence = 16;
+
+/// Largest count prefix; more digits are ignored
+pub const max_count = 99_999;
+
+/// Key sequence (for chords like "g g" and leader menus like "<space> l r")
 pub const KeySequence = struct {
-    keys: [4]Key = undefined,
+    keys: [max_sequence]Key = undefined,
     len: usize = 0,
 
     pub fn append(self: *KeySequence, key: Key) !void {
@@ -71,6 +111,16 @@ pub const Binding = struct {
         };
     }
 
+    pub fn fromKeys(keys: []const Key, command: []const u8) Binding {
+        var sequence: KeySequence = .{};
+        for (keys) |key| sequence.append(key) catch unreachable;
+
+        return .{
+            .keys = sequence,
+            .command = command,
+        };
+    }
+
     pub fn fromChord(key1: Key, key2: Key, command: []const u8) Binding {
         var keys: KeySequence = .{};
         keys.append(key1) catch unreachable;
@@ -82,6 +132,18 @@ pub const Binding = struct {
         };
     }
 
+    /// Whether the binding's keys begin with `prefix` and continue past it
+    pub fn extendsSequence(self: *const Binding, prefix: []const Key) bool {
+        const binding_keys = self.keys.constSlice();
+        if (binding_keys.len <= prefix.len) return false;
+
+        for (prefix, binding_keys[0..prefix.len]) |pk, bk| {
+            if (!pk.eql(bk)) return false;
+        }
+
+        return true;
+    }
+
     pub fn matchesSequence(self: *const Binding, seq: []const Key) bool {
         const binding_keys = self.keys.constSlice();
         if (binding_keys.len != seq.len) return false;
@@ -119,6 +181,21 @@ pub const Keymap = struct {
         try self.bindings.append(self.allocator, binding);
     }
 
+    /// Remove every binding for a key sequence, returns whether there were any
+    pub fn unbind(self: *Keymap, keys: []const Key) bool {
+        var removed = false;
+        var i: usize = 0;
+        while (i < self.bindings.items.len) {
+            if (self.bindings.items[i].matchesSequence(keys)) {
+                _ = self.bindings.orderedRemove(i);
+                removed = true;
+            } else {
+                i += 1;
+            }
+        }
+        return removed;
+    }
+
     /// Find command for key sequence
     pub fn lookup(self: *const Keymap, keys: []const Key) ?[]const u8 {
         for (self.bindings.items) |*binding| {
@@ -135,10 +212,32 @@ pub const Keymap = struct {
     }
 };
 
+/// A key that can follow the pending keys
+pub const Continuation = struct {
+    key: Key,
+    command: ?[]const u8, // Command bound to pending keys + key, if any
+    longer: usize, // Number of longer bindings through this key (a submenu)
+
+    fn lessThan(_: void, a: Continuation, b: Continuation) bool {
+        // Characters first, letters case-insensitively with lowercase ahead
+        if (a.key == .char and b.key == .char) {
+            const la = if (a.key.char < 128) std.ascii.toLower(@intCast(a.key.char)) else a.key.char;
+            const lb = if (b.key.char < 128) std.ascii.toLower(@intCast(b.key.char)) else b.key.char;
+            if (la != lb) return la < lb;
+            return a.key.char > b.key.char;
+        }
+        if (a.key == .char or b.key == .char) return a.key == .char;
+        return @intFromEnum(a.key.special) < @intFromEnum(b.key.special);
+    }
+};
+
 /// Keymap manager - manages keymaps for all modes
 pub const KeymapManager = struct {
     keymaps: std.EnumArray(Mode, Keymap),
     pending_keys: KeySequence,
+    pending_at: i64, // When the last pending key arrived (ms), for the which-key delay
+    count: ?usize, // Count typed before the pending keys (`3` of `3dd`)
+    leader: Key, // Key that `<leader>` stands for; defaults are bound with it
     allocator: std.mem.Allocator,
 
     /// Initialize keymap manager
@@ -146,6 +245,9 @@ pub const KeymapManager = struct {
         var manager = KeymapManager{
             .keymaps = undefined,
             .pending_keys = .{},
+            .pending_at = 0,
+            .count = null,
+            .leader = .{ .char = ' ' },
             .allocator = allocator,
         };
 
@@ -166,6 +268,15 @@ pub const KeymapManager = struct {
         }
     }
 
+    /// Remove every binding in every mode (before binding the defaults again)
+    pub fn reset(self: *KeymapManager) void {
+        var iter = self.keymaps.iterator();
+        while (iter.next()) |entry| {
+            entry.value.bindings.clearRetainingCapacity();
+        }
+        self.clearPending();
+    }
+
     /// Get keymap for mode
     pub fn getKeymap(self: *KeymapManager, mode: Mode) *Keymap {
         return self.keymaps.getPtr(mode);
@@ -176,10 +287,28 @@ pub const KeymapManager = struct {
         try self.getKeymap(mode).bind(binding);
     }
 
+    /// Remove bindings for a key sequence from mode
+    pub fn unbind(self: *KeymapManager, mode: Mode, keys: []const Key) bool {
+        return self.getKeymap(mode).unbind(keys);
+    }
+
     /// Process key input and return command if sequence matched
+    /// Digits before a sequence in normal, select and operator-pending mode
+    /// build a count instead (`0` only continues one); collect it with `takeCount`.
     pub fn processKey(self: *KeymapManager, mode: Mode, key: Key) !?[]const u8 {
+        const counts = mode == .normal or mode == .select or mode == .operator_pending;
+        if (self.pending_keys.len == 0 and counts and key == .char) {
+            const c = key.char;
+            if ((c >= '1' and c <= '9') or (c == '0' and self.count != null)) {
+                self.count = @min((self.count orelse 0) * 10 + (c - '0'), max_count);
+                self.pending_at = std.time.milliTimestamp();
+                return null;
+            }
+        }
+
         // Add key to pending sequence
         try self.pending_keys.append(key);
+        self.pending_at = std.time.milliTimestamp();
 
         // Try to match against bindings
         const keymap = self.getKeymap(mode);
@@ -193,27 +322,16 @@ pub const KeymapManager = struct {
 
         // Check if any binding starts with this sequence (potential chord)
         var has_potential = false;
-        for (keymap.getBindings()) |binding| {
-            const binding_keys = binding.keys.constSlice();
-            if (binding_keys.len > pending.len) {
-                // Check if binding starts with pending sequence
-                var matches = true;
-                for (pending, 0..) |pk, i| {
-                    if (!pk.eql(binding_keys[i])) {
-                        matches = false;
-                        break;
-                    }
-                }
-                if (matches) {
-                    has_potential = true;
-                    break;
-                }
+        for (keymap.getBindings()) |*binding| {
+            if (binding.extendsSequence(pending)) {
+                has_potential = true;
+                break;
             }
         }
 
         if (!has_potential) {
-            // No potential matches - clear pending
-            self.pending_keys.len = 0;
+            // No potential matches - clear pending (and the count that went with it)
+            self.clearPending();
             return null;
         }
 
@@ -221,19 +339,170 @@ pub const KeymapManager = struct {
         return null;
     }
 
-    /// Clear pending key sequence
+    /// Clear pending key sequence and count
     pub fn clearPending(self: *KeymapManager) void {
         self.pending_keys.len = 0;
+        self.count = null;
     }
 
-    /// Check if there are pending keys
+    /// Check if there are pending keys or a count
     pub fn hasPending(self: *const KeymapManager) bool {
-        return self.pending_keys.len > 0;
+        return self.pending_keys.len > 0 or self.count != null;
+    }
+
+    /// Count for the command just returned by processKey (null if none was typed)
+    pub fn takeCount(self: *KeymapManager) ?usize {
+        const count = self.count;
+        self.count = null;
+        return count;
+    }
+
+    /// Keys that can follow the pending sequence in `mode`, sorted for display (caller frees)
+    pub fn continuations(self: *const KeymapManager, mode: Mode, allocator: std.mem.Allocator) ![]Continuation {
+        const keymap = self.keymaps.getPtrConst(mode);
+        const pending = self.pending_keys.constSlice();
+
+        var result = std.ArrayList(Continuation).empty;
+        errdefer result.deinit(allocator);
+
+        for (keymap.getBindings()) |*binding| {
+            if (!binding.extendsSequence(pending)) continue;
+            const keys = binding.keys.constSlice();
+            const next = keys[pending.len];
+
+            const entry = for (result.items) |*item| {
+                if (item.key.eql(next)) break item;
+            } else blk: {
+                try result.append(allocator, .{ .key = next, .command = null, .longer = 0 });
+                break :blk &result.items[result.items.len - 1];
+            };
+
+            if (keys.len == pending.len + 1) {
+                // The first binding wins, as in lookup
+                if (entry.command == null) entry.command = binding.command;
+            } else {
+                entry.longer += 1;
+            }
+        }
+
+        std.mem.sort(Continuation, result.items, {}, Continuation.lessThan);
+        return result.toOwnedSlice(allocator);
+    }
+};
+
+/// Errors in key notation
+pub const NotationError = error{
+    EmptyKeys,
+    UnclosedKeyName,
+    UnknownKeyName,
+    UnsupportedModifier,
+    InvalidUtf8,
+    SequenceFull,
+};
+
+/// Names accepted inside `<...>`, besides the special key names themselves (`<f5>`, `<page_up>`)
+const key_names = [_]struct { []const u8, Key }{
+    .{ "space", .{ .char = ' ' } },
+    .{ "lt", .{ .char = '<' } },
+    .{ "hash", .{ .char = '#' } },
+    .{ "esc", .{ .special = .escape } },
+    .{ "cr", .{ .special = .enter } },
+    .{ "ret", .{ .special = .enter } },
+    .{ "bs", .{ .special = .backspace } },
+    .{ "del", .{ .special = .delete } },
+    .{ "ins", .{ .special = .insert } },
+    .{ "pageup", .{ .special = .page_up } },
+    .{ "pagedown", .{ .special = .page_down } },
+};
+
+/// Parse key notation such as `g d`, `<C-s>` or `<leader>f f`
+/// Spaces only separate keys (write `<space>` for the key itself) and every other
+/// character is a key of its own, so `gd` and `g d` are the same sequence.
+pub fn parseKeys(notation: []const u8, leader: Key) NotationError!KeySequence {
+    var keys: KeySequence = .{};
+    var i: usize = 0;
+    while (i < notation.len) {
+        const c = notation[i];
+        if (c == ' ' or c == '\t') {
+            i += 1;
+        } else if (c == '<') {
+            const close = std.mem.indexOfScalarPos(u8, notation, i + 1, '>') orelse return error.UnclosedKeyName;
+            const name = notation[i + 1 .. close];
+            try keys.append(if (std.ascii.eqlIgnoreCase(name, "leader")) leader else try parseKeyName(name));
+            i = close + 1;
+        } else {
+            const len = std.unicode.utf8ByteSequenceLength(c) catch return error.InvalidUtf8;
+            if (i + len > notation.len) return error.InvalidUtf8;
+            const codepoint = std.unicode.utf8Decode(notation[i .. i + len]) catch return error.InvalidUtf8;
+            try keys.append(.{ .char = codepoint });
+            i += len;
+        }
+    }
+    if (keys.len == 0) return error.EmptyKeys;
+    return keys;
+}
+
+/// Key for the text between `<` and `>`
+/// Ctrl combines with letters only: the terminal reports it as a control
+/// character, and other modifiers don't reach the keymap.
+fn parseKeyName(name: []const u8) NotationError!Key {
+    if (name.len > 2 and name[1] == '-') {
+        const rest = name[2..];
+        return switch (std.ascii.toLower(name[0])) {
+            'c' => if (rest.len == 1 and std.ascii.isAlphabetic(rest[0]))
+                .{ .char = std.ascii.toLower(rest[0]) & 0x1f }
+            else
+                error.UnsupportedModifier,
+            'a', 'm', 's' => error.UnsupportedModifier,
+            else => error.UnknownKeyName,
+        };
+    }
+
+    var buf: [16]u8 = undefined;
+    if (name.len > buf.len) return error.UnknownKeyName;
+    const lower = std.ascii.lowerString(&buf, name);
+    for (key_names) |entry| {
+        if (std.mem.eql(u8, entry[0], lower)) return entry[1];
     }
+    if (std.meta.stringToEnum(input.Key, lower)) |special| return .{ .special = special };
+    return error.UnknownKeyName;
+}
+
+/// Message for a key notation error
+pub fn notationErrorMessage(err: NotationError) []const u8 {
+    return switch (err) {
+        error.EmptyKeys => "No keys given",
+        error.UnclosedKeyName => "Missing '>' after key name",
+        error.UnknownKeyName => "Unknown key name",
+        error.UnsupportedModifier => "Only Ctrl with a letter is supported (<C-x>)",
+        error.InvalidUtf8 => "Invalid UTF-8 in keys",
+        error.SequenceFull => "Too many keys",
+    };
+}
+
+/// Mode named by a `[keys.<mode>]` config section (command mode has no keymap)
+pub fn modeFromName(name: []const u8) ?Mode {
+    const mode = std.meta.stringToEnum(Mode, name) orelse return null;
+    return if (mode == .command) null else mode;
+}
+
+/// Keys of the operators and the commands that start them
+const operator_keys = [_]struct { []const Key, []const u8 }{
+    .{ &.{.{ .char = 'd' }}, "operator_delete" },
+    .{ &.{.{ .char = 'c' }}, "operator_change" },
+    .{ &.{.{ .char = 'y' }}, "operator_yank" },
+    .{ &.{.{ .char = '>' }}, "operator_indent" },
+    .{ &.{.{ .char = '<' }}, "operator_dedent" },
+    .{ &.{.{ .char = '=' }}, "operator_reindent" },
+    .{ &.{ .{ .char = 'g' }, .{ .char = 'u' } }, "operator_lowercase" },
+    .{ &.{ .{ .char = 'g' }, .{ .char = 'U' } }, "operator_uppercase" },
 };
 
 /// Setup default keymaps
 pub fn setupDefaults(manager: *KeymapManager) !void {
+    // Space unless the config picks another leader
+    const leader = manager.leader;
+
     // Normal mode bindings
     const normal_map = manager.getKeymap(.normal);
 
@@ -262,6 +531,18 @@ pub fn setupDefaults(manager: *KeymapManager) !void {
     try normal_map.bind(Binding.fromChord(.{ .char = 'g' }, .{ .char = 'g' }, "move_file_start"));
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'G' }, "move_file_end"));
 
+    // Motion: find/till character on the line
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = 'f' }, "find_char_forward"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = 'F' }, "find_char_backward"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = 't' }, "till_char_forward"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = 'T' }, "till_char_backward"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = ';' }, "repeat_find_till"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = ',' }, "reverse_find_till"));
+
+    // Repeat last change and play macros
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = '.' }, "repeat_last_action"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = '@' }, "play_macro"));
+
     // Insert mode
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'i' }, "insert_mode"));
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'a' }, "insert_after"));
@@ -276,44 +557,47 @@ pub fn setupDefaults(manager: *KeymapManager) !void {
     // Deletion
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'x' }, "delete_char"));
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'X' }, "delete_char_before"));
-    try normal_map.bind(Binding.fromChord(.{ .char = 'd' }, .{ .char = 'd' }, "delete_line"));
-    try normal_map.bind(Binding.fromChord(.{ .char = 'd' }, .{ .char = 'w' }, "delete_word"));
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'D' }, "delete_to_end"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = 'r' }, "replace_char"));
 
     // Line manipulation
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'J' }, "join_lines"));
 
-    // Indentation
-    try normal_map.bind(Binding.fromSingleKey(.{ .char = '>' }, "indent_line"));
-    try normal_map.bind(Binding.fromSingleKey(.{ .char = '<' }, "dedent_line"));
+    // Operators, which wait for a motion or text object (`dw`, `ci(`, `>ip`)
+    for (operator_keys) |entry| {
+        try normal_map.bind(Binding.fromKeys(entry[0], entry[1]));
+    }
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = '"' }, "select_register"));
 
     // Undo/redo
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'u' }, "undo"));
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'U' }, "redo"));
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 0x12 }, "redo")); // Ctrl+R
+    try normal_map.bind(Binding.fromChord(.{ .char = 'g' }, .{ .char = '-' }, "undo_earlier"));
+    try normal_map.bind(Binding.fromChord(.{ .char = 'g' }, .{ .char = '+' }, "undo_later"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'u' }, "toggle_undo_tree"));
 
-    // Clipboard (yank/paste)
-    try normal_map.bind(Binding.fromChord(.{ .char = 'y' }, .{ .char = 'y' }, "yank_line"));
+    // Clipboard (paste; yank is the `y` operator)
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'p' }, "paste_after"));
     try normal_map.bind(Binding.fromSingleKey(.{ .char = 'P' }, "paste_before"));
 
     // Command palette
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'p' }, "toggle_palette"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'p' }, "toggle_palette"));
 
     // File finder
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'f' }, "toggle_file_finder"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'f' }, "toggle_file_finder"));
 
     // File tree
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'e' }, "toggle_file_tree"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'e' }, "toggle_file_tree"));
 
     // Buffer switcher
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'b' }, "toggle_buffer_switcher"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'b' }, "toggle_buffer_switcher"));
 
     // File operations
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'w' }, "save"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'w' }, "save"));
 
     // Visual/display operations
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 's' }, "toggle_syntax"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 's' }, "toggle_syntax"));
 
     // Search operations
     try normal_map.bind(Binding.fromSingleKey(.{ .char = '*' }, "start_search"));
@@ -324,19 +608,40 @@ pub fn setupDefaults(manager: *KeymapManager) !void {
     // Buffer management
     try normal_map.bind(Binding.fromChord(.{ .char = ']' }, .{ .char = 'b' }, "next_buffer"));
     try normal_map.bind(Binding.fromChord(.{ .char = '[' }, .{ .char = 'b' }, "previous_buffer"));
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'c' }, "close_buffer"));
+
+    // Syntax navigation
+    try normal_map.bind(Binding.fromChord(.{ .char = ']' }, .{ .char = 'f' }, "move_next_function"));
+    try normal_map.bind(Binding.fromChord(.{ .char = '[' }, .{ .char = 'f' }, "move_prev_function"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'c' }, "close_buffer"));
+
+    // Structural selection (these switch to select mode)
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = '+' }, "expand_selection"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = '-' }, "shrink_selection"));
+    try normal_map.bind(Binding.fromChord(.{ .char = ']' }, .{ .char = 'n' }, "select_next_sibling"));
+    try normal_map.bind(Binding.fromChord(.{ .char = '[' }, .{ .char = 'n' }, "select_prev_sibling"));
 
     // Navigation and viewport control
     try normal_map.bind(Binding.fromChord(.{ .char = 'z' }, .{ .char = 'z' }, "center_cursor"));
 
+    // Folding
+    try normal_map.bind(Binding.fromChord(.{ .char = 'z' }, .{ .char = 'a' }, "toggle_fold"));
+    try normal_map.bind(Binding.fromChord(.{ .char = 'z' }, .{ .char = 'c' }, "close_fold"));
+    try normal_map.bind(Binding.fromChord(.{ .char = 'z' }, .{ .char = 'o' }, "open_fold"));
+    try normal_map.bind(Binding.fromChord(.{ .char = 'z' }, .{ .char = 'R' }, "open_all_folds"));
+    try normal_map.bind(Binding.fromChord(.{ .char = 'z' }, .{ .char = 'M' }, "close_all_folds"));
+
     // Window splits
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'h' }, "split_horizontal"));
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'v' }, "split_vertical"));
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'q' }, "close_window"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'h' }, "split_horizontal"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'v' }, "split_vertical"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'q' }, "close_window"));
 
     // Window navigation
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'n' }, "next_window"));
-    try normal_map.bind(Binding.fromChord(.{ .char = ' ' }, .{ .char = 'N' }, "previous_window"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'n' }, "next_window"));
+    try normal_map.bind(Binding.fromChord(leader, .{ .char = 'N' }, "previous_window"));
+
+    // Shell filters (Alt-! is handled by the app, keys here carry no modifiers)
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = '|' }, "shell_pipe"));
+    try normal_map.bind(Binding.fromSingleKey(.{ .char = '!' }, "shell_insert"));
 
     // Insert mode bindings
     const insert_map = manager.getKeymap(.insert);
@@ -349,6 +654,16 @@ pub fn setupDefaults(manager: *KeymapManager) !void {
     // Visual mode operations
     try select_map.bind(Binding.fromSingleKey(.{ .char = 'd' }, "delete_selection"));
     try select_map.bind(Binding.fromSingleKey(.{ .char = 'y' }, "yank_selection"));
+    try select_map.bind(Binding.fromSingleKey(.{ .char = '|' }, "shell_pipe"));
+    try select_map.bind(Binding.fromSingleKey(.{ .char = '!' }, "shell_insert"));
+    try select_map.bind(Binding.fromSingleKey(.{ .char = '"' }, "select_register"));
+
+    // Other operators act on the selection at once
+    for (operator_keys) |entry| {
+        const first = entry[0][0].char;
+        if (first == 'd' or first == 'y') continue;
+        try select_map.bind(Binding.fromKeys(entry[0], entry[1]));
+    }
 
     // Motion commands in select mode (extend selection)
     try select_map.bind(Binding.fromSingleKey(.{ .char = 'h' }, "move_left"));
@@ -368,6 +683,12 @@ pub fn setupDefaults(manager: *KeymapManager) !void {
     try select_map.bind(Binding.fromSingleKey(.{ .char = '$' }, "move_line_end"));
     try select_map.bind(Binding.fromChord(.{ .char = 'g' }, .{ .char = 'g' }, "move_file_start"));
     try select_map.bind(Binding.fromSingleKey(.{ .char = 'G' }, "move_file_end"));
+    try select_map.bind(Binding.fromChord(.{ .char = ']' }, .{ .char = 'f' }, "move_next_function"));
+    try select_map.bind(Binding.fromChord(.{ .char = '[' }, .{ .char = 'f' }, "move_prev_function"));
+    try select_map.bind(Binding.fromSingleKey(.{ .char = '+' }, "expand_selection"));
+    try select_map.bind(Binding.fromSingleKey(.{ .char = '-' }, "shrink_selection"));
+    try select_map.bind(Binding.fromChord(.{ .char = ']' }, .{ .char = 'n' }, "select_next_sibling"));
+    try select_map.bind(Binding.fromChord(.{ .char = '[' }, .{ .char = 'n' }, "select_prev_sibling"));
 
     // LSP keybindings (normal mode)
     // Ctrl+Space for completion (note: ctrl modifier doesn't work yet, needs terminal input support)
@@ -379,6 +700,101 @@ pub fn setupDefaults(manager: *KeymapManager) !void {
     try normal_map.bind(Binding.fromChord(.{ .char = 'g' }, .{ .char = 'a' }, "lsp_code_actions"));
     try normal_map.bind(Binding.fromChord(.{ .char = 'g' }, .{ .char = 'o' }, "lsp_document_symbols"));
     try normal_map.bind(Binding.fromChord(.{ .char = 'g' }, .{ .char = 'S' }, "lsp_signature_help"));
+
+    // LSP menu under the leader (Space l ...)
+    const lsp_menu = [_]struct { u21, []const u8 }{
+        .{ 'd', "lsp_goto_definition" },
+        .{ 'r', "lsp_find_references" },
+        .{ 'R', "lsp_rename" },
+        .{ 'f', "lsp_format_document" },
+        .{ 'a', "lsp_code_actions" },
+        .{ 's', "lsp_document_symbols" },
+        .{ 'k', "lsp_show_hover" },
+    };
+    for (lsp_menu) |entry| {
+        try normal_map.bind(Binding.fromKeys(&.{ leader, .{ .char = 'l' }, .{ .char = entry[0] } }, entry[1]));
+    }
+
+    // Operator-pending mode: the motion or text object after an operator
+    const pending_map = manager.getKeymap(.operator_pending);
+    const motions = [_]struct { []const Key, []const u8 }{
+        .{ &.{.{ .char = 'h' }}, "move_left" },
+        .{ &.{.{ .char = 'j' }}, "move_down" },
+        .{ &.{.{ .char = 'k' }}, "move_up" },
+        .{ &.{.{ .char = 'l' }}, "move_right" },
+        .{ &.{.{ .special = .left }}, "move_left" },
+        .{ &.{.{ .special = .down }}, "move_down" },
+        .{ &.{.{ .special = .up }}, "move_up" },
+        .{ &.{.{ .special = .right }}, "move_right" },
+        .{ &.{.{ .char = 'w' }}, "move_word_forward" },
+        .{ &.{.{ .char = 'b' }}, "move_word_backward" },
+        .{ &.{.{ .char = 'e' }}, "move_word_end" },
+        .{ &.{.{ .char = '0' }}, "move_line_start" },
+        .{ &.{.{ .char = '$' }}, "move_line_end" },
+        .{ &.{ .{ .char = 'g' }, .{ .char = 'g' } }, "move_file_start" },
+        .{ &.{.{ .char = 'G' }}, "move_file_end" },
+        .{ &.{.{ .char = 'f' }}, "find_char_forward" },
+        .{ &.{.{ .char = 'F' }}, "find_char_backward" },
+        .{ &.{.{ .char = 't' }}, "till_char_forward" },
+        .{ &.{.{ .char = 'T' }}, "till_char_backward" },
+        .{ &.{.{ .char = ';' }}, "repeat_find_till" },
+        .{ &.{.{ .char = ',' }}, "reverse_find_till" },
+        .{ &.{.{ .char = '%' }}, "jump_to_matching_bracket" },
+        .{ &.{.{ .char = '}' }}, "move_next_paragraph" },
+        .{ &.{.{ .char = '{' }}, "move_prev_paragraph" },
+        .{ &.{.{ .char = 'n' }}, "find_next" },
+        .{ &.{.{ .char = 'N' }}, "find_previous" },
+        .{ &.{ .{ .char = ']' }, .{ .char = 'f' } }, "move_next_function" },
+        .{ &.{ .{ .char = '[' }, .{ .char = 'f' } }, "move_prev_function" },
+    };
+    for (motions) |entry| {
+        try pending_map.bind(Binding.fromKeys(entry[0], entry[1]));
+    }
+
+    // An operator typed twice takes whole lines (`dd`, `>>`, `gUU`)
+    for (operator_keys) |entry| {
+        try pending_map.bind(Binding.fromKeys(entry[0], "operator_lines"));
+    }
+    try pending_map.bind(Binding.fromSingleKey(.{ .char = 'u' }, "operator_lines"));
+    try pending_map.bind(Binding.fromSingleKey(.{ .char = 'U' }, "operator_lines"));
+
+    // Text objects: i for inside, a for around
+    const objects = [_]struct { u21, []const u8, []const u8 }{
+        .{ 'w', "select_word", "select_around_word" },
+        .{ 'p', "select_paragraph_inside", "select_paragraph_around" },
+        .{ 'i', "select_indent_inside", "select_indent_around" },
+        .{ 'l', "select_line_inside", "select_line_around" },
+        .{ 'e', "select_buffer_inside", "select_buffer_around" },
+        .{ '(', "select_inner_paren", "select_around_paren" },
+        .{ ')', "select_inner_paren", "select_around_paren" },
+        .{ 'b', "select_inner_paren", "select_around_paren" },
+        .{ '[', "select_inner_bracket", "select_around_bracket" },
+        .{ ']', "select_inner_bracket", "select_around_bracket" },
+        .{ '{', "select_inner_brace", "select_around_brace" },
+        .{ '}', "select_inner_brace", "select_around_brace" },
+        .{ 'B', "select_inner_brace", "select_around_brace" },
+        .{ '<', "select_inner_angle", "select_around_angle" },
+        .{ '>', "select_inner_angle", "select_around_angle" },
+        .{ '"', "select_inner_quote", "select_around_quote" },
+        .{ '\'', "select_inner_single_quote", "select_around_single_quote" },
+        .{ '`', "select_inner_backtick", "select_around_backtick" },
+        // From the language's textobjects.scm
+        .{ 'f', "select_inner_function", "select_around_function" },
+        .{ 'c', "select_inner_class", "select_around_class" },
+        .{ 'a', "select_inner_argument", "select_around_argument" },
+        .{ '/', "select_inner_comment", "select_around_comment" },
+    };
+    for (objects) |entry| {
+        try pending_map.bind(Binding.fromChord(.{ .char = 'i' }, .{ .char = entry[0] }, entry[1]));
+        try pending_map.bind(Binding.fromChord(.{ .char = 'a' }, .{ .char = entry[0] }, entry[2]));
+
+        // Select mode takes them too, replacing the selection (`vaf`)
+        try select_map.bind(Binding.fromChord(.{ .char = 'i' }, .{ .char = entry[0] }, entry[1]));
+        try select_map.bind(Binding.fromChord(.{ .char = 'a' }, .{ .char = entry[0] }, entry[2]));
+    }
+
+    // A default bound to the leader key alone (`,` or `;` as leader) would shadow every leader chord
+    _ = normal_map.unbind(&.{leader});
 }
 
 test "keymap: bind and lookup" {
@@ -441,3 +857,129 @@ test "keymap manager: process chord" {
     try std.testing.expectEqualStrings("move_file_start", second.?);
     try std.testing.expect(!manager.hasPending());
 }
+
+test "keymap: parse key notation" {
+    const space: Key = .{ .char = ' ' };
+    const gd = try parseKeys("g d", space);
+    try std.testing.expectEqual(@as(usize, 2), gd.len);
+    try std.testing.expect(gd.keys[0].eql(.{ .char = 'g' }));
+    try std.testing.expect(gd.keys[1].eql(.{ .char = 'd' }));
+
+    // Spaces only separate keys
+    const leader = try parseKeys("<space>f f", space);
+    try std.testing.expectEqual(@as(usize, 3), leader.len);
+    try std.testing.expect(leader.keys[0].eql(.{ .char = ' ' }));
+    try std.testing.expect(leader.keys[2].eql(.{ .char = 'f' }));
+
+    const save = try parseKeys("<C-s>", space);
+    try std.testing.expect(save.keys[0].eql(.{ .char = 0x13 }));
+    const escape = try parseKeys("<Esc>", space);
+    try std.testing.expect(escape.keys[0].eql(.{ .special = .escape }));
+    const f5 = try parseKeys("<f5>", space);
+    try std.testing.expect(f5.keys[0].eql(.{ .special = .f5 }));
+
+    // <leader> is whatever key the leader is set to
+    const comma = try parseKeys("<leader>w", .{ .char = ',' });
+    try std.testing.expect(comma.keys[0].eql(.{ .char = ',' }));
+
+    try std.testing.expectError(error.EmptyKeys, parseKeys("  ", space));
+    try std.testing.expectError(error.UnclosedKeyName, parseKeys("<space", space));
+    try std.testing.expectError(error.UnknownKeyName, parseKeys("<nope>", space));
+    try std.testing.expectError(error.UnsupportedModifier, parseKeys("<A-x>", space));
+    try std.testing.expectError(error.SequenceFull, parseKeys("abcdefghijklmnopq", space));
+
+    // Notation written back parses to the same keys
+    var buf: [64]u8 = undefined;
+    var stream = std.io.fixedBufferStream(&buf);
+    for ([_]Key{ space, .{ .char = 0x13 }, .{ .char = '<' }, .{ .char = 'x' }, .{ .special = .escape }, .{ .special = .page_down } }) |key| {
+        stream.reset();
+        try key.writeNotation(stream.writer());
+        const parsed = try parseKeys(stream.getWritten(), space);
+        try std.testing.expect(parsed.keys[0].eql(key));
+    }
+}
+
+test "keymap manager: unbind and rebind" {
+    const allocator = std.testing.allocator;
+    var manager = KeymapManager.init(allocator);
+    defer manager.deinit();
+
+    try setupDefaults(&manager);
+
+    const gd = try parseKeys("gd", manager.leader);
+    try std.testing.expect(manager.unbind(.normal, gd.constSlice()));
+    try std.testing.expect(!manager.unbind(.normal, gd.constSlice()));
+    try std.testing.expect(manager.getKeymap(.normal).lookup(gd.constSlice()) == null);
+
+    // Other chords on the same prefix still work
+    _ = try manager.processKey(.normal, .{ .char = 'g' });
+    const command = try manager.processKey(.normal, .{ .char = 'g' });
+    try std.testing.expectEqualStrings("move_file_start", command.?);
+}
+
+test "keymap manager: continuations of a leader menu" {
+    const allocator = std.testing.allocator;
+    var manager = KeymapManager.init(allocator);
+    defer manager.deinit();
+
+    try setupDefaults(&manager);
+    try manager.bind(.normal, Binding.fromKeys(&.{ manager.leader, .{ .char = 'l' }, .{ .char = 'x' }, .{ .char = 'y' }, .{ .char = 'z' } }, "lsp_rename"));
+
+    _ = try manager.processKey(.normal, manager.leader);
+    const top = try manager.continuations(.normal, allocator);
+    defer allocator.free(top);
+
+    // Space l is a submenu, Space f a command
+    const menu = for (top) |item| {
+        if (item.key.eql(.{ .char = 'l' })) break item;
+    } else return error.TestUnexpectedResult;
+    try std.testing.expect(menu.command == null);
+    try std.testing.expectEqual(@as(usize, 8), menu.longer);
+
+    const finder = for (top) |item| {
+        if (item.key.eql(.{ .char = 'f' })) break item;
+    } else return error.TestUnexpectedResult;
+    try std.testing.expectEqualStrings("toggle_file_finder", finder.command.?);
+
+    // Sorted case-insensitively, lowercase first
+    for (top[0 .. top.len - 1], top[1..]) |a, b| {
+        try std.testing.expect(!Continuation.lessThan({}, b, a));
+    }
+
+    // Longer than the old four-key limit
+    _ = try manager.processKey(.normal, .{ .char = 'l' });
+    _ = try manager.processKey(.normal, .{ .char = 'x' });
+    _ = try manager.processKey(.normal, .{ .char = 'y' });
+    const command = try manager.processKey(.normal, .{ .char = 'z' });
+    try std.testing.expectEqualStrings("lsp_rename", command.?);
+}
+
+test "keymap manager: count prefix" {
+    const allocator = std.testing.allocator;
+    var manager = KeymapManager.init(allocator);
+    defer manager.deinit();
+
+    try setupDefaults(&manager);
+
+    // 10d3j: digits build a count for the operator, and another for its motion
+    try std.testing.expect(try manager.processKey(.normal, .{ .char = '1' }) == null);
+    try std.testing.expect(try manager.processKey(.normal, .{ .char = '0' }) == null);
+    try std.testing.expect(manager.hasPending());
+    const command = try manager.processKey(.normal, .{ .char = 'd' });
+    try std.testing.expectEqualStrings("operator_delete", command.?);
+    try std.testing.expectEqual(@as(?usize, 10), manager.takeCount());
+    try std.testing.expectEqual(@as(?usize, null), manager.takeCount());
+    try std.testing.expect(try manager.processKey(.operator_pending, .{ .char = '3' }) == null);
+    try std.testing.expectEqualStrings("move_down", (try manager.processKey(.operator_pending, .{ .char = 'j' })).?);
+    try std.testing.expectEqual(@as(?usize, 3), manager.takeCount());
+
+    // A leading 0 is still a motion, and insert mode digits are text
+    try std.testing.expectEqualStrings("move_line_start", (try manager.processKey(.normal, .{ .char = '0' })).?);
+    try std.testing.expect(try manager.processKey(.insert, .{ .char = '5' }) == null);
+    try std.testing.expect(!manager.hasPending());
+
+    // An unmatched sequence drops its count
+    _ = try manager.processKey(.normal, .{ .char = '3' });
+    _ = try manager.processKey(.normal, .{ .char = 'Q' });
+    try std.testing.expect(!manager.hasPending());
+}
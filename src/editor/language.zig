@@ -0,0 +1,93 @@
+//! File languages
+//! One table of file extensions shared by syntax highlighting (with or without
+//! tree-sitter) and the language servers, so they agree on what a file is.
+
+const std = @import("std");
+
+/// Language configuration
+pub const Language = enum {
+    zig,
+    c,
+    cpp,
+    rust,
+    go,
+    python,
+    javascript,
+    jsx,
+    typescript,
+    tsx,
+    json,
+    markdown,
+    plain_text,
+
+    const Extension = struct { ext: []const u8, language: Language };
+    const extensions = [_]Extension{
+        .{ .ext = ".zig", .language = .zig },
+        .{ .ext = ".zon", .language = .zig },
+        .{ .ext = ".c", .language = .c },
+        .{ .ext = ".h", .language = .c },
+        .{ .ext = ".cc", .language = .cpp },
+        .{ .ext = ".cpp", .language = .cpp },
+        .{ .ext = ".cxx", .language = .cpp },
+        .{ .ext = ".hpp", .language = .cpp },
+        .{ .ext = ".rs", .language = .rust },
+        .{ .ext = ".go", .language = .go },
+        .{ .ext = ".py", .language = .python },
+        .{ .ext = ".js", .language = .javascript },
+        .{ .ext = ".mjs", .language = .javascript },
+        .{ .ext = ".cjs", .language = .javascript },
+        .{ .ext = ".jsx", .language = .jsx },
+        .{ .ext = ".ts", .language = .typescript },
+        .{ .ext = ".tsx", .language = .tsx },
+        .{ .ext = ".json", .language = .json },
+        .{ .ext = ".md", .language = .markdown },
+    };
+
+    pub fn fromFilename(filename: []const u8) Language {
+        const ext = std.fs.path.extension(filename);
+        for (extensions) |entry| {
+            if (std.mem.eql(u8, ext, entry.ext)) return entry.language;
+        }
+        return .plain_text;
+    }
+
+    /// Name of the language's query directory (`queries/<name>/`)
+    pub fn getName(self: Language) []const u8 {
+        return switch (self) {
+            .zig => "zig",
+            .c => "c",
+            .cpp => "cpp",
+            .rust => "rust",
+            .go => "go",
+            .python => "python",
+            .javascript => "javascript",
+            .jsx => "jsx",
+            .typescript => "typescript",
+            .tsx => "tsx",
+            .json => "json",
+            .markdown => "markdown",
+            .plain_text => "plain_text",
+        };
+    }
+
+    /// LSP languageId (null for plain text, which no server handles)
+    pub fn lspId(self: Language) ?[]const u8 {
+        return switch (self) {
+            .jsx => "javascriptreact",
+            .tsx => "typescriptreact",
+            .plain_text => null,
+            else => self.getName(),
+        };
+    }
+};
+
+test "language: lsp id from path" {
+    try std.testing.expectEqualStrings("zig", Language.fromFilename("src/main.zig").lspId().?);
+    try std.testing.expectEqualStrings("rust", Language.fromFilename("lib.rs").lspId().?);
+    try std.testing.expectEqualStrings("typescriptreact", Language.fromFilename("App.tsx").lspId().?);
+    try std.testing.expectEqualStrings("cpp", Language.fromFilename("/tmp/a.hpp").lspId().?);
+    try std.testing.expectEqualStrings("javascriptreact", Language.fromFilename("App.jsx").lspId().?);
+    try std.testing.expectEqualStrings("zig", Language.fromFilename("build.zig.zon").lspId().?);
+    try std.testing.expect(Language.fromFilename("README").lspId() == null);
+    try std.testing.expect(Language.fromFilename("notes.txt").lspId() == null);
+}
@@ -9,6 +9,7 @@ pub const Mode = enum {
     insert,
     select,
     command,
+    operator_pending, // After an operator key (`d`, `c`, `y`...), waiting for its motion
 
     /// Get human-readable name
     pub fn name(self: Mode) []const u8 {
@@ -17,6 +18,7 @@ pub const Mode = enum {
             .insert => "INSERT",
             .select => "SELECT",
             .command => "COMMAND",
+            .operator_pending => "O-PENDING",
         };
     }
 
@@ -27,6 +29,7 @@ pub const Mode = enum {
             .insert => "I",
             .select => "S",
             .command => "C",
+            .operator_pending => "O",
         };
     }
 
@@ -34,7 +37,7 @@ pub const Mode = enum {
     pub fn acceptsTextInput(self: Mode) bool {
         return switch (self) {
             .insert, .command => true,
-            .normal, .select => false,
+            .normal, .select, .operator_pending => false,
         };
     }
 
@@ -42,7 +45,7 @@ pub const Mode = enum {
     pub fn showsSelections(self: Mode) bool {
         return switch (self) {
             .select => true,
-            .normal, .insert, .command => false,
+            .normal, .insert, .command, .operator_pending => false,
         };
     }
 };
@@ -130,6 +133,11 @@ pub const ModeManager = struct {
         try self.transitionTo(.command);
     }
 
+    /// Enter operator-pending mode
+    pub fn enterOperatorPending(self: *ModeManager) !void {
+        try self.transitionTo(.operator_pending);
+    }
+
     /// Return to previous mode
     pub fn returnToPrevious(self: *ModeManager) !void {
         try self.transitionTo(self.previous);
@@ -226,4 +234,5 @@ test "mode: capabilities" {
     try std.testing.expect(!Mode.normal.acceptsTextInput());
     try std.testing.expect(Mode.select.showsSelections());
     try std.testing.expect(!Mode.normal.showsSelections());
+    try std.testing.expect(!Mode.operator_pending.acceptsTextInput());
 }
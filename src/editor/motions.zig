@@ -73,6 +73,13 @@ pub fn moveUp(selection: Cursor.Selection, buffer: *const Buffer.Buffer) Cursor.
     return selection.moveTo(.{ .line = new_line, .col = new_col });
 }
 
+/// Move cursor to `line`, keeping its column where the line is long enough
+pub fn moveToLine(selection: Cursor.Selection, buffer: *const Buffer.Buffer, line: usize) Cursor.Selection {
+    const new_line = @min(line, buffer.rope.lineCount() - 1);
+    const new_col = @min(selection.head.col, getLineLength(buffer, new_line));
+    return selection.moveTo(.{ .line = new_line, .col = new_col });
+}
+
 /// Move to start of line (column 0)
 pub fn moveLineStart(selection: Cursor.Selection, _: *const Buffer.Buffer) Cursor.Selection {
     return selection.moveTo(.{ .line = selection.head.line, .col = 0 });
@@ -220,60 +227,15 @@ pub fn moveWordEnd(selection: Cursor.Selection, buffer: *const Buffer.Buffer) Cu
 
 /// Get length of a line (in characters, not bytes)
 fn getLineLength(buffer: *const Buffer.Buffer, line: usize) usize {
-    const allocator = std.heap.page_allocator;
-    const line_text = getLineText(allocator, buffer, line) catch return 0;
-    defer allocator.free(line_text);
-
-    return std.unicode.utf8CountCodepoints(line_text) catch line_text.len;
+    if (line >= buffer.rope.lineCount()) return 0;
+    const line_start = buffer.rope.lineToByte(line);
+    return buffer.rope.byteToChar(buffer.rope.lineEnd(line)) - buffer.rope.byteToChar(line_start);
 }
 
 /// Get text of a specific line
 fn getLineText(allocator: std.mem.Allocator, buffer: *const Buffer.Buffer, line: usize) ![]u8 {
-    const line_count = buffer.rope.lineCount();
-    if (line >= line_count) return allocator.alloc(u8, 0);
-
-    // Find byte offset of line start
-    var current_line: usize = 0;
-    var byte_offset: usize = 0;
-    const total_bytes = buffer.rope.len();
-
-    while (current_line < line and byte_offset < total_bytes) {
-        const text = try buffer.rope.slice(allocator, byte_offset, total_bytes);
-        defer allocator.free(text);
-
-        // Find next newline
-        for (text, 0..) |ch, i| {
-            if (ch == '\n') {
-                byte_offset += i + 1;
-                current_line += 1;
-                break;
-            }
-        }
-
-        if (current_line < line and byte_offset >= total_bytes) break;
-    }
-
-    // Now get the line text
-    const line_start = byte_offset;
-    var line_end = byte_offset;
-
-    if (line_start < total_bytes) {
-        const remaining = try buffer.rope.slice(allocator, line_start, total_bytes);
-        defer allocator.free(remaining);
-
-        for (remaining, 0..) |ch, i| {
-            if (ch == '\n') {
-                line_end = line_start + i;
-                break;
-            }
-        }
-
-        if (line_end == line_start) {
-            line_end = total_bytes;
-        }
-    }
-
-    return buffer.rope.slice(allocator, line_start, line_end);
+    if (line >= buffer.rope.lineCount()) return allocator.alloc(u8, 0);
+    return buffer.rope.lineSlice(allocator, line);
 }
 
 /// Check if character is a word character (alphanumeric or underscore)
@@ -0,0 +1,253 @@
+//! Operators that act on a motion or text object (`dw`, `ci(`, `y3j`, `gUiw`)
+//! An operator key waits for a motion or text object, then acts on the text it covers
+
+const std = @import("std");
+const Cursor = @import("cursor.zig");
+const Buffer = @import("../buffer/manager.zig");
+const Registers = @import("registers.zig");
+const Actions = @import("actions.zig");
+const Indent = @import("indent.zig");
+
+/// Operator kinds
+pub const Operator = enum {
+    delete, // d
+    change, // c
+    yank, // y
+    indent, // >
+    dedent, // <
+    lowercase, // gu
+    uppercase, // gU
+    reindent, // =
+
+    /// Keys that type it, for the status line
+    pub fn keys(self: Operator) []const u8 {
+        return switch (self) {
+            .delete => "d",
+            .change => "c",
+            .yank => "y",
+            .indent => ">",
+            .dedent => "<",
+            .lowercase => "gu",
+            .uppercase => "gU",
+            .reindent => "=",
+        };
+    }
+
+    /// Whether it always acts on whole lines (`>w` indents the line)
+    pub fn isLinewise(self: Operator) bool {
+        return switch (self) {
+            .indent, .dedent, .reindent => true,
+            else => false,
+        };
+    }
+};
+
+/// How the command after an operator marks out the text it acts on
+pub const Target = enum {
+    auto, // Exclusive for motions; commands of other categories can't follow an operator
+    exclusive, // Up to where the cursor lands (`w`, `0`, `}`)
+    inclusive, // Including the character it lands on when moving forward (`e`, `f`, `%`)
+    linewise, // Whole lines, from the cursor's to the one it lands on (`j`, `G`, `dd`)
+    object, // The selection the command makes (`iw`, `i(`)
+    object_lines, // The whole lines that selection covers (`ip`, `ii`)
+    none, // Can't follow an operator
+};
+
+/// Operator waiting for its motion or text object
+pub const Pending = struct {
+    operator: Operator,
+    count: ?usize, // Count typed before the operator (the 2 of `2d3w`)
+    register: ?Registers.RegisterId, // Register picked with `"x` before it
+    origin: Cursor.Position, // Cursor position when the operator was typed
+    motion: ?[]const u8 = null, // Command run for it; set while a motion waits for its key (`dfx`)
+    motion_count: ?usize = null, // Count the motion ran with
+};
+
+/// Byte range an operator acts on
+pub const Span = struct {
+    start: usize,
+    end: usize,
+    linewise: bool,
+};
+
+/// Count the motion runs with: counts on the operator and the motion multiply (`2d3w` is six words)
+pub fn combineCounts(operator_count: ?usize, motion_count: ?usize) ?usize {
+    if (operator_count == null and motion_count == null) return null;
+    return (operator_count orelse 1) * (motion_count orelse 1);
+}
+
+/// Text between the cursor before a motion (`from`) and after it (`to`)
+/// Null when the motion covered nothing, such as an `f` that found no match.
+pub fn motionSpan(buffer: *const Buffer.Buffer, from: Cursor.Position, to: Cursor.Position, target: Target) !?Span {
+    if (target == .linewise) return lineSpan(buffer, @min(from.line, to.line), @max(from.line, to.line));
+    if (from.eql(to)) return null;
+
+    const rope = &buffer.rope;
+    const forward = from.lessThan(to);
+    const start = try Actions.positionToByteOffset(buffer, if (forward) from else to);
+    var end = try Actions.positionToByteOffset(buffer, if (forward) to else from);
+
+    if (forward and target == .inclusive) {
+        end = @min(end + 1, rope.lineEnd(rope.byteToLine(end)));
+    } else if (forward and target == .exclusive and to.col == 0 and to.line > from.line) {
+        // A motion that ends at the start of a later line (`dw` on a line's last word)
+        // stops at the end of the line before it rather than joining the lines
+        end = @max(start, rope.lineEnd(to.line - 1));
+    }
+
+    if (end <= start) return null;
+    return .{ .start = start, .end = end, .linewise = false };
+}
+
+/// Text a text object selected, or the whole lines it touches
+pub fn objectSpan(buffer: *const Buffer.Buffer, selection: Cursor.Selection, lines: bool) !?Span {
+    const range = selection.range();
+    if (lines) {
+        // A selection that ends at the start of a line (`al` takes the newline) doesn't cover it
+        const last = if (range.end.col == 0 and range.end.line > range.start.line) range.end.line - 1 else range.end.line;
+        return lineSpan(buffer, range.start.line, last);
+    }
+    if (selection.isCollapsed()) return null;
+
+    const start = try Actions.positionToByteOffset(buffer, range.start);
+    const end = try Actions.positionToByteOffset(buffer, range.end);
+    if (end <= start) return null;
+    return .{ .start = start, .end = end, .linewise = false };
+}
+
+/// Lines `first` through `last` (0-based), with the newline after each
+pub fn lineSpan(buffer: *const Buffer.Buffer, first: usize, last: usize) Span {
+    const rope = &buffer.rope;
+    const last_line = @min(last, rope.lineCount() - 1);
+    const end = if (last_line + 1 < rope.lineCount()) rope.lineToByte(last_line + 1) else rope.len();
+    return .{ .start = rope.lineToByte(@min(first, last_line)), .end = end, .linewise = true };
+}
+
+/// First character on a line that isn't a space or tab
+pub fn firstNonBlank(buffer: *const Buffer.Buffer, line: usize) Cursor.Position {
+    const rope = &buffer.rope;
+    const clamped = @min(line, rope.lineCount() - 1);
+    const start = rope.lineToByte(clamped);
+    const end = rope.lineEnd(clamped);
+
+    var cursor = rope.cursorAt(start);
+    var col: usize = 0;
+    while (start + col < end) : (col += 1) {
+        const c = cursor.nextByte() orelse break;
+        if (c != ' ' and c != '\t') break;
+    }
+    return .{ .line = clamped, .col = col };
+}
+
+/// New text for the operators that rewrite text in place (`>`, `<`, `gu`, `gU`, `=`); caller frees
+pub fn rewrite(allocator: std.mem.Allocator, operator: Operator, text: []const u8, unit: []const u8, above: []const u8) ![]u8 {
+    var out = std.ArrayList(u8).empty;
+    errdefer out.deinit(allocator);
+
+    switch (operator) {
+        .lowercase, .uppercase => {
+            try out.ensureTotalCapacity(allocator, text.len);
+            for (text) |c| {
+                out.appendAssumeCapacity(if (operator == .lowercase) std.ascii.toLower(c) else std.ascii.toUpper(c));
+            }
+        },
+        .indent, .dedent, .reindent => {
+            // Bracket depth for `=`: one level past the line above if it opens a block
+            const above_content = std.mem.trimRight(u8, above, " \t\r");
+            const base = Indent.leading(above);
+            var depth: usize = if (above_content.len > 0 and Indent.isOpener(above_content[above_content.len - 1])) 1 else 0;
+
+            var lines = std.mem.splitScalar(u8, text, '\n');
+            var first = true;
+            while (lines.next()) |line| {
+                if (!first) try out.append(allocator, '\n');
+                first = false;
+
+                const content = std.mem.trimLeft(u8, line, " \t");
+                if (std.mem.trimRight(u8, content, "\r").len == 0) {
+                    // Blank lines stay as they are (`=` clears their whitespace)
+                    if (operator != .reindent) try out.appendSlice(allocator, line) else try out.appendSlice(allocator, content);
+                    continue;
+                }
+
+                switch (operator) {
+                    .indent => {
+                        try out.appendSlice(allocator, unit);
+                        try out.appendSlice(allocator, line);
+                    },
+                    .dedent => try out.appendSlice(allocator, Indent.dedented(line, unit.len)),
+                    else => {
+                        var leading_closers: usize = 0;
+                        while (leading_closers < content.len and Indent.isCloser(content[leading_closers])) leading_closers += 1;
+
+                        try out.appendSlice(allocator, base);
+                        for (0..depth -| leading_closers) |_| try out.appendSlice(allocator, unit);
+                        try out.appendSlice(allocator, content);
+
+                        for (content) |c| {
+                            if (Indent.isOpener(c)) depth += 1 else if (Indent.isCloser(c)) depth -|= 1;
+                        }
+                    },
+                }
+            }
+        },
+        .delete, .change, .yank => try out.appendSlice(allocator, text),
+    }
+
+    return out.toOwnedSlice(allocator);
+}
+
+// === Tests ===
+
+test "operator: motion and object spans" {
+    const allocator = std.testing.allocator;
+    var buffer = try Buffer.Buffer.initFromString(allocator, 0, "one two\nthree\nfour");
+    defer buffer.deinit();
+
+    // `e` includes the character it lands on, `w` stops before the next word
+    const inclusive = (try motionSpan(&buffer, .{ .line = 0, .col = 0 }, .{ .line = 0, .col = 2 }, .inclusive)).?;
+    try std.testing.expectEqual(@as(usize, 3), inclusive.end);
+    const exclusive = (try motionSpan(&buffer, .{ .line = 0, .col = 4 }, .{ .line = 1, .col = 0 }, .exclusive)).?;
+    try std.testing.expectEqual(@as(usize, 4), exclusive.start);
+    try std.testing.expectEqual(@as(usize, 7), exclusive.end); // Not past the newline
+
+    // Backward motions end before the cursor
+    const backward = (try motionSpan(&buffer, .{ .line = 0, .col = 4 }, .{ .line = 0, .col = 0 }, .inclusive)).?;
+    try std.testing.expectEqual(@as(usize, 0), backward.start);
+    try std.testing.expectEqual(@as(usize, 4), backward.end);
+
+    // Linewise spans take whole lines, the last one without a newline
+    const lines = (try motionSpan(&buffer, .{ .line = 2, .col = 1 }, .{ .line = 1, .col = 3 }, .linewise)).?;
+    try std.testing.expect(lines.linewise);
+    try std.testing.expectEqual(@as(usize, 8), lines.start);
+    try std.testing.expectEqual(buffer.rope.len(), lines.end);
+
+    try std.testing.expect(try motionSpan(&buffer, .{ .line = 0, .col = 1 }, .{ .line = 0, .col = 1 }, .inclusive) == null);
+
+    const object = (try objectSpan(&buffer, Cursor.Selection.init(.{ .line = 1, .col = 0 }, .{ .line = 2, .col = 0 }), true)).?;
+    try std.testing.expectEqual(@as(usize, 8), object.start);
+    try std.testing.expectEqual(@as(usize, 14), object.end);
+
+    try std.testing.expectEqual(@as(?usize, 6), combineCounts(2, 3));
+    try std.testing.expectEqual(@as(?usize, null), combineCounts(null, null));
+}
+
+test "operator: rewrite indents, dedents, reindents and changes case" {
+    const allocator = std.testing.allocator;
+
+    const indented = try rewrite(allocator, .indent, "a\n\n  b", "    ", "");
+    defer allocator.free(indented);
+    try std.testing.expectEqualStrings("    a\n\n      b", indented);
+
+    const dedent = try rewrite(allocator, .dedent, "      a\n\tb\n  c", "    ", "");
+    defer allocator.free(dedent);
+    try std.testing.expectEqualStrings("  a\nb\nc", dedent);
+
+    const reindented = try rewrite(allocator, .reindent, "x();\nif (y) {\nz();\n}\n", "  ", "  fn f() {");
+    defer allocator.free(reindented);
+    try std.testing.expectEqualStrings("    x();\n    if (y) {\n      z();\n    }\n", reindented);
+
+    const upper = try rewrite(allocator, .uppercase, "mixed Case", "", "");
+    defer allocator.free(upper);
+    try std.testing.expectEqualStrings("MIXED CASE", upper);
+}
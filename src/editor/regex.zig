@@ -0,0 +1,861 @@
+//! Regular expression engine for search and replace
+//! Backtracking matcher supporting classes, anchors, quantifiers, alternation and groups
+//!
+//! Supported syntax:
+//!   literals, `.` (any character except newline), `[abc]`, `[^a-z]`
+//!   `\w \W \d \D \s \S`, escapes `\n \t \r` and escaped metacharacters
+//!   anchors `^ $` (line start/end), `\b \B` (word boundary)
+//!   quantifiers `* + ? {n} {n,} {n,m}`, lazy variants with trailing `?`
+//!   alternation `|`, capturing `( )` and non-capturing `(?: )` groups
+//!
+//! Patterns are compiled into a small instruction program and executed with an
+//! explicit backtracking stack, so deeply nested patterns don't recurse.
+
+const std = @import("std");
+
+/// Maximum number of capture groups (including the implicit group 0)
+pub const MAX_GROUPS = 32;
+
+/// Upper bound for `{n,m}` counts to keep compiled programs small
+pub const MAX_REPEAT = 1000;
+
+/// Instruction budget for matching at one start position; guards against
+/// catastrophic backtracking on pathological patterns
+pub const MAX_STEPS: usize = 10_000_000;
+
+pub const Options = struct {
+    case_insensitive: bool = false,
+};
+
+pub const CompileError = error{
+    UnexpectedEnd,
+    UnbalancedParen,
+    UnbalancedBracket,
+    InvalidRange,
+    InvalidRepeat,
+    NothingToRepeat,
+    TooManyGroups,
+    OutOfMemory,
+};
+
+pub const MatchError = error{
+    MatchLimitExceeded,
+    OutOfMemory,
+};
+
+/// Byte range within the searched text
+pub const Span = struct {
+    start: usize,
+    end: usize,
+
+    pub fn len(self: Span) usize {
+        return self.end - self.start;
+    }
+};
+
+/// Capture group spans for a single match; group 0 is the whole match
+pub const Captures = struct {
+    slots: [MAX_GROUPS * 2]?usize = [_]?usize{null} ** (MAX_GROUPS * 2),
+    count: usize = 0,
+
+    /// Get span of capture group, or null if the group didn't participate
+    pub fn get(self: *const Captures, group: usize) ?Span {
+        if (group >= self.count) return null;
+        const start = self.slots[group * 2] orelse return null;
+        const end = self.slots[group * 2 + 1] orelse return null;
+        return .{ .start = start, .end = end };
+    }
+};
+
+/// Characters `[...]` or a shorthand matches: ASCII by bitset, the rest by codepoint range
+const Class = struct {
+    ascii: std.StaticBitSet(128) = .initEmpty(),
+    ranges: []const CodepointRange = &.{}, // Members past ASCII (owned)
+    negated: bool = false,
+
+    fn matches(self: Class, cp: u21) bool {
+        const member = if (cp < 128) self.ascii.isSet(cp) else for (self.ranges) |range| {
+            if (cp >= range.lo and cp <= range.hi) break true;
+        } else false;
+        return member != self.negated;
+    }
+};
+
+const CodepointRange = struct { lo: u21, hi: u21 }; // Inclusive
+
+const Inst = union(enum) {
+    char: u8,
+    any,
+    class: usize,
+    split: struct { x: usize, y: usize },
+    jmp: usize,
+    save: usize,
+    line_start,
+    line_end,
+    word_boundary,
+    not_word_boundary,
+    /// Record loop entry position (used to stop empty iterations)
+    loop_enter: usize,
+    /// Fail if the loop body consumed nothing since loop_enter
+    loop_check: usize,
+    match,
+};
+
+const Assertion = enum { line_start, line_end, word_boundary, not_word_boundary };
+
+const Node = union(enum) {
+    empty,
+    char: u8,
+    any,
+    class: usize,
+    assertion: Assertion,
+    group: struct { index: ?usize, child: *Node },
+    concat: []*Node,
+    alternate: []*Node,
+    repeat: struct { child: *Node, min: u32, max: ?u32, greedy: bool },
+};
+
+/// Compiled regular expression
+pub const Regex = struct {
+    allocator: std.mem.Allocator,
+    program: []Inst,
+    classes: []Class,
+    group_count: usize,
+    loop_count: usize,
+    case_insensitive: bool,
+
+    /// Compile pattern into a program
+    pub fn compile(allocator: std.mem.Allocator, pattern: []const u8, options: Options) CompileError!Regex {
+        var arena = std.heap.ArenaAllocator.init(allocator);
+        defer arena.deinit();
+
+        var classes = std.ArrayList(Class).empty;
+        errdefer {
+            for (classes.items) |class| allocator.free(class.ranges);
+            classes.deinit(allocator);
+        }
+
+        var parser = Parser{
+            .pattern = pattern,
+            .arena = arena.allocator(),
+            .allocator = allocator,
+            .classes = &classes,
+            .case_insensitive = options.case_insensitive,
+        };
+        const root = try parser.parseAlternation();
+        if (parser.pos < pattern.len) {
+            // Only an unmatched ')' can stop the top-level parse early
+            return error.UnbalancedParen;
+        }
+
+        var compiler = Compiler{ .allocator = allocator };
+        errdefer compiler.program.deinit(allocator);
+
+        try compiler.emit(.{ .save = 0 });
+        try compiler.compileNode(root);
+        try compiler.emit(.{ .save = 1 });
+        try compiler.emit(.match);
+
+        const program = try compiler.program.toOwnedSlice(allocator);
+        errdefer allocator.free(program);
+
+        return .{
+            .allocator = allocator,
+            .program = program,
+            .classes = try classes.toOwnedSlice(allocator),
+            .group_count = parser.group_count,
+            .loop_count = compiler.loop_count,
+            .case_insensitive = options.case_insensitive,
+        };
+    }
+
+    pub fn deinit(self: *Regex) void {
+        self.allocator.free(self.program);
+        for (self.classes) |class| self.allocator.free(class.ranges);
+        self.allocator.free(self.classes);
+    }
+
+    /// Try to match starting exactly at `start`
+    pub fn matchAt(self: *const Regex, text: []const u8, start: usize, caps: *Captures) MatchError!bool {
+        var steps: usize = 0;
+        return self.run(text, start, caps, &steps);
+    }
+
+    /// Find leftmost match at or after `start`
+    pub fn find(self: *const Regex, text: []const u8, start: usize, caps: *Captures) MatchError!?Span {
+        if (start > text.len) return null;
+
+        var pos = start;
+        while (pos <= text.len) : (pos += 1) {
+            // Fast reject when the pattern begins with a literal byte
+            if (self.firstLiteral()) |c| {
+                const idx = std.mem.indexOfScalarPos(u8, text, pos, c) orelse return null;
+                pos = idx;
+            }
+            if (insideChar(text, pos)) continue;
+            // Each start gets its own budget, so a long text isn't mistaken for backtracking
+            var steps: usize = 0;
+            if (try self.run(text, pos, caps, &steps)) {
+                return caps.get(0);
+            }
+        }
+        return null;
+    }
+
+    /// Literal byte the pattern must start with, if known (case-sensitive only)
+    fn firstLiteral(self: *const Regex) ?u8 {
+        if (self.case_insensitive or self.program.len < 2) return null;
+        return switch (self.program[1]) {
+            .char => |c| c,
+            else => null,
+        };
+    }
+
+    const Frame = union(enum) {
+        branch: struct { pc: usize, pos: usize },
+        restore_slot: struct { slot: usize, value: ?usize },
+        restore_loop: struct { slot: usize, value: ?usize },
+    };
+
+    fn run(self: *const Regex, text: []const u8, start: usize, caps: *Captures, steps: *usize) MatchError!bool {
+        caps.* = .{ .count = self.group_count };
+
+        var loops_buf: [256]?usize = [_]?usize{null} ** 256;
+        const loops = loops_buf[0..@min(self.loop_count, loops_buf.len)];
+
+        var stack = std.ArrayList(Frame).empty;
+        defer stack.deinit(self.allocator);
+
+        var pc: usize = 0;
+        var pos: usize = start;
+
+        while (true) {
+            steps.* += 1;
+            if (steps.* > MAX_STEPS) return error.MatchLimitExceeded;
+
+            const ok = switch (self.program[pc]) {
+                .char => |c| blk: {
+                    if (pos < text.len and self.charEql(text[pos], c)) {
+                        pos += 1;
+                        pc += 1;
+                        break :blk true;
+                    }
+                    break :blk false;
+                },
+                .any => blk: {
+                    if (pos < text.len and text[pos] != '\n') {
+                        pos += decodeAt(text, pos).len;
+                        pc += 1;
+                        break :blk true;
+                    }
+                    break :blk false;
+                },
+                .class => |idx| blk: {
+                    if (pos < text.len) {
+                        const unit = decodeAt(text, pos);
+                        if (self.classes[idx].matches(unit.cp)) {
+                            pos += unit.len;
+                            pc += 1;
+                            break :blk true;
+                        }
+                    }
+                    break :blk false;
+                },
+                .split => |s| blk: {
+                    try stack.append(self.allocator, .{ .branch = .{ .pc = s.y, .pos = pos } });
+                    pc = s.x;
+                    break :blk true;
+                },
+                .jmp => |target| blk: {
+                    pc = target;
+                    break :blk true;
+                },
+                .save => |slot| blk: {
+                    if (slot < caps.slots.len) {
+                        try stack.append(self.allocator, .{ .restore_slot = .{ .slot = slot, .value = caps.slots[slot] } });
+                        caps.slots[slot] = pos;
+                    }
+                    pc += 1;
+                    break :blk true;
+                },
+                .line_start => blk: {
+                    const at = pos == 0 or text[pos - 1] == '\n';
+                    if (at) pc += 1;
+                    break :blk at;
+                },
+                .line_end => blk: {
+                    const at = pos == text.len or text[pos] == '\n';
+                    if (at) pc += 1;
+                    break :blk at;
+                },
+                .word_boundary, .not_word_boundary => blk: {
+                    const before = pos > 0 and isWordChar(text[pos - 1]);
+                    const after = pos < text.len and isWordChar(text[pos]);
+                    const at_boundary = before != after;
+                    const want = self.program[pc] == .word_boundary;
+                    if (at_boundary == want) pc += 1;
+                    break :blk at_boundary == want;
+                },
+                .loop_enter => |slot| blk: {
+                    if (slot < loops.len) {
+                        try stack.append(self.allocator, .{ .restore_loop = .{ .slot = slot, .value = loops[slot] } });
+                        loops[slot] = pos;
+                    }
+                    pc += 1;
+                    break :blk true;
+                },
+                .loop_check => |slot| blk: {
+                    if (slot < loops.len) {
+                        if (loops[slot]) |entered| {
+                            if (entered == pos) break :blk false;
+                        }
+                    }
+                    pc += 1;
+                    break :blk true;
+                },
+                .match => return true,
+            };
+
+            if (ok) continue;
+
+            // Backtrack: undo state changes until the next alternative branch
+            var resumed = false;
+            while (stack.pop()) |frame| {
+                switch (frame) {
+                    .restore_slot => |r| caps.slots[r.slot] = r.value,
+                    .restore_loop => |r| loops[r.slot] = r.value,
+                    .branch => |b| {
+                        pc = b.pc;
+                        pos = b.pos;
+                        resumed = true;
+                        break;
+                    },
+                }
+            }
+            if (!resumed) return false;
+        }
+    }
+
+    fn charEql(self: *const Regex, a: u8, b: u8) bool {
+        if (self.case_insensitive) return std.ascii.toLower(a) == std.ascii.toLower(b);
+        return a == b;
+    }
+};
+
+/// Check if byte is a word character (`\w`)
+pub fn isWordChar(c: u8) bool {
+    return std.ascii.isAlphanumeric(c) or c == '_';
+}
+
+const Char = struct { len: usize, cp: u21 };
+
+/// Character at `pos`: its length in bytes and codepoint
+/// A byte that doesn't start a valid UTF-8 sequence stands alone as U+FFFD.
+fn decodeAt(text: []const u8, pos: usize) Char {
+    if (text[pos] < 0x80) return .{ .len = 1, .cp = text[pos] };
+    const invalid = Char{ .len = 1, .cp = std.unicode.replacement_character };
+    const n = std.unicode.utf8ByteSequenceLength(text[pos]) catch return invalid;
+    if (pos + n > text.len) return invalid;
+    const cp = std.unicode.utf8Decode(text[pos..][0..n]) catch return invalid;
+    return .{ .len = n, .cp = cp };
+}
+
+/// Whether `pos` falls inside a multibyte character rather than at its start
+fn insideChar(text: []const u8, pos: usize) bool {
+    var lead = pos;
+    while (lead > 0 and pos - lead < 3 and lead < text.len and text[lead] & 0xC0 == 0x80) lead -= 1;
+    if (lead == pos) return false;
+    return decodeAt(text, lead).len > pos - lead;
+}
+
+/// Expand replacement template using capture groups
+/// `$0`-`$9` and `${n}` insert groups, `$$` inserts a literal dollar sign.
+/// Groups that didn't participate in the match expand to nothing.
+pub fn expandReplacement(
+    allocator: std.mem.Allocator,
+    template: []const u8,
+    text: []const u8,
+    caps: *const Captures,
+) ![]u8 {
+    var out = std.ArrayList(u8).empty;
+    errdefer out.deinit(allocator);
+
+    var i: usize = 0;
+    while (i < template.len) {
+        const c = template[i];
+        if (c != '$' or i + 1 >= template.len) {
+            try out.append(allocator, c);
+            i += 1;
+            continue;
+        }
+
+        const next = template[i + 1];
+        if (next == '$') {
+            try out.append(allocator, '$');
+            i += 2;
+        } else if (std.ascii.isDigit(next)) {
+            try appendGroup(allocator, &out, text, caps, next - '0');
+            i += 2;
+        } else if (next == '{') {
+            const close = std.mem.indexOfScalarPos(u8, template, i + 2, '}') orelse {
+                try out.append(allocator, c);
+                i += 1;
+                continue;
+            };
+            const group = std.fmt.parseInt(usize, template[i + 2 .. close], 10) catch {
+                try out.append(allocator, c);
+                i += 1;
+                continue;
+            };
+            try appendGroup(allocator, &out, text, caps, group);
+            i = close + 1;
+        } else {
+            try out.append(allocator, c);
+            i += 1;
+        }
+    }
+
+    return out.toOwnedSlice(allocator);
+}
+
+fn appendGroup(
+    allocator: std.mem.Allocator,
+    out: *std.ArrayList(u8),
+    text: []const u8,
+    caps: *const Captures,
+    group: usize,
+) !void {
+    if (caps.get(group)) |span| {
+        try out.appendSlice(allocator, text[span.start..span.end]);
+    }
+}
+
+// === Parser ===
+
+const Parser = struct {
+    pattern: []const u8,
+    pos: usize = 0,
+    arena: std.mem.Allocator,
+    allocator: std.mem.Allocator,
+    classes: *std.ArrayList(Class),
+    group_count: usize = 1,
+    case_insensitive: bool,
+
+    fn peek(self: *const Parser) ?u8 {
+        if (self.pos >= self.pattern.len) return null;
+        return self.pattern[self.pos];
+    }
+
+    fn newNode(self: *Parser, node: Node) CompileError!*Node {
+        const ptr = try self.arena.create(Node);
+        ptr.* = node;
+        return ptr;
+    }
+
+    fn parseAlternation(self: *Parser) CompileError!*Node {
+        var branches = std.ArrayList(*Node).empty;
+        try branches.append(self.arena, try self.parseConcat());
+        while (self.peek() == '|') {
+            self.pos += 1;
+            try branches.append(self.arena, try self.parseConcat());
+        }
+        if (branches.items.len == 1) return branches.items[0];
+        return self.newNode(.{ .alternate = branches.items });
+    }
+
+    fn parseConcat(self: *Parser) CompileError!*Node {
+        var items = std.ArrayList(*Node).empty;
+        while (self.peek()) |c| {
+            if (c == '|' or c == ')') break;
+            const atom = try self.parseAtom();
+            try items.append(self.arena, try self.parseQuantifier(atom));
+        }
+        if (items.items.len == 0) return self.newNode(.empty);
+        if (items.items.len == 1) return items.items[0];
+        return self.newNode(.{ .concat = items.items });
+    }
+
+    fn parseQuantifier(self: *Parser, atom: *Node) CompileError!*Node {
+        var node = atom;
+        while (self.peek()) |c| {
+            var min: u32 = 0;
+            var max: ?u32 = null;
+            switch (c) {
+                '*' => self.pos += 1,
+                '+' => {
+                    min = 1;
+                    self.pos += 1;
+                },
+                '?' => {
+                    max = 1;
+                    self.pos += 1;
+                },
+                '{' => {
+                    const bounds = self.parseBraces() orelse return node;
+                    min = bounds.min;
+                    max = bounds.max;
+                },
+                else => return node,
+            }
+
+            switch (node.*) {
+                .empty, .assertion => return error.NothingToRepeat,
+                else => {},
+            }
+
+            var greedy = true;
+            if (self.peek() == '?') {
+                greedy = false;
+                self.pos += 1;
+            }
+            node = try self.newNode(.{ .repeat = .{ .child = node, .min = min, .max = max, .greedy = greedy } });
+        }
+        return node;
+    }
+
+    /// Parse `{n}`, `{n,}` or `{n,m}`; a brace that isn't a valid count is a literal
+    fn parseBraces(self: *Parser) ?struct { min: u32, max: ?u32 } {
+        const close = std.mem.indexOfScalarPos(u8, self.pattern, self.pos, '}') orelse return null;
+        const body = self.pattern[self.pos + 1 .. close];
+        var min: u32 = 0;
+        var max: ?u32 = null;
+        if (std.mem.indexOfScalar(u8, body, ',')) |comma| {
+            min = std.fmt.parseInt(u32, body[0..comma], 10) catch return null;
+            if (comma + 1 < body.len) {
+                max = std.fmt.parseInt(u32, body[comma + 1 ..], 10) catch return null;
+            }
+        } else {
+            min = std.fmt.parseInt(u32, body, 10) catch return null;
+            max = min;
+        }
+        if (min > MAX_REPEAT) return null;
+        if (max) |m| {
+            if (m < min or m > MAX_REPEAT) return null;
+        }
+        self.pos = close + 1;
+        return .{ .min = min, .max = max };
+    }
+
+    fn parseAtom(self: *Parser) CompileError!*Node {
+        const c = self.pattern[self.pos];
+        self.pos += 1;
+        switch (c) {
+            '.' => return self.newNode(.any),
+            '^' => return self.newNode(.{ .assertion = .line_start }),
+            '$' => return self.newNode(.{ .assertion = .line_end }),
+            '(' => {
+                var index: ?usize = null;
+                if (std.mem.startsWith(u8, self.pattern[self.pos..], "?:")) {
+                    self.pos += 2;
+                } else {
+                    if (self.group_count >= MAX_GROUPS) return error.TooManyGroups;
+                    index = self.group_count;
+                    self.group_count += 1;
+                }
+                const child = try self.parseAlternation();
+                if (self.peek() != ')') return error.UnbalancedParen;
+                self.pos += 1;
+                return self.newNode(.{ .group = .{ .index = index, .child = child } });
+            },
+            ')' => return error.UnbalancedParen,
+            '*', '+', '?' => return error.NothingToRepeat,
+            '[' => return self.parseClass(),
+            '\\' => return self.parseEscape(),
+            else => return self.literal(c),
+        }
+    }
+
+    fn literal(self: *Parser, c: u8) CompileError!*Node {
+        return self.newNode(.{ .char = c });
+    }
+
+    fn parseEscape(self: *Parser) CompileError!*Node {
+        const c = self.peek() orelse return error.UnexpectedEnd;
+        self.pos += 1;
+        switch (c) {
+            'b' => return self.newNode(.{ .assertion = .word_boundary }),
+            'B' => return self.newNode(.{ .assertion = .not_word_boundary }),
+            'w', 'W', 'd', 'D', 's', 'S' => {
+                var class = Class{};
+                var ranges = std.ArrayList(CodepointRange).empty;
+                try self.addShorthand(&class, &ranges, c);
+                return self.newNode(.{ .class = try self.addClass(class, ranges.items) });
+            },
+            else => return self.literal(escapedByte(c)),
+        }
+    }
+
+    fn parseClass(self: *Parser) CompileError!*Node {
+        var class = Class{};
+        var ranges = std.ArrayList(CodepointRange).empty;
+        if (self.peek() == '^') {
+            class.negated = true;
+            self.pos += 1;
+        }
+
+        var first = true;
+        while (true) {
+            const c = self.peek() orelse return error.UnbalancedBracket;
+            // A leading ']' is taken literally
+            if (c == ']' and !first) {
+                self.pos += 1;
+                break;
+            }
+            first = false;
+
+            var lo = self.nextChar();
+            if (lo == '\\') {
+                const e = self.peek() orelse return error.UnexpectedEnd;
+                self.pos += 1;
+                switch (e) {
+                    'w', 'W', 'd', 'D', 's', 'S' => {
+                        try self.addShorthand(&class, &ranges, e);
+                        continue;
+                    },
+                    else => lo = escapedByte(e),
+                }
+            }
+
+            // Range a-z (a trailing '-' is literal)
+            var hi = lo;
+            if (self.peek() == '-' and self.pos + 1 < self.pattern.len and self.pattern[self.pos + 1] != ']') {
+                self.pos += 1;
+                hi = self.nextChar();
+                if (hi == '\\') {
+                    const e = self.peek() orelse return error.UnexpectedEnd;
+                    self.pos += 1;
+                    hi = escapedByte(e);
+                }
+                if (hi < lo) return error.InvalidRange;
+            }
+            if (lo < 128) class.ascii.setRangeValue(.{ .start = lo, .end = @as(usize, @min(hi, 127)) + 1 }, true);
+            if (hi >= 128) try ranges.append(self.arena, .{ .lo = @max(lo, 128), .hi = hi });
+        }
+
+        if (self.case_insensitive) {
+            var ch: usize = 'a';
+            while (ch <= 'z') : (ch += 1) {
+                const upper = ch - 'a' + 'A';
+                if (class.ascii.isSet(ch) or class.ascii.isSet(upper)) {
+                    class.ascii.set(ch);
+                    class.ascii.set(upper);
+                }
+            }
+        }
+
+        return self.newNode(.{ .class = try self.addClass(class, ranges.items) });
+    }
+
+    /// Next character of the pattern as a codepoint
+    fn nextChar(self: *Parser) u21 {
+        const unit = decodeAt(self.pattern, self.pos);
+        self.pos += unit.len;
+        return unit.cp;
+    }
+
+    /// Add `\w`, `\d`, `\s` or their negations to a class
+    /// Only ASCII characters are words, digits or spaces, so the negations take everything past it.
+    fn addShorthand(self: *Parser, class: *Class, ranges: *std.ArrayList(CodepointRange), c: u8) CompileError!void {
+        var set = std.StaticBitSet(128).initEmpty();
+        for (0..128) |b| {
+            const byte: u8 = @intCast(b);
+            const member = switch (std.ascii.toLower(c)) {
+                'w' => isWordChar(byte),
+                'd' => std.ascii.isDigit(byte),
+                's' => std.ascii.isWhitespace(byte),
+                else => false,
+            };
+            if (member) set.set(b);
+        }
+        if (std.ascii.isUpper(c)) {
+            set.toggleAll();
+            try ranges.append(self.arena, .{ .lo = 128, .hi = std.math.maxInt(u21) });
+        }
+        class.ascii.setUnion(set);
+    }
+
+    fn addClass(self: *Parser, class: Class, ranges: []const CodepointRange) CompileError!usize {
+        var owned = class;
+        owned.ranges = try self.allocator.dupe(CodepointRange, ranges);
+        errdefer self.allocator.free(owned.ranges);
+        try self.classes.append(self.allocator, owned);
+        return self.classes.items.len - 1;
+    }
+};
+
+fn escapedByte(c: u8) u8 {
+    return switch (c) {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        else => c,
+    };
+}
+
+// === Compiler ===
+
+const Compiler = struct {
+    allocator: std.mem.Allocator,
+    program: std.ArrayList(Inst) = .empty,
+    loop_count: usize = 0,
+
+    fn emit(self: *Compiler, inst: Inst) CompileError!void {
+        try self.program.append(self.allocator, inst);
+    }
+
+    fn here(self: *const Compiler) usize {
+        return self.program.items.len;
+    }
+
+    fn compileNode(self: *Compiler, node: *const Node) CompileError!void {
+        switch (node.*) {
+            .empty => {},
+            .char => |c| try self.emit(.{ .char = c }),
+            .any => try self.emit(.any),
+            .class => |idx| try self.emit(.{ .class = idx }),
+            .assertion => |a| try self.emit(switch (a) {
+                .line_start => .line_start,
+                .line_end => .line_end,
+                .word_boundary => .word_boundary,
+                .not_word_boundary => .not_word_boundary,
+            }),
+            .group => |g| {
+                if (g.index) |idx| try self.emit(.{ .save = idx * 2 });
+                try self.compileNode(g.child);
+                if (g.index) |idx| try self.emit(.{ .save = idx * 2 + 1 });
+            },
+            .concat => |items| {
+                for (items) |item| try self.compileNode(item);
+            },
+            .alternate => |branches| {
+                // split L1, next; L1: a; jmp end; next: split L2, ...
+                var jumps = std.ArrayList(usize).empty;
+                defer jumps.deinit(self.allocator);
+
+                for (branches, 0..) |branch, i| {
+                    if (i + 1 < branches.len) {
+                        const split = self.here();
+                        try self.emit(.{ .split = .{ .x = split + 1, .y = 0 } });
+                        try self.compileNode(branch);
+                        try jumps.append(self.allocator, self.here());
+                        try self.emit(.{ .jmp = 0 });
+                        self.program.items[split].split.y = self.here();
+                    } else {
+                        try self.compileNode(branch);
+                    }
+                }
+                const end = self.here();
+                for (jumps.items) |j| self.program.items[j].jmp = end;
+            },
+            .repeat => |r| try self.compileRepeat(r.child, r.min, r.max, r.greedy),
+        }
+    }
+
+    fn compileRepeat(self: *Compiler, child: *const Node, min: u32, max: ?u32, greedy: bool) CompileError!void {
+        var i: u32 = 0;
+        while (i < min) : (i += 1) try self.compileNode(child);
+
+        if (max) |m| {
+            // Optional copies: split body, end (each)
+            var splits = std.ArrayList(usize).empty;
+            defer splits.deinit(self.allocator);
+            while (i < m) : (i += 1) {
+                try splits.append(self.allocator, self.here());
+                try self.emit(.{ .split = .{ .x = 0, .y = 0 } });
+                try self.compileNode(child);
+            }
+            const end = self.here();
+            for (splits.items) |s| {
+                self.program.items[s].split = if (greedy)
+                    .{ .x = s + 1, .y = end }
+                else
+                    .{ .x = end, .y = s + 1 };
+            }
+            return;
+        }
+
+        // Unbounded: L: split body, out; body: enter; child; check; jmp L; out:
+        const slot = self.loop_count;
+        self.loop_count += 1;
+
+        const loop = self.here();
+        try self.emit(.{ .split = .{ .x = 0, .y = 0 } });
+        try self.emit(.{ .loop_enter = slot });
+        try self.compileNode(child);
+        try self.emit(.{ .loop_check = slot });
+        try self.emit(.{ .jmp = loop });
+        const out = self.here();
+        self.program.items[loop].split = if (greedy)
+            .{ .x = loop + 1, .y = out }
+        else
+            .{ .x = out, .y = loop + 1 };
+    }
+};
+
+// === Tests ===
+
+fn expectFind(pattern: []const u8, text: []const u8, expected: ?[]const u8) !void {
+    var re = try Regex.compile(std.testing.allocator, pattern, .{});
+    defer re.deinit();
+    var caps = Captures{};
+    const span = try re.find(text, 0, &caps);
+    if (expected) |e| {
+        try std.testing.expect(span != null);
+        try std.testing.expectEqualStrings(e, text[span.?.start..span.?.end]);
+    } else {
+        try std.testing.expect(span == null);
+    }
+}
+
+test "regex: classes, anchors, quantifiers and alternation" {
+    try expectFind("fn \\w+\\(", "pub fn main() void", "fn main(");
+    try expectFind("[0-9]+", "abc 1234 def", "1234");
+    try expectFind("[^a-z ]+", "abc DEF", "DEF");
+    try expectFind("^foo", "bar\nfoo", "foo");
+    try expectFind("bar$", "bar baz", null);
+    try expectFind("\\bcat\\b", "concat cat", "cat");
+    try expectFind("colou?r", "the color red", "color");
+    try expectFind("a{2,3}", "a aaaa", "aaa");
+    try expectFind("a.*?b", "axxbyyb", "axxb");
+    try expectFind("cat|dog", "hotdog", "dog");
+    try expectFind("(?:ab)+", "xababx", "abab");
+    try expectFind("(a*)*b", "aaab", "aaab");
+    try std.testing.expectError(error.UnbalancedParen, Regex.compile(std.testing.allocator, "(ab", .{}));
+    try std.testing.expectError(error.NothingToRepeat, Regex.compile(std.testing.allocator, "*a", .{}));
+}
+
+test "regex: dot and classes match whole UTF-8 characters" {
+    try expectFind("[^a]", "é", "é");
+    try expectFind("a.b", "aéb", "aéb");
+    try expectFind("[é-ë]+", "caféëx", "éë");
+    try expectFind("\\W+", "a→b", "→");
+    try expectFind("[^é]x", "éx", null); // Not from the middle of the é
+
+    // Every match of `[^a]` is a whole character, so replacing them keeps the text valid
+    var re = try Regex.compile(std.testing.allocator, "[^a]", .{});
+    defer re.deinit();
+    const text = "aé😀";
+    var caps = Captures{};
+    var pos: usize = 0;
+    var matches: usize = 0;
+    while (try re.find(text, pos, &caps)) |span| : (pos = span.end) {
+        try std.testing.expect(std.unicode.utf8ValidateSlice(text[span.start..span.end]));
+        matches += 1;
+    }
+    try std.testing.expectEqual(@as(usize, 2), matches);
+}
+
+test "regex: captures and replacement expansion" {
+    const allocator = std.testing.allocator;
+    var re = try Regex.compile(allocator, "(\\w+)@(\\w+)", .{ .case_insensitive = true });
+    defer re.deinit();
+
+    const text = "mail: USER@host";
+    var caps = Captures{};
+    const span = (try re.find(text, 0, &caps)).?;
+    try std.testing.expectEqual(@as(usize, 6), span.start);
+
+    const out = try expandReplacement(allocator, "$2 at $1 ($$0=${0})", text, &caps);
+    defer allocator.free(out);
+    try std.testing.expectEqualStrings("host at USER ($0=USER@host)", out);
+}
@@ -3,14 +3,20 @@
 
 const std = @import("std");
 const Command = @import("command.zig");
+const Operator = @import("operator.zig");
+const Motions = @import("motions.zig");
 
 /// Recordable action type
 pub const Action = struct {
     command_name: []const u8,
-    // Could be extended with parameters for more complex actions
+    count: ?usize = null, // Count it was run with (`3dd`), replayed by `.`
+    operator: ?Operator.Operator = null, // Set when command_name is the motion an operator took (`dw`)
+    find: ?Motions.FindTillState = null, // Character an `f`/`t` motion found, for `dfx`
+    inserted: ?[]const u8 = null, // Text typed after a change (`cw` + text), owned
 
     pub fn deinit(self: *Action, allocator: std.mem.Allocator) void {
         allocator.free(self.command_name);
+        if (self.inserted) |text| allocator.free(text);
     }
 };
 
@@ -33,7 +39,7 @@ pub const RepeatSystem = struct {
     }
 
     /// Record an action for later repeat
-    pub fn recordAction(self: *RepeatSystem, command_name: []const u8) !void {
+    pub fn recordAction(self: *RepeatSystem, command_name: []const u8, count: ?usize) !void {
         // Don't record if we're currently replaying
         if (self.recording) return;
 
@@ -46,9 +52,37 @@ pub const RepeatSystem = struct {
         const name_copy = try self.allocator.dupe(u8, command_name);
         self.last_action = Action{
             .command_name = name_copy,
+            .count = count,
         };
     }
 
+    /// Record an operator with the motion or text object it acted on
+    /// `count` is the one the motion ran with, both counts multiplied (`2d3w` is 6).
+    pub fn recordOperator(
+        self: *RepeatSystem,
+        operator: Operator.Operator,
+        motion_name: []const u8,
+        count: ?usize,
+        find: ?Motions.FindTillState,
+    ) !void {
+        if (self.recording) return;
+
+        try self.recordAction(motion_name, count);
+        self.last_action.?.operator = operator;
+        self.last_action.?.find = find;
+    }
+
+    /// Keep the text typed after the last action if it was a change, for `.` to type again
+    pub fn recordInserted(self: *RepeatSystem, text: []const u8) !void {
+        if (self.recording) return;
+        const action = if (self.last_action) |*action| action else return;
+        if (action.operator == null or action.operator.? != .change) return;
+
+        const copy = try self.allocator.dupe(u8, text);
+        if (action.inserted) |old| self.allocator.free(old);
+        action.inserted = copy;
+    }
+
     /// Get the last recorded action
     pub fn getLastAction(self: *const RepeatSystem) ?Action {
         return self.last_action;
@@ -83,11 +117,25 @@ pub const RepeatSystem = struct {
             "scroll_page_up",
             "scroll_page_down",
             "center_cursor",
+            "toggle_fold",
+            "close_fold",
+            "open_fold",
+            "open_all_folds",
+            "close_all_folds",
             "enter_insert_mode",
             "enter_normal_mode",
             "enter_select_mode",
             "enter_command_mode",
             "repeat_last_action",
+            "select_register",
+            "operator_delete",
+            "operator_change",
+            "operator_yank",
+            "operator_indent",
+            "operator_dedent",
+            "operator_lowercase",
+            "operator_uppercase",
+            "operator_reindent",
             "undo",
             "redo",
             "save_buffer",
@@ -111,11 +159,39 @@ test "repeat: record and get action" {
     var repeat = RepeatSystem.init(allocator);
     defer repeat.deinit();
 
-    try repeat.recordAction("delete_line");
+    try repeat.recordAction("delete_line", 3);
 
     const action = repeat.getLastAction();
     try std.testing.expect(action != null);
     try std.testing.expect(std.mem.eql(u8, action.?.command_name, "delete_line"));
+    try std.testing.expectEqual(@as(?usize, 3), action.?.count);
+}
+
+test "repeat: record operator" {
+    const allocator = std.testing.allocator;
+    var repeat = RepeatSystem.init(allocator);
+    defer repeat.deinit();
+
+    try repeat.recordOperator(.change, "select_inner_word", null, null);
+    const action = repeat.getLastAction().?;
+    try std.testing.expectEqualStrings("select_inner_word", action.command_name);
+    try std.testing.expectEqual(@as(?Operator.Operator, .change), action.operator);
+
+    // The change keeps what was typed after it
+    try repeat.recordInserted("new");
+    try std.testing.expectEqualStrings("new", repeat.getLastAction().?.inserted.?);
+
+    // A plain action afterwards replaces it, operator and all
+    try repeat.recordAction("delete_char", null);
+    try std.testing.expectEqual(@as(?Operator.Operator, null), repeat.getLastAction().?.operator);
+    try repeat.recordInserted("ignored");
+    try std.testing.expect(repeat.getLastAction().?.inserted == null);
+
+    // Nothing is recorded while replaying
+    repeat.startReplay();
+    try repeat.recordOperator(.delete, "move_word_forward", 2, null);
+    repeat.endReplay();
+    try std.testing.expectEqualStrings("delete_char", repeat.getLastAction().?.command_name);
 }
 
 test "repeat: should record filtering" {
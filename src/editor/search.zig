@@ -3,12 +3,15 @@
 
 const std = @import("std");
 const Cursor = @import("cursor.zig");
+const Regex = @import("regex.zig");
+const Rope = @import("../buffer/rope.zig").Rope;
 
 /// Search options
 pub const SearchOptions = struct {
     case_sensitive: bool = true,
     whole_word: bool = false,
     wrap_around: bool = true,
+    regex: bool = false, // Interpret query as a regular expression
 };
 
 /// Search state
@@ -23,10 +26,12 @@ pub const Search = struct {
     match_count: usize = 0,
     match_index: usize = 0,
     replacements_made: usize = 0,
+    match_limit_hit: bool = false, // A regex search gave up on a backtracking pattern
     options: SearchOptions = .{},
     history: std.ArrayList([]const u8),
     history_index: ?usize = null,
     max_history: usize = 50,
+    line_scratch: std.ArrayList(u8) = .empty, // A line split across rope leaves, gathered for a regex
     allocator: std.mem.Allocator,
 
     pub const Match = struct {
@@ -52,6 +57,7 @@ pub const Search = struct {
             self.allocator.free(entry);
         }
         self.history.deinit(self.allocator);
+        self.line_scratch.deinit(self.allocator);
     }
 
     /// Set search query
@@ -176,6 +182,12 @@ pub const Search = struct {
         }
     }
 
+    /// Whether a regex search gave up since the last call, rather than finding nothing
+    pub fn takeMatchLimitHit(self: *Search) bool {
+        defer self.match_limit_hit = false;
+        return self.match_limit_hit;
+    }
+
     /// Toggle case sensitivity
     pub fn toggleCaseSensitive(self: *Search) void {
         self.options.case_sensitive = !self.options.case_sensitive;
@@ -191,6 +203,11 @@ pub const Search = struct {
         self.options.wrap_around = !self.options.wrap_around;
     }
 
+    /// Toggle regex matching
+    pub fn toggleRegex(self: *Search) void {
+        self.options.regex = !self.options.regex;
+    }
+
     /// Get match statistics string
     pub fn getMatchInfo(self: *const Search) [64]u8 {
         var buf: [64]u8 = undefined;
@@ -221,6 +238,43 @@ pub const Search = struct {
         return before_is_boundary and after_is_boundary;
     }
 
+    /// Byte offset of a position in text (stops at end of text)
+    fn positionToOffset(text: []const u8, pos: Cursor.Position) usize {
+        var offset: usize = 0;
+        var line: usize = 0;
+        var col: usize = 0;
+        while (offset < text.len) : (offset += 1) {
+            if (line > pos.line or (line == pos.line and col >= pos.col)) break;
+            if (text[offset] == '\n') {
+                line += 1;
+                col = 0;
+            } else {
+                col += 1;
+            }
+        }
+        return offset;
+    }
+
+    /// Walks text forward converting increasing byte offsets to positions
+    const PositionTracker = struct {
+        text: []const u8,
+        offset: usize = 0,
+        line: usize = 0,
+        col: usize = 0,
+
+        fn advanceTo(self: *PositionTracker, target: usize) Cursor.Position {
+            while (self.offset < target and self.offset < self.text.len) : (self.offset += 1) {
+                if (self.text[self.offset] == '\n') {
+                    self.line += 1;
+                    self.col = 0;
+                } else {
+                    self.col += 1;
+                }
+            }
+            return .{ .line = self.line, .col = self.col };
+        }
+    };
+
     /// Check if query matches at given position with respect to search options
     fn matchesAt(self: *const Search, text: []const u8, offset: usize) bool {
         const query = self.getQuery();
@@ -244,6 +298,137 @@ pub const Search = struct {
         return true;
     }
 
+    // === Regex Matching ===
+
+    /// Compile current query as a regex, honoring case and whole-word options
+    pub fn compileRegex(self: *const Search, allocator: std.mem.Allocator) !Regex.Regex {
+        const query = self.getQuery();
+        const options = Regex.Options{ .case_insensitive = !self.options.case_sensitive };
+        if (!self.options.whole_word) return Regex.Regex.compile(allocator, query, options);
+
+        const wrapped = try std.fmt.allocPrint(allocator, "\\b(?:{s})\\b", .{query});
+        defer allocator.free(wrapped);
+        return Regex.Regex.compile(allocator, wrapped, options);
+    }
+
+    /// Build replacement text for the match starting at byte offset
+    /// In regex mode, `$1`-style references expand to the match's capture groups.
+    pub fn expandReplacement(
+        self: *const Search,
+        allocator: std.mem.Allocator,
+        text: []const u8,
+        offset: usize,
+    ) ![]u8 {
+        if (!self.options.regex) return allocator.dupe(u8, self.getReplaceText());
+
+        var re = try self.compileRegex(allocator);
+        defer re.deinit();
+        return self.expandReplacementWith(allocator, &re, text, offset);
+    }
+
+    /// expandReplacement with the query already compiled (null outside regex mode),
+    /// for replacing many matches without compiling the pattern for each
+    pub fn expandReplacementWith(
+        self: *const Search,
+        allocator: std.mem.Allocator,
+        re: ?*const Regex.Regex,
+        text: []const u8,
+        offset: usize,
+    ) ![]u8 {
+        const template = self.getReplaceText();
+        const compiled = re orelse return allocator.dupe(u8, template);
+
+        var caps = Regex.Captures{};
+        if (!try compiled.matchAt(text, offset, &caps)) return allocator.dupe(u8, template);
+        return Regex.expandReplacement(allocator, template, text, &caps);
+    }
+
+    fn findNextRegex(self: *Search, text: []const u8, start_pos: Cursor.Position) ?Match {
+        // Incomplete patterns are common while typing; treat them as no match
+        var re = self.compileRegex(self.allocator) catch return null;
+        defer re.deinit();
+
+        var caps = Regex.Captures{};
+        const start = positionToOffset(text, start_pos);
+        const span = (re.find(text, start, &caps) catch |err| {
+            if (err == error.MatchLimitExceeded) self.match_limit_hit = true;
+            return null;
+        }) orelse return null;
+
+        var tracker = PositionTracker{ .text = text };
+        const match = Match{
+            .start = tracker.advanceTo(span.start),
+            .end = tracker.advanceTo(span.end),
+        };
+        self.current_match = match;
+        return match;
+    }
+
+    fn findPreviousRegex(self: *Search, text: []const u8, start_pos: Cursor.Position) ?Match {
+        var re = self.compileRegex(self.allocator) catch return null;
+        defer re.deinit();
+
+        const limit = positionToOffset(text, start_pos);
+        var caps = Regex.Captures{};
+        var last: ?Regex.Span = null;
+        var offset: usize = 0;
+
+        // Keep the last match starting before the cursor
+        while (offset < limit) {
+            const span = (re.find(text, offset, &caps) catch |err| {
+                if (err == error.MatchLimitExceeded) self.match_limit_hit = true;
+                break;
+            }) orelse break;
+            if (span.start >= limit) break;
+            last = span;
+            offset = span.start + 1;
+        }
+
+        const span = last orelse return null;
+        var tracker = PositionTracker{ .text = text };
+        const match = Match{
+            .start = tracker.advanceTo(span.start),
+            .end = tracker.advanceTo(span.end),
+        };
+        self.current_match = match;
+        return match;
+    }
+
+    fn findAllRegex(self: *Search, text: []const u8, allocator: std.mem.Allocator) ![]Match {
+        var re = self.compileRegex(allocator) catch |err| switch (err) {
+            error.OutOfMemory => return err,
+            else => return &[_]Match{},
+        };
+        defer re.deinit();
+
+        var matches = std.ArrayList(Match).empty;
+        errdefer matches.deinit(allocator);
+
+        var tracker = PositionTracker{ .text = text };
+        var caps = Regex.Captures{};
+        var offset: usize = 0;
+
+        while (offset <= text.len) {
+            const span = (re.find(text, offset, &caps) catch |err| switch (err) {
+                error.OutOfMemory => return err,
+                error.MatchLimitExceeded => {
+                    self.match_limit_hit = true;
+                    break;
+                },
+            }) orelse break;
+
+            try matches.append(allocator, Match{
+                .start = tracker.advanceTo(span.start),
+                .end = tracker.advanceTo(span.end),
+            });
+
+            // Step past empty matches so the scan always makes progress
+            offset = if (span.len() == 0) span.end + 1 else span.end;
+        }
+
+        return matches.toOwnedSlice(allocator);
+    }
+
     /// Find next match in text starting from position
     pub fn findNext(
         self: *Search,
@@ -251,6 +436,7 @@ pub const Search = struct {
         start_pos: Cursor.Position,
     ) ?Match {
         if (self.query_len == 0) return null;
+        if (self.options.regex) return self.findNextRegex(text, start_pos);
 
         const query = self.getQuery();
 
@@ -323,6 +509,7 @@ pub const Search = struct {
         start_pos: Cursor.Position,
     ) ?Match {
         if (self.query_len == 0) return null;
+        if (self.options.regex) return self.findPreviousRegex(text, start_pos);
 
         const query = self.getQuery();
 
@@ -390,13 +577,249 @@ pub const Search = struct {
         return null;
     }
 
+    // === Rope Search ===
+    // Buffers are searched in place; copying them out for every search would
+    // cost a copy of the whole file, which large files are mapped to avoid.
+    // A regex runs over one line at a time, so its matches don't span lines.
+
+    /// Find next match in a rope starting from position
+    pub fn findNextInRope(self: *Search, rope: *const Rope, start_pos: Cursor.Position) ?Match {
+        if (self.query_len == 0) return null;
+        if (self.options.regex) return self.findNextRegexInRope(rope, start_pos);
+
+        var offset = ropeOffset(rope, start_pos);
+        var cursor = rope.cursorAt(offset);
+        while (offset + self.query_len <= rope.len()) : (offset += 1) {
+            const c = cursor.nextByte() orelse break;
+            if (self.firstByteMatches(c) and self.matchesInRope(rope, offset)) return self.ropeMatch(rope, offset);
+        }
+        return null;
+    }
+
+    /// Find previous match in a rope (search backwards)
+    pub fn findPreviousInRope(self: *Search, rope: *const Rope, start_pos: Cursor.Position) ?Match {
+        if (self.query_len == 0) return null;
+        if (self.options.regex) return self.findPreviousRegexInRope(rope, start_pos);
+
+        var offset = ropeOffset(rope, start_pos);
+        var cursor = rope.cursorAt(offset);
+        while (offset > 0) {
+            const c = cursor.prevByte() orelse break;
+            offset -= 1;
+            if (self.firstByteMatches(c) and self.matchesInRope(rope, offset)) return self.ropeMatch(rope, offset);
+        }
+        return null;
+    }
+
+    fn findNextRegexInRope(self: *Search, rope: *const Rope, start_pos: Cursor.Position) ?Match {
+        // Incomplete patterns are common while typing; treat them as no match
+        var re = self.compileRegex(self.allocator) catch return null;
+        defer re.deinit();
+
+        var caps = Regex.Captures{};
+        var lines = rope.lines(start_pos.line);
+        while (lines.next()) |line| {
+            const text = self.lineText(line) catch return null;
+            const from = if (line.number == start_pos.line) start_pos.col else 0;
+            if (from > text.len) continue;
+            const span = (re.find(text, from, &caps) catch |err| {
+                if (err == error.MatchLimitExceeded) self.match_limit_hit = true;
+                return null;
+            }) orelse continue;
+            return self.lineMatch(line.number, span);
+        }
+        return null;
+    }
+
+    fn findPreviousRegexInRope(self: *Search, rope: *const Rope, start_pos: Cursor.Position) ?Match {
+        var re = self.compileRegex(self.allocator) catch return null;
+        defer re.deinit();
+
+        var caps = Regex.Captures{};
+        var number = @min(start_pos.line, rope.lineCount() - 1) + 1;
+        while (number > 0) {
+            number -= 1;
+            const text = self.lineText(rope.lineAt(number)) catch return null;
+            const limit = if (number == start_pos.line) @min(start_pos.col, text.len + 1) else text.len + 1;
+
+            // Keep the last match starting before the limit
+            var last: ?Regex.Span = null;
+            var offset: usize = 0;
+            while (offset < limit) {
+                const span = (re.find(text, offset, &caps) catch |err| {
+                    if (err == error.MatchLimitExceeded) self.match_limit_hit = true;
+                    break;
+                }) orelse break;
+                if (span.start >= limit) break;
+                last = span;
+                offset = span.start + 1;
+            }
+            if (last) |span| return self.lineMatch(number, span);
+        }
+        return null;
+    }
+
+    /// A line's text, borrowed from its leaf or gathered into line_scratch when it
+    /// spans several; valid until the next call
+    pub fn lineText(self: *Search, line: Rope.Line) ![]const u8 {
+        var pieces = line.chunks();
+        const first = pieces.next() orelse return "";
+        if (first.len == line.end - line.start) return first;
+
+        self.line_scratch.clearRetainingCapacity();
+        try self.line_scratch.appendSlice(self.allocator, first);
+        while (pieces.next()) |piece| try self.line_scratch.appendSlice(self.allocator, piece);
+        return self.line_scratch.items;
+    }
+
+    fn lineMatch(self: *Search, line: usize, span: Regex.Span) Match {
+        const match = Match{
+            .start = .{ .line = line, .col = span.start },
+            .end = .{ .line = line, .col = span.end },
+        };
+        self.current_match = match;
+        return match;
+    }
+
+    /// Byte offset of a position in a rope, with the same clamping as positionToOffset
+    fn ropeOffset(rope: *const Rope, pos: Cursor.Position) usize {
+        return @min(rope.lineToByte(pos.line) + pos.col, rope.lineToByte(pos.line + 1));
+    }
+
+    fn firstByteMatches(self: *const Search, c: u8) bool {
+        const first = self.query[0];
+        return if (self.options.case_sensitive) c == first else std.ascii.toLower(c) == std.ascii.toLower(first);
+    }
+
+    /// Rope version of matchesAt
+    fn matchesInRope(self: *const Search, rope: *const Rope, offset: usize) bool {
+        const query = self.getQuery();
+        if (offset + query.len > rope.len()) return false;
+
+        var cursor = rope.cursorAt(offset);
+        const before = cursor.prevByte();
+        if (before != null) _ = cursor.nextByte();
+        for (query) |q| {
+            const c = cursor.nextByte() orelse return false;
+            const same = if (self.options.case_sensitive) c == q else std.ascii.toLower(c) == std.ascii.toLower(q);
+            if (!same) return false;
+        }
+
+        if (self.options.whole_word) {
+            if (before) |c| if (!isWordBoundary(c)) return false;
+            if (cursor.peekByte()) |c| if (!isWordBoundary(c)) return false;
+        }
+        return true;
+    }
+
+    fn ropeMatch(self: *Search, rope: *const Rope, offset: usize) Match {
+        const match = self.ropeSpan(rope, offset);
+        self.current_match = match;
+        return match;
+    }
+
+    /// Positions of the literal match at `offset`
+    fn ropeSpan(self: *const Search, rope: *const Rope, offset: usize) Match {
+        const line = rope.byteToLine(offset);
+        const start = Cursor.Position{ .line = line, .col = offset - rope.lineToByte(line) };
+
+        var end = start;
+        for (self.getQuery()) |c| {
+            if (c == '\n') {
+                end.line += 1;
+                end.col = 0;
+            } else {
+                end.col += 1;
+            }
+        }
+
+        return .{ .start = start, .end = end };
+    }
+
+    /// Matches starting on lines `first_line..end_line` of a rope (highlighting, replace-all)
+    /// Literal queries are matched through a rope cursor; a regex runs line by line.
+    pub fn findAllInRope(
+        self: *Search,
+        rope: *const Rope,
+        first_line: usize,
+        end_line: usize,
+        allocator: std.mem.Allocator,
+    ) ![]Match {
+        if (self.query_len == 0) return &[_]Match{};
+        if (self.options.regex) return self.findAllRegexInRope(rope, first_line, end_line, allocator);
+
+        const start = rope.lineToByte(first_line);
+        const end = rope.lineToByte(end_line);
+
+        var matches = std.ArrayList(Match).empty;
+        errdefer matches.deinit(allocator);
+
+        var offset = start;
+        var cursor = rope.cursorAt(offset);
+        while (offset < end and offset + self.query_len <= rope.len()) {
+            const c = cursor.nextByte() orelse break;
+            if (self.firstByteMatches(c) and self.matchesInRope(rope, offset)) {
+                try matches.append(allocator, self.ropeSpan(rope, offset));
+                // Skip past this match
+                offset += self.query_len;
+                cursor.seek(offset);
+                continue;
+            }
+            offset += 1;
+        }
+        return matches.toOwnedSlice(allocator);
+    }
+
+    fn findAllRegexInRope(
+        self: *Search,
+        rope: *const Rope,
+        first_line: usize,
+        end_line: usize,
+        allocator: std.mem.Allocator,
+    ) ![]Match {
+        var re = self.compileRegex(allocator) catch |err| switch (err) {
+            error.OutOfMemory => return err,
+            else => return &[_]Match{},
+        };
+        defer re.deinit();
+
+        var matches = std.ArrayList(Match).empty;
+        errdefer matches.deinit(allocator);
+
+        var caps = Regex.Captures{};
+        var lines = rope.lines(first_line);
+        outer: while (lines.next()) |line| {
+            if (line.number >= end_line) break;
+            const text = try self.lineText(line);
+            var offset: usize = 0;
+            while (offset <= text.len) {
+                const span = (re.find(text, offset, &caps) catch |err| switch (err) {
+                    error.OutOfMemory => return err,
+                    error.MatchLimitExceeded => {
+                        self.match_limit_hit = true;
+                        break :outer;
+                    },
+                }) orelse break;
+                try matches.append(allocator, .{
+                    .start = .{ .line = line.number, .col = span.start },
+                    .end = .{ .line = line.number, .col = span.end },
+                });
+
+                // Step past empty matches so the scan always makes progress
+                offset = if (span.len() == 0) span.end + 1 else span.end;
+            }
+        }
+        return matches.toOwnedSlice(allocator);
+    }
+
     /// Find all matches in text for highlighting
     pub fn findAll(
-        self: *const Search,
+        self: *Search,
         text: []const u8,
         allocator: std.mem.Allocator,
     ) ![]Match {
         if (self.query_len == 0) return &[_]Match{};
+        if (self.options.regex) return self.findAllRegex(text, allocator);
 
         const query = self.getQuery();
         var matches = std.ArrayList(Match).empty;
@@ -459,6 +882,11 @@ pub const Search = struct {
         if (self.query_len == 0) {
             return &[_]Match{};
         }
+        if (self.options.regex) {
+            const matches = try self.findAllRegex(text, allocator);
+            self.match_count = matches.len;
+            return matches;
+        }
 
         const query = self.getQuery();
         var matches = std.ArrayList(Match).empty;
@@ -548,3 +976,87 @@ test "search: no match" {
 
     try std.testing.expect(match == null);
 }
+
+test "search: find in rope" {
+    const allocator = std.testing.allocator;
+    var search = Search.initWithOptions(allocator, .{ .case_sensitive = false, .whole_word = true });
+    defer search.deinit();
+
+    var rope = try Rope.initFromString(allocator, "Error: disk\nerrors: 2\nan ERROR here\n");
+    defer rope.deinit();
+
+    try search.setQuery("error");
+    const first = search.findNextInRope(&rope, .{ .line = 0, .col = 1 }).?;
+    try std.testing.expectEqual(@as(usize, 2), first.start.line); // "errors" isn't a whole word
+    try std.testing.expectEqual(@as(usize, 3), first.start.col);
+    try std.testing.expectEqual(@as(usize, 8), first.end.col);
+
+    const prev = search.findPreviousInRope(&rope, first.start).?;
+    try std.testing.expectEqual(@as(usize, 0), prev.start.line);
+    try std.testing.expectEqual(@as(usize, 0), prev.start.col);
+    try std.testing.expect(search.findPreviousInRope(&rope, prev.start) == null);
+
+    // Only matches on the lines asked for, positioned in the whole rope
+    const visible = try search.findAllInRope(&rope, 1, 3, allocator);
+    defer allocator.free(visible);
+    try std.testing.expectEqual(@as(usize, 1), visible.len);
+    try std.testing.expectEqual(@as(usize, 2), visible[0].start.line);
+
+    search.options.regex = true;
+    try search.setQuery("e\\w+s");
+    const regex_visible = try search.findAllInRope(&rope, 1, 2, allocator);
+    defer allocator.free(regex_visible);
+    try std.testing.expectEqual(@as(usize, 1), regex_visible.len);
+    try std.testing.expectEqual(@as(usize, 1), regex_visible[0].start.line);
+
+    // A regex is matched line by line too
+    const regex_next = search.findNextInRope(&rope, .{ .line = 0, .col = 0 }).?;
+    try std.testing.expectEqual(Cursor.Position{ .line = 1, .col = 0 }, regex_next.start);
+    try std.testing.expectEqual(Cursor.Position{ .line = 1, .col = 6 }, regex_next.end);
+    try std.testing.expectEqual(regex_next, search.findPreviousInRope(&rope, .{ .line = 2, .col = 0 }).?);
+    try std.testing.expect(search.findPreviousInRope(&rope, regex_next.start) == null);
+}
+
+test "search: regex find and replacement" {
+    const allocator = std.testing.allocator;
+    var search = Search.initWithOptions(allocator, .{ .regex = true });
+    defer search.deinit();
+
+    try search.setQuery("fn (\\w+)\\(");
+    try search.setReplaceText("fn new_$1(");
+
+    const text = "const x = 1;\npub fn main() void {}\nfn helper() void {}";
+    const match = search.findNext(text, .{ .line = 0, .col = 0 });
+    try std.testing.expect(match != null);
+    try std.testing.expectEqual(@as(usize, 1), match.?.start.line);
+    try std.testing.expectEqual(@as(usize, 4), match.?.start.col);
+    try std.testing.expectEqual(@as(usize, 12), match.?.end.col);
+
+    const all = try search.findAllMatches(text, allocator);
+    defer allocator.free(all);
+    try std.testing.expectEqual(@as(usize, 2), all.len);
+    try std.testing.expectEqual(@as(usize, 2), all[1].start.line);
+
+    const prev = search.findPrevious(text, .{ .line = 2, .col = 0 });
+    try std.testing.expectEqual(@as(usize, 1), prev.?.start.line);
+
+    const replacement = try search.expandReplacement(allocator, text, 17);
+    defer allocator.free(replacement);
+    try std.testing.expectEqualStrings("fn new_main(", replacement);
+}
+
+test "search: regex that backtracks too much is reported, not a miss" {
+    const allocator = std.testing.allocator;
+    var search = Search.initWithOptions(allocator, .{ .regex = true });
+    defer search.deinit();
+
+    try search.setQuery("(a|a)*b");
+    const text = "a" ** 40;
+    try std.testing.expect(search.findNext(text, .{ .line = 0, .col = 0 }) == null);
+    try std.testing.expect(search.takeMatchLimitHit());
+    try std.testing.expect(!search.takeMatchLimitHit());
+
+    try search.setQuery("a+b");
+    try std.testing.expect(search.findNext("aab", .{ .line = 0, .col = 0 }) != null);
+    try std.testing.expect(!search.takeMatchLimitHit());
+}
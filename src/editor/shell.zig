@@ -0,0 +1,133 @@
+//! External shell commands for filters and pipes
+//! Runs `sh -c <command>`, feeding it text on stdin and collecting what it
+//! prints. Input is written from a separate thread: a filter like `sort` or
+//! `jq` can fill the stdout pipe before it has read all its input, and writing
+//! everything first would deadlock against it.
+
+const std = @import("std");
+
+/// Limit on each of stdout and stderr
+pub const MAX_OUTPUT = 64 * 1024 * 1024;
+
+/// How a selection pipe uses the command's output
+pub const PipeMode = enum {
+    replace, // `|`: selection text is the input, output replaces it
+    insert, // `!`: output goes before the selection
+    append, // `Alt-!`: output goes after the selection
+
+    /// Prompt shown while the command is typed
+    pub fn prompt(self: PipeMode) []const u8 {
+        return switch (self) {
+            .replace => "pipe: ",
+            .insert => "insert output: ",
+            .append => "append output: ",
+        };
+    }
+};
+
+/// Result of a finished command
+pub const Output = struct {
+    stdout: []u8,
+    stderr: []u8,
+    status: u8, // Exit status; 128 + signal number if a signal killed it
+
+    pub fn deinit(self: *Output, allocator: std.mem.Allocator) void {
+        allocator.free(self.stdout);
+        allocator.free(self.stderr);
+    }
+
+    pub fn succeeded(self: *const Output) bool {
+        return self.status == 0;
+    }
+
+    /// Message for a failed command: its status and the first line of stderr
+    pub fn failureMessage(self: *const Output, allocator: std.mem.Allocator, command: []const u8) ![]u8 {
+        const detail = firstLine(self.stderr);
+        if (detail.len == 0) return std.fmt.allocPrint(allocator, "{s}: exited with status {d}", .{ command, self.status });
+        return std.fmt.allocPrint(allocator, "{s}: exited with status {d}: {s}", .{ command, self.status, detail });
+    }
+};
+
+/// Run `command` through the shell with `input` on stdin (null: no stdin)
+/// A command that fails still returns its output; check `succeeded()`.
+pub fn run(allocator: std.mem.Allocator, command: []const u8, input: ?[]const u8) !Output {
+    var child = std.process.Child.init(&[_][]const u8{ "/bin/sh", "-c", command }, allocator);
+    child.stdin_behavior = if (input != null) .Pipe else .Ignore;
+    child.stdout_behavior = .Pipe;
+    child.stderr_behavior = .Pipe;
+    try child.spawn();
+
+    var feeder: ?std.Thread = null;
+    if (input) |bytes| {
+        const stdin = child.stdin.?;
+        child.stdin = null; // The feeder closes it once everything is written
+        feeder = std.Thread.spawn(.{}, feed, .{ stdin, bytes }) catch |err| {
+            stdin.close();
+            _ = child.kill() catch {};
+            return err;
+        };
+    }
+
+    var stdout = std.ArrayList(u8).empty;
+    defer stdout.deinit(allocator);
+    var stderr = std.ArrayList(u8).empty;
+    defer stderr.deinit(allocator);
+    const collected = child.collectOutput(allocator, &stdout, &stderr, MAX_OUTPUT);
+
+    // Too much output: stop the command so the feeder sees a broken pipe
+    if (collected) |_| {} else |_| _ = child.kill() catch {};
+    if (feeder) |thread| thread.join();
+    try collected;
+
+    const term = try child.wait();
+    const status: u8 = switch (term) {
+        .Exited => |code| code,
+        .Signal => |sig| @intCast(@min(128 + sig, 255)),
+        else => 255,
+    };
+
+    const out = try stdout.toOwnedSlice(allocator);
+    errdefer allocator.free(out);
+    return .{
+        .stdout = out,
+        .stderr = try stderr.toOwnedSlice(allocator),
+        .status = status,
+    };
+}
+
+/// Write all of `bytes` to a command's stdin, then close it
+/// Commands that exit without reading (like `date`) break the pipe; that's fine.
+fn feed(stdin: std.fs.File, bytes: []const u8) void {
+    defer stdin.close();
+    stdin.writeAll(bytes) catch {};
+}
+
+/// Text up to the first newline, with surrounding whitespace trimmed
+pub fn firstLine(text: []const u8) []const u8 {
+    const trimmed = std.mem.trim(u8, text, &std.ascii.whitespace);
+    const end = std.mem.indexOfScalar(u8, trimmed, '\n') orelse trimmed.len;
+    return std.mem.trimRight(u8, trimmed[0..end], "\r");
+}
+
+// === Tests ===
+
+test "shell: filters input and reports failures" {
+    const allocator = std.testing.allocator;
+
+    var sorted = try run(allocator, "sort", "b\nc\na\n");
+    defer sorted.deinit(allocator);
+    try std.testing.expect(sorted.succeeded());
+    try std.testing.expectEqualStrings("a\nb\nc\n", sorted.stdout);
+
+    // Output that doesn't wait for the input
+    var ignored = try run(allocator, "echo hi", "unread");
+    defer ignored.deinit(allocator);
+    try std.testing.expectEqualStrings("hi\n", ignored.stdout);
+
+    var failed = try run(allocator, "echo oops >&2; exit 3", null);
+    defer failed.deinit(allocator);
+    try std.testing.expectEqual(@as(u8, 3), failed.status);
+    const msg = try failed.failureMessage(allocator, "check");
+    defer allocator.free(msg);
+    try std.testing.expectEqualStrings("check: exited with status 3: oops", msg);
+}
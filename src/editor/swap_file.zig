@@ -0,0 +1,298 @@
+//! Swap files for crash recovery
+//! Each open file has a swap file in ~/.aesop/swap/ owned by the editor process
+//! editing it. While the buffer has unsaved changes the swap file holds its full
+//! text; otherwise it only marks the file as in use.
+//!
+//! A swap file left behind by a process that no longer exists means aesop (or
+//! its terminal) died with unsaved edits, which can then be recovered. One whose
+//! process is still alive means another instance is editing the same file.
+
+const std = @import("std");
+const builtin = @import("builtin");
+
+const MAGIC = "AESOPSWP";
+const VERSION: u32 = 1;
+
+/// Largest swap file we are willing to read
+const MAX_FILE_SIZE = 256 * 1024 * 1024;
+
+pub const DecodeError = error{
+    InvalidSwapFile,
+    VersionMismatch,
+    OutOfMemory,
+};
+
+/// Contents of a swap file
+pub const Swap = struct {
+    pid: i32, // Process that wrote it
+    timestamp: i64, // When it was written (ms)
+    path: []const u8, // Absolute path of the file being edited
+    base_hash: u64, // Hash of the on-disk content the edits started from
+    text: ?[]const u8, // Unsaved buffer text, null if the buffer was unmodified
+
+    /// Free strings of a swap returned by read()
+    pub fn deinit(self: *Swap, allocator: std.mem.Allocator) void {
+        allocator.free(self.path);
+        if (self.text) |text| allocator.free(text);
+    }
+
+    /// Check whether the process that wrote this swap file is still running
+    pub fn ownerAlive(self: *const Swap) bool {
+        return processAlive(self.pid);
+    }
+};
+
+/// Hash used to tie a swap file to the on-disk content
+pub fn contentHash(text: []const u8) u64 {
+    return std.hash.Wyhash.hash(0, text);
+}
+
+/// Our process id, as recorded in swap files
+pub fn currentPid() i32 {
+    // libc isn't always linked on Linux; elsewhere it always is
+    return if (builtin.os.tag == .linux) std.os.linux.getpid() else @intCast(std.c.getpid());
+}
+
+/// Check whether a process exists (signal 0 probes without delivering anything)
+fn processAlive(pid: i32) bool {
+    if (pid <= 0) return false;
+    std.posix.kill(pid, 0) catch |err| return err == error.PermissionDenied;
+    return true;
+}
+
+/// Open (creating if needed) ~/.aesop/swap
+pub fn openSwapDir() !std.fs.Dir {
+    const home = std.posix.getenv("HOME") orelse return error.NoHomeDirectory;
+    var home_dir = try std.fs.openDirAbsolute(home, .{});
+    defer home_dir.close();
+
+    try home_dir.makePath(".aesop/swap");
+    return home_dir.openDir(".aesop/swap", .{ .iterate = true });
+}
+
+/// Swap file name for an absolute path
+fn fileName(buf: *[32]u8, abs_path: []const u8) []const u8 {
+    const hash = std.hash.Wyhash.hash(0, abs_path);
+    return std.fmt.bufPrint(buf, "{x:0>16}.swp", .{hash}) catch unreachable;
+}
+
+/// Write the swap file for `swap.path`
+pub fn write(dir: std.fs.Dir, allocator: std.mem.Allocator, swap: Swap) !void {
+    var name_buf: [32]u8 = undefined;
+    const name = fileName(&name_buf, swap.path);
+
+    const data = try encode(allocator, swap);
+    defer allocator.free(data);
+
+    // Temp file + rename: a crash mid-write must not destroy the previous swap
+    var tmp_buf: [40]u8 = undefined;
+    const tmp_name = try std.fmt.bufPrint(&tmp_buf, "{s}.tmp", .{name});
+    try dir.writeFile(.{ .sub_path = tmp_name, .data = data });
+    errdefer dir.deleteFile(tmp_name) catch {};
+    try dir.rename(tmp_name, name);
+}
+
+/// Read the swap file for `abs_path`, if any
+/// Corrupt files and files recording a different path are deleted and null is returned.
+pub fn read(dir: std.fs.Dir, allocator: std.mem.Allocator, abs_path: []const u8) !?Swap {
+    var name_buf: [32]u8 = undefined;
+    const name = fileName(&name_buf, abs_path);
+
+    var swap = (try readFile(dir, allocator, name)) orelse return null;
+    if (!std.mem.eql(u8, swap.path, abs_path)) {
+        swap.deinit(allocator);
+        dir.deleteFile(name) catch {};
+        return null;
+    }
+    return swap;
+}
+
+fn readFile(dir: std.fs.Dir, allocator: std.mem.Allocator, name: []const u8) !?Swap {
+    const data = dir.readFileAlloc(allocator, name, MAX_FILE_SIZE) catch |err| switch (err) {
+        error.FileNotFound => return null,
+        error.OutOfMemory => return err,
+        else => {
+            dir.deleteFile(name) catch {};
+            return null;
+        },
+    };
+    defer allocator.free(data);
+
+    return decode(allocator, data) catch |err| switch (err) {
+        error.OutOfMemory => return err,
+        else => {
+            dir.deleteFile(name) catch {};
+            return null;
+        },
+    };
+}
+
+/// Delete the swap file for `abs_path`
+pub fn remove(dir: std.fs.Dir, abs_path: []const u8) void {
+    var name_buf: [32]u8 = undefined;
+    dir.deleteFile(fileName(&name_buf, abs_path)) catch {};
+}
+
+/// Paths with unsaved changes left behind by processes that have exited
+/// Caller frees each path and the slice.
+pub fn listOrphans(dir: std.fs.Dir, allocator: std.mem.Allocator) ![][]const u8 {
+    var paths = std.ArrayList([]const u8).empty;
+    errdefer {
+        for (paths.items) |path| allocator.free(path);
+        paths.deinit(allocator);
+    }
+
+    var it = dir.iterate();
+    while (try it.next()) |entry| {
+        if (entry.kind != .file or !std.mem.endsWith(u8, entry.name, ".swp")) continue;
+
+        var swap = (try readFile(dir, allocator, entry.name)) orelse continue;
+        defer swap.deinit(allocator);
+
+        if (swap.text == null or swap.ownerAlive()) continue;
+        try paths.append(allocator, try allocator.dupe(u8, swap.path));
+    }
+
+    return paths.toOwnedSlice(allocator);
+}
+
+// === Encoding ===
+
+/// Serialize a swap into the on-disk format
+pub fn encode(allocator: std.mem.Allocator, swap: Swap) ![]u8 {
+    var bytes = std.ArrayList(u8).empty;
+    errdefer bytes.deinit(allocator);
+
+    var buf: [8]u8 = undefined;
+    try bytes.appendSlice(allocator, MAGIC);
+    std.mem.writeInt(u32, buf[0..4], VERSION, .little);
+    try bytes.appendSlice(allocator, buf[0..4]);
+    std.mem.writeInt(i32, buf[0..4], swap.pid, .little);
+    try bytes.appendSlice(allocator, buf[0..4]);
+    std.mem.writeInt(i64, &buf, swap.timestamp, .little);
+    try bytes.appendSlice(allocator, &buf);
+    std.mem.writeInt(u64, &buf, swap.base_hash, .little);
+    try bytes.appendSlice(allocator, &buf);
+    std.mem.writeInt(u32, buf[0..4], @intCast(swap.path.len), .little);
+    try bytes.appendSlice(allocator, buf[0..4]);
+    try bytes.appendSlice(allocator, swap.path);
+
+    // Text runs to the end of the file, so a torn write shows up as a bad length
+    try bytes.append(allocator, if (swap.text != null) 1 else 0);
+    if (swap.text) |text| {
+        std.mem.writeInt(u64, &buf, text.len, .little);
+        try bytes.appendSlice(allocator, &buf);
+        try bytes.appendSlice(allocator, text);
+    }
+
+    return bytes.toOwnedSlice(allocator);
+}
+
+const Reader = struct {
+    data: []const u8,
+    pos: usize = 0,
+
+    fn bytes(self: *Reader, n: u64) DecodeError![]const u8 {
+        if (n > self.data.len - self.pos) return error.InvalidSwapFile;
+        const out = self.data[self.pos .. self.pos + @as(usize, @intCast(n))];
+        self.pos += out.len;
+        return out;
+    }
+
+    fn int(self: *Reader, comptime T: type) DecodeError!T {
+        const raw = try self.bytes(@sizeOf(T));
+        return std.mem.readInt(T, raw[0..@sizeOf(T)], .little);
+    }
+};
+
+/// Parse and validate a swap file
+pub fn decode(allocator: std.mem.Allocator, data: []const u8) DecodeError!Swap {
+    var r = Reader{ .data = data };
+
+    if (!std.mem.eql(u8, try r.bytes(MAGIC.len), MAGIC)) return error.InvalidSwapFile;
+    if (try r.int(u32) != VERSION) return error.VersionMismatch;
+
+    const pid = try r.int(i32);
+    const timestamp = try r.int(i64);
+    const base_hash = try r.int(u64);
+    const path = try r.bytes(try r.int(u32));
+
+    const text: ?[]const u8 = switch (try r.int(u8)) {
+        0 => null,
+        1 => try r.bytes(try r.int(u64)),
+        else => return error.InvalidSwapFile,
+    };
+    if (r.pos != data.len) return error.InvalidSwapFile;
+
+    const owned_path = try allocator.dupe(u8, path);
+    errdefer allocator.free(owned_path);
+    const owned_text = if (text) |t| try allocator.dupe(u8, t) else null;
+
+    return .{
+        .pid = pid,
+        .timestamp = timestamp,
+        .path = owned_path,
+        .base_hash = base_hash,
+        .text = owned_text,
+    };
+}
+
+// === Tests ===
+
+test "swap file: round trip and orphan detection" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{ .iterate = true });
+    defer tmp.cleanup();
+
+    // Ours: alive, so never reported as an orphan
+    try write(tmp.dir, allocator, .{
+        .pid = currentPid(),
+        .timestamp = 1000,
+        .path = "/project/live.zig",
+        .base_hash = contentHash("old"),
+        .text = "new",
+    });
+
+    // A process id that can't exist stands in for a crashed editor
+    try write(tmp.dir, allocator, .{
+        .pid = std.math.maxInt(i32),
+        .timestamp = 2000,
+        .path = "/project/crashed.zig",
+        .base_hash = contentHash("disk"),
+        .text = "unsaved",
+    });
+
+    var swap = (try read(tmp.dir, allocator, "/project/crashed.zig")).?;
+    defer swap.deinit(allocator);
+    try std.testing.expectEqualStrings("unsaved", swap.text.?);
+    try std.testing.expectEqual(contentHash("disk"), swap.base_hash);
+    try std.testing.expect(!swap.ownerAlive());
+
+    const orphans = try listOrphans(tmp.dir, allocator);
+    defer {
+        for (orphans) |path| allocator.free(path);
+        allocator.free(orphans);
+    }
+    try std.testing.expectEqual(@as(usize, 1), orphans.len);
+    try std.testing.expectEqualStrings("/project/crashed.zig", orphans[0]);
+
+    remove(tmp.dir, "/project/crashed.zig");
+    try std.testing.expect((try read(tmp.dir, allocator, "/project/crashed.zig")) == null);
+}
+
+test "swap file: rejects truncated data" {
+    const allocator = std.testing.allocator;
+    const data = try encode(allocator, .{
+        .pid = 1,
+        .timestamp = 0,
+        .path = "/f",
+        .base_hash = 0,
+        .text = "hello",
+    });
+    defer allocator.free(data);
+
+    try std.testing.expectError(error.InvalidSwapFile, decode(allocator, data[0 .. data.len - 2]));
+    var swap = try decode(allocator, data);
+    defer swap.deinit(allocator);
+    try std.testing.expectEqualStrings("hello", swap.text.?);
+}
@@ -1,9 +1,11 @@
 //! Enhanced text object implementations
-//! Paragraph, indent level, line, and buffer text objects
+//! Paragraph, indent level, line, and buffer text objects, plus the syntax
+//! objects (function, class, argument, comment) that tree-sitter queries find
 
 const std = @import("std");
 const Cursor = @import("cursor.zig");
 const Buffer = @import("../buffer/manager.zig");
+const Rope = @import("../buffer/rope.zig").Rope;
 
 /// Text object result - range of positions
 pub const Range = struct {
@@ -281,6 +283,287 @@ pub fn selectBufferInside(
     return selectBufferAround(buffer, allocator);
 }
 
+// === Syntax Text Objects ===
+
+/// Byte range of a syntax node
+pub const ByteRange = struct {
+    start: usize,
+    end: usize,
+
+    pub fn len(self: ByteRange) usize {
+        return self.end - self.start;
+    }
+
+    /// Smallest range covering both, for a capture that spans several nodes
+    pub fn merge(self: ?ByteRange, other: ByteRange) ByteRange {
+        const range = self orelse return other;
+        return .{ .start = @min(range.start, other.start), .end = @max(range.end, other.end) };
+    }
+};
+
+/// Kinds of object a `textobjects.scm` query captures, as `@<kind>.around` and `@<kind>.inside`
+pub const SyntaxKind = enum {
+    function, // af / if
+    class, // ac / ic: classes, structs, enums and other type definitions
+    parameter, // aa / ia: parameters and call arguments
+    comment, // a/ / i/
+
+    /// Kind and part named by a capture such as `function.around` or `parameter.inside`
+    pub fn fromCapture(name: []const u8) ?struct { kind: SyntaxKind, around: bool } {
+        const dot = std.mem.indexOfScalar(u8, name, '.') orelse return null;
+        const kind = std.meta.stringToEnum(SyntaxKind, name[0..dot]) orelse return null;
+        const part = name[dot + 1 ..];
+        if (std.mem.eql(u8, part, "around")) return .{ .kind = kind, .around = true };
+        if (std.mem.eql(u8, part, "inside")) return .{ .kind = kind, .around = false };
+        return null;
+    }
+};
+
+/// One match of a textobjects query: the node `a` takes, the node `i` takes, or both
+pub const SyntaxObject = struct {
+    around: ?ByteRange = null,
+    inside: ?ByteRange = null,
+
+    /// The whole object, which the cursor must be in
+    fn extent(self: SyntaxObject) ByteRange {
+        return self.around orelse self.inside.?;
+    }
+
+    fn part(self: SyntaxObject, around: bool) ?ByteRange {
+        return if (around) self.around else self.inside;
+    }
+};
+
+/// Get a syntax text object (`af`, `ic`, `aa`, `i/`...) from the objects a query found
+/// Takes the innermost object holding the selection whose range isn't the
+/// selection already, so repeating it reaches the one enclosing it.
+/// - `a` takes whole lines when the object has its lines to itself, and an
+///   argument takes the comma and space after it (or before it, for the last one)
+/// - `i` takes what is inside a body's braces, or a comment without its markers
+pub fn selectSyntaxObject(
+    buffer: *const Buffer.Buffer,
+    objects: []const SyntaxObject,
+    kind: SyntaxKind,
+    selection: Range,
+    around: bool,
+    allocator: std.mem.Allocator,
+) !?Range {
+    _ = allocator;
+    var text = Bytes.init(&buffer.rope);
+    const start = text.offsetOf(selection.start);
+    const end = text.offsetOf(selection.end);
+
+    // Queries that capture only one part (`@parameter.inside`) serve for both
+    var has_part = false;
+    for (objects) |object| {
+        if (object.part(around) != null) has_part = true;
+    }
+
+    var best: ?ByteRange = null;
+    var best_len: usize = std.math.maxInt(usize);
+    for (objects) |object| {
+        if (has_part and object.part(around) == null) continue;
+
+        const extent = object.extent();
+        const holds = if (start == end)
+            extent.start <= start and start < extent.end
+        else
+            extent.start <= start and end <= extent.end;
+        if (!holds or extent.len() >= best_len) continue;
+
+        const range = objectRange(&text, objects, object, kind, around);
+        if (range.start == start and range.end == end) continue;
+        best = range;
+        best_len = extent.len();
+    }
+
+    const range = best orelse return null;
+    return Range{
+        .start = text.positionOf(range.start),
+        .end = text.positionOf(range.end),
+    };
+}
+
+/// Get the start of the next or previous syntax object after the cursor (`]f`, `[f`)
+pub fn syntaxObjectStart(
+    buffer: *const Buffer.Buffer,
+    objects: []const SyntaxObject,
+    pos: Cursor.Position,
+    forward: bool,
+    allocator: std.mem.Allocator,
+) !?Cursor.Position {
+    _ = allocator;
+    var text = Bytes.init(&buffer.rope);
+    const offset = text.offsetOf(pos);
+    var best: ?usize = null;
+    for (objects) |object| {
+        const start = object.extent().start;
+        const closer = if (best) |b| (if (forward) start < b else start > b) else true;
+        const ahead = if (forward) start > offset else start < offset;
+        if (ahead and closer) best = start;
+    }
+
+    const start = best orelse return null;
+    return text.positionOf(start);
+}
+
+/// A rope's bytes by offset, read through one cursor so nearby reads stay in its leaf
+const Bytes = struct {
+    rope: *const Rope,
+    cursor: Rope.Cursor,
+    len: usize,
+
+    fn init(rope: *const Rope) Bytes {
+        return .{ .rope = rope, .cursor = rope.cursorAt(0), .len = rope.len() };
+    }
+
+    fn at(self: *Bytes, pos: usize) u8 {
+        self.cursor.seek(pos);
+        return self.cursor.peekByte() orelse 0;
+    }
+
+    /// Offset of a line and byte column, kept within the line
+    fn offsetOf(self: *Bytes, pos: Cursor.Position) usize {
+        return @min(self.rope.lineToByte(pos.line) + pos.col, self.rope.lineEnd(pos.line));
+    }
+
+    fn positionOf(self: *Bytes, offset: usize) Cursor.Position {
+        const line = self.rope.byteToLine(offset);
+        return .{ .line = line, .col = offset - self.rope.lineToByte(line) };
+    }
+
+    /// First `c` in `start..end`
+    fn indexOf(self: *Bytes, start: usize, end: usize, c: u8) ?usize {
+        for (start..end) |i| {
+            if (self.at(i) == c) return i;
+        }
+        return null;
+    }
+
+    fn startsWith(self: *Bytes, pos: usize, prefix: []const u8) bool {
+        if (pos + prefix.len > self.len) return false;
+        for (prefix, pos..) |c, i| {
+            if (self.at(i) != c) return false;
+        }
+        return true;
+    }
+};
+
+/// Bytes `a` or `i` takes for one object
+fn objectRange(text: *Bytes, objects: []const SyntaxObject, object: SyntaxObject, kind: SyntaxKind, around: bool) ByteRange {
+    if (around) {
+        if (object.around) |node| {
+            if (kind == .comment) return wholeLines(text, commentRun(text, objects, node));
+            if (kind == .parameter) return node;
+            return wholeLines(text, node);
+        }
+        return withSeparator(text, object.inside.?);
+    }
+
+    const node = object.inside orelse object.around.?;
+    return switch (kind) {
+        .function, .class => blockContents(text, node),
+        .comment => commentText(text, node),
+        .parameter => node,
+    };
+}
+
+/// A node that has its lines to itself, grown to the whole lines (indentation and newline included)
+fn wholeLines(text: *Bytes, node: ByteRange) ByteRange {
+    var start = node.start;
+    while (start > 0 and isBlank(text.at(start - 1))) start -= 1;
+    if (start > 0 and text.at(start - 1) != '\n') return node;
+
+    // Some grammars end a line comment after its newline
+    if (node.end > node.start and text.at(node.end - 1) == '\n') return .{ .start = start, .end = node.end };
+
+    var end = node.end;
+    while (end < text.len and (isBlank(text.at(end)) or text.at(end) == '\r')) end += 1;
+    if (end < text.len and text.at(end) != '\n') return node;
+    return .{ .start = start, .end = @min(end + 1, text.len) };
+}
+
+/// An argument with the comma and space after it; the last one takes the comma before it instead
+fn withSeparator(text: *Bytes, node: ByteRange) ByteRange {
+    var end = node.end;
+    while (end < text.len and isBlank(text.at(end))) end += 1;
+    if (end < text.len and text.at(end) == ',') {
+        end += 1;
+        while (end < text.len and std.ascii.isWhitespace(text.at(end))) end += 1;
+        return .{ .start = node.start, .end = end };
+    }
+
+    var start = node.start;
+    while (start > 0 and std.ascii.isWhitespace(text.at(start - 1))) start -= 1;
+    if (start > 0 and text.at(start - 1) == ',') return .{ .start = start - 1, .end = node.end };
+    return node;
+}
+
+/// What a body holds between its braces, as whole lines when it spans them
+/// Bodies without braces (Python blocks) are taken as they are.
+fn blockContents(text: *Bytes, node: ByteRange) ByteRange {
+    var start = node.start;
+    var end = node.end;
+
+    // `struct { ... }` and `{ ... }` alike: the first `{` through the closing `}`
+    if (end > start and text.at(end - 1) == '}') {
+        if (text.indexOf(start, end - 1, '{')) |open| {
+            start = open + 1;
+            end -= 1;
+        }
+    }
+
+    while (start < end and std.ascii.isWhitespace(text.at(start))) start += 1;
+    while (end > start and std.ascii.isWhitespace(text.at(end - 1))) end -= 1;
+    if (start == end) return .{ .start = start, .end = end };
+    return wholeLines(text, .{ .start = start, .end = end });
+}
+
+/// A comment together with the comments on the lines right before and after it
+fn commentRun(text: *Bytes, objects: []const SyntaxObject, node: ByteRange) ByteRange {
+    var run = node;
+    var grew = true;
+    while (grew) {
+        grew = false;
+        for (objects) |object| {
+            const other = object.around orelse continue;
+            const joins = (other.start >= run.end and onNextLine(text, run.end, other.start)) or
+                (other.end <= run.start and onNextLine(text, other.end, run.start));
+            if (joins) {
+                run = ByteRange.merge(run, other);
+                grew = true;
+            }
+        }
+    }
+    return run;
+}
+
+/// Whether the text between two nodes (`start..end`) is only the break to the next line
+fn onNextLine(text: *Bytes, start: usize, end: usize) bool {
+    var newlines: usize = 0;
+    for (start..end) |i| {
+        const c = text.at(i);
+        if (c == '\n') newlines += 1 else if (!isBlank(c) and c != '\r') return false;
+    }
+    return newlines <= 1;
+}
+
+/// Text of a comment without its markers (`//`, `///`, `#`, `--`, `/*` and `*/`)
+fn commentText(text: *Bytes, node: ByteRange) ByteRange {
+    var start = node.start;
+    var end = node.end;
+    while (end > start and std.ascii.isWhitespace(text.at(end - 1))) end -= 1;
+    if (end - start >= 4 and text.startsWith(start, "/*") and text.startsWith(end - 2, "*/")) end -= 2;
+    while (start < end and std.mem.indexOfScalar(u8, "/*#-;!", text.at(start)) != null) start += 1;
+    while (start < end and std.ascii.isWhitespace(text.at(start))) start += 1;
+    while (end > start and std.ascii.isWhitespace(text.at(end - 1))) end -= 1;
+    return .{ .start = start, .end = end };
+}
+
+fn isBlank(c: u8) bool {
+    return c == ' ' or c == '\t';
+}
+
 // === Helper Functions ===
 
 fn isLineBlank(line: []const u8) bool {
@@ -456,3 +739,52 @@ test "textobject: select indent level" {
     // Should select lines 2-3 (the inner indented block)
     try std.testing.expectEqual(@as(usize, 2), range.?.start.line);
 }
+
+test "textobject: syntax objects" {
+    const allocator = std.testing.allocator;
+    const buffer = try Buffer.Buffer.initFromString(allocator, 0, "fn outer(a: u8, b: u8) void {\n    call(a, b);\n}\n// one\n// two");
+    defer buffer.deinit();
+
+    const functions = [_]SyntaxObject{.{ .around = .{ .start = 0, .end = 47 }, .inside = .{ .start = 28, .end = 47 } }};
+    const parameters = [_]SyntaxObject{ .{ .inside = .{ .start = 9, .end = 14 } }, .{ .inside = .{ .start = 16, .end = 21 } } };
+    const comments = [_]SyntaxObject{
+        .{ .around = .{ .start = 48, .end = 54 }, .inside = .{ .start = 48, .end = 54 } },
+        .{ .around = .{ .start = 55, .end = 61 }, .inside = .{ .start = 55, .end = 61 } },
+    };
+    const cursor = Range{ .start = .{ .line = 1, .col = 4 }, .end = .{ .line = 1, .col = 4 } };
+
+    // Functions: whole lines around, the lines between the braces inside
+    const around = (try selectSyntaxObject(&buffer, &functions, .function, cursor, true, allocator)).?;
+    try std.testing.expectEqual(Cursor.Position{ .line = 0, .col = 0 }, around.start);
+    try std.testing.expectEqual(Cursor.Position{ .line = 3, .col = 0 }, around.end);
+    const inside = (try selectSyntaxObject(&buffer, &functions, .function, cursor, false, allocator)).?;
+    try std.testing.expectEqual(Cursor.Position{ .line = 1, .col = 0 }, inside.start);
+    try std.testing.expectEqual(Cursor.Position{ .line = 2, .col = 0 }, inside.end);
+
+    // Arguments take the separator after them, or before the last one
+    const first = Range{ .start = .{ .line = 0, .col = 10 }, .end = .{ .line = 0, .col = 10 } };
+    const first_arg = (try selectSyntaxObject(&buffer, &parameters, .parameter, first, true, allocator)).?;
+    try std.testing.expectEqual(@as(usize, 9), first_arg.start.col);
+    try std.testing.expectEqual(@as(usize, 16), first_arg.end.col);
+    const last = Range{ .start = .{ .line = 0, .col = 16 }, .end = .{ .line = 0, .col = 16 } };
+    const last_arg = (try selectSyntaxObject(&buffer, &parameters, .parameter, last, true, allocator)).?;
+    try std.testing.expectEqual(@as(usize, 14), last_arg.start.col);
+    try std.testing.expectEqual(@as(usize, 21), last_arg.end.col);
+
+    // Comments on adjacent lines go together; inside leaves out the markers
+    const comment = Range{ .start = .{ .line = 3, .col = 1 }, .end = .{ .line = 3, .col = 1 } };
+    const run = (try selectSyntaxObject(&buffer, &comments, .comment, comment, true, allocator)).?;
+    try std.testing.expectEqual(Cursor.Position{ .line = 3, .col = 0 }, run.start);
+    try std.testing.expectEqual(Cursor.Position{ .line = 4, .col = 6 }, run.end);
+    const second = Range{ .start = .{ .line = 4, .col = 0 }, .end = .{ .line = 4, .col = 0 } };
+    const words = (try selectSyntaxObject(&buffer, &comments, .comment, second, false, allocator)).?;
+    try std.testing.expectEqual(Cursor.Position{ .line = 4, .col = 3 }, words.start);
+
+    // Selecting it again looks for an enclosing one
+    try std.testing.expect(try selectSyntaxObject(&buffer, &functions, .function, around, true, allocator) == null);
+
+    const previous = (try syntaxObjectStart(&buffer, &functions, .{ .line = 1, .col = 0 }, false, allocator)).?;
+    try std.testing.expectEqual(Cursor.Position{ .line = 0, .col = 0 }, previous);
+    try std.testing.expect(try syntaxObjectStart(&buffer, &functions, .{ .line = 0, .col = 3 }, true, allocator) == null);
+    try std.testing.expectEqual(SyntaxKind.parameter, SyntaxKind.fromCapture("parameter.inside").?.kind);
+}
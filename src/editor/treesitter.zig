@@ -1,9 +1,14 @@
-//! Tree-sitter integration for syntax highlighting
+//! Tree-sitter integration for syntax highlighting and text objects
 //! Provides incremental parsing and syntax tree queries
 
 const std = @import("std");
 const Buffer = @import("../buffer/manager.zig").Buffer;
+const TextChange = @import("../buffer/manager.zig").TextChange;
+const Rope = @import("../buffer/rope.zig").Rope;
 const Highlight = @import("highlight.zig");
+const TextObjects = @import("textobjects.zig");
+const Indent = @import("indent.zig");
+const Fold = @import("fold.zig");
 const ts = @import("../treesitter/bindings.zig");
 
 /// Syntax node type - represents a parsed syntax element
@@ -23,6 +28,14 @@ pub const SyntaxNode = struct {
     }
 };
 
+/// Where structural selection moves from the node covering a byte range
+pub const NodeStep = enum {
+    parent, // Smallest node larger than the range (expand)
+    first_child, // First named child inside it (shrink)
+    next_sibling, // Next named sibling, or that of the nearest ancestor with one
+    prev_sibling, // Previous named sibling, likewise
+};
+
 /// Highlight group - maps to terminal colors/styles
 pub const HighlightGroup = enum {
     keyword, // if, for, return, etc.
@@ -80,63 +93,21 @@ pub const HighlightToken = struct {
     group: HighlightGroup,
 };
 
-/// Language configuration
-pub const Language = enum {
-    zig,
-    c,
-    rust,
-    go,
-    python,
-    javascript,
-    typescript,
-    tsx,
-    json,
-    markdown,
-    plain_text,
-
-    pub fn fromFilename(filename: []const u8) Language {
-        if (std.mem.endsWith(u8, filename, ".zig")) return .zig;
-        if (std.mem.endsWith(u8, filename, ".c") or std.mem.endsWith(u8, filename, ".h")) return .c;
-        if (std.mem.endsWith(u8, filename, ".rs")) return .rust;
-        if (std.mem.endsWith(u8, filename, ".go")) return .go;
-        if (std.mem.endsWith(u8, filename, ".py")) return .python;
-        if (std.mem.endsWith(u8, filename, ".js")) return .javascript;
-        if (std.mem.endsWith(u8, filename, ".tsx")) return .tsx; // Check .tsx before .ts
-        if (std.mem.endsWith(u8, filename, ".ts")) return .typescript;
-        if (std.mem.endsWith(u8, filename, ".json")) return .json;
-        if (std.mem.endsWith(u8, filename, ".md")) return .markdown;
-        return .plain_text;
-    }
-
-    pub fn getName(self: Language) []const u8 {
-        return switch (self) {
-            .zig => "zig",
-            .c => "c",
-            .rust => "rust",
-            .go => "go",
-            .python => "python",
-            .javascript => "javascript",
-            .typescript => "typescript",
-            .tsx => "tsx",
-            .json => "json",
-            .markdown => "markdown",
-            .plain_text => "plain_text",
-        };
-    }
-};
+pub const Language = @import("language.zig").Language;
 
-/// Load and compile a highlight query for a language
-fn loadHighlightQuery(
+/// Load and compile a query for a language, such as "highlights" or "textobjects"
+fn loadQuery(
     allocator: std.mem.Allocator,
     ts_language: *const ts.TSLanguage,
     language: Language,
+    name: []const u8,
 ) !*ts.TSQuery {
-    // Construct path to query file: queries/{language}/highlights.scm
+    // Construct path to query file: queries/{language}/{name}.scm
     const lang_name = language.getName();
     const query_path = try std.fmt.allocPrint(
         allocator,
-        "queries/{s}/highlights.scm",
-        .{lang_name},
+        "queries/{s}/{s}.scm",
+        .{ lang_name, name },
     );
     defer allocator.free(query_path);
 
@@ -146,7 +117,10 @@ fn loadHighlightQuery(
         query_path,
         1024 * 1024, // Max 1MB query file
     ) catch |err| {
-        std.debug.print("Failed to read query file '{s}': {}\n", .{ query_path, err });
+        // Every language has highlights; the other queries are optional
+        if (err != error.FileNotFound or std.mem.eql(u8, name, "highlights")) {
+            std.debug.print("Failed to read query file '{s}': {}\n", .{ query_path, err });
+        }
         return error.QueryFileNotFound;
     };
     defer allocator.free(query_source);
@@ -303,7 +277,9 @@ fn getTreeSitterLanguage(language: Language) ?*const ts.TSLanguage {
         .tsx => ts.tree_sitter_tsx(),
 
         // These don't have tree-sitter grammars in our bindings yet
+        .cpp,
         .javascript,
+        .jsx,
         .json,
         .plain_text,
         => null,
@@ -319,6 +295,9 @@ pub const Parser = struct {
     ts_language: ?*const ts.TSLanguage,
     ts_query: ?*ts.TSQuery,
     ts_query_cursor: ?*ts.TSQueryCursor,
+    ts_objects_query: ?*ts.TSQuery, // textobjects.scm; null if the language has none
+    ts_indents_query: ?*ts.TSQuery, // indents.scm; null if the language has none
+    ts_folds_query: ?*ts.TSQuery, // folds.scm; null if the language has none
 
     pub fn init(allocator: std.mem.Allocator, language: Language) !Parser {
         // Create tree-sitter parser
@@ -338,13 +317,31 @@ pub const Parser = struct {
 
         // Load and compile highlight query (if language grammar available)
         const ts_query: ?*ts.TSQuery = if (ts_language) |lang|
-            loadHighlightQuery(allocator, lang, language) catch |err| blk: {
+            loadQuery(allocator, lang, language, "highlights") catch |err| blk: {
                 std.debug.print("Warning: Failed to load highlight query: {}\n", .{err});
                 break :blk null;
             }
         else
             null;
 
+        // Text objects (`af`, `ic`, `]f`) come from an optional textobjects.scm
+        const ts_objects_query: ?*ts.TSQuery = if (ts_language) |lang|
+            loadQuery(allocator, lang, language, "textobjects") catch null
+        else
+            null;
+
+        // Smart indentation comes from an optional indents.scm
+        const ts_indents_query: ?*ts.TSQuery = if (ts_language) |lang|
+            loadQuery(allocator, lang, language, "indents") catch null
+        else
+            null;
+
+        // Fold ranges come from an optional folds.scm
+        const ts_folds_query: ?*ts.TSQuery = if (ts_language) |lang|
+            loadQuery(allocator, lang, language, "folds") catch null
+        else
+            null;
+
         // Create query cursor (if we have a query)
         var ts_query_cursor: ?*ts.TSQueryCursor = null;
         if (ts_query != null) {
@@ -359,10 +356,22 @@ pub const Parser = struct {
             .ts_language = ts_language,
             .ts_query = ts_query,
             .ts_query_cursor = ts_query_cursor,
+            .ts_objects_query = ts_objects_query,
+            .ts_indents_query = ts_indents_query,
+            .ts_folds_query = ts_folds_query,
         };
     }
 
     pub fn deinit(self: *Parser) void {
+        if (self.ts_folds_query) |query| {
+            ts.ts_query_delete(query);
+        }
+        if (self.ts_indents_query) |query| {
+            ts.ts_query_delete(query);
+        }
+        if (self.ts_objects_query) |query| {
+            ts.ts_query_delete(query);
+        }
         if (self.ts_query_cursor) |cursor| {
             ts.ts_query_cursor_delete(cursor);
         }
@@ -397,6 +406,43 @@ pub const Parser = struct {
         self.ts_tree = new_tree;
     }
 
+    /// Parse straight from a rope, reading leaf by leaf instead of copying the text
+    pub fn parseRope(self: *Parser, rope: *const Rope) !void {
+        const parser = self.ts_parser orelse return error.NoParser;
+
+        const input = ts.TSInput{
+            .payload = @constCast(rope),
+            .read = readRope,
+            .encoding = .TSInputEncodingUTF8,
+        };
+        const new_tree = ts.ts_parser_parse(parser, self.ts_tree, input) orelse return error.ParseFailed;
+
+        if (self.ts_tree) |old_tree| {
+            ts.ts_tree_delete(old_tree);
+        }
+
+        self.ts_tree = new_tree;
+    }
+
+    /// TSInput callback: the rest of the leaf holding `byte_index`
+    fn readRope(payload: ?*anyopaque, byte_index: u32, position: ts.TSPoint, bytes_read: *u32) callconv(.C) [*c]const u8 {
+        _ = position;
+        const rope: *const Rope = @ptrCast(@alignCast(payload.?));
+        var cursor = rope.cursorAt(byte_index);
+        const chunk = cursor.chunk();
+        bytes_read.* = @intCast(chunk.len);
+        return chunk.ptr;
+    }
+
+    /// Drop the syntax tree, so the next parse starts from scratch
+    /// For text that changed without its edits reaching applyEdit.
+    pub fn clearTree(self: *Parser) void {
+        if (self.ts_tree) |tree| {
+            ts.ts_tree_delete(tree);
+            self.ts_tree = null;
+        }
+    }
+
     /// Apply an edit to the syntax tree for incremental parsing
     /// Call this before re-parsing after a text change
     pub fn applyEdit(self: *Parser, edit: ts.TSInputEdit) void {
@@ -405,19 +451,29 @@ pub const Parser = struct {
         }
     }
 
-    /// Get highlights for a line range using tree-sitter queries
+    /// Apply a change the buffer recorded (see `Buffer.changesSince`)
+    pub fn applyBufferEdit(self: *Parser, change: TextChange) void {
+        self.applyEdit(.{
+            .start_byte = @intCast(change.start_byte),
+            .old_end_byte = @intCast(change.old_end_byte),
+            .new_end_byte = @intCast(change.new_end_byte),
+            .start_point = .{ .row = @intCast(change.start.line), .column = @intCast(change.start.byte_col) },
+            .old_end_point = .{ .row = @intCast(change.old_end.line), .column = @intCast(change.old_end.byte_col) },
+            .new_end_point = .{ .row = @intCast(change.new_end.line), .column = @intCast(change.new_end.byte_col) },
+        });
+    }
+
+    /// Get highlights for lines `start_line..end_line` of the parsed text using tree-sitter queries
+    /// Token offsets are into the whole rope.
     pub fn getHighlights(
         self: *Parser,
-        text: []const u8,
+        rope: *const Rope,
         start_line: usize,
         end_line: usize,
     ) ![]HighlightToken {
-        _ = start_line;
-        _ = end_line;
-
         // If query-based highlighting is not available, fall back to basic highlighting
         if (self.ts_query == null or self.ts_query_cursor == null or self.ts_tree == null) {
-            return try basicHighlight(self.allocator, text, self.language);
+            return basicHighlightLines(self.allocator, rope, start_line, end_line, self.language);
         }
 
         const query = self.ts_query.?;
@@ -427,7 +483,10 @@ pub const Parser = struct {
         // Get root node
         const root_node = ts.ts_tree_root_node(tree);
 
-        // Execute query on root node
+        // Execute query on root node, limited to the lines asked for
+        const start_byte = rope.lineToByte(start_line);
+        const end_byte = rope.lineToByte(end_line);
+        ts.ts_query_cursor_set_byte_range(cursor, @intCast(start_byte), @intCast(end_byte));
         ts.ts_query_cursor_exec(cursor, query, root_node);
 
         // Collect all matches
@@ -468,8 +527,212 @@ pub const Parser = struct {
 
         return tokens.toOwnedSlice(self.allocator);
     }
+
+    /// Objects of one kind that the textobjects query captures in the current tree
+    /// Empty if the language has no textobjects.scm or nothing has been parsed. Caller frees.
+    pub fn textObjects(self: *Parser, allocator: std.mem.Allocator, kind: TextObjects.SyntaxKind) ![]TextObjects.SyntaxObject {
+        var objects = std.ArrayList(TextObjects.SyntaxObject).empty;
+        errdefer objects.deinit(allocator);
+
+        const query = self.ts_objects_query orelse return objects.toOwnedSlice(allocator);
+        const tree = self.ts_tree orelse return objects.toOwnedSlice(allocator);
+
+        // A cursor of its own, so highlighting's isn't disturbed
+        const cursor = ts.ts_query_cursor_new() orelse return error.OutOfMemory;
+        defer ts.ts_query_cursor_delete(cursor);
+        ts.ts_query_cursor_exec(cursor, query, ts.ts_tree_root_node(tree));
+
+        var match: ts.TSQueryMatch = undefined;
+        while (ts.ts_query_cursor_next_match(cursor, &match)) {
+            var object = TextObjects.SyntaxObject{};
+            for (match.captures[0..match.capture_count]) |capture| {
+                var name_len: u32 = 0;
+                const name_ptr = ts.ts_query_capture_name_for_id(query, capture.index, &name_len);
+                const captured = TextObjects.SyntaxKind.fromCapture(name_ptr[0..name_len]) orelse continue;
+                if (captured.kind != kind) continue;
+
+                const range = TextObjects.ByteRange{
+                    .start = ts.ts_node_start_byte(capture.node),
+                    .end = ts.ts_node_end_byte(capture.node),
+                };
+                if (captured.around) {
+                    object.around = TextObjects.ByteRange.merge(object.around, range);
+                } else {
+                    object.inside = TextObjects.ByteRange.merge(object.inside, range);
+                }
+            }
+            if (object.around != null or object.inside != null) try objects.append(allocator, object);
+        }
+
+        return objects.toOwnedSlice(allocator);
+    }
+
+    /// Nodes indents.scm captures that intersect the byte range `start..end`, sorted by start
+    /// Null if the language has no indents.scm or nothing has been parsed. Caller frees.
+    pub fn indentCaptures(self: *Parser, allocator: std.mem.Allocator, start: usize, end: usize) !?[]Indent.Capture {
+        const query = self.ts_indents_query orelse return null;
+        const tree = self.ts_tree orelse return null;
+
+        const cursor = ts.ts_query_cursor_new() orelse return error.OutOfMemory;
+        defer ts.ts_query_cursor_delete(cursor);
+        ts.ts_query_cursor_set_byte_range(cursor, @intCast(start), @intCast(end));
+        ts.ts_query_cursor_exec(cursor, query, ts.ts_tree_root_node(tree));
+
+        var captures = std.ArrayList(Indent.Capture).empty;
+        errdefer captures.deinit(allocator);
+
+        var match: ts.TSQueryMatch = undefined;
+        while (ts.ts_query_cursor_next_match(cursor, &match)) {
+            for (match.captures[0..match.capture_count]) |capture| {
+                var name_len: u32 = 0;
+                const name_ptr = ts.ts_query_capture_name_for_id(query, capture.index, &name_len);
+                const kind = Indent.CaptureKind.fromName(name_ptr[0..name_len]) orelse continue;
+                try captures.append(allocator, .{
+                    .kind = kind,
+                    .start = ts.ts_node_start_byte(capture.node),
+                    .end = ts.ts_node_end_byte(capture.node),
+                    .start_line = ts.ts_node_start_point(capture.node).row,
+                    .end_line = ts.ts_node_end_point(capture.node).row,
+                });
+            }
+        }
+
+        std.mem.sort(Indent.Capture, captures.items, {}, struct {
+            fn lessThan(_: void, a: Indent.Capture, b: Indent.Capture) bool {
+                return a.start < b.start;
+            }
+        }.lessThan);
+        return try captures.toOwnedSlice(allocator);
+    }
+
+    /// Lines of every `@fold` node in the current tree (see Fold.normalize); caller frees
+    /// Null when the language has no folds.scm or nothing has been parsed.
+    pub fn foldRanges(self: *Parser, allocator: std.mem.Allocator) !?[]Fold.Range {
+        const query = self.ts_folds_query orelse return null;
+        const tree = self.ts_tree orelse return null;
+
+        const cursor = ts.ts_query_cursor_new() orelse return error.OutOfMemory;
+        defer ts.ts_query_cursor_delete(cursor);
+        ts.ts_query_cursor_exec(cursor, query, ts.ts_tree_root_node(tree));
+
+        var ranges = std.ArrayList(Fold.Range).empty;
+        errdefer ranges.deinit(allocator);
+
+        var match: ts.TSQueryMatch = undefined;
+        while (ts.ts_query_cursor_next_match(cursor, &match)) {
+            for (match.captures[0..match.capture_count]) |capture| {
+                var name_len: u32 = 0;
+                const name_ptr = ts.ts_query_capture_name_for_id(query, capture.index, &name_len);
+                if (!std.mem.eql(u8, name_ptr[0..name_len], "fold")) continue;
+
+                const start = ts.ts_node_start_point(capture.node);
+                const end = ts.ts_node_end_point(capture.node);
+                // A node ending at the start of a line (taking the newline) doesn't cover it
+                const end_line = if (end.column == 0 and end.row > start.row) end.row - 1 else end.row;
+                try ranges.append(allocator, .{ .start = start.row, .end = end_line });
+            }
+        }
+
+        const kept = Fold.normalize(ranges.items).len;
+        ranges.shrinkRetainingCapacity(kept);
+        return try ranges.toOwnedSlice(allocator);
+    }
+
+    /// Whether `byte` is inside code the parser couldn't make sense of
+    /// Indentation from the tree is unreliable there (an unclosed `{` while typing).
+    pub fn inErrorNode(self: *const Parser, byte: usize) bool {
+        const tree = self.ts_tree orelse return false;
+        const root = ts.ts_tree_root_node(tree);
+        if (byte > ts.ts_node_end_byte(root)) return false;
+
+        var node = ts.ts_node_named_descendant_for_byte_range(root, @intCast(byte), @intCast(byte));
+        while (!node.isNull()) : (node = ts.ts_node_parent(node)) {
+            if (std.mem.eql(u8, std.mem.span(ts.ts_node_type(node)), "ERROR")) return true;
+        }
+        return false;
+    }
+
+    /// Named node one `step` away from the byte range `start..end` in the current tree
+    /// Null when there is no tree or nowhere to go (expanding the whole file).
+    pub fn syntaxNode(self: *const Parser, start: usize, end: usize, step: NodeStep) ?SyntaxNode {
+        const tree = self.ts_tree orelse return null;
+        const root = ts.ts_tree_root_node(tree);
+        if (end > ts.ts_node_end_byte(root)) return null;
+
+        // Outermost node exactly covering the range, so `x` in `(x)` steps to the parentheses
+        var node = ts.ts_node_named_descendant_for_byte_range(root, @intCast(start), @intCast(end));
+        if (node.isNull()) return null;
+        while (true) {
+            const parent = ts.ts_node_parent(node);
+            if (parent.isNull() or !sameRange(parent, node)) break;
+            node = parent;
+        }
+
+        switch (step) {
+            .parent => {
+                // The covering node may be larger than the range already (a cursor inside a word)
+                if (ts.ts_node_start_byte(node) < start or ts.ts_node_end_byte(node) > end) return toSyntaxNode(node);
+                var parent = ts.ts_node_parent(node);
+                while (!parent.isNull() and !ts.ts_node_is_named(parent)) parent = ts.ts_node_parent(parent);
+                return if (parent.isNull()) null else toSyntaxNode(parent);
+            },
+            .first_child => {
+                // Skip children that are the whole node (a lone expression in a statement)
+                var child = ts.ts_node_named_child(node, 0);
+                while (!child.isNull() and sameRange(child, node)) child = ts.ts_node_named_child(child, 0);
+                return if (child.isNull()) null else toSyntaxNode(child);
+            },
+            .next_sibling, .prev_sibling => {
+                while (!node.isNull()) {
+                    const sibling = if (step == .next_sibling) ts.ts_node_next_named_sibling(node) else ts.ts_node_prev_named_sibling(node);
+                    if (!sibling.isNull()) return toSyntaxNode(sibling);
+                    node = ts.ts_node_parent(node);
+                }
+                return null;
+            },
+        }
+    }
 };
 
+fn sameRange(a: ts.TSNode, b: ts.TSNode) bool {
+    return ts.ts_node_start_byte(a) == ts.ts_node_start_byte(b) and ts.ts_node_end_byte(a) == ts.ts_node_end_byte(b);
+}
+
+fn toSyntaxNode(node: ts.TSNode) SyntaxNode {
+    return .{
+        .start_byte = ts.ts_node_start_byte(node),
+        .end_byte = ts.ts_node_end_byte(node),
+        .start_line = ts.ts_node_start_point(node).row,
+        .end_line = ts.ts_node_end_point(node).row,
+        .node_type = std.mem.span(ts.ts_node_type(node)),
+    };
+}
+
+/// basicHighlight over lines `start_line..end_line` of a rope, one line at a time
+fn basicHighlightLines(allocator: std.mem.Allocator, rope: *const Rope, start_line: usize, end_line: usize, language: Language) ![]HighlightToken {
+    var tokens = std.ArrayList(HighlightToken).empty;
+    errdefer tokens.deinit(allocator);
+
+    var lines = rope.lines(start_line);
+    while (lines.next()) |line| {
+        if (line.number >= end_line) break;
+        const line_text = try rope.slice(allocator, line.start, line.end);
+        defer allocator.free(line_text);
+        const line_tokens = try basicHighlight(allocator, line_text, language);
+        defer allocator.free(line_tokens);
+
+        for (line_tokens) |token| {
+            try tokens.append(allocator, .{
+                .start_byte = line.start + token.start_byte,
+                .end_byte = line.start + token.end_byte,
+                .line = line.number,
+                .group = token.group,
+            });
+        }
+    }
+    return tokens.toOwnedSlice(allocator);
+}
+
 /// Basic regex-free keyword highlighting (temporary until tree-sitter is integrated)
 /// Uses the enhanced tokenizer from highlight.zig
 fn basicHighlight(allocator: std.mem.Allocator, text: []const u8, language: Language) ![]HighlightToken {
@@ -624,3 +887,19 @@ test "treesitter: comment highlight" {
 
     try std.testing.expect(found_comment);
 }
+
+test "treesitter: function text objects" {
+    const allocator = std.testing.allocator;
+    var parser = try Parser.init(allocator, .zig);
+    defer parser.deinit();
+
+    const text = "fn main() void {\n    run();\n}\n";
+    try parser.parse(text);
+
+    const functions = try parser.textObjects(allocator, .function);
+    defer allocator.free(functions);
+
+    try std.testing.expectEqual(@as(usize, 1), functions.len);
+    try std.testing.expectEqual(@as(usize, 0), functions[0].around.?.start);
+    try std.testing.expectEqualStrings("{\n    run();\n}", text[functions[0].inside.?.start..functions[0].inside.?.end]);
+}
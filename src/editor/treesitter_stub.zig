@@ -2,6 +2,11 @@
 //! Provides the same API as treesitter.zig but without any functionality
 
 const std = @import("std");
+const TextObjects = @import("textobjects.zig");
+const Indent = @import("indent.zig");
+const Fold = @import("fold.zig");
+const Rope = @import("../buffer/rope.zig").Rope;
+const TextChange = @import("../buffer/manager.zig").TextChange;
 
 /// Syntax node type - represents a parsed syntax element
 pub const SyntaxNode = struct {
@@ -24,6 +29,14 @@ pub const SyntaxNode = struct {
     }
 };
 
+/// Where structural selection moves from the node covering a byte range
+pub const NodeStep = enum {
+    parent,
+    first_child,
+    next_sibling,
+    prev_sibling,
+};
+
 /// Highlight group - maps to terminal colors/styles
 pub const HighlightGroup = enum {
     keyword,
@@ -58,47 +71,7 @@ pub const HighlightToken = struct {
     group: HighlightGroup,
 };
 
-/// Language configuration
-pub const Language = enum {
-    zig,
-    c,
-    rust,
-    go,
-    python,
-    javascript,
-    typescript,
-    json,
-    markdown,
-    plain_text,
-
-    pub fn fromFilename(filename: []const u8) Language {
-        if (std.mem.endsWith(u8, filename, ".zig")) return .zig;
-        if (std.mem.endsWith(u8, filename, ".c") or std.mem.endsWith(u8, filename, ".h")) return .c;
-        if (std.mem.endsWith(u8, filename, ".rs")) return .rust;
-        if (std.mem.endsWith(u8, filename, ".go")) return .go;
-        if (std.mem.endsWith(u8, filename, ".py")) return .python;
-        if (std.mem.endsWith(u8, filename, ".js")) return .javascript;
-        if (std.mem.endsWith(u8, filename, ".ts")) return .typescript;
-        if (std.mem.endsWith(u8, filename, ".json")) return .json;
-        if (std.mem.endsWith(u8, filename, ".md")) return .markdown;
-        return .plain_text;
-    }
-
-    pub fn getName(self: Language) []const u8 {
-        return switch (self) {
-            .zig => "zig",
-            .c => "c",
-            .rust => "rust",
-            .go => "go",
-            .python => "python",
-            .javascript => "javascript",
-            .typescript => "typescript",
-            .json => "json",
-            .markdown => "markdown",
-            .plain_text => "plain_text",
-        };
-    }
-};
+pub const Language = @import("language.zig").Language;
 
 /// Stub parser that does nothing
 pub const Parser = struct {
@@ -124,16 +97,72 @@ pub const Parser = struct {
         _ = text;
     }
 
+    /// Parse from a rope (no-op)
+    pub fn parseRope(self: *Parser, rope: *const Rope) !void {
+        _ = self;
+        _ = rope;
+    }
+
     /// Get syntax highlights (returns empty array)
-    pub fn getHighlights(self: *Parser, text: []const u8, start_line: usize, end_line: usize) ![]HighlightToken {
+    pub fn getHighlights(self: *Parser, rope: *const Rope, start_line: usize, end_line: usize) ![]HighlightToken {
         _ = self;
-        _ = text;
+        _ = rope;
         _ = start_line;
         _ = end_line;
         // Return empty array - no syntax highlighting when tree-sitter is disabled
         return &[_]HighlightToken{};
     }
 
+    /// Drop the syntax tree (no-op)
+    pub fn clearTree(self: *Parser) void {
+        _ = self;
+    }
+
+    /// Apply a recorded buffer change (no-op)
+    pub fn applyBufferEdit(self: *Parser, change: TextChange) void {
+        _ = self;
+        _ = change;
+    }
+
+    /// Get syntax text objects (returns an empty list)
+    pub fn textObjects(self: *Parser, allocator: std.mem.Allocator, kind: TextObjects.SyntaxKind) ![]TextObjects.SyntaxObject {
+        _ = self;
+        _ = kind;
+        return allocator.alloc(TextObjects.SyntaxObject, 0);
+    }
+
+    /// Get indents.scm captures (returns null, so indentation follows the line above)
+    pub fn indentCaptures(self: *Parser, allocator: std.mem.Allocator, start: usize, end: usize) !?[]Indent.Capture {
+        _ = self;
+        _ = allocator;
+        _ = start;
+        _ = end;
+        return null;
+    }
+
+    /// Get folds.scm ranges (returns null, so folds follow indentation)
+    pub fn foldRanges(self: *Parser, allocator: std.mem.Allocator) !?[]Fold.Range {
+        _ = self;
+        _ = allocator;
+        return null;
+    }
+
+    /// Check for a parse error at a byte (returns false)
+    pub fn inErrorNode(self: *const Parser, byte: usize) bool {
+        _ = self;
+        _ = byte;
+        return false;
+    }
+
+    /// Get a node for structural selection (returns null)
+    pub fn syntaxNode(self: *const Parser, start: usize, end: usize, step: NodeStep) ?SyntaxNode {
+        _ = self;
+        _ = start;
+        _ = end;
+        _ = step;
+        return null;
+    }
+
     /// Get syntax tree root (returns null)
     pub fn getRoot(self: *const Parser) ?SyntaxNode {
         _ = self;
@@ -1,9 +1,10 @@
-//! Undo/redo system with branch support
+//! Undo/redo system with a branching undo tree
 //! Tracks text operations with cursor positions
 
 const std = @import("std");
 const Cursor = @import("cursor.zig");
 const Rope = @import("../buffer/rope.zig").Rope;
+const Buffer = @import("../buffer/manager.zig").Buffer;
 
 /// Type of operation for undo/redo
 pub const OperationType = enum {
@@ -94,135 +95,262 @@ pub const OperationGroup = struct {
     }
 };
 
-/// Saved branch when history diverges
-const Branch = struct {
-    branch_point: usize, // Index where this branch diverged
-    groups: std.ArrayList(OperationGroup), // The discarded future
-    timestamp: i64,
+/// Node in the undo tree; its group transforms the parent's state into this one
+pub const UndoNode = struct {
+    group: OperationGroup,
+    parent: ?usize, // null for the root (state before any recorded edit)
+    children: std.ArrayList(usize),
+    redo_child: ?usize, // Branch redo follows (most recently visited child)
 
-    pub fn deinit(self: *Branch, allocator: std.mem.Allocator) void {
-        for (self.groups.items) |*group| {
-            group.deinit(allocator);
-        }
-        self.groups.deinit(allocator);
+    fn deinit(self: *UndoNode, allocator: std.mem.Allocator) void {
+        self.group.deinit(allocator);
+        self.children.deinit(allocator);
     }
 };
 
-/// Undo history with branch support (vim-style undo tree)
+/// Direction of a single step through the tree
+pub const StepDirection = enum { undo, redo };
+
+/// One group application on the way between two states
+pub const Step = struct {
+    node: usize,
+    direction: StepDirection,
+};
+
+/// Undo history as a tree (vim-style)
+/// Editing after an undo starts a new branch instead of discarding the redo
+/// future. Node indices double as sequence numbers, so index order is
+/// chronological and `g-`/`g+` simply step to the neighbouring index.
 pub const UndoHistory = struct {
-    groups: std.ArrayList(OperationGroup),
-    current_index: usize, // Points to the operation we'd undo next
-    branches: std.ArrayList(Branch), // Saved alternate histories
+    nodes: std.ArrayList(UndoNode), // nodes[0] is the root once anything is pushed
+    current: usize, // Node whose state the buffer is in
     allocator: std.mem.Allocator,
 
     /// Max groups to keep (to prevent unbounded memory growth)
     const MAX_GROUPS = 1000;
-    const MAX_BRANCHES = 10;
 
     pub fn init(allocator: std.mem.Allocator) UndoHistory {
         return .{
-            .groups = std.ArrayList(OperationGroup).empty,
-            .current_index = 0,
-            .branches = std.ArrayList(Branch).empty,
+            .nodes = std.ArrayList(UndoNode).empty,
+            .current = 0,
             .allocator = allocator,
         };
     }
 
     pub fn deinit(self: *UndoHistory) void {
-        for (self.groups.items) |*group| {
-            group.deinit(self.allocator);
-        }
-        self.groups.deinit(self.allocator);
-
-        for (self.branches.items) |*branch| {
-            branch.deinit(self.allocator);
+        for (self.nodes.items) |*node| {
+            node.deinit(self.allocator);
         }
-        self.branches.deinit(self.allocator);
+        self.nodes.deinit(self.allocator);
     }
 
-    /// Add an operation group to history
+    /// Add an operation group as a child of the current state
     pub fn push(self: *UndoHistory, group: OperationGroup) !void {
-        // If we're not at the end, we're creating a new branch - save the discarded future
-        if (self.current_index < self.groups.items.len) {
-            // Save the future as a branch before discarding
-            var branch_groups = std.ArrayList(OperationGroup).empty;
-            while (self.current_index < self.groups.items.len) {
-                const discarded = self.groups.pop().?; // Safe because we checked length
-                try branch_groups.insert(self.allocator, 0, discarded); // Insert at front to maintain order
-            }
+        // Root represents the text before the first recorded edit
+        if (self.nodes.items.len == 0) {
+            var root_group = OperationGroup.init(self.allocator, group.cursor_before);
+            root_group.timestamp = group.timestamp;
+            try self.nodes.append(self.allocator, .{
+                .group = root_group,
+                .parent = null,
+                .children = .empty,
+                .redo_child = null,
+            });
+            self.current = 0;
+        }
 
-            if (branch_groups.items.len > 0) {
-                const branch = Branch{
-                    .branch_point = self.current_index,
-                    .groups = branch_groups,
-                    .timestamp = std.time.milliTimestamp(),
-                };
-                try self.branches.append(self.allocator, branch);
-
-                // Limit number of saved branches
-                if (self.branches.items.len > MAX_BRANCHES) {
-                    var oldest = self.branches.orderedRemove(0);
-                    oldest.deinit(self.allocator);
-                }
-            }
+        const index = self.nodes.items.len;
+        try self.nodes.append(self.allocator, .{
+            .group = group,
+            .parent = self.current,
+            .children = .empty,
+            .redo_child = null,
+        });
+        // Caller keeps ownership of the group if linking fails
+        errdefer _ = self.nodes.pop();
+
+        const parent = &self.nodes.items[self.current];
+        try parent.children.append(self.allocator, index);
+        parent.redo_child = index;
+        self.current = index;
+
+        self.prune() catch {};
+    }
+
+    /// Drop the oldest state once history grows past MAX_GROUPS
+    /// The root's child toward the current state becomes the new root; branches
+    /// off the root that don't lead there go with it. Retried on the next push
+    /// if it runs out of memory.
+    fn prune(self: *UndoHistory) !void {
+        if (self.nodes.items.len <= MAX_GROUPS + 1) return;
+        if (self.current == 0) return;
+
+        const nodes = self.nodes.items;
+        var toward = self.current;
+        while (nodes[toward].parent.? != 0) toward = nodes[toward].parent.?;
+
+        // New index of each node kept; parents come before children, so one pass decides
+        const map = try self.allocator.alloc(?usize, nodes.len);
+        defer self.allocator.free(map);
+        var kept: usize = 0;
+        for (nodes, 0..) |node, i| {
+            const keep = if (node.parent) |p| (if (p == 0) i == toward else map[p] != null) else false;
+            map[i] = if (keep) kept else null;
+            if (keep) kept += 1;
         }
 
-        // DISABLED: Check if we can merge with previous group
-        // Merging causes issues when multiple editing sessions happen within 1 second.
-        // TODO: Re-enable with proper session tracking (track mode enter/exit)
-        // if (self.groups.items.len > 0) {
-        //     const last = &self.groups.items[self.groups.items.len - 1];
-        //     if (last.canMerge(&group)) {
-        //         // Merge operations into last group
-        //         for (group.operations.items) |op| {
-        //             try last.operations.append(self.allocator, op);
-        //         }
-        //         last.cursor_after = group.cursor_after;
-        //         last.timestamp = group.timestamp;
-        //         // Don't deinit group since we transferred ownership of operations
-        //         return;
-        //     }
-        // }
-
-        // Add new group
-        try self.groups.append(self.allocator, group);
-        self.current_index = self.groups.items.len;
-
-        // Enforce max size
-        if (self.groups.items.len > MAX_GROUPS) {
-            var oldest = self.groups.orderedRemove(0);
-            oldest.deinit(self.allocator);
-            self.current_index -= 1;
+        for (nodes, 0..) |*node, i| {
+            const to = map[i] orelse {
+                node.deinit(self.allocator);
+                continue;
+            };
+            node.parent = if (node.parent) |p| map[p] else null;
+            if (node.redo_child) |c| node.redo_child = map[c];
+            for (node.children.items) |*child| child.* = map[child.*].?;
+            nodes[to] = node.*; // Never past `i`, so nodes not yet visited stay put
         }
+        self.nodes.shrinkRetainingCapacity(kept);
+        self.current = map[self.current].?;
+
+        // New root's group led from the dropped state; it can no longer be undone
+        const root = &self.nodes.items[0];
+        const cursor = root.group.cursor_after;
+        const timestamp = root.group.timestamp;
+        root.group.deinit(self.allocator);
+        root.group = OperationGroup.init(self.allocator, cursor);
+        root.group.timestamp = timestamp;
     }
 
     /// Get the next group to undo (returns null if at beginning)
     pub fn getUndo(self: *UndoHistory) ?*OperationGroup {
-        if (self.current_index == 0) return null;
-        self.current_index -= 1;
-        return &self.groups.items[self.current_index];
+        if (self.current == 0 or self.current >= self.nodes.items.len) return null;
+        const index = self.current;
+        const parent = self.nodes.items[index].parent orelse return null;
+        self.nodes.items[parent].redo_child = index;
+        self.current = parent;
+        return &self.nodes.items[index].group;
     }
 
-    /// Get the next group to redo (returns null if at end)
+    /// Get the next group to redo along the most recent branch (returns null if at a leaf)
     pub fn getRedo(self: *UndoHistory) ?*OperationGroup {
-        if (self.current_index >= self.groups.items.len) return null;
-        const group = &self.groups.items[self.current_index];
-        self.current_index += 1;
-        return group;
+        if (self.current >= self.nodes.items.len) return null;
+        const child = self.nodes.items[self.current].redo_child orelse return null;
+        self.current = child;
+        return &self.nodes.items[child].group;
     }
 
     /// Check if undo is available
     pub fn canUndo(self: *const UndoHistory) bool {
-        return self.current_index > 0;
+        return self.current > 0;
     }
 
     /// Check if redo is available
     pub fn canRedo(self: *const UndoHistory) bool {
-        return self.current_index < self.groups.items.len;
+        if (self.current >= self.nodes.items.len) return false;
+        return self.nodes.items[self.current].redo_child != null;
+    }
+
+    /// Sequence number of the current state (0 = original text)
+    pub fn currentSeq(self: *const UndoHistory) usize {
+        return self.current;
+    }
+
+    /// Number of states in the tree, including the original text
+    pub fn stateCount(self: *const UndoHistory) usize {
+        return @max(self.nodes.items.len, 1);
+    }
+
+    /// Chronologically previous state (`g-`)
+    pub fn earlierSeq(self: *const UndoHistory) ?usize {
+        if (self.current == 0) return null;
+        return self.current - 1;
+    }
+
+    /// Chronologically next state (`g+`)
+    pub fn laterSeq(self: *const UndoHistory) ?usize {
+        if (self.current + 1 >= self.nodes.items.len) return null;
+        return self.current + 1;
+    }
+
+    fn depthOf(self: *const UndoHistory, index: usize) usize {
+        var depth: usize = 0;
+        var node = index;
+        while (self.nodes.items[node].parent) |p| : (depth += 1) node = p;
+        return depth;
     }
 
-    /// Apply an operation group to a rope (for undo - reverse operations)
-    pub fn applyUndo(group: *const OperationGroup, rope: *Rope, allocator: std.mem.Allocator) !void {
+    /// Steps that move from the current state to `target`:
+    /// undo up to the common ancestor, then redo down the target's branch
+    pub fn pathTo(self: *const UndoHistory, allocator: std.mem.Allocator, target: usize) ![]Step {
+        if (target >= self.stateCount()) return error.InvalidState;
+
+        var steps = std.ArrayList(Step).empty;
+        errdefer steps.deinit(allocator);
+        if (self.nodes.items.len == 0) return steps.toOwnedSlice(allocator);
+
+        var redo_path = std.ArrayList(usize).empty;
+        defer redo_path.deinit(allocator);
+
+        var a = self.current;
+        var b = target;
+        var depth_a = self.depthOf(a);
+        var depth_b = self.depthOf(b);
+
+        while (depth_a > depth_b) : (depth_a -= 1) {
+            try steps.append(allocator, .{ .node = a, .direction = .undo });
+            a = self.nodes.items[a].parent.?;
+        }
+        while (depth_b > depth_a) : (depth_b -= 1) {
+            try redo_path.append(allocator, b);
+            b = self.nodes.items[b].parent.?;
+        }
+        while (a != b) {
+            try steps.append(allocator, .{ .node = a, .direction = .undo });
+            try redo_path.append(allocator, b);
+            a = self.nodes.items[a].parent.?;
+            b = self.nodes.items[b].parent.?;
+        }
+
+        var i = redo_path.items.len;
+        while (i > 0) {
+            i -= 1;
+            try steps.append(allocator, .{ .node = redo_path.items[i], .direction = .redo });
+        }
+
+        return steps.toOwnedSlice(allocator);
+    }
+
+    /// Move the buffer to any state in the tree
+    /// Returns the cursor position to restore, or null if nothing changed.
+    pub fn jumpTo(self: *UndoHistory, target: usize, buffer: *Buffer, allocator: std.mem.Allocator) !?Cursor.Position {
+        const steps = try self.pathTo(allocator, target);
+        defer allocator.free(steps);
+
+        var cursor: ?Cursor.Position = null;
+        for (steps) |step| {
+            const node = &self.nodes.items[step.node];
+            const parent = node.parent.?;
+            switch (step.direction) {
+                .undo => {
+                    try applyUndo(&node.group, buffer);
+                    self.current = parent;
+                    cursor = node.group.cursor_before;
+                },
+                .redo => {
+                    try applyRedo(&node.group, buffer);
+                    self.current = step.node;
+                    cursor = node.group.cursor_after;
+                },
+            }
+            // Plain redo should follow the branch we just travelled
+            self.nodes.items[parent].redo_child = step.node;
+        }
+        return cursor;
+    }
+
+    /// Apply an operation group to a buffer (for undo - reverse operations)
+    /// Edits go through the buffer so they are recorded like any other change.
+    pub fn applyUndo(group: *const OperationGroup, buffer: *Buffer) !void {
         // Apply operations in reverse order
         var i = group.operations.items.len;
         while (i > 0) {
@@ -230,157 +358,130 @@ pub const UndoHistory = struct {
             const op = group.operations.items[i];
 
             // Convert position to byte offset
-            const offset = try positionToOffset(rope, op.position, allocator);
+            const offset = positionToOffset(&buffer.rope, op.position);
 
             switch (op.op_type) {
                 .insert => {
                     // Undo insert: delete the inserted text
-                    try rope.delete(offset, offset + op.text.len);
+                    try buffer.delete(offset, offset + op.text.len);
                 },
                 .delete => {
                     // Undo delete: re-insert the deleted text
-                    try rope.insert(offset, op.text);
+                    try buffer.insert(offset, op.text);
                 },
                 .replace => {
                     // Undo replace: restore old text
                     if (op.old_text) |old| {
-                        try rope.delete(offset, offset + op.text.len);
-                        try rope.insert(offset, old);
+                        try buffer.delete(offset, offset + op.text.len);
+                        try buffer.insert(offset, old);
                     }
                 },
             }
         }
     }
 
-    /// Apply an operation group to a rope (for redo - forward operations)
-    pub fn applyRedo(group: *const OperationGroup, rope: *Rope, allocator: std.mem.Allocator) !void {
+    /// Apply an operation group to a buffer (for redo - forward operations)
+    pub fn applyRedo(group: *const OperationGroup, buffer: *Buffer) !void {
         // Apply operations in forward order
         for (group.operations.items) |op| {
             // Convert position to byte offset
-            const offset = try positionToOffset(rope, op.position, allocator);
+            const offset = positionToOffset(&buffer.rope, op.position);
 
             switch (op.op_type) {
                 .insert => {
                     // Redo insert: insert the text
-                    try rope.insert(offset, op.text);
+                    try buffer.insert(offset, op.text);
                 },
                 .delete => {
                     // Redo delete: delete the text
-                    try rope.delete(offset, offset + op.text.len);
+                    try buffer.delete(offset, offset + op.text.len);
                 },
                 .replace => {
                     // Redo replace: apply new text
                     if (op.old_text) |old| {
-                        try rope.delete(offset, offset + old.len);
-                        try rope.insert(offset, op.text);
+                        try buffer.delete(offset, offset + old.len);
+                        try buffer.insert(offset, op.text);
                     }
                 },
             }
         }
     }
 
-    /// Get number of saved branches
-    pub fn branchCount(self: *const UndoHistory) usize {
-        return self.branches.items.len;
-    }
-
-    /// Get branch info for display
-    pub const BranchInfo = struct {
-        index: usize,
-        branch_point: usize,
-        operations_count: usize,
+    /// Tree entry for display
+    pub const TreeEntry = struct {
+        seq: usize,
+        depth: usize, // Branch nesting level (0 = main line)
         timestamp: i64,
+        inserted: usize, // Bytes inserted by this state's group
+        deleted: usize, // Bytes deleted by this state's group
+        is_current: bool,
     };
 
-    /// List all available branches
-    pub fn listBranches(self: *const UndoHistory, allocator: std.mem.Allocator) ![]BranchInfo {
-        var list = std.ArrayList(BranchInfo).empty;
-        defer list.deinit(allocator);
-
-        for (self.branches.items, 0..) |branch, i| {
-            try list.append(allocator, BranchInfo{
-                .index = i,
-                .branch_point = branch.branch_point,
-                .operations_count = branch.groups.items.len,
-                .timestamp = branch.timestamp,
+    /// List states in tree order for display
+    /// Depth-first from the root; the first child continues its parent's line
+    /// and later (divergent) children are nested one level deeper.
+    pub fn listTree(self: *const UndoHistory, allocator: std.mem.Allocator) ![]TreeEntry {
+        var list = std.ArrayList(TreeEntry).empty;
+        errdefer list.deinit(allocator);
+
+        if (self.nodes.items.len == 0) {
+            try list.append(allocator, .{
+                .seq = 0,
+                .depth = 0,
+                .timestamp = 0,
+                .inserted = 0,
+                .deleted = 0,
+                .is_current = true,
             });
+            return list.toOwnedSlice(allocator);
         }
 
-        return list.toOwnedSlice(allocator);
-    }
-
-    /// Switch to a saved branch by index
-    /// Returns error if index invalid or branch point doesn't match current state
-    pub fn switchToBranch(self: *UndoHistory, branch_index: usize) !void {
-        if (branch_index >= self.branches.items.len) {
-            return error.InvalidBranchIndex;
-        }
-
-        const branch = &self.branches.items[branch_index];
-
-        // Can only switch if we're at the branch point
-        if (self.current_index != branch.branch_point) {
-            return error.NotAtBranchPoint;
-        }
-
-        // Save current future as a new branch before switching
-        if (self.current_index < self.groups.items.len) {
-            var current_future = std.ArrayList(OperationGroup).empty;
-            while (self.current_index < self.groups.items.len) {
-                const future_group = self.groups.pop().?; // Safe because we checked length
-                try current_future.insert(self.allocator, 0, future_group);
+        const Pending = struct { node: usize, depth: usize };
+        var stack = std.ArrayList(Pending).empty;
+        defer stack.deinit(allocator);
+        try stack.append(allocator, .{ .node = 0, .depth = 0 });
+
+        while (stack.pop()) |item| {
+            const node = &self.nodes.items[item.node];
+            var inserted: usize = 0;
+            var deleted: usize = 0;
+            for (node.group.operations.items) |op| {
+                switch (op.op_type) {
+                    .insert => inserted += op.text.len,
+                    .delete => deleted += op.text.len,
+                    .replace => {
+                        inserted += op.text.len;
+                        if (op.old_text) |old| deleted += old.len;
+                    },
+                }
             }
 
-            if (current_future.items.len > 0) {
-                const new_branch = Branch{
-                    .branch_point = self.current_index,
-                    .groups = current_future,
-                    .timestamp = std.time.milliTimestamp(),
-                };
-                try self.branches.insert(self.allocator, branch_index, new_branch);
-
-                // Limit branches
-                if (self.branches.items.len > MAX_BRANCHES) {
-                    var oldest = self.branches.orderedRemove(0);
-                    oldest.deinit(self.allocator);
-                }
+            try list.append(allocator, .{
+                .seq = item.node,
+                .depth = item.depth,
+                .timestamp = node.group.timestamp,
+                .inserted = inserted,
+                .deleted = deleted,
+                .is_current = item.node == self.current,
+            });
+
+            // Push in reverse so the first child is visited next
+            var i = node.children.items.len;
+            while (i > 0) {
+                i -= 1;
+                const depth = if (i == 0) item.depth else item.depth + 1;
+                try stack.append(allocator, .{ .node = node.children.items[i], .depth = depth });
             }
         }
 
-        // Restore the selected branch
-        const removed_branch = self.branches.orderedRemove(branch_index);
-        for (removed_branch.groups.items) |group| {
-            try self.groups.append(self.allocator, group);
-        }
-        // Don't deinit removed_branch since we transferred ownership
+        return list.toOwnedSlice(allocator);
     }
 };
 
 /// Convert Position (line, col) to byte offset in rope
-fn positionToOffset(rope: *const Rope, pos: Cursor.Position, allocator: std.mem.Allocator) !usize {
-    const text = try rope.toString(allocator);
-    defer allocator.free(text);
-
-    var offset: usize = 0;
-    var line: usize = 0;
-    var col: usize = 0;
-
-    while (offset < text.len) {
-        if (line == pos.line and col == pos.col) {
-            return offset;
-        }
-
-        if (text[offset] == '\n') {
-            line += 1;
-            col = 0;
-        } else {
-            col += 1;
-        }
-        offset += 1;
-    }
-
-    // If we reach here, position is at or beyond end of file
-    return offset;
+fn positionToOffset(rope: *const Rope, pos: Cursor.Position) usize {
+    const line_start = rope.lineToByte(pos.line);
+    return @min(line_start + pos.col, rope.lineEnd(pos.line));
 }
 
 // === Tests ===
@@ -410,10 +511,10 @@ test "operation group: merge" {
     defer g2.deinit(allocator);
 
     const op1 = try Operation.init(allocator, .insert, .{ .line = 0, .col = 0 }, "a", null);
-    try g1.addOperation(op1);
+    try g1.addOperation(allocator, op1);
 
     const op2 = try Operation.init(allocator, .insert, .{ .line = 0, .col = 1 }, "b", null);
-    try g2.addOperation(op2);
+    try g2.addOperation(allocator, op2);
 
     try std.testing.expect(g1.canMerge(&g2));
 }
@@ -425,7 +526,7 @@ test "undo history: push and undo" {
 
     var group = OperationGroup.init(allocator, .{ .line = 0, .col = 0 });
     const op = try Operation.init(allocator, .insert, .{ .line = 0, .col = 0 }, "test", null);
-    try group.addOperation(op);
+    try group.addOperation(allocator, op);
 
     try history.push(group);
 
@@ -445,7 +546,7 @@ test "undo history: redo" {
 
     var group = OperationGroup.init(allocator, .{ .line = 0, .col = 0 });
     const op = try Operation.init(allocator, .insert, .{ .line = 0, .col = 0 }, "test", null);
-    try group.addOperation(op);
+    try group.addOperation(allocator, op);
 
     try history.push(group);
 
@@ -456,3 +557,70 @@ test "undo history: redo" {
     try std.testing.expect(redo_group != null);
     try std.testing.expect(!history.canRedo());
 }
+
+test "undo history: divergent edits keep both branches" {
+    const allocator = std.testing.allocator;
+    var buffer = try Buffer.initFromString(allocator, 1, "");
+    defer buffer.deinit();
+    var history = UndoHistory.init(allocator);
+    defer history.deinit();
+
+    var first = OperationGroup.init(allocator, .{ .line = 0, .col = 0 });
+    try first.addOperation(allocator, try Operation.init(allocator, .insert, .{ .line = 0, .col = 0 }, "abc", null));
+    try buffer.insert(0, "abc");
+    try history.push(first);
+
+    try UndoHistory.applyUndo(history.getUndo().?, &buffer);
+
+    var second = OperationGroup.init(allocator, .{ .line = 0, .col = 0 });
+    try second.addOperation(allocator, try Operation.init(allocator, .insert, .{ .line = 0, .col = 0 }, "xy", null));
+    try buffer.insert(0, "xy");
+    try history.push(second);
+
+    // Both branches survive: root -> 1 and root -> 2
+    try std.testing.expectEqual(@as(usize, 3), history.stateCount());
+    try std.testing.expect(!history.canRedo());
+
+    const steps = try history.pathTo(allocator, 1);
+    defer allocator.free(steps);
+    try std.testing.expectEqual(@as(usize, 2), steps.len);
+    try std.testing.expectEqual(StepDirection.undo, steps[0].direction);
+    try std.testing.expectEqual(@as(usize, 1), steps[1].node);
+
+    // g- from state 2 lands on state 1 in the other branch
+    _ = try history.jumpTo(history.earlierSeq().?, &buffer, allocator);
+    const text = try buffer.getText();
+    defer allocator.free(text);
+    try std.testing.expectEqualStrings("abc", text);
+    try std.testing.expectEqual(@as(usize, 1), history.currentSeq());
+    try std.testing.expectEqual(@as(?usize, 2), history.laterSeq());
+
+    const entries = try history.listTree(allocator);
+    defer allocator.free(entries);
+    try std.testing.expectEqual(@as(usize, 3), entries.len);
+    try std.testing.expectEqual(@as(usize, 1), entries[2].depth);
+    try std.testing.expect(entries[1].is_current);
+}
+
+test "undo history: stays capped after branching at the root" {
+    const allocator = std.testing.allocator;
+    var history = UndoHistory.init(allocator);
+    defer history.deinit();
+
+    try history.push(OperationGroup.init(allocator, .{ .line = 0, .col = 0 }));
+    _ = history.getUndo();
+    try history.push(OperationGroup.init(allocator, .{ .line = 1, .col = 0 }));
+    try std.testing.expectEqual(@as(usize, 2), history.nodes.items[0].children.items.len);
+
+    // The stale branch goes first, then the oldest states on the current one
+    for (0..UndoHistory.MAX_GROUPS + 10) |i| {
+        try history.push(OperationGroup.init(allocator, .{ .line = i + 2, .col = 0 }));
+    }
+    try std.testing.expectEqual(@as(usize, UndoHistory.MAX_GROUPS + 1), history.nodes.items.len);
+    try std.testing.expectEqual(history.nodes.items.len - 1, history.currentSeq());
+    try std.testing.expectEqual(@as(?usize, null), history.nodes.items[0].parent);
+
+    var undone: usize = 0;
+    while (history.getUndo()) |_| undone += 1;
+    try std.testing.expectEqual(@as(usize, UndoHistory.MAX_GROUPS), undone);
+}
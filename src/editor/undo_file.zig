@@ -0,0 +1,349 @@
+//! Persistent undo history
+//! Serializes a buffer's undo tree to ~/.aesop/undo/ so it survives restarts
+//!
+//! Files are named after a hash of the absolute file path and record the full
+//! path plus a hash of the file content the history ends at. A history file is
+//! only restored when both match; anything stale, foreign, or corrupt is
+//! deleted and the buffer starts with a fresh history.
+
+const std = @import("std");
+const Cursor = @import("cursor.zig");
+const Undo = @import("undo.zig");
+
+const MAGIC = "AESOPUND";
+const VERSION: u32 = 1;
+const NONE: u32 = std.math.maxInt(u32);
+
+/// Largest history file we are willing to read
+const MAX_FILE_SIZE = 64 * 1024 * 1024;
+
+pub const DecodeError = error{
+    InvalidUndoFile,
+    VersionMismatch,
+    PathMismatch,
+    ContentMismatch,
+    OutOfMemory,
+};
+
+/// Hash of file content the history must match
+pub fn contentHash(text: []const u8) u64 {
+    return std.hash.Wyhash.hash(0, text);
+}
+
+/// Open (creating if needed) `path`, or ~/.aesop/undo when it's null
+pub fn openUndoDir(path: ?[]const u8) !std.fs.Dir {
+    if (path) |dir_path| {
+        try std.fs.cwd().makePath(dir_path);
+        return std.fs.cwd().openDir(dir_path, .{});
+    }
+
+    const home = std.posix.getenv("HOME") orelse return error.NoHomeDirectory;
+    var home_dir = try std.fs.openDirAbsolute(home, .{});
+    defer home_dir.close();
+
+    try home_dir.makePath(".aesop/undo");
+    return home_dir.openDir(".aesop/undo", .{});
+}
+
+/// History file name for an absolute path
+fn fileName(buf: *[32]u8, abs_path: []const u8) []const u8 {
+    const hash = std.hash.Wyhash.hash(0, abs_path);
+    return std.fmt.bufPrint(buf, "{x:0>16}.undo", .{hash}) catch unreachable;
+}
+
+/// Write history for `abs_path`, whose content is currently `text`
+pub fn save(
+    dir: std.fs.Dir,
+    allocator: std.mem.Allocator,
+    history: *const Undo.UndoHistory,
+    abs_path: []const u8,
+    text: []const u8,
+) !void {
+    var name_buf: [32]u8 = undefined;
+    const name = fileName(&name_buf, abs_path);
+
+    // Nothing worth keeping; drop any old file so it can't be restored later
+    if (history.nodes.items.len == 0) {
+        dir.deleteFile(name) catch {};
+        return;
+    }
+
+    const data = try encode(allocator, history, abs_path, contentHash(text));
+    defer allocator.free(data);
+
+    // Write to a temp file and rename so a crash never leaves a torn history
+    var tmp_buf: [40]u8 = undefined;
+    const tmp_name = try std.fmt.bufPrint(&tmp_buf, "{s}.tmp", .{name});
+    // A write that fails partway can leave the temp file behind too
+    errdefer dir.deleteFile(tmp_name) catch {};
+    try dir.writeFile(.{ .sub_path = tmp_name, .data = data });
+    try dir.rename(tmp_name, name);
+}
+
+/// Load history for `abs_path` if one exists and matches `text`
+/// Mismatched or unreadable files are deleted and null is returned.
+pub fn load(
+    dir: std.fs.Dir,
+    allocator: std.mem.Allocator,
+    abs_path: []const u8,
+    text: []const u8,
+) !?Undo.UndoHistory {
+    var name_buf: [32]u8 = undefined;
+    const name = fileName(&name_buf, abs_path);
+
+    const data = dir.readFileAlloc(allocator, name, MAX_FILE_SIZE) catch |err| switch (err) {
+        error.FileNotFound => return null,
+        error.OutOfMemory => return err,
+        else => {
+            dir.deleteFile(name) catch {};
+            return null;
+        },
+    };
+    defer allocator.free(data);
+
+    return decode(allocator, data, abs_path, contentHash(text)) catch |err| switch (err) {
+        error.OutOfMemory => return err,
+        else => {
+            dir.deleteFile(name) catch {};
+            return null;
+        },
+    };
+}
+
+// === Encoding ===
+
+const Writer = struct {
+    bytes: std.ArrayList(u8) = .empty,
+    allocator: std.mem.Allocator,
+
+    fn int(self: *Writer, comptime T: type, value: T) !void {
+        var buf: [@sizeOf(T)]u8 = undefined;
+        std.mem.writeInt(T, &buf, value, .little);
+        try self.bytes.appendSlice(self.allocator, &buf);
+    }
+
+    fn slice(self: *Writer, data: []const u8) !void {
+        try self.int(u32, @intCast(data.len));
+        try self.bytes.appendSlice(self.allocator, data);
+    }
+
+    fn position(self: *Writer, pos: Cursor.Position) !void {
+        try self.int(u64, pos.line);
+        try self.int(u64, pos.col);
+    }
+};
+
+/// Serialize history into the on-disk format
+pub fn encode(
+    allocator: std.mem.Allocator,
+    history: *const Undo.UndoHistory,
+    abs_path: []const u8,
+    content_hash: u64,
+) ![]u8 {
+    var w = Writer{ .allocator = allocator };
+    errdefer w.bytes.deinit(allocator);
+
+    try w.bytes.appendSlice(allocator, MAGIC);
+    try w.int(u32, VERSION);
+    try w.slice(abs_path);
+    try w.int(u64, content_hash);
+    try w.int(u32, @intCast(history.nodes.items.len));
+    try w.int(u32, @intCast(history.current));
+
+    for (history.nodes.items) |node| {
+        try w.int(u32, if (node.parent) |p| @intCast(p) else NONE);
+        try w.int(u32, if (node.redo_child) |c| @intCast(c) else NONE);
+        try w.int(i64, node.group.timestamp);
+        try w.position(node.group.cursor_before);
+        try w.position(node.group.cursor_after);
+        try w.int(u32, @intCast(node.group.operations.items.len));
+
+        for (node.group.operations.items) |op| {
+            try w.int(u8, @intFromEnum(op.op_type));
+            try w.position(op.position);
+            try w.int(i64, op.timestamp);
+            try w.slice(op.text);
+            if (op.old_text) |old| {
+                try w.int(u8, 1);
+                try w.slice(old);
+            } else {
+                try w.int(u8, 0);
+            }
+        }
+    }
+
+    return w.bytes.toOwnedSlice(allocator);
+}
+
+// === Decoding ===
+
+const Reader = struct {
+    data: []const u8,
+    pos: usize = 0,
+
+    fn bytes(self: *Reader, n: usize) DecodeError![]const u8 {
+        if (n > self.data.len - self.pos) return error.InvalidUndoFile;
+        const out = self.data[self.pos .. self.pos + n];
+        self.pos += n;
+        return out;
+    }
+
+    fn int(self: *Reader, comptime T: type) DecodeError!T {
+        const raw = try self.bytes(@sizeOf(T));
+        return std.mem.readInt(T, raw[0..@sizeOf(T)], .little);
+    }
+
+    fn slice(self: *Reader) DecodeError![]const u8 {
+        const n = try self.int(u32);
+        return self.bytes(n);
+    }
+
+    fn position(self: *Reader) DecodeError!Cursor.Position {
+        const line = try self.int(u64);
+        const col = try self.int(u64);
+        return .{ .line = @intCast(line), .col = @intCast(col) };
+    }
+
+    fn index(self: *Reader) DecodeError!?usize {
+        const value = try self.int(u32);
+        return if (value == NONE) null else value;
+    }
+};
+
+/// Parse and validate a history file
+pub fn decode(
+    allocator: std.mem.Allocator,
+    data: []const u8,
+    abs_path: []const u8,
+    content_hash: u64,
+) DecodeError!Undo.UndoHistory {
+    var r = Reader{ .data = data };
+
+    if (!std.mem.eql(u8, try r.bytes(MAGIC.len), MAGIC)) return error.InvalidUndoFile;
+    if (try r.int(u32) != VERSION) return error.VersionMismatch;
+    if (!std.mem.eql(u8, try r.slice(), abs_path)) return error.PathMismatch;
+    if (try r.int(u64) != content_hash) return error.ContentMismatch;
+
+    const node_count = try r.int(u32);
+    const current = try r.int(u32);
+    if (node_count == 0 or current >= node_count) return error.InvalidUndoFile;
+
+    var history = Undo.UndoHistory.init(allocator);
+    errdefer history.deinit();
+
+    var i: usize = 0;
+    while (i < node_count) : (i += 1) {
+        const parent = try r.index();
+        const redo_child = try r.index();
+
+        // Only the root lacks a parent, and parents always precede children
+        if ((i == 0) != (parent == null)) return error.InvalidUndoFile;
+        if (parent) |p| if (p >= i) return error.InvalidUndoFile;
+        if (redo_child) |c| if (c <= i or c >= node_count) return error.InvalidUndoFile;
+
+        var group = Undo.OperationGroup.init(allocator, .{ .line = 0, .col = 0 });
+        var group_owned = true;
+        errdefer if (group_owned) group.deinit(allocator);
+
+        group.timestamp = try r.int(i64);
+        group.cursor_before = try r.position();
+        group.cursor_after = try r.position();
+
+        const op_count = try r.int(u32);
+        var j: usize = 0;
+        while (j < op_count) : (j += 1) {
+            const tag = try r.int(u8);
+            const op_type = std.meta.intToEnum(Undo.OperationType, tag) catch return error.InvalidUndoFile;
+            const position = try r.position();
+            const timestamp = try r.int(i64);
+            const text = try r.slice();
+            const old_text: ?[]const u8 = switch (try r.int(u8)) {
+                0 => null,
+                1 => try r.slice(),
+                else => return error.InvalidUndoFile,
+            };
+
+            var op = try Undo.Operation.init(allocator, op_type, position, text, old_text);
+            op.timestamp = timestamp;
+            group.operations.append(allocator, op) catch |err| {
+                op.deinit(allocator);
+                return err;
+            };
+        }
+
+        try history.nodes.append(allocator, .{
+            .group = group,
+            .parent = parent,
+            .children = .empty,
+            .redo_child = redo_child,
+        });
+        group_owned = false;
+
+        if (parent) |p| {
+            try history.nodes.items[p].children.append(allocator, i);
+        }
+    }
+
+    if (r.pos != data.len) return error.InvalidUndoFile;
+
+    // Redo pointers must name a real child
+    for (history.nodes.items, 0..) |node, idx| {
+        if (node.redo_child) |c| {
+            if (history.nodes.items[c].parent != idx) return error.InvalidUndoFile;
+        }
+    }
+
+    history.current = current;
+    return history;
+}
+
+// === Tests ===
+
+test "undo file: round trip and mismatch detection" {
+    const allocator = std.testing.allocator;
+
+    var history = Undo.UndoHistory.init(allocator);
+    defer history.deinit();
+
+    var first = Undo.OperationGroup.init(allocator, .{ .line = 0, .col = 0 });
+    try first.addOperation(allocator, try Undo.Operation.init(allocator, .insert, .{ .line = 0, .col = 0 }, "abc", null));
+    try history.push(first);
+    _ = history.getUndo();
+
+    var second = Undo.OperationGroup.init(allocator, .{ .line = 0, .col = 0 });
+    try second.addOperation(allocator, try Undo.Operation.init(allocator, .replace, .{ .line = 0, .col = 0 }, "xy", "z"));
+    try history.push(second);
+
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    try save(tmp.dir, allocator, &history, "/project/main.zig", "xy");
+
+    // Matching content restores the whole tree, branches included
+    var restored = (try load(tmp.dir, allocator, "/project/main.zig", "xy")).?;
+    defer restored.deinit();
+    try std.testing.expectEqual(@as(usize, 3), restored.stateCount());
+    try std.testing.expectEqual(@as(usize, 2), restored.currentSeq());
+    try std.testing.expectEqual(@as(usize, 2), restored.nodes.items[0].children.items.len);
+    try std.testing.expectEqualStrings("z", restored.nodes.items[2].group.operations.items[0].old_text.?);
+
+    // Changed content: history is discarded and the stale file removed
+    try std.testing.expect((try load(tmp.dir, allocator, "/project/main.zig", "edited")) == null);
+    try std.testing.expect((try load(tmp.dir, allocator, "/project/main.zig", "xy")) == null);
+}
+
+test "undo file: rejects corrupt data" {
+    const allocator = std.testing.allocator;
+    var history = Undo.UndoHistory.init(allocator);
+    defer history.deinit();
+
+    var group = Undo.OperationGroup.init(allocator, .{ .line = 0, .col = 0 });
+    try group.addOperation(allocator, try Undo.Operation.init(allocator, .insert, .{ .line = 0, .col = 0 }, "a", null));
+    try history.push(group);
+
+    const data = try encode(allocator, &history, "/f", contentHash("a"));
+    defer allocator.free(data);
+
+    try std.testing.expectError(error.InvalidUndoFile, decode(allocator, data[0 .. data.len - 3], "/f", contentHash("a")));
+    try std.testing.expectError(error.PathMismatch, decode(allocator, data, "/g", contentHash("a")));
+}
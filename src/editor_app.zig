@@ -13,20 +13,26 @@ const statusline = @import("render/statusline.zig");
 const messageline = @import("render/messageline.zig");
 const keyhints = @import("render/keyhints.zig");
 const contextbar = @import("render/contextbar.zig");
+const whichkey = @import("render/whichkey.zig");
 const paletteline = @import("render/paletteline.zig");
 const filefinderline = @import("render/filefinderline.zig");
 const filetree = @import("render/filetree.zig");
 const completionline = @import("render/completion.zig");
 const bufferswitcher = @import("render/bufferswitcher.zig");
+const undotree = @import("render/undotree.zig");
+const recovery = @import("render/recovery.zig");
+const conflict = @import("render/conflict.zig");
 const gutter = @import("render/gutter.zig");
 const input_mod = @import("terminal/input.zig");
 const Keymap = @import("editor/keymap.zig");
+const Rope = @import("buffer/rope.zig").Rope;
 // Conditionally import tree-sitter or stub based on build configuration
 const TreeSitter = if (build_options.enable_treesitter)
     @import("editor/treesitter.zig")
 else
     @import("editor/treesitter_stub.zig");
 const commandline = @import("editor/commandline.zig");
+const ex = @import("editor/ex.zig");
 
 /// Get configuration file path using XDG Base Directory specification
 /// Priority:
@@ -68,6 +74,9 @@ fn getConfigPath(allocator: std.mem.Allocator) !?[]const u8 {
     return try allocator.dupe(u8, "aesop.conf");
 }
 
+/// Signal received by the handler, picked up by the event loop (0 = none)
+var pending_signal = std.atomic.Value(i32).init(0);
+
 /// Editor application
 pub const EditorApp = struct {
     editor: Editor,
@@ -76,8 +85,8 @@ pub const EditorApp = struct {
     running: bool,
     gutter_config: gutter.GutterConfig,
     mouse_drag_start: ?Cursor.Position,
-    syntax_parser: ?TreeSitter.Parser,
     command_buffer: std.ArrayList(u8), // Command line input buffer (for :q, :w, etc.)
+    line_scratch: std.ArrayList(u8), // Visible part of a line split across rope leaves, reused per line
 
     /// Initialize editor application
     pub fn init(allocator: std.mem.Allocator) !EditorApp {
@@ -103,6 +112,9 @@ pub const EditorApp = struct {
         editor.config.deinit();
         editor.config = config;
 
+        // Key bindings from the config replace the defaults (problems show as messages)
+        if (config_path) |path| _ = try editor.applyKeyBindings(path);
+
         var renderer = try Renderer.init(allocator);
         errdefer renderer.deinit();
 
@@ -115,6 +127,7 @@ pub const EditorApp = struct {
                 .absolute,
             .show_git_status = false, // TODO: Future feature
             .show_diagnostics = true, // Enable diagnostic icons in gutter
+            .show_folds = editor.config.fold_markers,
             .width = 5,
         };
 
@@ -125,8 +138,8 @@ pub const EditorApp = struct {
             .running = false,
             .gutter_config = gutter_cfg,
             .mouse_drag_start = null,
-            .syntax_parser = null,
             .command_buffer = .{}, // Unmanaged ArrayList
+            .line_scratch = .empty,
         };
 
         // Install signal handlers for terminal cleanup on crash
@@ -137,21 +150,28 @@ pub const EditorApp = struct {
 
     /// Clean up
     pub fn deinit(self: *EditorApp) void {
-        if (self.syntax_parser) |*parser| {
-            parser.deinit();
-        }
         self.command_buffer.deinit(self.allocator);
+        self.line_scratch.deinit(self.allocator);
         self.editor.deinit();
         self.renderer.deinit();
     }
 
-    /// Signal handler for SIGINT and SIGTERM
+    /// Signal handler for SIGINT, SIGTERM and SIGHUP
+    /// Writing swap files isn't signal-safe, so the event loop does that and exits.
     fn signalHandler(sig: i32) callconv(.c) void {
-        _ = sig;
-        // Emergency cleanup - restore terminal state
+        // A second signal means the loop isn't getting to it; leave right away
+        if (pending_signal.swap(sig, .seq_cst) != 0) {
+            renderer_mod.emergencyCleanup();
+            std.process.exit(128 + @as(u8, @intCast(sig)));
+        }
+    }
+
+    /// Save what we can and exit after a signal
+    fn exitOnSignal(self: *EditorApp, sig: i32) noreturn {
+        self.editor.flushSwapFiles();
+        self.renderer.exitRawMode() catch {};
         renderer_mod.emergencyCleanup();
-        // Exit immediately
-        std.process.exit(130); // 128 + SIGINT(2)
+        std.process.exit(128 + @as(u8, @intCast(sig)));
     }
 
     /// Install signal handlers for cleanup on crash/kill
@@ -172,29 +192,9 @@ pub const EditorApp = struct {
 
         // Install handler for SIGTERM (kill command)
         posix.sigaction(posix.SIG.TERM, &act, null);
-    }
-
-    /// Get or create parser for current buffer
-    fn ensureParser(self: *EditorApp) !void {
-        // Get active buffer
-        const buffer = self.editor.getActiveBuffer() orelse return;
-
-        // Determine language from buffer name
-        const buffer_name = buffer.metadata.getName();
-        const language = TreeSitter.Language.fromFilename(buffer_name);
 
-        // If we have a parser and it's for the right language, keep it
-        if (self.syntax_parser) |existing| {
-            if (existing.language == language) {
-                return;
-            }
-            // Wrong language - cleanup and recreate
-            var parser = self.syntax_parser.?;
-            parser.deinit();
-        }
-
-        // Create new parser for this language
-        self.syntax_parser = try TreeSitter.Parser.init(self.allocator, language);
+        // Install handler for SIGHUP (terminal closed)
+        posix.sigaction(posix.SIG.HUP, &act, null);
     }
 
     /// Run the editor
@@ -205,6 +205,10 @@ pub const EditorApp = struct {
         } else {
             try self.editor.newBuffer();
         }
+        self.editor.reportOrphanedSwaps();
+
+        // Unsaved edits must survive whatever made us bail out
+        errdefer self.editor.flushSwapFiles();
 
         // Enter raw mode
         try self.renderer.enterRawMode();
@@ -217,6 +221,9 @@ pub const EditorApp = struct {
         var parser = input_mod.Parser{};
 
         while (self.running) {
+            const sig = pending_signal.load(.seq_cst);
+            if (sig != 0) self.exitOnSignal(sig);
+
             // Render
             try self.render();
 
@@ -231,13 +238,37 @@ pub const EditorApp = struct {
                 }
             }
 
+            // Process language server responses and notifications
+            self.editor.pollLsp();
+
+            // Keep swap files current for crash recovery
+            self.editor.updateSwapFiles();
+
+            // Pick up files rewritten by other programs
+            self.editor.checkExternalChanges();
+
             // Small sleep to avoid busy loop
             std.Thread.sleep(5 * std.time.ns_per_ms);
         }
+
+        // Quitting on purpose abandons any unsaved changes
+        self.editor.releaseSwapFiles();
     }
 
     /// Handle input event
     fn handleEvent(self: *EditorApp, event: input_mod.Event) !void {
+        // Crash recovery prompt takes precedence over everything
+        if (self.editor.swap_recovery != null) {
+            try self.handleRecoveryInput(event);
+            return;
+        }
+
+        // Then a file changing on disk under unsaved edits
+        if (self.editor.disk_conflict != null) {
+            try self.handleConflictInput(event);
+            return;
+        }
+
         // Handle palette input separately
         if (self.editor.palette.visible) {
             try self.handlePaletteInput(event);
@@ -263,6 +294,12 @@ pub const EditorApp = struct {
             return;
         }
 
+        // Handle undo tree input separately
+        if (self.editor.undo_tree_visible) {
+            try self.handleUndoTreeInput(event);
+            return;
+        }
+
         // Handle command mode input separately
         if (self.editor.getMode() == .command) {
             try self.handleCommandInput(event);
@@ -271,8 +308,9 @@ pub const EditorApp = struct {
 
         switch (event) {
             .char => |c| {
-                // Convert to keymap key
-                const key = Keymap.Key{ .char = c.codepoint };
+                // Convert to keymap key (Ctrl+letter as its control character, the way keymaps bind it)
+                const ctrl_letter = c.mods.ctrl and c.codepoint >= 'a' and c.codepoint <= 'z';
+                const key = Keymap.Key{ .char = if (ctrl_letter) c.codepoint & 0x1f else c.codepoint };
 
                 // Check for command mode entry (: in normal mode)
                 if (c.codepoint == ':' and !c.mods.ctrl and !c.mods.alt and
@@ -283,6 +321,32 @@ pub const EditorApp = struct {
                     return;
                 }
 
+                // : in select mode starts a command on the selected lines
+                if (c.codepoint == ':' and !c.mods.ctrl and !c.mods.alt and
+                    self.editor.getMode() == .select)
+                {
+                    if (self.editor.selections.primary(self.allocator)) |sel| {
+                        const range = sel.range();
+                        self.editor.visual_lines = .{ .first = range.start.line, .last = range.end.line };
+                    }
+                    try self.editor.enterCommandMode();
+                    try self.command_buffer.resize(self.allocator, 0);
+                    try self.command_buffer.appendSlice(self.allocator, "'<,'>");
+                    return;
+                }
+
+                // Alt-! appends shell output after each selection (keymaps don't carry modifiers)
+                if (c.codepoint == '!' and c.mods.alt and !self.editor.pending_command.isWaiting() and
+                    (self.editor.getMode() == .normal or self.editor.getMode() == .select))
+                {
+                    var ctx = @import("editor/command.zig").Context{ .editor = &self.editor };
+                    switch (self.editor.command_registry.execute("shell_append", &ctx)) {
+                        .success => {},
+                        .error_msg => |msg| try self.editor.messages.add(msg, .error_msg),
+                    }
+                    return;
+                }
+
                 // Check for quit command (Ctrl+Q in normal mode)
                 if (c.mods.ctrl and c.codepoint == 'q' and
                     self.editor.getMode() == .normal)
@@ -539,6 +603,98 @@ pub const EditorApp = struct {
         }
     }
 
+    /// Handle undo tree input (moving the selection previews that state)
+    fn handleUndoTreeInput(self: *EditorApp, event: input_mod.Event) !void {
+        switch (event) {
+            .key => |k| {
+                switch (k.key) {
+                    .escape => try self.editor.closeUndoTree(false),
+                    .enter => try self.editor.closeUndoTree(true),
+                    .up => try self.editor.moveUndoTreeSelection(-1),
+                    .down => try self.editor.moveUndoTreeSelection(1),
+                    else => {},
+                }
+            },
+            .char => |c| {
+                switch (c.codepoint) {
+                    'k' => try self.editor.moveUndoTreeSelection(-1),
+                    'j' => try self.editor.moveUndoTreeSelection(1),
+                    'q' => try self.editor.closeUndoTree(false),
+                    else => {},
+                }
+            },
+            else => {},
+        }
+    }
+
+    /// Handle swap recovery prompt input
+    fn handleRecoveryInput(self: *EditorApp, event: input_mod.Event) !void {
+        switch (event) {
+            .key => |k| {
+                switch (k.key) {
+                    .escape => try self.editor.resolveSwapRecovery(.defer_decision),
+                    .up => self.editor.scrollPromptDiff(-1),
+                    .down => self.editor.scrollPromptDiff(1),
+                    .page_up => self.editor.scrollPromptDiff(-10),
+                    .page_down => self.editor.scrollPromptDiff(10),
+                    else => {},
+                }
+            },
+            .char => |c| {
+                switch (c.codepoint) {
+                    'r' => try self.editor.resolveSwapRecovery(.recover),
+                    'x' => try self.editor.resolveSwapRecovery(.discard),
+                    'd' => {
+                        const pending = &self.editor.swap_recovery.?;
+                        pending.show_diff = !pending.show_diff;
+                    },
+                    'k' => self.editor.scrollPromptDiff(-1),
+                    'j' => self.editor.scrollPromptDiff(1),
+                    else => {},
+                }
+            },
+            else => {},
+        }
+    }
+
+    /// Handle disk conflict prompt input
+    fn handleConflictInput(self: *EditorApp, event: input_mod.Event) !void {
+        switch (event) {
+            .key => |k| {
+                switch (k.key) {
+                    .escape => try self.editor.resolveDiskConflict(.keep),
+                    .up => self.editor.scrollPromptDiff(-1),
+                    .down => self.editor.scrollPromptDiff(1),
+                    .page_up => self.editor.scrollPromptDiff(-10),
+                    .page_down => self.editor.scrollPromptDiff(10),
+                    else => {},
+                }
+            },
+            .char => |c| {
+                switch (c.codepoint) {
+                    'r' => try self.editor.resolveDiskConflict(.reload),
+                    'w' => self.editor.resolveDiskConflict(.overwrite) catch |err| {
+                        const msg = switch (err) {
+                            error.ReadOnlyFile => "File is read-only",
+                            error.PermissionDenied => "Permission denied writing file",
+                            error.BackupFailed => "Couldn't write the backup file, so nothing was saved (see backup_on_save)",
+                            else => "Failed to save file",
+                        };
+                        try self.editor.messages.add(msg, .error_msg);
+                    },
+                    'd' => {
+                        const pending = &self.editor.disk_conflict.?;
+                        pending.show_diff = !pending.show_diff;
+                    },
+                    'k' => self.editor.scrollPromptDiff(-1),
+                    'j' => self.editor.scrollPromptDiff(1),
+                    else => {},
+                }
+            },
+            else => {},
+        }
+    }
+
     /// Execute the currently selected buffer switcher selection (switch buffer)
     fn executeBufferSwitcherSelection(self: *EditorApp) !void {
         const buffers = try bufferswitcher.getBufferList(&self.editor, self.allocator);
@@ -606,25 +762,7 @@ pub const EditorApp = struct {
         try self.command_buffer.resize(self.allocator, 0);
         try self.editor.enterNormalMode();
 
-        // Execute command
-        if (cmd.shouldQuit()) {
-            if (cmd == .quit and cmd.quit.force) {
-                // Force quit
-                self.running = false;
-            } else {
-                // Check if buffer is modified
-                const buffer = self.editor.getActiveBuffer();
-                const is_modified = if (buffer) |buf| buf.metadata.modified else false;
-
-                if (is_modified and !cmd.quit.force) {
-                    const msg = "No write since last change (use :q! to force)";
-                    try self.editor.messages.add(msg, .error_msg);
-                } else {
-                    self.running = false;
-                }
-            }
-        }
-
+        // Execute command (write before quitting so :wq only quits once saved)
         if (cmd.shouldWrite()) {
             // Save file - need mutable buffer
             const active_id = self.editor.buffer_manager.active_buffer_id orelse return;
@@ -636,30 +774,70 @@ pub const EditorApp = struct {
                 else => null,
             };
 
-            if (save_path) |path| {
+            var options = self.editor.config.saveOptions();
+            options.force = cmd.isForced();
+            const result = if (save_path) |path|
                 // Save to specified path
-                try buffer.saveAs(path);
-            } else {
+                buffer.saveAs(path, options)
+            else if (buffer.metadata.filepath != null)
                 // Save to current file
-                if (buffer.metadata.filepath) |_| {
-                    try buffer.save();
-                } else {
-                    const msg = "No file name (use :w <filename>)";
-                    try self.editor.messages.add(msg, .error_msg);
-                    return;
-                }
-            }
+                buffer.save(options)
+            else {
+                const msg = "No file name (use :w <filename>)";
+                try self.editor.messages.add(msg, .error_msg);
+                return;
+            };
+
+            result catch |err| {
+                const msg = switch (err) {
+                    error.ReadOnlyFile => "File is read-only",
+                    error.PermissionDenied => "Permission denied writing file",
+                    error.BackupFailed => "Couldn't write the backup file, so nothing was saved (see backup_on_save)",
+                    error.FileChangedOnDisk => "File changed on disk since it was read (use :w! to overwrite)",
+                    error.UnrepresentableCharacter => "Text can't be written in this file's encoding (see :set fileencoding)",
+                    else => "Failed to save file",
+                };
+                try self.editor.messages.add(msg, .error_msg);
+                return; // Don't quit with unsaved changes
+            };
 
             const msg = try std.fmt.allocPrint(self.allocator, "Saved {s}", .{buffer.metadata.getName()});
             defer self.allocator.free(msg);
             try self.editor.messages.add(msg, .info);
         }
 
+        if (cmd.shouldQuit()) {
+            // Check if buffer is modified
+            const buffer = self.editor.getActiveBuffer();
+            const is_modified = if (buffer) |buf| buf.metadata.modified else false;
+
+            if (is_modified and !cmd.isForced()) {
+                const msg = "No write since last change (use :q! to force)";
+                try self.editor.messages.add(msg, .error_msg);
+            } else {
+                self.running = false;
+            }
+        }
+
         if (cmd == .edit) {
             // Open new file
             try self.editor.openFile(cmd.edit.path);
         }
 
+        if (cmd == .set) {
+            self.editor.setOption(cmd.set.option, cmd.set.value);
+        }
+
+        if (cmd == .ex) {
+            ex.execute(&self.editor, cmd.ex) catch |err| {
+                try self.editor.messages.add(ex.errorMessage(err), .error_msg);
+            };
+        }
+
+        if (cmd == .invalid) {
+            try self.editor.messages.add(cmd.invalid, .error_msg);
+        }
+
         if (cmd == .unknown) {
             if (cmd.unknown.len > 0) {
                 const msg = try std.fmt.allocPrint(self.allocator, "Unknown command: {s}", .{cmd.unknown});
@@ -709,7 +887,7 @@ pub const EditorApp = struct {
             const viewport_height: usize = size.height -| 2;
             const max_scroll = if (total_lines > viewport_height) total_lines - viewport_height else 0;
 
-            self.editor.scroll_offset = @min(self.editor.scroll_offset + @as(usize, @intCast(delta)), max_scroll);
+            self.editor.scroll_offset = @min(self.editor.lineAtRow(self.editor.scroll_offset, @intCast(delta)), max_scroll);
         }
     }
 
@@ -747,8 +925,8 @@ pub const EditorApp = struct {
         const gutter_end = tree_offset + gutter_width;
         if (screen_col < gutter_end) return;
 
-        // Convert screen position to buffer position (accounting for file tree and horizontal scroll)
-        const buffer_line = viewport.start_line + screen_row;
+        // Convert screen position to buffer position (accounting for file tree, folds and horizontal scroll)
+        const buffer_line = self.editor.lineAtRow(viewport.start_line, screen_row);
         const buffer_col = (screen_col - gutter_end) + self.editor.col_offset;
 
         // Clamp to valid buffer position
@@ -803,8 +981,8 @@ pub const EditorApp = struct {
         const gutter_end = tree_offset + gutter_width;
         if (screen_col < gutter_end) return;
 
-        // Convert screen position to buffer position (accounting for file tree and horizontal scroll)
-        const buffer_line = viewport.start_line + screen_row;
+        // Convert screen position to buffer position (accounting for file tree, folds and horizontal scroll)
+        const buffer_line = self.editor.lineAtRow(viewport.start_line, screen_row);
         const buffer_col = (screen_col - gutter_end) + self.editor.col_offset;
 
         // Clamp to valid buffer position
@@ -887,6 +1065,9 @@ pub const EditorApp = struct {
         return self.editor.palette.visible or
             self.editor.file_finder.visible or
             self.editor.buffer_switcher_visible or
+            self.editor.undo_tree_visible or
+            self.editor.swap_recovery != null or
+            self.editor.disk_conflict != null or
             self.editor.search.incremental or
             self.editor.pending_command.isWaiting() or
             self.isEmptyBuffer();
@@ -900,9 +1081,9 @@ pub const EditorApp = struct {
         // Buffer is empty if it has 0 lines, or 1 line with no content
         if (line_count == 0) return true;
         if (line_count == 1) {
-            const text = buffer.getText() catch return false;
-            defer self.allocator.free(text);
-            return text.len == 0 or (text.len == 1 and text[0] == '\n');
+            var cursor = buffer.rope.cursorAt(0);
+            const len = buffer.rope.len();
+            return len == 0 or (len == 1 and cursor.peekByte() == '\n');
         }
         return false;
     }
@@ -912,6 +1093,13 @@ pub const EditorApp = struct {
         // Clear screen
         self.renderer.clear();
 
+        // Mapped files truncated since the last frame can't be read past their new end
+        self.editor.guardMappedFiles();
+
+        // Fold ranges follow edits made since the last key (undo, external reloads);
+        // the gutter's markers need them even where nothing was folded yet
+        _ = (if (self.gutter_config.show_folds) self.editor.refreshFolds() else self.editor.usedFolds()) catch null;
+
         const size = self.renderer.getSize();
 
         // Check if we have a message to display
@@ -968,6 +1156,7 @@ pub const EditorApp = struct {
             file_uri,
             self.editor.getTheme(),
             gutter_col_offset,
+            if (self.gutter_config.show_folds) self.editor.activeFolds() else null,
         );
 
         // Render message line (if message exists)
@@ -1018,6 +1207,9 @@ pub const EditorApp = struct {
         if (!self.editor.palette.visible and
             !self.editor.file_finder.visible and
             !self.editor.buffer_switcher_visible and
+            !self.editor.undo_tree_visible and
+            self.editor.swap_recovery == null and
+            self.editor.disk_conflict == null and
             self.editor.getMode() != .command)
         {
             try self.renderCursor(size.height - reserved_lines);
@@ -1051,7 +1243,7 @@ pub const EditorApp = struct {
             const position = popup.calculatePosition(
                 size.width,
                 size.height,
-                @intCast(hover_cursor.line -| self.editor.scroll_offset),
+                @intCast(self.editor.rowOfLine(self.editor.scroll_offset, hover_cursor.line) orelse 0),
                 @intCast(hover_cursor.col),
                 dims.width,
                 dims.height,
@@ -1068,6 +1260,9 @@ pub const EditorApp = struct {
             );
         }
 
+        // Render which-key popup once a partial key sequence has waited long enough
+        try whichkey.render(&self.renderer, &self.editor, self.allocator);
+
         // Render buffer switcher (overlay on top of everything)
         try bufferswitcher.render(
             &self.renderer,
@@ -1077,6 +1272,13 @@ pub const EditorApp = struct {
             self.editor.buffer_switcher_selected,
         );
 
+        // Render undo tree (overlay on top of everything)
+        try undotree.render(&self.renderer, &self.editor, self.allocator);
+
+        // Render crash recovery prompt (above every other overlay)
+        try conflict.render(&self.renderer, &self.editor, self.allocator);
+        try recovery.render(&self.renderer, &self.editor, self.allocator);
+
         // Perform render
         try self.renderer.render();
     }
@@ -1097,8 +1299,8 @@ pub const EditorApp = struct {
                 continue; // Cursor is off-screen
             }
 
-            // Calculate screen position
-            const screen_row = @as(u16, @intCast(cursor_pos.line - viewport.start_line));
+            // Calculate screen position (cursors in closed folds aren't drawn)
+            const screen_row = @as(u16, @intCast(self.editor.rowOfLine(viewport.start_line, cursor_pos.line) orelse continue));
             const screen_col = gutter_width + @as(u16, @intCast(cursor_pos.col));
 
             // Get current cell at cursor position or create empty cell
@@ -1141,63 +1343,43 @@ pub const EditorApp = struct {
         const in_visual_mode = self.editor.getMode() == .select and !primary_sel.isCollapsed();
         const sel_range = if (in_visual_mode) primary_sel.range() else null;
 
-        // Get buffer text
-        const text = try buffer.getText();
-        defer self.allocator.free(text);
+        // Text is drawn straight from the rope's leaves; nothing copies the buffer per frame
+        const large = buffer.metadata.large_file;
 
-        // Get search matches (for highlighting)
-        const search_matches = if (self.editor.search.active)
-            try self.editor.search.findAll(text, self.allocator)
+        // Get search matches on the visible lines (for highlighting)
+        var no_matches = [_]@import("editor/search.zig").Search.Match{};
+        const search_matches: []@import("editor/search.zig").Search.Match = if (self.editor.search.active)
+            try self.editor.search.findAllInRope(&buffer.rope, viewport.start_line, viewport.end_line, self.allocator)
         else
-            &[_]@import("editor/search.zig").Search.Match{};
-        defer if (self.editor.search.active) self.allocator.free(search_matches);
-
-        // Get syntax highlights (if enabled)
-        const syntax_highlights = if (self.editor.config.syntax_highlighting) blk: {
-            try self.ensureParser();
-            if (self.syntax_parser) |*parser| {
-                // Parse text to create syntax tree
-                parser.parse(text) catch {
-                    // If parsing fails, fall back to no highlights
-                    break :blk &[_]TreeSitter.HighlightToken{};
-                };
-                break :blk try parser.getHighlights(text, viewport.start_line, viewport.end_line);
+            &no_matches;
+        defer if (search_matches.len > 0) self.allocator.free(search_matches);
+        self.editor.reportSearchLimit();
+
+        // Get syntax highlights (if enabled; never for large files)
+        const highlight = self.editor.config.syntax_highlighting and !large;
+        const syntax_highlights = if (highlight) blk: {
+            // The editor keeps the tree current (it's shared with the syntax text objects)
+            if (self.editor.syntaxParser()) |parser| {
+                break :blk try parser.getHighlights(&buffer.rope, viewport.start_line, viewport.end_line);
             }
+            // If parsing fails, fall back to no highlights
             break :blk &[_]TreeSitter.HighlightToken{};
         } else &[_]TreeSitter.HighlightToken{};
-        defer if (self.editor.config.syntax_highlighting and self.syntax_parser != null) self.allocator.free(syntax_highlights);
+        defer if (highlight) self.allocator.free(syntax_highlights);
 
         // Simple line rendering (just display lines)
-        var line_num: usize = viewport.start_line;
+        // The rope's line index jumps straight to the viewport instead of scanning from the top
+        const fold_set = self.editor.activeFolds();
         var row: u16 = 0;
-        var i: usize = 0;
-
-        // Skip lines before viewport
-        var current_line: usize = 0;
-        while (i < text.len and current_line < viewport.start_line) {
-            if (text[i] == '\n') {
-                current_line += 1;
-            }
-            i += 1;
-        }
+        var lines = buffer.rope.lines(viewport.start_line);
 
         // Render visible lines
-        while (i < text.len and line_num < viewport.end_line) {
-            const line_start = i;
-            var line_end = i;
+        while (lines.next()) |line| {
+            if (line.number >= viewport.end_line) break;
+            const line_num = line.number;
 
-            // Find end of line
-            while (line_end < text.len and text[line_end] != '\n') {
-                line_end += 1;
-            }
-
-            const line_text_full = text[line_start..line_end];
-
-            // Apply horizontal scrolling: slice line text from col_offset
-            const line_text = if (self.editor.col_offset < line_text_full.len)
-                line_text_full[self.editor.col_offset..]
-            else
-                "";
+            // Apply horizontal scrolling: the line's text from col_offset, no more than fits
+            const line_text = try self.visibleLineText(line, self.editor.col_offset, @as(usize, size.width) * 4);
 
             // Check if this line has selection or search matches
             const line_has_selection = if (sel_range) |range|
@@ -1234,7 +1416,7 @@ pub const EditorApp = struct {
                     sel_range,
                     search_matches,
                     syntax_highlights,
-                    text, // Full text buffer for byte offset calculation
+                    line.start, // Syntax tokens are positioned by byte offset
                     text_max_width,
                 );
             } else {
@@ -1250,15 +1432,48 @@ pub const EditorApp = struct {
                 );
             }
 
-            // Move to next line
-            i = if (line_end < text.len) line_end + 1 else line_end;
-            line_num += 1;
+            // A closed fold shows its first line and how many more it hides, then skips them
+            if (fold_set) |folds| {
+                if (folds.isClosed(line_num)) {
+                    const next = folds.nextVisible(line_num);
+                    var summary_buf: [48]u8 = undefined;
+                    const summary = std.fmt.bufPrint(&summary_buf, "  ··· {d} lines", .{next - line_num - 1}) catch "";
+                    const shown = std.unicode.utf8CountCodepoints(line_text) catch line_text.len;
+                    self.renderer.writeText(
+                        row,
+                        buffer_start_col + @as(u16, @intCast(@min(shown, size.width))),
+                        summary,
+                        self.editor.getTheme().ui.gutter_line_number,
+                        .default,
+                        .{},
+                        text_max_width,
+                    );
+                    if (next >= buffer.rope.lineCount()) break;
+                    lines = buffer.rope.lines(next);
+                }
+            }
+
             row += 1;
 
             if (row >= visible_lines) break;
         }
     }
 
+    /// Part of a line from `skip` bytes in, at most `limit` bytes long
+    /// Borrowed from the rope when it lies within one leaf, else gathered into `line_scratch`.
+    fn visibleLineText(self: *EditorApp, line: Rope.Line, skip: usize, limit: usize) ![]const u8 {
+        const start = @min(line.start + skip, line.end);
+        const end = @min(line.end, start + limit);
+        var pieces = line.rope.chunks(start, end);
+        const first = pieces.next() orelse return "";
+        if (first.len == end - start) return first;
+
+        self.line_scratch.clearRetainingCapacity();
+        try self.line_scratch.appendSlice(self.allocator, first);
+        while (pieces.next()) |piece| try self.line_scratch.appendSlice(self.allocator, piece);
+        return self.line_scratch.items;
+    }
+
     /// Render a line with selection, search, and syntax highlighting
     /// Optimized: batches consecutive characters with the same style (10-100x faster)
     fn renderLineWithHighlights(
@@ -1271,7 +1486,7 @@ pub const EditorApp = struct {
         opt_sel_range: anytype,
         search_matches: []const @import("editor/search.zig").Search.Match,
         syntax_highlights: []const TreeSitter.HighlightToken,
-        full_text: []const u8, // Full text buffer for byte offset calculation
+        line_start_byte: usize, // Buffer offset of the line's first byte
         max_width: u16,
     ) !void {
         if (line_text.len == 0) return;
@@ -1295,19 +1510,6 @@ pub const EditorApp = struct {
         var batch_start_screen_col: u16 = start_col;
         var current_style: ?StyleState = null;
 
-        // Calculate byte offset for start of this line (once)
-        var line_start_byte: usize = 0;
-        var temp_line: usize = 0;
-        for (full_text, 0..) |byte, idx| {
-            if (temp_line == line_num) {
-                line_start_byte = idx;
-                break;
-            }
-            if (byte == '\n') {
-                temp_line += 1;
-            }
-        }
-
         // Helper to flush current batch
         const flushBatch = struct {
             fn call(
@@ -15,6 +15,7 @@ pub const Client = struct {
     read_buffer: [65536]u8, // 64KB buffer for reading messages
     notification_handler: ?NotificationHandler, // Handler for server notifications
     notification_ctx: ?*anyopaque, // Context for notification handler
+    server_capabilities: ServerCapabilities, // Populated from the initialize response
 
     pub const State = enum {
         uninitialized,
@@ -45,6 +46,7 @@ pub const Client = struct {
             .read_buffer = undefined,
             .notification_handler = null,
             .notification_ctx = null,
+            .server_capabilities = .{},
         };
     }
 
@@ -85,7 +87,7 @@ pub const Client = struct {
         }
 
         self.state = .initialized;
-        _ = response;
+        self.server_capabilities = response.capabilities;
     }
 
     /// Send initialized notification
@@ -134,7 +136,7 @@ pub const Client = struct {
         callback: *const fn (ctx: ?*anyopaque, result: []const u8) anyerror!void,
         callback_ctx: ?*anyopaque,
     ) !u32 {
-        var process = &(self.process orelse return error.ProcessNotRunning);
+        const process = if (self.process) |*p| p else return error.ProcessNotRunning;
 
         const id = self.nextRequestId();
 
@@ -164,7 +166,7 @@ pub const Client = struct {
 
     /// Send JSON-RPC notification (no response expected)
     pub fn sendNotification(self: *Client, method: []const u8, params: anytype) !void {
-        var process = &(self.process orelse return error.ProcessNotRunning);
+        const process = if (self.process) |*p| p else return error.ProcessNotRunning;
 
         // Serialize notification using zigjr (notifications have no ID)
         const notification_json = try zigjr.composer.makeRequestJson(
@@ -279,7 +281,7 @@ pub const Client = struct {
 
     /// Poll for messages from server (non-blocking)
     pub fn poll(self: *Client) !void {
-        var process = &(self.process orelse return);
+        const process = if (self.process) |*p| p else return;
 
         if (!process.isRunning()) return;
 
@@ -358,7 +360,7 @@ pub const ServerCapabilities = struct {
 
 test "client: init and deinit" {
     const allocator = std.testing.allocator;
-    var client = Client.init(allocator);
+    var client = Client.init(allocator, null);
     defer client.deinit();
 
     try std.testing.expectEqual(Client.State.uninitialized, client.state);
@@ -366,7 +368,7 @@ test "client: init and deinit" {
 
 test "client: initialize request" {
     const allocator = std.testing.allocator;
-    var client = Client.init(allocator);
+    var client = Client.init(allocator, null);
     defer client.deinit();
 
     const params = InitializeParams{
@@ -380,7 +382,7 @@ test "client: initialize request" {
 
 test "client: initialize response" {
     const allocator = std.testing.allocator;
-    var client = Client.init(allocator);
+    var client = Client.init(allocator, null);
     defer client.deinit();
 
     const params = InitializeParams{
@@ -400,7 +402,7 @@ test "client: initialize response" {
 
 test "client: lifecycle" {
     const allocator = std.testing.allocator;
-    var client = Client.init(allocator);
+    var client = Client.init(allocator, null);
     defer client.deinit();
 
     // Initialize
@@ -7,16 +7,29 @@ const ResponseParser = @import("response_parser.zig");
 pub const Diagnostic = ResponseParser.Diagnostic;
 pub const DiagnosticSeverity = ResponseParser.DiagnosticSeverity;
 
+/// Diagnostics published for one URI, tagged with the LSP session that owns them
+pub const Entry = struct {
+    session_id: ?u32, // null when not published by a language server session
+    diagnostics: []Diagnostic,
+
+    fn deinit(self: *Entry, allocator: std.mem.Allocator) void {
+        for (self.diagnostics) |*diag| {
+            diag.deinit(allocator);
+        }
+        allocator.free(self.diagnostics);
+    }
+};
+
 /// Diagnostic manager - stores diagnostics per URI
 pub const DiagnosticManager = struct {
     allocator: std.mem.Allocator,
-    /// Map from file URI to diagnostics
-    diagnostics_by_uri: std.StringHashMap([]Diagnostic),
+    /// Map from file URI to diagnostics and their owning session
+    diagnostics_by_uri: std.StringHashMap(Entry),
 
     pub fn init(allocator: std.mem.Allocator) DiagnosticManager {
         return .{
             .allocator = allocator,
-            .diagnostics_by_uri = std.StringHashMap([]Diagnostic).init(allocator),
+            .diagnostics_by_uri = std.StringHashMap(Entry).init(allocator),
         };
     }
 
@@ -26,39 +39,44 @@ pub const DiagnosticManager = struct {
         while (iter.next()) |entry| {
             // Free the key (URI)
             self.allocator.free(entry.key_ptr.*);
-            // Free each diagnostic in the array
-            for (entry.value_ptr.*) |*diag| {
-                diag.deinit(self.allocator);
-            }
-            // Free the array itself
-            self.allocator.free(entry.value_ptr.*);
+            // Free the diagnostics
+            entry.value_ptr.deinit(self.allocator);
         }
         self.diagnostics_by_uri.deinit();
     }
 
     /// Update diagnostics for a given URI (takes ownership of uri and diagnostics)
     pub fn update(self: *DiagnosticManager, uri: []const u8, diagnostics: []Diagnostic) !void {
-        // Check if we already have diagnostics for this URI
-        if (self.diagnostics_by_uri.get(uri)) |old_diagnostics| {
-            // Free old diagnostics
-            for (old_diagnostics) |*diag| {
-                diag.deinit(self.allocator);
-            }
-            self.allocator.free(old_diagnostics);
+        try self.updateForSession(null, uri, diagnostics);
+    }
 
-            // Update with new diagnostics (reuse existing key)
-            try self.diagnostics_by_uri.put(uri, diagnostics);
+    /// Update diagnostics published by an LSP session (takes ownership of uri and diagnostics)
+    pub fn updateForSession(self: *DiagnosticManager, session_id: ?u32, uri: []const u8, diagnostics: []Diagnostic) !void {
+        const new_entry = Entry{ .session_id = session_id, .diagnostics = diagnostics };
+
+        // Check if we already have diagnostics for this URI
+        if (self.diagnostics_by_uri.getPtr(uri)) |old_entry| {
+            // Free old diagnostics and update in place (reuse existing key)
+            old_entry.deinit(self.allocator);
+            old_entry.* = new_entry;
             // Free the new URI since we're reusing the old key
             self.allocator.free(uri);
         } else {
             // New URI, insert directly
-            try self.diagnostics_by_uri.put(uri, diagnostics);
+            try self.diagnostics_by_uri.put(uri, new_entry);
         }
     }
 
     /// Get diagnostics for a given URI (returns borrowed slice)
     pub fn get(self: *const DiagnosticManager, uri: []const u8) ?[]const Diagnostic {
-        return self.diagnostics_by_uri.get(uri);
+        const entry = self.diagnostics_by_uri.get(uri) orelse return null;
+        return entry.diagnostics;
+    }
+
+    /// Get the session that published diagnostics for a URI
+    pub fn getOwner(self: *const DiagnosticManager, uri: []const u8) ?u32 {
+        const entry = self.diagnostics_by_uri.get(uri) orelse return null;
+        return entry.session_id;
     }
 
     /// Get diagnostics for a specific line in a file
@@ -148,10 +166,23 @@ pub const DiagnosticManager = struct {
     pub fn clear(self: *DiagnosticManager, uri: []const u8) void {
         if (self.diagnostics_by_uri.fetchRemove(uri)) |entry| {
             self.allocator.free(entry.key);
-            for (entry.value) |*diag| {
-                diag.deinit(self.allocator);
-            }
-            self.allocator.free(entry.value);
+            var value = entry.value;
+            value.deinit(self.allocator);
+        }
+    }
+
+    /// Clear all diagnostics owned by an LSP session (e.g. after its server exits)
+    pub fn clearSession(self: *DiagnosticManager, session_id: u32) void {
+        var iter = self.diagnostics_by_uri.iterator();
+        while (iter.next()) |entry| {
+            if (entry.value_ptr.session_id != session_id) continue;
+
+            const key = entry.key_ptr.*;
+            entry.value_ptr.deinit(self.allocator);
+            self.diagnostics_by_uri.removeByPtr(entry.key_ptr);
+            self.allocator.free(key);
+            // Removal invalidates the iterator; restart the scan
+            iter = self.diagnostics_by_uri.iterator();
         }
     }
 
@@ -160,10 +191,7 @@ pub const DiagnosticManager = struct {
         var iter = self.diagnostics_by_uri.iterator();
         while (iter.next()) |entry| {
             self.allocator.free(entry.key_ptr.*);
-            for (entry.value_ptr.*) |*diag| {
-                diag.deinit(self.allocator);
-            }
-            self.allocator.free(entry.value_ptr.*);
+            entry.value_ptr.deinit(self.allocator);
         }
         self.diagnostics_by_uri.clearRetainingCapacity();
     }
@@ -172,8 +200,8 @@ pub const DiagnosticManager = struct {
     pub fn getTotalCount(self: *const DiagnosticManager) usize {
         var total: usize = 0;
         var iter = self.diagnostics_by_uri.valueIterator();
-        while (iter.next()) |diagnostics| {
-            total += diagnostics.len;
+        while (iter.next()) |entry| {
+            total += entry.diagnostics.len;
         }
         return total;
     }
@@ -191,8 +219,8 @@ pub const DiagnosticManager = struct {
         var hints: usize = 0;
 
         var iter = self.diagnostics_by_uri.valueIterator();
-        while (iter.next()) |diagnostics| {
-            for (diagnostics.*) |diag| {
+        while (iter.next()) |entry| {
+            for (entry.diagnostics) |diag| {
                 switch (diag.severity) {
                     .@"error" => errors += 1,
                     .warning => warnings += 1,
@@ -333,3 +361,33 @@ test "diagnostics: get severest for line" {
     try std.testing.expect(severest != null);
     try std.testing.expectEqual(DiagnosticSeverity.@"error", severest.?.severity);
 }
+
+test "diagnostics: clear session drops only its entries" {
+    const allocator = std.testing.allocator;
+    var manager = DiagnosticManager.init(allocator);
+    defer manager.deinit();
+
+    const uris = [_][]const u8{ "file:///a.zig", "file:///b.py", "file:///c.zig" };
+    const owners = [_]u32{ 1, 2, 1 };
+    for (uris, owners) |uri, owner| {
+        var diagnostics = try allocator.alloc(Diagnostic, 1);
+        diagnostics[0] = Diagnostic{
+            .range = .{
+                .start = .{ .line = 0, .character = 0 },
+                .end = .{ .line = 0, .character = 1 },
+            },
+            .severity = .warning,
+            .code = null,
+            .source = null,
+            .message = try allocator.dupe(u8, "warning"),
+        };
+        try manager.updateForSession(owner, try allocator.dupe(u8, uri), diagnostics);
+    }
+
+    try std.testing.expectEqual(@as(?u32, 2), manager.getOwner("file:///b.py"));
+
+    manager.clearSession(1);
+    try std.testing.expectEqual(@as(usize, 1), manager.getTotalCount());
+    try std.testing.expect(manager.get("file:///a.zig") == null);
+    try std.testing.expect(manager.get("file:///b.py") != null);
+}
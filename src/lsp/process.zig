@@ -195,8 +195,8 @@ pub const Process = struct {
 
         // Read headers
         while (header_pos < header_buf.len) {
-            const byte = try stdout.reader().readByte();
-            header_buf[header_pos] = byte;
+            const n = try stdout.read(header_buf[header_pos .. header_pos + 1]);
+            if (n == 0) return error.EndOfStream;
             header_pos += 1;
 
             // Check for end of headers (\r\n\r\n)
@@ -240,6 +240,21 @@ pub const Process = struct {
     pub fn isRunning(self: *const Process) bool {
         return self.running;
     }
+
+    /// Check if the server has written output that can be read without blocking
+    pub fn hasPendingData(self: *const Process) bool {
+        const stdout = self.stdout orelse return false;
+
+        var fds = [_]std.posix.pollfd{.{
+            .fd = stdout.handle,
+            .events = std.posix.POLL.IN,
+            .revents = 0,
+        }};
+        const ready = std.posix.poll(&fds, 0) catch return false;
+
+        // HUP counts as pending so the reader observes EOF and the session can clean up
+        return ready > 0 and (fds[0].revents & (std.posix.POLL.IN | std.posix.POLL.HUP)) != 0;
+    }
 };
 
 // === Tests ===
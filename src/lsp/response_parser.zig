@@ -5,6 +5,8 @@ const std = @import("std");
 const Completion = @import("../editor/completion.zig");
 const CompletionItem = Completion.CompletionItem;
 const CompletionKind = Completion.CompletionKind;
+const InitializeResult = @import("client.zig").InitializeResult;
+const ServerCapabilities = @import("client.zig").ServerCapabilities;
 
 /// Parse LSP completion response and extract items
 pub fn parseCompletionResponse(allocator: std.mem.Allocator, json_text: []const u8) ![]CompletionItem {
@@ -1189,6 +1191,52 @@ pub fn parseRenameResponse(allocator: std.mem.Allocator, json_text: []const u8)
     return rename_edits.toOwnedSlice(allocator);
 }
 
+/// Parse initialize response and extract server capabilities
+pub fn parseInitializeResult(allocator: std.mem.Allocator, json_text: []const u8) !InitializeResult {
+    const parsed = try std.json.parseFromSlice(std.json.Value, allocator, json_text, .{});
+    defer parsed.deinit();
+
+    const root = parsed.value;
+    if (root != .object) return error.InvalidInitializeResult;
+
+    const caps_value = root.object.get("capabilities") orelse return error.MissingCapabilities;
+    if (caps_value != .object) return error.InvalidCapabilities;
+    const caps = caps_value.object;
+
+    var capabilities = ServerCapabilities{};
+
+    // textDocumentSync: TextDocumentSyncKind | { "change": TextDocumentSyncKind, ... }
+    if (caps.get("textDocumentSync")) |sync| {
+        if (sync == .integer) {
+            capabilities.text_document_sync = @intCast(sync.integer);
+        } else if (sync == .object) {
+            if (sync.object.get("change")) |change| {
+                if (change == .integer) capabilities.text_document_sync = @intCast(change.integer);
+            }
+        }
+    }
+
+    // Providers are either a boolean or an options object (object means supported)
+    capabilities.hover_provider = isProviderEnabled(caps.get("hoverProvider"));
+    capabilities.definition_provider = isProviderEnabled(caps.get("definitionProvider"));
+    capabilities.references_provider = isProviderEnabled(caps.get("referencesProvider"));
+    capabilities.document_formatting_provider = isProviderEnabled(caps.get("documentFormattingProvider"));
+    if (isProviderEnabled(caps.get("completionProvider"))) {
+        capabilities.completion_provider = .{};
+    }
+
+    return .{ .capabilities = capabilities };
+}
+
+fn isProviderEnabled(value: ?std.json.Value) bool {
+    const v = value orelse return false;
+    return switch (v) {
+        .bool => |b| b,
+        .object => true,
+        else => false,
+    };
+}
+
 // === Tests ===
 
 test "parse completion response: CompletionList format" {
@@ -1581,3 +1629,20 @@ test "parse formatting response: null (no edits)" {
 
     try std.testing.expectEqual(@as(usize, 0), edits.len);
 }
+
+test "parse initialize result: server capabilities" {
+    const allocator = std.testing.allocator;
+
+    const json =
+        \\{"capabilities":{"textDocumentSync":{"openClose":true,"change":2},"hoverProvider":true,
+        \\"completionProvider":{"triggerCharacters":["."]},"definitionProvider":{},"referencesProvider":false}}
+    ;
+
+    const result = try parseInitializeResult(allocator, json);
+    try std.testing.expectEqual(@as(?u8, 2), result.capabilities.text_document_sync);
+    try std.testing.expect(result.capabilities.hover_provider);
+    try std.testing.expect(result.capabilities.completion_provider != null);
+    try std.testing.expect(result.capabilities.definition_provider);
+    try std.testing.expect(!result.capabilities.references_provider);
+    try std.testing.expect(!result.capabilities.document_formatting_provider);
+}
@@ -0,0 +1,685 @@
+//! Language server registry
+//! Maps buffers to server sessions keyed by (language, workspace root), spawning servers on demand
+
+const std = @import("std");
+const Client = @import("client.zig").Client;
+const InitializeResult = @import("client.zig").InitializeResult;
+const Process = @import("process.zig").Process;
+const handlers = @import("handlers.zig");
+const ResponseParser = @import("response_parser.zig");
+const Sync = @import("sync.zig");
+
+/// Built-in server command line for a language (overridable with `lsp.<language>` config keys)
+pub fn defaultCommand(language_id: []const u8) ?[]const u8 {
+    const Entry = struct { id: []const u8, command: []const u8 };
+    const table = [_]Entry{
+        .{ .id = "zig", .command = "zls" },
+        .{ .id = "rust", .command = "rust-analyzer" },
+        .{ .id = "go", .command = "gopls" },
+        .{ .id = "python", .command = "pylsp" },
+        .{ .id = "c", .command = "clangd" },
+        .{ .id = "cpp", .command = "clangd" },
+        .{ .id = "javascript", .command = "typescript-language-server --stdio" },
+        .{ .id = "javascriptreact", .command = "typescript-language-server --stdio" },
+        .{ .id = "typescript", .command = "typescript-language-server --stdio" },
+        .{ .id = "typescriptreact", .command = "typescript-language-server --stdio" },
+    };
+
+    for (table) |entry| {
+        if (std.mem.eql(u8, language_id, entry.id)) return entry.command;
+    }
+    return null;
+}
+
+/// Files that mark a project root for a language, checked from the file's directory upwards
+pub fn rootMarkers(language_id: []const u8) []const []const u8 {
+    const Entry = struct { id: []const u8, markers: []const []const u8 };
+    const table = [_]Entry{
+        .{ .id = "zig", .markers = &.{ "build.zig", "build.zig.zon" } },
+        .{ .id = "rust", .markers = &.{"Cargo.toml"} },
+        .{ .id = "go", .markers = &.{ "go.work", "go.mod" } },
+        .{ .id = "python", .markers = &.{ "pyproject.toml", "setup.py", "setup.cfg" } },
+        .{ .id = "c", .markers = &.{ "compile_commands.json", ".clangd", "CMakeLists.txt", "Makefile" } },
+        .{ .id = "cpp", .markers = &.{ "compile_commands.json", ".clangd", "CMakeLists.txt", "Makefile" } },
+        .{ .id = "javascript", .markers = &.{ "package.json", "jsconfig.json" } },
+        .{ .id = "javascriptreact", .markers = &.{ "package.json", "jsconfig.json" } },
+        .{ .id = "typescript", .markers = &.{ "tsconfig.json", "package.json" } },
+        .{ .id = "typescriptreact", .markers = &.{ "tsconfig.json", "package.json" } },
+    };
+
+    for (table) |entry| {
+        if (std.mem.eql(u8, language_id, entry.id)) return entry.markers;
+    }
+    return &.{};
+}
+
+/// Find the workspace root for an absolute file path
+/// Nearest directory containing a language marker, else nearest `.git`, else the file's directory.
+/// Caller owns the returned path.
+pub fn findWorkspaceRoot(allocator: std.mem.Allocator, language_id: []const u8, abs_path: []const u8) ![]u8 {
+    const start_dir = std.fs.path.dirname(abs_path) orelse abs_path;
+
+    if (try findAncestorWith(allocator, start_dir, rootMarkers(language_id))) |root| return root;
+    if (try findAncestorWith(allocator, start_dir, &.{".git"})) |root| return root;
+    return allocator.dupe(u8, start_dir);
+}
+
+fn findAncestorWith(allocator: std.mem.Allocator, start_dir: []const u8, markers: []const []const u8) !?[]u8 {
+    if (markers.len == 0) return null;
+
+    var dir: ?[]const u8 = start_dir;
+    while (dir) |current| : (dir = std.fs.path.dirname(current)) {
+        for (markers) |marker| {
+            const candidate = try std.fs.path.join(allocator, &.{ current, marker });
+            defer allocator.free(candidate);
+
+            std.fs.accessAbsolute(candidate, .{}) catch continue;
+            return try allocator.dupe(u8, current);
+        }
+    }
+    return null;
+}
+
+/// Notification handler that also receives the session the notification came from
+pub const NotificationHandler = *const fn (ctx: ?*anyopaque, session: *Session, method: []const u8, params: []const u8) anyerror!void;
+
+/// Receives problems talking to a server, for the editor to show (servers run under the TUI,
+/// so nothing may be printed)
+pub const LogHandler = *const fn (ctx: ?*anyopaque, message: []const u8) void;
+
+fn report(handler: ?LogHandler, ctx: ?*anyopaque, comptime fmt: []const u8, args: anytype) void {
+    const log = handler orelse return;
+    var msg_buf: [256]u8 = undefined;
+    const msg = std.fmt.bufPrint(&msg_buf, fmt, args) catch "[LSP] Language server error";
+    log(ctx, msg);
+}
+
+/// A running language server for one (language, workspace root): process,
+/// JSON-RPC client, and documents waiting for the initialize handshake to finish
+pub const Session = struct {
+    allocator: std.mem.Allocator,
+    id: u32, // Unique per registry, used to tag data owned by this session
+    language_id: []const u8, // Owned
+    root_uri: []const u8, // Owned
+    argv: [][]const u8, // Owned, argv[0] is the server executable
+    client: Client,
+    sync: Sync.SyncManager, // Versions of documents opened in this server
+    sync_kind: Sync.SyncKind, // Negotiated from the server's textDocumentSync capability
+    pending_opens: std.ArrayList(PendingOpen),
+    notification_handler: ?NotificationHandler,
+    notification_ctx: ?*anyopaque,
+    log_handler: ?LogHandler,
+    log_ctx: ?*anyopaque,
+
+    const PendingOpen = struct {
+        uri: []const u8, // Owned
+        text: []const u8, // Owned
+    };
+
+    /// Create session for a whitespace-separated server command line (does not spawn)
+    pub fn create(
+        allocator: std.mem.Allocator,
+        id: u32,
+        language_id: []const u8,
+        root_uri: []const u8,
+        command_line: []const u8,
+    ) !*Session {
+        var argv = std.ArrayList([]const u8).empty;
+        errdefer {
+            for (argv.items) |arg| allocator.free(arg);
+            argv.deinit(allocator);
+        }
+
+        var tokens = std.mem.tokenizeAny(u8, command_line, " \t");
+        while (tokens.next()) |token| {
+            const arg = try allocator.dupe(u8, token);
+            errdefer allocator.free(arg);
+            try argv.append(allocator, arg);
+        }
+        if (argv.items.len == 0) return error.EmptyServerCommand;
+
+        const owned_argv = try argv.toOwnedSlice(allocator);
+        errdefer {
+            for (owned_argv) |arg| allocator.free(arg);
+            allocator.free(owned_argv);
+        }
+
+        const owned_id = try allocator.dupe(u8, language_id);
+        errdefer allocator.free(owned_id);
+        const owned_root = try allocator.dupe(u8, root_uri);
+        errdefer allocator.free(owned_root);
+
+        const self = try allocator.create(Session);
+        self.* = .{
+            .allocator = allocator,
+            .id = id,
+            .language_id = owned_id,
+            .root_uri = owned_root,
+            .argv = owned_argv,
+            .client = Client.init(allocator, Process.init(allocator, owned_argv[0], owned_argv[1..])),
+            .sync = Sync.SyncManager.init(allocator),
+            .sync_kind = .full,
+            .pending_opens = std.ArrayList(PendingOpen).empty,
+            .notification_handler = null,
+            .notification_ctx = null,
+            .log_handler = null,
+            .log_ctx = null,
+        };
+        self.client.setNotificationHandler(onNotification, self);
+        return self;
+    }
+
+    /// Stop the server and free the session
+    pub fn destroy(self: *Session) void {
+        if (self.client.process) |*process| {
+            process.deinit();
+        }
+        self.client.deinit();
+        self.sync.deinit();
+
+        for (self.pending_opens.items) |doc| {
+            self.allocator.free(doc.uri);
+            self.allocator.free(doc.text);
+        }
+        self.pending_opens.deinit(self.allocator);
+
+        for (self.argv) |arg| self.allocator.free(arg);
+        self.allocator.free(self.argv);
+        self.allocator.free(self.root_uri);
+        self.allocator.free(self.language_id);
+        self.allocator.destroy(self);
+    }
+
+    /// Spawn the server and send the initialize request
+    /// The session becomes ready once the response arrives through poll()
+    pub fn start(self: *Session) !void {
+        const process = &self.client.process.?;
+        try process.spawn();
+        errdefer process.stop() catch {};
+
+        _ = try handlers.initialize(&self.client, self.root_uri, onInitializeResponse, self);
+    }
+
+    /// Check if the handshake has completed
+    pub fn isReady(self: *const Session) bool {
+        return self.client.isReady();
+    }
+
+    /// Check if the server process has gone away
+    pub fn hasExited(self: *const Session) bool {
+        return self.client.state == .exited;
+    }
+
+    /// Send didOpen now, or queue it until the handshake completes
+    pub fn openDocument(self: *Session, uri: []const u8, text: []const u8) !void {
+        if (self.client.isReady()) {
+            return self.sendDidOpen(uri, text);
+        }
+
+        // Already queued: the latest text wins
+        if (self.findPendingOpen(uri)) |doc| {
+            const text_copy = try self.allocator.dupe(u8, text);
+            self.allocator.free(doc.text);
+            doc.text = text_copy;
+            return;
+        }
+
+        const uri_copy = try self.allocator.dupe(u8, uri);
+        errdefer self.allocator.free(uri_copy);
+        const text_copy = try self.allocator.dupe(u8, text);
+        errdefer self.allocator.free(text_copy);
+        try self.pending_opens.append(self.allocator, .{ .uri = uri_copy, .text = text_copy });
+    }
+
+    /// How `syncChanges`/`syncText` bring a document up to date
+    pub const SyncNeed = enum { nothing, changes, text };
+
+    /// Whether edits to `uri` go out as ranged changes, as the full text, or not at all
+    pub fn syncNeed(self: *Session, uri: []const u8) SyncNeed {
+        // Handshake still running: the queued didOpen just needs the current text
+        if (!self.client.isReady()) return if (self.findPendingOpen(uri) != null) .text else .nothing;
+        if (self.sync.getVersion(uri) == null) return .nothing; // Not opened in this server
+        return switch (self.sync_kind) {
+            .none => .nothing,
+            .incremental => .changes,
+            .full => .text,
+        };
+    }
+
+    /// Send didChange with ranged changes (for servers that sync incrementally)
+    pub fn syncChanges(self: *Session, uri: []const u8, events: []const Sync.TextDocumentContentChangeEvent) !void {
+        if (events.len == 0 or self.syncNeed(uri) == .nothing) return;
+        try self.sendDidChange(uri, events);
+    }
+
+    /// Send the document's full text, or refresh its queued didOpen before the handshake
+    pub fn syncText(self: *Session, uri: []const u8, text: []const u8) !void {
+        if (self.syncNeed(uri) == .nothing) return;
+        if (!self.client.isReady()) return self.openDocument(uri, text);
+
+        const events = [_]Sync.TextDocumentContentChangeEvent{.{ .text = text }};
+        try self.sendDidChange(uri, &events);
+    }
+
+    /// Send didClose, or drop the document from the queue if it was never opened
+    pub fn closeDocument(self: *Session, uri: []const u8) !void {
+        if (self.client.isReady()) {
+            _ = try self.sync.didClose(uri);
+            return handlers.didClose(&self.client, uri);
+        }
+
+        for (self.pending_opens.items, 0..) |doc, i| {
+            if (std.mem.eql(u8, doc.uri, uri)) {
+                self.allocator.free(doc.uri);
+                self.allocator.free(doc.text);
+                _ = self.pending_opens.orderedRemove(i);
+                return;
+            }
+        }
+    }
+
+    /// Drain all messages the server has written so far (never blocks)
+    pub fn poll(self: *Session) void {
+        const process = if (self.client.process) |*p| p else return;
+
+        while (process.isRunning() and process.hasPendingData()) {
+            self.client.poll() catch |err| {
+                if (err == error.EndOfStream or err == error.UnexpectedEOF) {
+                    report(self.log_handler, self.log_ctx, "[LSP] {s} server exited ({s})", .{ self.argv[0], self.root_uri });
+                    process.stop() catch {};
+                    self.client.state = .exited;
+                    return;
+                }
+                report(self.log_handler, self.log_ctx, "[LSP] Failed to handle message from {s}: {s}", .{ self.argv[0], @errorName(err) });
+            };
+        }
+    }
+
+    fn findPendingOpen(self: *Session, uri: []const u8) ?*PendingOpen {
+        for (self.pending_opens.items) |*doc| {
+            if (std.mem.eql(u8, doc.uri, uri)) return doc;
+        }
+        return null;
+    }
+
+    fn sendDidOpen(self: *Session, uri: []const u8, text: []const u8) !void {
+        const params = try self.sync.didOpen(uri, self.language_id, text);
+        try handlers.didOpen(&self.client, uri, self.language_id, @intCast(params.text_document.version), text);
+    }
+
+    fn sendDidChange(self: *Session, uri: []const u8, events: []const Sync.TextDocumentContentChangeEvent) !void {
+        const params = try self.sync.didChangeEvents(uri, events);
+        try handlers.didChange(&self.client, uri, @intCast(params.text_document.version), params.content_changes);
+    }
+
+    /// Forward client notifications with this session attached
+    fn onNotification(ctx: ?*anyopaque, method: []const u8, params: []const u8) !void {
+        const self: *Session = @ptrCast(@alignCast(ctx orelse return error.NullContext));
+        if (self.notification_handler) |handler| {
+            try handler(self.notification_ctx, self, method, params);
+        }
+    }
+
+    /// Initialize response: record capabilities, complete handshake, flush queued documents
+    fn onInitializeResponse(ctx: ?*anyopaque, result_json: []const u8) !void {
+        const self: *Session = @ptrCast(@alignCast(ctx orelse return error.NullContext));
+
+        const result = ResponseParser.parseInitializeResult(self.allocator, result_json) catch |err| blk: {
+            report(self.log_handler, self.log_ctx, "[LSP] Failed to parse initialize result: {s}", .{@errorName(err)});
+            break :blk InitializeResult{ .capabilities = .{} };
+        };
+        try self.client.handleInitializeResponse(result);
+        self.sync_kind = Sync.SyncKind.fromCapability(result.capabilities.text_document_sync);
+        try handlers.sendInitialized(&self.client);
+
+        for (self.pending_opens.items) |doc| {
+            self.sendDidOpen(doc.uri, doc.text) catch |err| {
+                report(self.log_handler, self.log_ctx, "[LSP] Failed to send didOpen for {s}: {s}", .{ doc.uri, @errorName(err) });
+            };
+            self.allocator.free(doc.uri);
+            self.allocator.free(doc.text);
+        }
+        self.pending_opens.clearRetainingCapacity();
+    }
+};
+
+/// A language and workspace root whose server failed to start (owned strings)
+const FailedStart = struct {
+    language_id: []const u8,
+    root_uri: []const u8,
+};
+
+/// LSP session manager: many concurrent servers keyed by (language, workspace root),
+/// plus the document URI -> session routing used for requests
+pub const ServerRegistry = struct {
+    allocator: std.mem.Allocator,
+    sessions: std.ArrayList(*Session),
+    documents: std.StringHashMap(*Session), // Open document URI -> owning session (owned keys)
+    unavailable: std.ArrayList(FailedStart), // Keyed like sessions
+    next_session_id: u32,
+    notification_handler: ?NotificationHandler,
+    notification_ctx: ?*anyopaque,
+    log_handler: ?LogHandler,
+    log_ctx: ?*anyopaque,
+
+    pub fn init(allocator: std.mem.Allocator) ServerRegistry {
+        return .{
+            .allocator = allocator,
+            .sessions = std.ArrayList(*Session).empty,
+            .documents = std.StringHashMap(*Session).init(allocator),
+            .unavailable = std.ArrayList(FailedStart).empty,
+            .next_session_id = 1,
+            .notification_handler = null,
+            .notification_ctx = null,
+            .log_handler = null,
+            .log_ctx = null,
+        };
+    }
+
+    /// Stop all servers and free sessions
+    pub fn deinit(self: *ServerRegistry) void {
+        for (self.sessions.items) |session| {
+            session.destroy();
+        }
+        self.sessions.deinit(self.allocator);
+
+        var doc_iter = self.documents.keyIterator();
+        while (doc_iter.next()) |key| {
+            self.allocator.free(key.*);
+        }
+        self.documents.deinit();
+
+        for (self.unavailable.items) |failed| {
+            self.allocator.free(failed.language_id);
+            self.allocator.free(failed.root_uri);
+        }
+        self.unavailable.deinit(self.allocator);
+    }
+
+    /// Set handler for server notifications (applied to current and future sessions)
+    pub fn setNotificationHandler(self: *ServerRegistry, handler: NotificationHandler, ctx: ?*anyopaque) void {
+        self.notification_handler = handler;
+        self.notification_ctx = ctx;
+
+        for (self.sessions.items) |session| {
+            session.notification_handler = handler;
+            session.notification_ctx = ctx;
+        }
+    }
+
+    /// Set where server errors are reported (applied to current and future sessions)
+    pub fn setLogHandler(self: *ServerRegistry, handler: LogHandler, ctx: ?*anyopaque) void {
+        self.log_handler = handler;
+        self.log_ctx = ctx;
+
+        for (self.sessions.items) |session| {
+            session.log_handler = handler;
+            session.log_ctx = ctx;
+        }
+    }
+
+    /// Number of running sessions
+    pub fn count(self: *const ServerRegistry) usize {
+        return self.sessions.items.len;
+    }
+
+    /// Get existing session for a language and workspace root
+    pub fn get(self: *const ServerRegistry, language_id: []const u8, root_uri: []const u8) ?*Session {
+        for (self.sessions.items) |session| {
+            if (std.mem.eql(u8, session.language_id, language_id) and
+                std.mem.eql(u8, session.root_uri, root_uri))
+            {
+                return session;
+            }
+        }
+        return null;
+    }
+
+    /// Check if the server for a language and workspace root failed to start
+    pub fn isUnavailable(self: *const ServerRegistry, language_id: []const u8, root_uri: []const u8) bool {
+        for (self.unavailable.items) |failed| {
+            if (std.mem.eql(u8, failed.language_id, language_id) and
+                std.mem.eql(u8, failed.root_uri, root_uri))
+            {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    /// Get session by id
+    pub fn getById(self: *const ServerRegistry, id: u32) ?*Session {
+        for (self.sessions.items) |session| {
+            if (session.id == id) return session;
+        }
+        return null;
+    }
+
+    /// Get session for a language and workspace root, spawning its server on first use
+    /// Returns error.ServerUnavailable if an earlier start attempt for them failed
+    pub fn ensure(self: *ServerRegistry, language_id: []const u8, root_uri: []const u8, command_line: []const u8) !*Session {
+        if (self.get(language_id, root_uri)) |session| return session;
+        if (self.isUnavailable(language_id, root_uri)) return error.ServerUnavailable;
+
+        const session = try Session.create(self.allocator, self.next_session_id, language_id, root_uri, command_line);
+        session.notification_handler = self.notification_handler;
+        session.notification_ctx = self.notification_ctx;
+        session.log_handler = self.log_handler;
+        session.log_ctx = self.log_ctx;
+
+        session.start() catch |err| {
+            session.destroy();
+            // Remember the failure so every open doesn't try to spawn again
+            self.noteUnavailable(language_id, root_uri) catch {};
+            return err;
+        };
+
+        self.sessions.append(self.allocator, session) catch |err| {
+            session.destroy();
+            return err;
+        };
+        self.next_session_id += 1;
+        return session;
+    }
+
+    fn noteUnavailable(self: *ServerRegistry, language_id: []const u8, root_uri: []const u8) !void {
+        const language_copy = try self.allocator.dupe(u8, language_id);
+        errdefer self.allocator.free(language_copy);
+        const root_copy = try self.allocator.dupe(u8, root_uri);
+        errdefer self.allocator.free(root_copy);
+        try self.unavailable.append(self.allocator, .{ .language_id = language_copy, .root_uri = root_copy });
+    }
+
+    /// Open a document in a session and remember it as the document's owner
+    pub fn openDocument(self: *ServerRegistry, session: *Session, uri: []const u8, text: []const u8) !void {
+        const gop = try self.documents.getOrPut(uri);
+        if (!gop.found_existing) {
+            gop.key_ptr.* = self.allocator.dupe(u8, uri) catch |err| {
+                self.documents.removeByPtr(gop.key_ptr);
+                return err;
+            };
+        }
+        gop.value_ptr.* = session;
+
+        try session.openDocument(uri, text);
+    }
+
+    /// Close a document in its owning session
+    pub fn closeDocument(self: *ServerRegistry, uri: []const u8) void {
+        const entry = self.documents.fetchRemove(uri) orelse return;
+        defer self.allocator.free(entry.key);
+
+        entry.value.closeDocument(uri) catch |err| {
+            report(self.log_handler, self.log_ctx, "[LSP] Failed to send didClose for {s}: {s}", .{ uri, @errorName(err) });
+        };
+    }
+
+    /// Get the session that owns an open document
+    pub fn sessionForUri(self: *const ServerRegistry, uri: []const u8) ?*Session {
+        return self.documents.get(uri);
+    }
+
+    /// Get ready client for a document (null while its server is starting or if it has none)
+    pub fn clientForUri(self: *const ServerRegistry, uri: []const u8) ?*Client {
+        const session = self.documents.get(uri) orelse return null;
+        if (!session.isReady()) return null;
+        return &session.client;
+    }
+
+    /// Process pending messages from every server
+    pub fn pollAll(self: *ServerRegistry) void {
+        for (self.sessions.items) |session| {
+            session.poll();
+        }
+    }
+
+    /// Remove one session whose server has exited, returning its id so
+    /// callers can drop data it owned (null when none have exited)
+    pub fn takeExited(self: *ServerRegistry) ?u32 {
+        for (self.sessions.items, 0..) |session, i| {
+            if (!session.hasExited()) continue;
+
+            self.forgetDocuments(session);
+            _ = self.sessions.swapRemove(i);
+            const id = session.id;
+            session.destroy();
+            return id;
+        }
+        return null;
+    }
+
+    fn forgetDocuments(self: *ServerRegistry, session: *Session) void {
+        var iter = self.documents.iterator();
+        while (iter.next()) |entry| {
+            if (entry.value_ptr.* != session) continue;
+            const key = entry.key_ptr.*;
+            self.documents.removeByPtr(entry.key_ptr);
+            self.allocator.free(key);
+            // Removal invalidates the iterator; restart the scan
+            iter = self.documents.iterator();
+        }
+    }
+};
+
+// === Tests ===
+
+test "servers: default commands" {
+    try std.testing.expectEqualStrings("zls", defaultCommand("zig").?);
+    try std.testing.expectEqualStrings("clangd", defaultCommand("cpp").?);
+    try std.testing.expectEqualStrings("typescript-language-server --stdio", defaultCommand("typescript").?);
+    try std.testing.expect(defaultCommand("markdown") == null);
+}
+
+test "servers: workspace root from markers" {
+    const allocator = std.testing.allocator;
+    var tmp = std.testing.tmpDir(.{});
+    defer tmp.cleanup();
+
+    // repo/.git, repo/tools/pyproject.toml, repo/tools/gen/x.py, repo/src/main.zig (no build.zig)
+    try tmp.dir.makePath("repo/.git");
+    try tmp.dir.makePath("repo/tools/gen");
+    try tmp.dir.makePath("repo/src");
+    try tmp.dir.writeFile(.{ .sub_path = "repo/tools/pyproject.toml", .data = "" });
+
+    const base = try tmp.dir.realpathAlloc(allocator, "repo");
+    defer allocator.free(base);
+
+    const py_file = try std.fs.path.join(allocator, &.{ base, "tools", "gen", "x.py" });
+    defer allocator.free(py_file);
+    const py_root = try findWorkspaceRoot(allocator, "python", py_file);
+    defer allocator.free(py_root);
+    const expected_py = try std.fs.path.join(allocator, &.{ base, "tools" });
+    defer allocator.free(expected_py);
+    try std.testing.expectEqualStrings(expected_py, py_root);
+
+    // No zig marker: falls back to the enclosing git repository
+    const zig_file = try std.fs.path.join(allocator, &.{ base, "src", "main.zig" });
+    defer allocator.free(zig_file);
+    const zig_root = try findWorkspaceRoot(allocator, "zig", zig_file);
+    defer allocator.free(zig_root);
+    try std.testing.expectEqualStrings(base, zig_root);
+}
+
+test "servers: session splits command line" {
+    const allocator = std.testing.allocator;
+    const session = try Session.create(allocator, 1, "typescript", "file:///proj", "typescript-language-server  --stdio");
+    defer session.destroy();
+
+    try std.testing.expectEqual(@as(usize, 2), session.argv.len);
+    try std.testing.expectEqualStrings("typescript-language-server", session.argv[0]);
+    try std.testing.expectEqualStrings("--stdio", session.argv[1]);
+    try std.testing.expect(!session.isReady());
+
+    try std.testing.expectError(error.EmptyServerCommand, Session.create(allocator, 2, "zig", "file:///proj", "  "));
+}
+
+test "servers: queue documents until ready" {
+    const allocator = std.testing.allocator;
+    const session = try Session.create(allocator, 1, "zig", "file:///proj", "zls");
+    defer session.destroy();
+
+    try session.openDocument("file:///proj/a.zig", "const a = 1;");
+    try session.openDocument("file:///proj/b.zig", "const b = 2;");
+    try std.testing.expectEqual(@as(usize, 2), session.pending_opens.items.len);
+
+    // Edits before the handshake refresh the queued text instead of sending didChange
+    try std.testing.expectEqual(Session.SyncNeed.text, session.syncNeed("file:///proj/b.zig"));
+    try std.testing.expectEqual(Session.SyncNeed.nothing, session.syncNeed("file:///proj/c.zig"));
+    try session.syncText("file:///proj/b.zig", "const b = 2;\n");
+    try std.testing.expectEqualStrings("const b = 2;\n", session.pending_opens.items[1].text);
+
+    // Closing before the handshake just drops the queued open
+    try session.closeDocument("file:///proj/a.zig");
+    try std.testing.expectEqual(@as(usize, 1), session.pending_opens.items.len);
+    try std.testing.expectEqualStrings("file:///proj/b.zig", session.pending_opens.items[0].uri);
+}
+
+test "servers: missing server is remembered as unavailable" {
+    const allocator = std.testing.allocator;
+    var registry = ServerRegistry.init(allocator);
+    defer registry.deinit();
+
+    try std.testing.expect(std.meta.isError(registry.ensure("zig", "file:///proj", "aesop-no-such-language-server")));
+    try std.testing.expectError(error.ServerUnavailable, registry.ensure("zig", "file:///proj", "aesop-no-such-language-server"));
+    try std.testing.expect(registry.isUnavailable("zig", "file:///proj"));
+
+    // Another workspace (or language) gets its own attempt
+    try std.testing.expect(!registry.isUnavailable("zig", "file:///other"));
+    if (registry.ensure("zig", "file:///other", "aesop-no-such-language-server")) |_| {
+        return error.TestUnexpectedResult;
+    } else |err| {
+        try std.testing.expect(err != error.ServerUnavailable);
+    }
+    try std.testing.expect(registry.get("zig", "file:///proj") == null);
+    try std.testing.expectEqual(@as(usize, 0), registry.count());
+}
+
+test "servers: documents route to their session" {
+    const allocator = std.testing.allocator;
+    var registry = ServerRegistry.init(allocator);
+    defer registry.deinit();
+
+    // Sessions created without spawning, as ensure() would after a successful start
+    const zig_session = try Session.create(allocator, 1, "zig", "file:///repo", "zls");
+    try registry.sessions.append(allocator, zig_session);
+    const py_session = try Session.create(allocator, 2, "python", "file:///repo/tools", "pylsp");
+    try registry.sessions.append(allocator, py_session);
+
+    try registry.openDocument(zig_session, "file:///repo/build.zig", "");
+    try registry.openDocument(py_session, "file:///repo/tools/gen.py", "");
+
+    try std.testing.expectEqual(zig_session, registry.get("zig", "file:///repo").?);
+    try std.testing.expectEqual(py_session, registry.sessionForUri("file:///repo/tools/gen.py").?);
+    try std.testing.expectEqual(zig_session, registry.sessionForUri("file:///repo/build.zig").?);
+    try std.testing.expect(registry.clientForUri("file:///repo/build.zig") == null); // Not initialized
+
+    // An exited session is reaped along with its documents
+    py_session.client.state = .exited;
+    try std.testing.expectEqual(@as(?u32, 2), registry.takeExited());
+    try std.testing.expect(registry.sessionForUri("file:///repo/tools/gen.py") == null);
+    try std.testing.expectEqual(@as(usize, 1), registry.count());
+    try std.testing.expect(registry.takeExited() == null);
+
+    registry.closeDocument("file:///repo/build.zig");
+    try std.testing.expect(registry.sessionForUri("file:///repo/build.zig") == null);
+}
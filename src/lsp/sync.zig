@@ -3,6 +3,7 @@
 
 const std = @import("std");
 const Client = @import("client.zig").Client;
+const Range = @import("response_parser.zig").Range;
 
 /// Text document identifier
 pub const TextDocumentIdentifier = struct {
@@ -24,8 +25,39 @@ pub const TextDocumentItem = struct {
 };
 
 /// Text document content change
+/// With a range, `text` replaces that range; without one it is the full document.
 pub const TextDocumentContentChangeEvent = struct {
-    text: []const u8, // Full document text (incremental changes not yet supported)
+    range: ?Range = null,
+    text: []const u8,
+
+    /// Omit `range` entirely for full sync (servers reject `"range": null`)
+    pub fn jsonStringify(self: TextDocumentContentChangeEvent, jw: anytype) !void {
+        try jw.beginObject();
+        if (self.range) |range| {
+            try jw.objectField("range");
+            try jw.write(range);
+        }
+        try jw.objectField("text");
+        try jw.write(self.text);
+        try jw.endObject();
+    }
+};
+
+/// How the server wants document changes (LSP TextDocumentSyncKind)
+pub const SyncKind = enum(u8) {
+    none = 0,
+    full = 1,
+    incremental = 2,
+
+    /// Map the server's textDocumentSync capability (absent means no sync, per spec)
+    pub fn fromCapability(value: ?u8) SyncKind {
+        const kind = value orelse return .none;
+        return switch (kind) {
+            0 => .none,
+            2 => .incremental,
+            else => .full,
+        };
+    }
 };
 
 /// Document synchronization manager
@@ -71,7 +103,13 @@ pub const SyncManager = struct {
         const lang_copy = try self.allocator.dupe(u8, language_id);
         errdefer self.allocator.free(lang_copy);
 
-        try self.documents.put(uri, .{
+        // Reopening replaces the previous state
+        if (self.documents.fetchRemove(uri)) |old| {
+            self.allocator.free(old.value.uri);
+            self.allocator.free(old.value.language_id);
+        }
+
+        try self.documents.put(uri_copy, .{
             .uri = uri_copy,
             .language_id = lang_copy,
             .version = 1,
@@ -109,6 +147,25 @@ pub const SyncManager = struct {
         };
     }
 
+    /// Create didChange notification for caller-owned content change events
+    pub fn didChangeEvents(
+        self: *SyncManager,
+        uri: []const u8,
+        events: []const TextDocumentContentChangeEvent,
+    ) !DidChangeParams {
+        const state = self.documents.getPtr(uri) orelse return error.DocumentNotOpen;
+
+        state.version += 1;
+
+        return .{
+            .text_document = .{
+                .uri = uri,
+                .version = state.version,
+            },
+            .content_changes = events,
+        };
+    }
+
     /// Create didSave notification
     pub fn didSave(
         self: *SyncManager,
@@ -221,3 +278,33 @@ test "sync: getVersion" {
     _ = try sync.didChange("file:///test.zig", "const x = 43;");
     try std.testing.expectEqual(@as(i32, 2), sync.getVersion("file:///test.zig").?);
 }
+
+test "sync: incremental content changes" {
+    const allocator = std.testing.allocator;
+    var sync = SyncManager.init(allocator);
+    defer sync.deinit();
+
+    _ = try sync.didOpen("file:///test.zig", "zig", "const x = 42;");
+
+    const events = [_]TextDocumentContentChangeEvent{.{
+        .range = .{
+            .start = .{ .line = 0, .character = 10 },
+            .end = .{ .line = 0, .character = 12 },
+        },
+        .text = "7",
+    }};
+
+    const params = try sync.didChangeEvents("file:///test.zig", &events);
+    try std.testing.expectEqual(@as(i32, 2), params.text_document.version);
+    try std.testing.expectEqual(@as(u32, 10), params.content_changes[0].range.?.start.character);
+    try std.testing.expectEqual(@as(u32, 12), params.content_changes[0].range.?.end.character);
+
+    // Full sync events serialize without a range
+    const full = TextDocumentContentChangeEvent{ .text = "abc" };
+    const json = try std.json.Stringify.valueAlloc(allocator, full, .{});
+    defer allocator.free(json);
+    try std.testing.expectEqualStrings("{\"text\":\"abc\"}", json);
+
+    try std.testing.expectEqual(SyncKind.incremental, SyncKind.fromCapability(2));
+    try std.testing.expectEqual(SyncKind.none, SyncKind.fromCapability(null));
+}
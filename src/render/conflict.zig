@@ -0,0 +1,47 @@
+//! Disk conflict prompt rendering
+//! Shown when a file with unsaved edits is rewritten on disk by something else
+
+const std = @import("std");
+const renderer = @import("renderer.zig");
+const popup = @import("popup.zig");
+const recovery = @import("recovery.zig");
+
+const Editor = @import("../editor/editor.zig").Editor;
+
+/// Render the conflict prompt (centered)
+pub fn render(rend: *renderer.Renderer, editor: *Editor, allocator: std.mem.Allocator) !void {
+    const conflict = editor.disk_conflict orelse return;
+
+    if (conflict.show_diff) {
+        try recovery.renderDiffPopup(rend, conflict.diff, conflict.scroll, "Yours -> On Disk (r/w/Esc to decide, d to go back)", allocator);
+        return;
+    }
+
+    const name = if (editor.buffer_manager.getBuffer(conflict.buffer_id)) |buffer|
+        buffer.metadata.getName()
+    else
+        "file";
+
+    var content = std.ArrayList(u8).empty;
+    defer content.deinit(allocator);
+    const writer = content.writer(allocator);
+
+    try writer.print("{s} changed on disk, but you have unsaved changes.\n\n", .{name});
+    try writer.writeAll("r reload from disk   w write yours   d diff   Esc keep editing");
+
+    const size = rend.getSize();
+    const config = popup.PopupConfig{
+        .max_width = 72,
+        .max_height = 6,
+        .border = .double,
+        .title = "File Changed on Disk",
+    };
+    var dims = popup.calculateDimensions(content.items, config);
+    dims.width = @max(dims.width, 28);
+    const position = popup.PopupPosition{
+        .row = (size.height -| dims.height) / 2,
+        .col = (size.width -| dims.width) / 2,
+    };
+
+    try popup.render(rend, position, dims.width, dims.height, content.items, config);
+}
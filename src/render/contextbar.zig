@@ -36,10 +36,14 @@ const Context = enum {
     insert_mode,
     select_mode,
     command_mode,
+    operator_pending,
     incremental_search,
     palette_open,
     file_finder_open,
     buffer_switcher_open,
+    undo_tree_open,
+    swap_recovery,
+    disk_conflict,
     pending_command,
 };
 
@@ -48,9 +52,12 @@ fn detectContext(editor: *const Editor, is_empty_buffer: bool) Context {
     // Priority order: overlays > special states > modes
 
     // Check overlays first
+    if (editor.swap_recovery != null) return .swap_recovery;
+    if (editor.disk_conflict != null) return .disk_conflict;
     if (editor.palette.visible) return .palette_open;
     if (editor.file_finder.visible) return .file_finder_open;
     if (editor.buffer_switcher_visible) return .buffer_switcher_open;
+    if (editor.undo_tree_visible) return .undo_tree_open;
 
     // Check special states
     if (editor.search.incremental) return .incremental_search;
@@ -63,6 +70,7 @@ fn detectContext(editor: *const Editor, is_empty_buffer: bool) Context {
         .insert => .insert_mode,
         .select => .select_mode,
         .command => .command_mode,
+        .operator_pending => .operator_pending,
     };
 }
 
@@ -114,6 +122,15 @@ fn getHintsForContext(context: Context, theme: *const Theme, allocator: std.mem.
             .{ .key = "0/$", .action = "line-ends", .color = cyan, .priority = 4 },
         },
 
+        .operator_pending => &[_]Hint{
+            .{ .key = "w/b/e", .action = "motion", .color = cyan, .priority = 9 },
+            .{ .key = "iw/aw", .action = "word", .color = teal, .priority = 8 },
+            .{ .key = "i(/a\"", .action = "pair", .color = teal, .priority = 7 },
+            .{ .key = "ip", .action = "paragraph", .color = teal, .priority = 6 },
+            .{ .key = "same key", .action = "whole-line", .color = purple, .priority = 5 },
+            .{ .key = "ESC", .action = "cancel", .color = pink, .priority = 4 },
+        },
+
         .command_mode => &[_]Hint{
             .{ .key = "Enter", .action = "execute", .color = teal, .priority = 9 },
             .{ .key = "ESC", .action = "cancel", .color = pink, .priority = 8 },
@@ -155,6 +172,26 @@ fn getHintsForContext(context: Context, theme: *const Theme, allocator: std.mem.
             .{ .key = "Space c", .action = "close-buffer", .color = purple, .priority = 6 },
         },
 
+        .undo_tree_open => &[_]Hint{
+            .{ .key = "↑↓/jk", .action = "preview", .color = cyan, .priority = 9 },
+            .{ .key = "Enter", .action = "jump", .color = teal, .priority = 8 },
+            .{ .key = "ESC", .action = "cancel", .color = pink, .priority = 7 },
+        },
+
+        .swap_recovery => &[_]Hint{
+            .{ .key = "r", .action = "recover", .color = teal, .priority = 9 },
+            .{ .key = "d", .action = "diff", .color = cyan, .priority = 8 },
+            .{ .key = "x", .action = "discard", .color = pink, .priority = 7 },
+            .{ .key = "ESC", .action = "decide-later", .color = white, .priority = 6 },
+        },
+
+        .disk_conflict => &[_]Hint{
+            .{ .key = "r", .action = "reload", .color = teal, .priority = 9 },
+            .{ .key = "w", .action = "write-yours", .color = pink, .priority = 8 },
+            .{ .key = "d", .action = "diff", .color = cyan, .priority = 7 },
+            .{ .key = "ESC", .action = "keep-editing", .color = white, .priority = 6 },
+        },
+
         .pending_command => &[_]Hint{
             .{ .key = "w", .action = "word", .color = cyan, .priority = 9 },
             .{ .key = "d", .action = "line", .color = cyan, .priority = 8 },
@@ -1,4 +1,4 @@
-//! Gutter rendering - line numbers, git status, diagnostics
+//! Gutter rendering - line numbers, git status, diagnostics, fold markers
 
 const std = @import("std");
 const renderer = @import("renderer.zig");
@@ -7,6 +7,7 @@ const Attrs = renderer.Attrs;
 const diagnostics_render = @import("diagnostics.zig");
 const LspDiagnostics = @import("../lsp/diagnostics.zig");
 const Theme = @import("../editor/theme.zig").Theme;
+const Fold = @import("../editor/fold.zig");
 
 /// Gutter configuration
 pub const GutterConfig = struct {
@@ -14,6 +15,7 @@ pub const GutterConfig = struct {
     line_number_style: LineNumberStyle = .relative,
     show_git_status: bool = false, // Git integration pending
     show_diagnostics: bool = true, // LSP diagnostic indicators enabled
+    show_folds: bool = false, // Marker column for where folds start
     width: u16 = 5, // Width of gutter in characters
 
     pub const LineNumberStyle = enum {
@@ -31,7 +33,7 @@ pub fn render(
     cursor_line: usize,
     theme: *const Theme,
 ) !void {
-    try renderWithDiagnostics(rend, config, start_line, end_line, cursor_line, null, null, theme);
+    try renderWithDiagnostics(rend, config, start_line, end_line, cursor_line, null, null, theme, 0, null);
 }
 
 /// Render gutter with diagnostic support
@@ -45,14 +47,16 @@ pub fn renderWithDiagnostics(
     file_uri: ?[]const u8,
     theme: *const Theme,
     col_offset: u16,
+    folds: ?*const Fold.FoldSet,
 ) !void {
     if (!config.show_line_numbers) return;
 
     var row: u16 = 0;
     var line = start_line;
 
+    // Lines hidden in closed folds get no row
     while (line < end_line) : ({
-        line += 1;
+        line = if (folds) |fold_set| fold_set.nextVisible(line) else line + 1;
         row += 1;
     }) {
         const line_num = switch (config.line_number_style) {
@@ -61,6 +65,13 @@ pub fn renderWithDiagnostics(
                 if (line == cursor_line) {
                     break :blk line + 1; // Show absolute on cursor line
                 }
+                // Rows rather than lines, so the number is the count `j`/`k` need over folds
+                if (folds) |fold_set| {
+                    break :blk if (line > cursor_line)
+                        fold_set.rowsBetween(cursor_line, line)
+                    else
+                        fold_set.rowsBetween(line, cursor_line);
+                }
                 const dist = if (line > cursor_line)
                     line - cursor_line
                 else
@@ -81,6 +92,14 @@ pub fn renderWithDiagnostics(
 
         rend.writeText(row, col_offset, text, fg_color, .default, if (line == cursor_line) .{ .bold = true } else .{}, null);
 
+        if (config.show_folds) {
+            if (folds) |fold_set| {
+                if (foldMarker(fold_set, line)) |marker| {
+                    rend.writeText(row, col_offset + 5, marker, theme.ui.gutter_line_number, .default, .{}, null);
+                }
+            }
+        }
+
         // Render diagnostic icon if present
         if (config.show_diagnostics) {
             if (diagnostic_manager) |manager| {
@@ -109,6 +128,13 @@ pub fn renderWithDiagnostics(
     }
 }
 
+/// Marker for a line that starts a fold: `▸` closed, `▾` open
+pub fn foldMarker(folds: *const Fold.FoldSet, line: usize) ?[]const u8 {
+    if (folds.isClosed(line)) return "▸";
+    if (folds.startingAt(line) != null) return "▾";
+    return null;
+}
+
 /// Calculate gutter width based on configuration
 pub fn calculateWidth(config: GutterConfig, total_lines: usize) u16 {
     _ = total_lines; // Unused - format string determines width
@@ -118,5 +144,21 @@ pub fn calculateWidth(config: GutterConfig, total_lines: usize) u16 {
     // Previous bug: calculated dynamic width (e.g., 2 for <10 lines) but format always used 5 chars
     // This caused text to start at column 2 while gutter occupied columns 0-4,
     // resulting in 3 characters being overwritten by line numbers
-    return 5; // 4 digits + 1 space, matching the format string
+    // Fold markers take a column after that, and a space before the text
+    return if (config.show_folds) 7 else 5; // 4 digits + 1 space, matching the format string
+}
+
+test "gutter: fold markers and width" {
+    const allocator = std.testing.allocator;
+    var folds = Fold.FoldSet{};
+    defer folds.deinit(allocator);
+    folds.update(allocator, try allocator.dupe(Fold.Range, &.{.{ .start = 2, .end = 5 }}), 0, null);
+
+    try std.testing.expectEqualStrings("▾", foldMarker(&folds, 2).?);
+    try std.testing.expect(foldMarker(&folds, 3) == null);
+    try folds.closeAll(allocator);
+    try std.testing.expectEqualStrings("▸", foldMarker(&folds, 2).?);
+
+    try std.testing.expectEqual(@as(u16, 5), calculateWidth(.{}, 100));
+    try std.testing.expectEqual(@as(u16, 7), calculateWidth(.{ .show_folds = true }, 100));
 }
@@ -13,37 +13,33 @@ pub fn render(rend: *renderer.Renderer, editor: *const Editor) !void {
     const size = rend.getSize();
     const status_row = size.height - 1;
 
-    // Check if there are pending keys
-    if (!editor.keymap_manager.hasPending()) {
+    // Check if there are pending keys, or an operator or register waiting for them
+    const operator = editor.pending_operator;
+    if (!editor.keymap_manager.hasPending() and operator == null and editor.pending_register == null) {
         return; // No hints to show
     }
 
-    // Get pending key sequence
+    // Get pending count and key sequence
+    const count = editor.keymap_manager.count;
     const pending = editor.keymap_manager.pending_keys.constSlice();
-    if (pending.len == 0) return;
 
     // Format pending keys
-    var buf: [64]u8 = undefined;
+    var buf: [128]u8 = undefined;
     var fbs = std.io.fixedBufferStream(&buf);
     const writer = fbs.writer();
 
     writer.writeAll(" [") catch return;
+    if (editor.pending_register) |register| writer.print("\"{c}", .{register.toChar()}) catch return;
+    if (operator) |op| {
+        // `2d3` so far: the operator's count and keys, then what was typed after it
+        if (op.count) |n| writer.print("{d}", .{n}) catch return;
+        writer.writeAll(op.operator.keys()) catch return;
+    }
+    if (count) |n| writer.print("{d}", .{n}) catch return;
 
     for (pending, 0..) |key, i| {
         if (i > 0) writer.writeAll(" ") catch return;
-
-        switch (key) {
-            .char => |c| {
-                if (c >= 32 and c < 127) {
-                    writer.writeByte(@intCast(c)) catch return;
-                } else {
-                    writer.print("U+{X}", .{c}) catch return;
-                }
-            },
-            .special => |s| {
-                writer.print("<{s}>", .{@tagName(s)}) catch return;
-            },
-        }
+        key.writeNotation(writer) catch return;
     }
 
     writer.writeAll("...] ") catch return;
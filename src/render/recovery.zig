@@ -0,0 +1,148 @@
+//! Swap recovery prompt rendering
+//! Asks whether to recover a crashed session's unsaved edits, with an optional diff
+
+const std = @import("std");
+const renderer = @import("renderer.zig");
+const popup = @import("popup.zig");
+
+const Editor = @import("../editor/editor.zig").Editor;
+const SwapRecovery = @import("../editor/editor.zig").SwapRecovery;
+const Diff = @import("../editor/diff.zig");
+
+const MAX_WIDTH = 72;
+
+/// Render the recovery prompt (centered)
+pub fn render(rend: *renderer.Renderer, editor: *Editor, allocator: std.mem.Allocator) !void {
+    if (editor.swap_recovery == null) return;
+    const pending = &editor.swap_recovery.?;
+
+    if (pending.show_diff) {
+        try renderDiffPopup(rend, pending.diff, pending.scroll, "Disk -> Recovered (r/x to decide, d to go back)", allocator);
+    } else {
+        try renderSummary(rend, editor, pending, allocator);
+    }
+}
+
+fn renderSummary(rend: *renderer.Renderer, editor: *Editor, pending: *const SwapRecovery, allocator: std.mem.Allocator) !void {
+    const name = if (editor.buffer_manager.getBuffer(pending.buffer_id)) |buffer|
+        buffer.metadata.getName()
+    else
+        std.fs.path.basename(pending.swap.path);
+
+    var content = std.ArrayList(u8).empty;
+    defer content.deinit(allocator);
+    const writer = content.writer(allocator);
+
+    const day_seconds = std.time.epoch.EpochSeconds{ .secs = @intCast(@divFloor(@max(pending.swap.timestamp, 0), 1000)) };
+    const time = day_seconds.getDaySeconds();
+
+    try writer.print("Unsaved changes to {s} were found\n", .{name});
+    try writer.print("from a session that ended at {d:0>2}:{d:0>2}:{d:0>2} UTC (pid {d}).\n", .{
+        time.getHoursIntoDay(),
+        time.getMinutesIntoHour(),
+        time.getSecondsIntoMinute(),
+        pending.swap.pid,
+    });
+    if (pending.diskChanged()) {
+        try writer.writeAll("The file has changed on disk since then.\n");
+    }
+    try writer.writeAll("\nr recover   d diff   x discard   Esc decide later");
+
+    const size = rend.getSize();
+    const config = popup.PopupConfig{
+        .max_width = MAX_WIDTH,
+        .max_height = 8,
+        .border = .double,
+        .title = "Recover Unsaved Changes",
+    };
+    var dims = popup.calculateDimensions(content.items, config);
+    dims.width = @max(dims.width, 28);
+    const position = popup.PopupPosition{
+        .row = (size.height -| dims.height) / 2,
+        .col = (size.width -| dims.width) / 2,
+    };
+
+    try popup.render(rend, position, dims.width, dims.height, content.items, config);
+}
+
+/// Render a scrollable, colored line diff in a centered popup
+pub fn renderDiffPopup(
+    rend: *renderer.Renderer,
+    lines: []const Diff.DiffLine,
+    scroll: usize,
+    title: []const u8,
+    allocator: std.mem.Allocator,
+) !void {
+    const size = rend.getSize();
+    const config = popup.PopupConfig{
+        .max_width = MAX_WIDTH,
+        .max_height = @max(1, @min(24, size.height -| 6)),
+        .border = .double,
+        .title = title,
+    };
+
+    const first = @min(scroll, lines.len -| 1);
+    const visible = @min(lines.len - first, config.max_height);
+
+    // Lines are cut to the popup width so the popup doesn't wrap them
+    var content = std.ArrayList(u8).empty;
+    defer content.deinit(allocator);
+    var line_buf: [MAX_WIDTH * 4]u8 = undefined;
+    for (lines[first .. first + visible], 0..) |line, i| {
+        if (i > 0) try content.append(allocator, '\n');
+        try content.appendSlice(allocator, formatDiffLine(&line_buf, line, MAX_WIDTH));
+    }
+
+    var dims = popup.calculateDimensions(content.items, config);
+    dims.width = MAX_WIDTH + 2;
+    const position = popup.PopupPosition{
+        .row = (size.height -| dims.height) / 2,
+        .col = (size.width -| dims.width) / 2,
+    };
+
+    try popup.render(rend, position, dims.width, dims.height, content.items, config);
+
+    // Color removed/added lines over the plain popup content
+    const inner_width = dims.width -| 2;
+    for (lines[first .. first + visible], 0..) |line, i| {
+        const fg: renderer.Color = switch (line.kind) {
+            .removed => .{ .standard = .red },
+            .added => .{ .standard = .green },
+            .context, .separator => continue,
+        };
+        const row = position.row + 1 + @as(u16, @intCast(i));
+        rend.writeText(row, position.col + 1, formatDiffLine(&line_buf, line, MAX_WIDTH), fg, .{ .standard = .black }, .{}, inner_width);
+    }
+}
+
+/// Format a diff line as "- text", "+ text", "  text", or "  ..." between hunks
+/// Text is cut (on a UTF-8 boundary) so the line fits in `max_len` bytes.
+pub fn formatDiffLine(buf: []u8, line: Diff.DiffLine, max_len: usize) []const u8 {
+    const prefix = switch (line.kind) {
+        .removed => "- ",
+        .added => "+ ",
+        .context => "  ",
+        .separator => return "  ...",
+    };
+
+    var cut = @min(line.text.len, @min(max_len, buf.len) -| prefix.len);
+    while (cut > 0 and cut < line.text.len and (line.text[cut] & 0xC0) == 0x80) cut -= 1;
+
+    @memcpy(buf[0..prefix.len], prefix);
+    @memcpy(buf[prefix.len .. prefix.len + cut], line.text[0..cut]);
+
+    // Tabs would render at a different width than the popup measured
+    for (buf[prefix.len .. prefix.len + cut]) |*c| {
+        if (c.* == '\t') c.* = ' ';
+    }
+    return buf[0 .. prefix.len + cut];
+}
+
+test "recovery: format diff line" {
+    var buf: [64]u8 = undefined;
+    try std.testing.expectEqualStrings("+ fn main", formatDiffLine(&buf, .{ .kind = .added, .text = "fn\tmain" }, 64));
+    try std.testing.expectEqualStrings("  ...", formatDiffLine(&buf, .{ .kind = .separator, .text = "" }, 64));
+
+    // "é" is two bytes; it's dropped rather than split
+    try std.testing.expectEqualStrings("- a", formatDiffLine(&buf, .{ .kind = .removed, .text = "aé" }, 4));
+}
@@ -52,13 +52,16 @@ pub fn render(rend: *renderer.Renderer, editor: *const Editor) !void {
         display_name;
 
     var buf: [512]u8 = undefined;
+    var format_buf: [32]u8 = undefined;
     const buffer_info = std.fmt.bufPrint(
         &buf,
-        " {s}{s}{s}",
+        " {s}{s}{s}{s}  {s}",
         .{
             truncated_name,
             if (info.modified) " [+]" else "",
             if (info.readonly) " [RO]" else "",
+            if (info.large_file) " [large]" else "",
+            info.file_format.describe(&format_buf),
         },
     ) catch "";
 
@@ -129,7 +132,7 @@ pub fn render(rend: *renderer.Renderer, editor: *const Editor) !void {
 
 fn getModeColor(mode: Mode, theme: *const Theme) Color {
     return switch (mode) {
-        .normal => theme.ui.statusline_mode_normal_bg,
+        .normal, .operator_pending => theme.ui.statusline_mode_normal_bg,
         .insert => theme.ui.statusline_mode_insert_bg,
         .select => theme.ui.statusline_mode_select_bg,
         .command => theme.ui.statusline_mode_command_bg,
@@ -138,7 +141,7 @@ fn getModeColor(mode: Mode, theme: *const Theme) Color {
 
 fn getModeFgColor(mode: Mode, theme: *const Theme) Color {
     return switch (mode) {
-        .normal => theme.ui.statusline_mode_normal_fg,
+        .normal, .operator_pending => theme.ui.statusline_mode_normal_fg,
         .insert => theme.ui.statusline_mode_insert_fg,
         .select => theme.ui.statusline_mode_select_fg,
         .command => theme.ui.statusline_mode_command_fg,
@@ -0,0 +1,111 @@
+//! Undo tree rendering
+//! Shows the active buffer's undo states in a popup for previewing and jumping
+
+const std = @import("std");
+const renderer = @import("renderer.zig");
+const popup = @import("popup.zig");
+
+const Editor = @import("../editor/editor.zig").Editor;
+const UndoHistory = @import("../editor/undo.zig").UndoHistory;
+
+/// Render undo tree popup (centered)
+pub fn render(rend: *renderer.Renderer, editor: *Editor, allocator: std.mem.Allocator) !void {
+    if (!editor.undo_tree_visible) return;
+
+    const buffer = editor.activeWindowBuffer() orelse return;
+    const entries = try buffer.undo_history.listTree(allocator);
+    defer allocator.free(entries);
+
+    if (entries.len == 0) return;
+
+    const size = rend.getSize();
+    const config = popup.PopupConfig{
+        .max_width = 50,
+        .max_height = @max(1, @min(20, size.height -| 6)),
+        .border = .single,
+        .title = "Undo Tree",
+    };
+
+    // Scroll so the selected entry stays visible
+    const visible_rows: usize = @min(entries.len, config.max_height);
+    const selected = @min(editor.undo_tree_selected, entries.len - 1);
+    const first = if (selected >= visible_rows) selected + 1 - visible_rows else 0;
+
+    var content = std.ArrayList(u8).empty;
+    defer content.deinit(allocator);
+
+    var line_buf: [128]u8 = undefined;
+    for (entries[first .. first + visible_rows], 0..) |entry, i| {
+        if (i > 0) try content.append(allocator, '\n');
+        try content.appendSlice(allocator, formatEntry(&line_buf, entry));
+    }
+
+    var dims = popup.calculateDimensions(content.items, config);
+    dims.width = @max(dims.width, 24);
+    const position = popup.PopupPosition{
+        .row = (size.height -| dims.height) / 2,
+        .col = (size.width -| dims.width) / 2,
+    };
+
+    try popup.render(rend, position, dims.width, dims.height, content.items, config);
+
+    // Highlight selected state over the plain popup content
+    const row = position.row + 1 + @as(u16, @intCast(selected - first));
+    const inner_width = dims.width -| 2;
+    var col: u16 = 0;
+    while (col < inner_width) : (col += 1) {
+        rend.output.setCell(row, position.col + 1 + col, .{
+            .char = ' ',
+            .fg = .{ .standard = .black },
+            .bg = .{ .standard = .blue },
+            .attrs = .{},
+        });
+    }
+    const selected_text = formatEntry(&line_buf, entries[selected]);
+    rend.writeText(row, position.col + 1, selected_text, .{ .standard = .black }, .{ .standard = .blue }, .{ .bold = true }, inner_width);
+}
+
+/// Format one state as "| * 12  14:03:22 UTC  +5 -0"
+/// Branches are indented one `|` per nesting level; `*` marks the current state.
+/// Times are UTC (std has no time zone database), and labelled so.
+pub fn formatEntry(buf: []u8, entry: UndoHistory.TreeEntry) []const u8 {
+    var stream = std.io.fixedBufferStream(buf);
+    const writer = stream.writer();
+
+    var depth: usize = 0;
+    while (depth < @min(entry.depth, 8)) : (depth += 1) {
+        writer.writeAll("| ") catch break;
+    }
+
+    const marker: u8 = if (entry.is_current) '*' else 'o';
+    if (entry.seq == 0) {
+        writer.print("{c} {d:>3}  original", .{ marker, entry.seq }) catch {};
+        return stream.getWritten();
+    }
+
+    const day_seconds = std.time.epoch.EpochSeconds{ .secs = @intCast(@divFloor(@max(entry.timestamp, 0), 1000)) };
+    const time = day_seconds.getDaySeconds();
+    writer.print("{c} {d:>3}  {d:0>2}:{d:0>2}:{d:0>2} UTC  +{d} -{d}", .{
+        marker,
+        entry.seq,
+        time.getHoursIntoDay(),
+        time.getMinutesIntoHour(),
+        time.getSecondsIntoMinute(),
+        entry.inserted,
+        entry.deleted,
+    }) catch {};
+    return stream.getWritten();
+}
+
+test "undotree: format entry" {
+    var buf: [128]u8 = undefined;
+    const entry = UndoHistory.TreeEntry{
+        .seq = 3,
+        .depth = 1,
+        .timestamp = (13 * 3600 + 5 * 60 + 9) * 1000,
+        .inserted = 4,
+        .deleted = 1,
+        .is_current = true,
+    };
+    try std.testing.expectEqualStrings("| *   3  13:05:09 UTC  +4 -1", formatEntry(&buf, entry));
+}
@@ -0,0 +1,102 @@
+//! Which-key popup rendering
+//! Lists the keys that can follow a partial key sequence once the user pauses on it
+
+const std = @import("std");
+const renderer = @import("renderer.zig");
+const popup = @import("popup.zig");
+
+const Editor = @import("../editor/editor.zig").Editor;
+const Keymap = @import("../editor/keymap.zig");
+const Registry = @import("../editor/command.zig").Registry;
+
+/// Render the popup above the status line, on the right
+pub fn render(rend: *renderer.Renderer, editor: *const Editor, allocator: std.mem.Allocator) !void {
+    const manager = &editor.keymap_manager;
+    const operator = editor.pending_operator;
+    // A bare count has every binding as a continuation, but an operator's motions are worth listing
+    if (manager.pending_keys.len == 0 and operator == null) return;
+
+    const waited = std.time.milliTimestamp() - manager.pending_at;
+    if (waited < @as(i64, @intCast(@min(editor.config.which_key_delay_ms, std.math.maxInt(i64))))) return;
+
+    const items = try manager.continuations(editor.getMode(), allocator);
+    defer allocator.free(items);
+    if (items.len == 0) return;
+
+    const size = rend.getSize();
+    const max_rows: usize = @max(1, size.height -| 4);
+    const rows = @min(items.len, max_rows);
+
+    var content = std.ArrayList(u8).empty;
+    defer content.deinit(allocator);
+
+    var line_buf: [128]u8 = undefined;
+    for (items[0..rows], 0..) |item, i| {
+        if (i > 0) try content.append(allocator, '\n');
+        try content.appendSlice(allocator, formatContinuation(&line_buf, item, &editor.command_registry));
+    }
+
+    // Title is the prefix typed so far, e.g. "<space> l" or "d i"
+    var title_buf: [64]u8 = undefined;
+    var title_stream = std.io.fixedBufferStream(&title_buf);
+    if (operator) |op| title_stream.writer().writeAll(op.operator.keys()) catch {};
+    for (manager.pending_keys.constSlice(), 0..) |key, i| {
+        if (i > 0 or operator != null) title_stream.writer().writeByte(' ') catch break;
+        key.writeNotation(title_stream.writer()) catch break;
+    }
+
+    const config = popup.PopupConfig{
+        .max_width = 50,
+        .max_height = @intCast(rows),
+        .border = .single,
+        .title = title_stream.getWritten(),
+    };
+    var dims = popup.calculateDimensions(content.items, config);
+    dims.width = @max(dims.width, @as(u16, @intCast(title_stream.getWritten().len + 4)));
+
+    const position = popup.PopupPosition{
+        .row = (size.height -| 1) -| dims.height,
+        .col = size.width -| dims.width,
+    };
+    try popup.render(rend, position, dims.width, dims.height, content.items, config);
+}
+
+/// Format one continuation as "f        Open file finder" or "l        +7 keys"
+/// A key that is both a command and a submenu shows the command with the count after it.
+pub fn formatContinuation(buf: []u8, item: Keymap.Continuation, registry: *const Registry) []const u8 {
+    var stream = std.io.fixedBufferStream(buf);
+    const writer = stream.writer();
+
+    var key_buf: [32]u8 = undefined;
+    var key_stream = std.io.fixedBufferStream(&key_buf);
+    item.key.writeNotation(key_stream.writer()) catch {};
+    writer.print("{s:<8} ", .{key_stream.getWritten()}) catch return stream.getWritten();
+
+    if (item.command) |name| {
+        const description = if (registry.get(name)) |command| command.description else name;
+        writer.writeAll(description) catch return stream.getWritten();
+        if (item.longer > 0) writer.print(" (+{d})", .{item.longer}) catch {};
+    } else {
+        writer.print("+{d} {s}", .{ item.longer, if (item.longer == 1) "key" else "keys" }) catch {};
+    }
+    return stream.getWritten();
+}
+
+test "whichkey: format continuation" {
+    const allocator = std.testing.allocator;
+    var registry = Registry.init(allocator);
+    defer registry.deinit();
+    try @import("../editor/command.zig").registerBuiltins(&registry);
+
+    var buf: [128]u8 = undefined;
+    const save = Keymap.Continuation{ .key = .{ .char = 'w' }, .command = "save", .longer = 0 };
+    const expected = try std.fmt.allocPrint(allocator, "w        {s}", .{registry.get("save").?.description});
+    defer allocator.free(expected);
+    try std.testing.expectEqualStrings(expected, formatContinuation(&buf, save, &registry));
+
+    const menu = Keymap.Continuation{ .key = .{ .char = 'l' }, .command = null, .longer = 7 };
+    try std.testing.expectEqualStrings("l        +7 keys", formatContinuation(&buf, menu, &registry));
+
+    const ctrl = Keymap.Continuation{ .key = .{ .char = 0x13 }, .command = null, .longer = 1 };
+    try std.testing.expectEqualStrings("<C-s>    +1 key", formatContinuation(&buf, ctrl, &registry));
+}
@@ -200,6 +200,15 @@ pub extern fn ts_node_next_sibling(node: TSNode) TSNode;
 /// Get the previous sibling
 pub extern fn ts_node_prev_sibling(node: TSNode) TSNode;
 
+/// Get the next named sibling
+pub extern fn ts_node_next_named_sibling(node: TSNode) TSNode;
+
+/// Get the previous named sibling
+pub extern fn ts_node_prev_named_sibling(node: TSNode) TSNode;
+
+/// Get the smallest named node that spans a byte range
+pub extern fn ts_node_named_descendant_for_byte_range(node: TSNode, start: u32, end: u32) TSNode;
+
 /// Get the parent node
 pub extern fn ts_node_parent(node: TSNode) TSNode;
 
@@ -261,6 +270,9 @@ pub extern fn ts_query_cursor_exec(
     node: TSNode,
 ) void;
 
+/// Limit a query cursor to matches that intersect a byte range
+pub extern fn ts_query_cursor_set_byte_range(cursor: *TSQueryCursor, start_byte: u32, end_byte: u32) void;
+
 /// Get next match
 pub extern fn ts_query_cursor_next_match(
     cursor: *TSQueryCursor,